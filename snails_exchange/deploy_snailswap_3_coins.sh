@@ -39,7 +39,11 @@ near view stableswap.snails_fi.testnet get_pool '{"pool_id":0}'
 near view stableswap.snails_fi.testnet fees_info '{"pool_id":0}'
 near view stableswap.snails_fi.testnet get_virtual_price '{"pool_id":0}'
 near view stableswap.snails_fi.testnet get_amp_factor '{"pool_id":0}'
-near call stableswap.snails_fi.testnet change_fees_setting '{"pool_id":0, "fees":{"admin_trade_fee_numerator":50,"admin_trade_fee_denominator":100,"admin_withdraw_fee_numerator":40,"admin_withdraw_fee_denominator":100,"trade_fee_numerator":3,"trade_fee_denominator":1000,"withdraw_fee_numerator":0,"withdraw_fee_denominator":1000}  }' --accountId stableswap.snails_fi.testnet
+# fees can be applied MIN_FEE_CHANGE_DELAY (3 days) after they are committed
+APPLY_AT_SEC=$(( $(date +%s) + 3 * 86400 ))
+near call stableswap.snails_fi.testnet commit_new_fees '{"pool_id":0, "fees":{"admin_trade_fee_numerator":50,"admin_trade_fee_denominator":100,"admin_withdraw_fee_numerator":40,"admin_withdraw_fee_denominator":100,"trade_fee_numerator":3,"trade_fee_denominator":1000,"withdraw_fee_numerator":0,"withdraw_fee_denominator":1000,"referral_fee_numerator":0,"referral_fee_denominator":1,"lp_fee_share_numerator":0,"lp_fee_share_denominator":1}, "apply_at_sec":'$APPLY_AT_SEC'}' --accountId stableswap.snails_fi.testnet
+# separately, once APPLY_AT_SEC has passed
+# near call stableswap.snails_fi.testnet apply_new_fees '{"pool_id":0}' --accountId stableswap.snails_fi.testnet
 
 near call stableswap.snails_fi.testnet set_amp_params  '{"pool_id":0,"initial_amp_factor":100,"target_amp_factor":200,"stop_ramp_ts":1640182177}' --accountId snails_fi.testnet
 
@@ -4,6 +4,7 @@ use near_sdk::collections::UnorderedMap;
 
 use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId, Balance, Promise};
 
+use crate::utils::{panic_storage_deposit, MAX_WITHDRAW_ALL_TRANSFERS};
 use crate::*;
 
 pub const U128_STORAGE: StorageUsage = 16;
@@ -45,6 +46,15 @@ impl From<Account> for VAccount {
     }
 }
 
+/// Withdrawal sent to the token contract, kept until its callback settles it.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct PendingWithdrawal {
+    pub sender_id: AccountId,
+    pub token_id: AccountId,
+    /// Amount debited from the sender's deposits.
+    pub amount: Balance,
+}
+
 /// Account deposits information and storage cost.
 #[derive(BorshSerialize, BorshDeserialize)]
 pub struct Account {
@@ -120,11 +130,6 @@ impl Account {
         }
     }
 
-    pub fn get_tokens(&self) -> Vec<AccountId> {
-        let a: Vec<AccountId> = self.tokens.keys().collect();
-        a
-    }
-
     pub fn get_balance(&self, token_id: &AccountId) -> Option<Balance> {
         if let Some(token_balance) = self.tokens.get(token_id) {
             Some(token_balance)
@@ -133,6 +138,11 @@ impl Account {
         }
     }
 
+    /// Returns balance of every registered token, zero balances included.
+    pub fn get_balances(&self) -> Vec<(AccountId, Balance)> {
+        self.tokens.to_vec()
+    }
+
     /// Asserts there is sufficient amount of $NEAR to cover storage usage.
     pub fn assert_storage_usage(&self) {
         assert!(
@@ -157,11 +167,12 @@ impl Account {
         }
     }
 
-    /// Unregisters `token_id` from this account balance.
-    /// Panics if the `token_id` balance is not 0.
-    pub(crate) fn unregister(&mut self, token_id: &AccountId) {
+    /// Unregisters `token_id` from this account balance and returns the dust left.
+    /// Panics if the `token_id` balance is above `dust_tolerance`.
+    pub(crate) fn unregister(&mut self, token_id: &AccountId, dust_tolerance: Balance) -> Balance {
         let amount = self.tokens.remove(token_id).unwrap_or_default();
-        assert_eq!(amount, 0, "{}", NON_ZERO_TOKEN_BALANCE);
+        assert!(amount <= dust_tolerance, "{}", NON_ZERO_TOKEN_BALANCE);
+        amount
     }
 
     /// Withdraw amount of `token` from the internal balance.
@@ -211,7 +222,9 @@ impl SnailSwap {
     /// This should be only place to directly use `self.accounts`.
     pub fn internal_save_account(&mut self, account_id: &AccountId, account: Account) {
         account.assert_storage_usage();
-        self.accounts.insert(&account_id, &account.into());
+        if self.accounts.insert(&account_id, &account.into()).is_none() {
+            self.account_count += 1;
+        }
     }
 
     /// Registers account in deposited amounts with given amount of $NEAR.
@@ -245,12 +258,42 @@ impl SnailSwap {
         withdraw_amount
     }
 
+    /// Counts `amount` of `token_id` received by the contract for deposits or pools.
+    pub(crate) fn internal_add_managed(&mut self, token_id: &AccountId, amount: Balance) {
+        let managed = self.managed_balances.get(token_id).unwrap_or(0);
+        self.managed_balances
+            .insert(token_id, &(managed.checked_add(amount).unwrap()));
+    }
+
+    /// Uncounts `amount` of `token_id` sent out by the contract.
+    /// Saturates, as tokens received before tracking started are not counted.
+    /// Tokens without a managed balance stay untracked, so they can't be retrieved.
+    pub(crate) fn internal_sub_managed(&mut self, token_id: &AccountId, amount: Balance) {
+        if let Some(managed) = self.managed_balances.get(token_id) {
+            self.managed_balances
+                .insert(token_id, &managed.saturating_sub(amount));
+        }
+    }
+
+    /// Sends tokens already debited from the sender's deposits.
+    /// The withdrawal is recorded under a fresh nonce, so its callback reverts
+    /// exactly this debit whatever happens to the account meanwhile.
     pub(crate) fn internal_send_tokens(
-        &self,
+        &mut self,
         sender_id: &AccountId,
         token_id: &AccountId,
         amount: Balance,
     ) -> Promise {
+        let nonce = self.next_withdrawal_nonce;
+        self.next_withdrawal_nonce += 1;
+        self.pending_withdrawals.insert(
+            &nonce,
+            &PendingWithdrawal {
+                sender_id: sender_id.clone(),
+                token_id: token_id.clone(),
+                amount,
+            },
+        );
         ext_fungible_token::ft_transfer(
             sender_id.clone(),
             U128(amount),
@@ -260,9 +303,7 @@ impl SnailSwap {
             GAS_FOR_FT_TRANSFER,
         )
         .then(ext_self::exchange_callback_post_withdraw(
-            token_id.clone(),
-            sender_id.clone(),
-            U128(amount),
+            nonce,
             env::current_account_id(),
             0,
             GAS_FOR_RESOLVE_TRANSFER,
@@ -280,12 +321,58 @@ impl SnailSwap {
             .unwrap_or(0)
     }
 
+    /// Deposits into the account if it has storage for the token, otherwise to lostfound.
+    /// Returns true if deposited into the account.
+    pub(crate) fn internal_deposit_or_lostfound(
+        &mut self,
+        account_id: &AccountId,
+        token_id: &AccountId,
+        amount: Balance,
+    ) -> bool {
+        if let Some(mut account) = self.internal_get_account(account_id) {
+            if account.deposit_with_storage_check(token_id, amount) {
+                self.accounts.insert(account_id, &account.into());
+                return true;
+            }
+        }
+        self.internal_lostfound(token_id, amount);
+        false
+    }
+
+    /// Unregisters the account from the LP tokens of every pool.
+    /// Shares left are forfeited to lostfound if `force`, otherwise they fail the call.
+    pub(crate) fn internal_unregister_lp_shares(&mut self, account_id: &AccountId, force: bool) {
+        let owner_id = self.owner_id.clone();
+        for pool_id in 0..self.pools.len() {
+            let mut pool = self.pools.get(pool_id).unwrap();
+            if !pool.is_lp_token_registered(account_id) {
+                continue;
+            }
+            let shares = pool.share_balance_of(account_id);
+            if shares > 0 {
+                assert!(force, "ERR_STORAGE_UNREGISTER_SHARES_NOT_EMPTY");
+                if !pool.is_lp_token_registered(&owner_id) {
+                    pool.share_register(&owner_id);
+                }
+                pool.share_transfer(account_id, &owner_id, shares);
+            }
+            pool.share_unregister(account_id);
+            self.pools.replace(pool_id, &pool);
+        }
+    }
+
     /// save token to owner account as lostfound, no need to care about storage
     /// only global whitelisted token can be stored in lost-found
     pub(crate) fn internal_lostfound(&mut self, token_id: &AccountId, amount: u128) {
         let mut lostfound = self.internal_unwrap_or_default_account(&self.owner_id);
         lostfound.deposit(token_id, amount);
-        self.accounts.insert(&self.owner_id, &lostfound.into());
+        if self
+            .accounts
+            .insert(&self.owner_id, &lostfound.into())
+            .is_none()
+        {
+            self.account_count += 1;
+        }
     }
 }
 
@@ -300,24 +387,80 @@ impl SnailSwap {
     pub fn register_tokens(&mut self, token_ids: Vec<AccountId>) {
         assert_one_yocto();
         self.assert_contract_running();
+        for token_id in token_ids.iter() {
+            self.assert_token_accepted(token_id);
+        }
         let sender_id = env::predecessor_account_id();
         let mut account = self.internal_unwrap_account(&sender_id);
         account.register(&token_ids);
         self.internal_save_account(&sender_id, account);
     }
 
+    /// Registers all tokens of given pool in the user's account deposit
+    /// and the user to the pool LP token.
+    /// Attached NEAR covers the LP registration and whatever the account
+    /// storage lacks for the new tokens, the rest is refunded.
+    #[payable]
+    pub fn register_for_pool(&mut self, pool_id: u64) {
+        self.assert_contract_running();
+        let sender_id = env::predecessor_account_id();
+        let mut account = self.internal_unwrap_account(&sender_id);
+
+        let prev_storage = env::storage_usage();
+        let mut pool = self.internal_get_pool(pool_id);
+        if !pool.is_lp_token_registered(&sender_id) {
+            pool.share_register(&sender_id);
+            self.pools.replace(pool_id, &pool);
+        }
+        let shares_cost = (env::storage_usage()
+            .checked_sub(prev_storage)
+            .unwrap_or_default() as Balance)
+            .checked_mul(env::storage_byte_cost())
+            .unwrap();
+        // base LP leg of a metapool is held as shares in the base pool, not as a deposit.
+        let tokens: Vec<AccountId> = pool
+            .tokens()
+            .iter()
+            .filter(|token_id| pool.base_pool_of(token_id).is_none())
+            .cloned()
+            .collect();
+        account.register(&tokens);
+        let missing = account
+            .storage_usage()
+            .checked_sub(account.near_amount)
+            .unwrap_or_default();
+        let storage_cost = shares_cost.checked_add(missing).unwrap();
+        let refund = env::attached_deposit()
+            .checked_sub(storage_cost)
+            .unwrap_or_else(|| panic_storage_deposit(storage_cost, env::attached_deposit()));
+        account.near_amount = account.near_amount.checked_add(missing).unwrap();
+        self.internal_save_account(&sender_id, account);
+
+        if refund > 0 {
+            Promise::new(sender_id).transfer(refund);
+        }
+    }
+
     /// Unregister given token from user's account deposit.
-    /// Panics if the balance of any given token is non 0.
+    /// Panics if the balance of any given token is above the dust tolerance,
+    /// dust left is moved to lostfound.
     #[payable]
     pub fn unregister_tokens(&mut self, token_ids: Vec<AccountId>) {
         assert_one_yocto();
         self.assert_contract_running();
         let sender_id = env::predecessor_account_id();
         let mut account = self.internal_unwrap_account(&sender_id);
+        let mut dust = vec![];
         for token_id in token_ids {
-            account.unregister(&token_id);
+            let amount = account.unregister(&token_id, self.dust_tolerance);
+            if amount > 0 {
+                dust.push((token_id, amount));
+            }
         }
         self.internal_save_account(&sender_id, account);
+        for (token_id, amount) in dust {
+            self.internal_lostfound(&token_id, amount);
+        }
     }
 
     /// Withdraws given token from the deposits of given user.
@@ -335,14 +478,59 @@ impl SnailSwap {
         let token_id: AccountId = token_id.into();
         let amount: u128 = amount.into();
         assert!(amount > 0, "{}", ILLEGAL_WITHDRAW_AMOUNT);
+        self.assert_token_not_paused(&token_id);
         let sender_id = env::predecessor_account_id();
         let mut account = self.internal_unwrap_account(&sender_id);
         // Note: subtraction and deregistration will be reverted if the promise fails.
         account.withdraw(&token_id, amount);
         if unregister == Some(true) {
-            account.unregister(&token_id);
+            account.unregister(&token_id, 0);
         }
         self.internal_save_account(&sender_id, account);
         self.internal_send_tokens(&sender_id, &token_id, amount)
     }
+
+    /// Withdraws every deposited token of the caller and unregisters them.
+    /// At most MAX_WITHDRAW_ALL_TRANSFERS transfers are made per call to stay within gas,
+    /// tokens left are kept registered and a later call withdraws them.
+    /// Each transfer reverts on its own if it fails, like `withdraw`.
+    /// Returns the tokens and amounts sent.
+    #[payable]
+    pub fn withdraw_all(&mut self) -> Vec<(AccountId, U128)> {
+        assert_one_yocto();
+        self.assert_contract_running();
+        let sender_id = env::predecessor_account_id();
+        let mut account = self.internal_unwrap_account(&sender_id);
+        let mut withdrawn = vec![];
+        let mut left = 0;
+        for (token_id, amount) in account.get_balances() {
+            if self.paused_tokens.contains(&token_id) {
+                // paused tokens stay deposited and registered.
+                continue;
+            }
+            if amount > 0 {
+                if withdrawn.len() == MAX_WITHDRAW_ALL_TRANSFERS {
+                    left += 1;
+                    continue;
+                }
+                // Note: subtraction and deregistration will be reverted if the promise fails.
+                account.withdraw(&token_id, amount);
+                withdrawn.push((token_id.clone(), amount));
+            }
+            account.unregister(&token_id, 0);
+        }
+        self.internal_save_account(&sender_id, account);
+        if left > 0 {
+            env::log_str(
+                format!("{} tokens left to withdraw, call withdraw_all again", left).as_str(),
+            );
+        }
+        withdrawn
+            .into_iter()
+            .map(|(token_id, amount)| {
+                self.internal_send_tokens(&sender_id, &token_id, amount);
+                (token_id, U128(amount))
+            })
+            .collect()
+    }
 }
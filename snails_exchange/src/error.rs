@@ -7,15 +7,20 @@ pub const LP_ALREADY_REGISTERED: &str = "LP already registered";
 // Accounts.
 
 pub const TOKEN_NOT_REG: &str = "Token not registered";
+pub const TOKEN_NOT_WHITELISTED: &str = "Token not whitelisted";
 pub const NON_ZERO_TOKEN_BALANCE: &str = "Non-zero token balance";
 pub const CALLBACK_POST_WITHDRAW_INVALID: &str = "Expected 1 promise result from withdraw";
+pub const PENDING_WITHDRAWAL_NOT_FOUND: &str = "Pending withdrawal not found";
+pub const CALLBACK_POST_WRAP_NEAR_INVALID: &str = "Expected 1 promise result from near_deposit";
 // pub const ERR26_ACCESS_KEY_NOT_ALLOWED: &str = "E26: access key not allowed";
 pub const WRONG_MSG_FORMAT: &str = "Illegal msg in ft_transfer_call";
+pub const INVALID_DIRECT_SWAP: &str = "Unknown pool or token in ft_transfer_call swap";
 pub const ILLEGAL_WITHDRAW_AMOUNT: &str = "Illegal withdraw amount";
 
 // Liquidity operations.
 
 pub const ZERO_SHARES: &str = "Minting zero shares";
+pub const NON_ZERO_SHARES: &str = "Non-zero shares balance";
 pub const TRANSFER_TO_SELF: &str = "Transfer to self";
 // Action result.
 
@@ -0,0 +1,44 @@
+//! NEP-297 events, logged as `EVENT_JSON:{...}` next to the plain logs.
+
+use near_sdk::serde_json::{json, Value};
+use near_sdk::{env, AccountId};
+
+pub const EVENT_STANDARD: &str = "snails_exchange";
+pub const EVENT_VERSION: &str = "1.0.0";
+
+fn emit_event(event: &str, data: Value) {
+    env::log_str(
+        format!(
+            "EVENT_JSON:{}",
+            json!({
+                "standard": EVENT_STANDARD,
+                "version": EVENT_VERSION,
+                "event": event,
+                "data": [data],
+            })
+        )
+        .as_str(),
+    );
+}
+
+/// Accounts made guardians by the owner, the ones already guardians are left out.
+pub(crate) fn emit_guardians_extended(owner_id: &AccountId, guardians: &[AccountId]) {
+    emit_event(
+        "extend_guardians",
+        json!({
+            "owner_id": owner_id,
+            "guardians": guardians,
+        }),
+    );
+}
+
+/// Accounts no longer guardians.
+pub(crate) fn emit_guardians_removed(owner_id: &AccountId, guardians: &[AccountId]) {
+    emit_event(
+        "remove_guardians",
+        json!({
+            "owner_id": owner_id,
+            "guardians": guardians,
+        }),
+    );
+}
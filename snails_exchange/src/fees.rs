@@ -25,6 +25,55 @@ pub struct Fees {
     pub withdraw_fee_numerator: u64,
     /// Withdraw fee denominator
     pub withdraw_fee_denominator: u64,
+    /// Referral fee numerator, carved out of the admin trade fee
+    #[serde(default)]
+    pub referral_fee_numerator: u64,
+    /// Referral fee denominator
+    #[serde(default = "default_fee_denominator")]
+    pub referral_fee_denominator: u64,
+    /// Share of the admin fee left in the pool for LPs, numerator
+    #[serde(default)]
+    pub lp_fee_share_numerator: u64,
+    /// Share of the admin fee left in the pool for LPs, denominator
+    #[serde(default = "default_fee_denominator")]
+    pub lp_fee_share_denominator: u64,
+}
+
+fn default_fee_denominator() -> u64 {
+    1
+}
+
+/// Fees before the referral fee and the LP share of the admin fee were added,
+/// read by `migrate`.
+#[derive(Clone, Copy, BorshSerialize, BorshDeserialize)]
+pub struct FeesV1 {
+    pub admin_trade_fee_numerator: u64,
+    pub admin_trade_fee_denominator: u64,
+    pub admin_withdraw_fee_numerator: u64,
+    pub admin_withdraw_fee_denominator: u64,
+    pub trade_fee_numerator: u64,
+    pub trade_fee_denominator: u64,
+    pub withdraw_fee_numerator: u64,
+    pub withdraw_fee_denominator: u64,
+}
+
+impl From<FeesV1> for Fees {
+    fn from(fees: FeesV1) -> Self {
+        Self {
+            admin_trade_fee_numerator: fees.admin_trade_fee_numerator,
+            admin_trade_fee_denominator: fees.admin_trade_fee_denominator,
+            admin_withdraw_fee_numerator: fees.admin_withdraw_fee_numerator,
+            admin_withdraw_fee_denominator: fees.admin_withdraw_fee_denominator,
+            trade_fee_numerator: fees.trade_fee_numerator,
+            trade_fee_denominator: fees.trade_fee_denominator,
+            withdraw_fee_numerator: fees.withdraw_fee_numerator,
+            withdraw_fee_denominator: fees.withdraw_fee_denominator,
+            referral_fee_numerator: 0,
+            referral_fee_denominator: default_fee_denominator(),
+            lp_fee_share_numerator: 0,
+            lp_fee_share_denominator: default_fee_denominator(),
+        }
+    }
 }
 
 impl Fees {
@@ -44,6 +93,22 @@ impl Fees {
             .to_u128()
     }
 
+    /// Apply referral fee
+    pub fn referral_fee(&self, fee_amount: u128) -> Option<u128> {
+        U192::from(fee_amount)
+            .checked_mul(self.referral_fee_numerator.into())?
+            .checked_div(self.referral_fee_denominator.into())?
+            .to_u128()
+    }
+
+    /// Apply LP share of the admin fee
+    pub fn lp_fee_share(&self, admin_fee_amount: u128) -> Option<u128> {
+        U192::from(admin_fee_amount)
+            .checked_mul(self.lp_fee_share_numerator.into())?
+            .checked_div(self.lp_fee_share_denominator.into())?
+            .to_u128()
+    }
+
     /// Compute trade fee from amount
     pub fn trade_fee(&self, trade_amount: u128) -> Option<u128> {
         U192::from(trade_amount)
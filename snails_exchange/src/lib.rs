@@ -13,19 +13,22 @@ use near_contract_standards::storage_management::{
     StorageBalance, StorageBalanceBounds, StorageManagement,
 };
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::{LookupMap, Vector};
+use near_sdk::collections::{LookupMap, UnorderedMap, UnorderedSet, Vector};
 use near_sdk::json_types::U128;
 use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::{
-    assert_one_yocto, env, log, near_bindgen, AccountId, Balance, BorshStorageKey, PanicOnDefault,
-    Promise, PromiseResult, StorageUsage,
+    assert_one_yocto, env, log, near_bindgen, AccountId, Balance, BorshStorageKey, Gas,
+    PanicOnDefault, Promise, PromiseOrValue, PromiseResult, StorageUsage,
 };
 
 use std::fmt;
 
 use crate::utils::{
-    assert_fees_info_valid, check_token_duplicates, ext_self, GAS_FOR_FT_TRANSFER,
-    GAS_FOR_RESOLVE_TRANSFER,
+    assert_decimals_valid, assert_fees_info_valid, assert_swap_valid, check_token_duplicates,
+    ext_self, ext_wrap_near, panic_storage_deposit, BPS_DENOMINATOR, GAS_FOR_FT_TRANSFER,
+    GAS_FOR_NEAR_DEPOSIT, GAS_FOR_RESOLVE_TRANSFER, GAS_FOR_SWAP_HOP, GAS_FOR_SWAP_NEAR_CALLBACK,
+    GAS_FOR_SWAP_WRAPPED_NEAR, MAX_COINS, MAX_ROUTE_LEN, MAX_SEED_IMBALANCE, MIN_FEE_CHANGE_DELAY,
+    PRECISION,
 };
 
 #[derive(BorshStorageKey, BorshSerialize)]
@@ -34,20 +37,33 @@ pub(crate) enum StorageKey {
     Accounts,
     Shares { pool_id: u32 },
     AccountTokens { account_id: AccountId },
+    Whitelist,
+    Guardian,
+    AccountVolumes,
+    PendingWithdrawals,
+    PausedTokens,
+    ManagedBalances,
 }
 
-use crate::account::{Account, VAccount};
+use crate::account::{Account, PendingWithdrawal, VAccount};
 use crate::error::*;
 pub use crate::fees::Fees;
-use crate::pool::Pool;
+use crate::pool::{Pool, PoolTrait};
 use crate::simple_pool::SimplePool;
-pub use crate::views::{ContractMetadata, PoolInfo};
+pub use crate::utils::{AmpParams, SimplePoolArgs, SwapAction, SwapVolume};
+pub use crate::views::{
+    BalanceCap, ContractMetadata, MathConstants, PoolFeeInfo, PoolInfo, RampSchedule, RouteQuote,
+    SingleDepositImpact, StorageReport, UnderlyingReturn,
+};
 
 mod account;
 mod bigint;
 mod error;
+mod events;
 mod fees;
+mod meta_pool;
 mod multi_fungible_token;
+mod owner;
 mod pool;
 mod simple_pool;
 mod snails;
@@ -84,6 +100,27 @@ pub struct SnailSwap {
     /// Running state
     state: RunningState,
     accounts: LookupMap<AccountId, VAccount>,
+    /// Set of tokens that can be deposited into the contract.
+    whitelisted_tokens: UnorderedSet<AccountId>,
+    /// Balance left on a token that can be swept to lostfound when unregistering it.
+    dust_tolerance: Balance,
+    /// Accounts allowed to shut pools down besides the owner.
+    guardians: UnorderedSet<AccountId>,
+    /// Contract wrapping NEAR for `swap_near`, disabled if not set.
+    wrap_near_id: Option<AccountId>,
+    /// Swap volume of each account across all pools, in 24 decimals.
+    account_volumes: UnorderedMap<AccountId, SwapVolume>,
+    /// Withdrawals sent out and waiting for their callback, by nonce.
+    pending_withdrawals: LookupMap<u64, PendingWithdrawal>,
+    /// Nonce of the next withdrawal.
+    next_withdrawal_nonce: u64,
+    /// Number of registered accounts.
+    account_count: u64,
+    /// Tokens that can't be deposited, withdrawn or moved by pool operations.
+    paused_tokens: UnorderedSet<AccountId>,
+    /// Amount of each token the contract holds for deposits and pools,
+    /// anything above it was sent without going through `ft_on_transfer`.
+    managed_balances: LookupMap<AccountId, Balance>,
 }
 
 #[near_bindgen]
@@ -97,6 +134,16 @@ impl SnailSwap {
             pools: Vector::new(StorageKey::Pools),
             state: RunningState::Running,
             accounts: LookupMap::new(StorageKey::Accounts),
+            whitelisted_tokens: UnorderedSet::new(StorageKey::Whitelist),
+            dust_tolerance: 0,
+            guardians: UnorderedSet::new(StorageKey::Guardian),
+            wrap_near_id: None,
+            account_volumes: UnorderedMap::new(StorageKey::AccountVolumes),
+            pending_withdrawals: LookupMap::new(StorageKey::PendingWithdrawals),
+            next_withdrawal_nonce: 0,
+            account_count: 0,
+            paused_tokens: UnorderedSet::new(StorageKey::PausedTokens),
+            managed_balances: LookupMap::new(StorageKey::ManagedBalances),
         }
     }
 
@@ -115,29 +162,52 @@ impl SnailSwap {
     ) -> u64 {
         self.assert_owner();
         self.assert_contract_running();
-        check_token_duplicates(&tokens);
-
-        assert_fees_info_valid(&fees);
-
-        self.internal_add_pool(Pool::SimplePool(SimplePool::new(
-            self.pools.len() as u32,
-            initial_amp_factor as u64,
-            target_amp_factor as u64,
-            start_ramp_ts as u64,
-            stop_ramp_ts as u64,
-            fees,
+        let pool = self.internal_new_simple_pool(SimplePoolArgs {
             tokens,
             decimals,
-        )))
+            initial_amp_factor,
+            target_amp_factor,
+            start_ramp_ts,
+            stop_ramp_ts,
+            fees,
+        });
+        self.internal_add_pool(pool)
+    }
+
+    /// Adds several "Simple Pools" at once, each validated like in `add_simple_pool`.
+    /// Attached NEAR should be enough to cover the storage added by all of them.
+    /// Returns the new pool ids in creation order.
+    #[payable]
+    pub fn add_simple_pools(&mut self, pools: Vec<SimplePoolArgs>) -> Vec<u64> {
+        self.assert_owner();
+        self.assert_contract_running();
+        assert!(!pools.is_empty(), "ERR_NO_POOLS");
+
+        let prev_storage = env::storage_usage();
+        let ids = pools
+            .into_iter()
+            .map(|args| {
+                let pool = self.internal_new_simple_pool(args);
+                self.internal_push_pool(pool)
+            })
+            .collect();
+        self.internal_check_storage(prev_storage);
+        ids
     }
 
     /// Add liquidity from already deposited amounts to given pool.
+    /// The stable pool takes `tokens_amount` as given, imbalance is charged as a fee on the
+    /// minted shares, so `min_mint_amount` bounds the slippage.
+    /// The first deposit of a pool leaves `LOCKED_INITIAL_SHARES` of its shares locked.
+    /// Shares are minted to `share_receiver` if given, which must be registered to the pool
+    /// LP token, tokens are always taken from the caller's deposits.
     #[payable]
     pub fn add_liquidity(
         &mut self,
         pool_id: u64,
         tokens_amount: Vec<U128>,
         min_mint_amount: Option<U128>,
+        share_receiver: Option<AccountId>,
     ) -> Balance {
         self.assert_contract_running();
         assert!(
@@ -149,14 +219,23 @@ impl SnailSwap {
         let sender_id = env::predecessor_account_id();
 
         /*3. deposit*/
-        let mut pool = self.pools.get(pool_id).expect("ERR_NO_POOL");
+        let mut pool = self.internal_get_pool(pool_id);
         let amounts: Vec<u128> = tokens_amount
             .into_iter()
             .map(|amount| amount.into())
             .collect();
+        self.assert_amounts_not_paused(pool.tokens(), &amounts);
+        let share_receiver = share_receiver.unwrap_or_else(|| sender_id.clone());
+        if share_receiver != sender_id {
+            assert!(
+                pool.is_lp_token_registered(&share_receiver),
+                "{}",
+                LP_NOT_REGISTERED
+            );
+        }
 
-        // Add amounts given to liquidity first. It will return the balanced amounts.
-        let (lp_shares, admin_fees) = pool.add_liquidity(&sender_id, &amounts);
+        // Add amounts given to liquidity first, the pool takes them all without rebalancing.
+        let (lp_shares, admin_fees) = pool.add_liquidity(&share_receiver, &amounts);
 
         if let Some(min_amounts) = min_mint_amount {
             // Check that all amounts are above request min amounts in case of front running that changes the exchange rate.
@@ -169,10 +248,16 @@ impl SnailSwap {
 
         // Subtract updated amounts from deposits. This will fail if there is not enough funds for any of the tokens.
         for i in 0..tokens.len() {
-            deposits.withdraw(&tokens[i], amounts[i]);
+            self.internal_withdraw_pool_token(
+                &pool,
+                &sender_id,
+                &mut deposits,
+                &tokens[i],
+                amounts[i],
+            );
         }
 
-        self.transfer_admin_fees(&tokens, &admin_fees);
+        self.transfer_admin_fees(&pool, &admin_fees);
         self.internal_save_account(&sender_id, deposits);
         self.pools.replace(pool_id, &pool);
         self.internal_check_storage(prev_storage);
@@ -180,23 +265,62 @@ impl SnailSwap {
         lp_shares
     }
 
-    fn transfer_admin_fees(&mut self, tokens: &[AccountId], admin_fees: &[u128]) {
+    /// Makes the initial deposit of an empty pool from the owner's deposits.
+    /// Each amount must be at least one whole token at the pool decimals (or rates),
+    /// and the amounts in 24 decimals within MAX_SEED_IMBALANCE of each other,
+    /// which catches amounts given at the wrong decimals. Only can be called by owner.
+    #[payable]
+    pub fn seed_pool(&mut self, pool_id: u64, amounts: Vec<U128>) -> U128 {
+        self.assert_owner();
+        let pool = self.internal_get_pool(pool_id);
+        assert_eq!(pool.share_total_balance(), 0, "ERR_POOL_ALREADY_SEEDED");
+        assert_eq!(amounts.len(), pool.tokens().len(), "ERR_WRONG_AMOUNTS_NUM");
+        let normalized: Vec<u128> = amounts
+            .iter()
+            .zip(pool.get_rates())
+            .map(|(amount, rate)| amount.0.checked_mul(rate).expect("ERR_SEED_OVERFLOW"))
+            .collect();
+        let min = *normalized.iter().min().unwrap();
+        let max = *normalized.iter().max().unwrap();
+        assert!(min >= PRECISION, "ERR_SEED_BELOW_ONE_TOKEN");
+        assert!(max / min < MAX_SEED_IMBALANCE, "ERR_SEED_IMBALANCED");
+
+        self.add_liquidity(pool_id, amounts, None, None).into()
+    }
+
+    fn transfer_admin_fees(&mut self, pool: &Pool, admin_fees: &[u128]) {
         //allocate fees
-        let mut exchange_account = self.internal_unwrap_or_default_account(&self.owner_id);
+        let owner_id = self.owner_id.clone();
+        let mut exchange_account = self.internal_unwrap_or_default_account(&owner_id);
+        let tokens = pool.tokens();
         for i in 0..tokens.len() {
-            exchange_account.deposit(&tokens[i], admin_fees[i]);
+            self.internal_deposit_pool_token(
+                pool,
+                &owner_id,
+                &mut exchange_account,
+                &tokens[i],
+                admin_fees[i],
+            );
         }
-        self.internal_save_account(&self.owner_id.clone(), exchange_account);
+        self.internal_save_account(&owner_id, exchange_account);
     }
 
     /// Remove liquidity from the pool into general pool of liquidity.
+    /// Fails with ERR_VP_BELOW_MIN if given and the virtual price of the pool is below min_virtual_price.
     #[payable]
-    pub fn remove_liquidity(&mut self, pool_id: u64, shares: U128, min_amounts: Vec<U128>) {
+    pub fn remove_liquidity(
+        &mut self,
+        pool_id: u64,
+        shares: U128,
+        min_amounts: Vec<U128>,
+        min_virtual_price: Option<U128>,
+    ) {
         assert_one_yocto();
         self.assert_contract_running();
         let prev_storage = env::storage_usage();
         let sender_id = env::predecessor_account_id();
-        let mut pool = self.pools.get(pool_id).expect("ERR_NO_POOL");
+        let mut pool = self.internal_get_pool(pool_id);
+        pool.assert_min_virtual_price(min_virtual_price);
 
         let (amounts, admin_fees) = pool.remove_liquidity(
             &sender_id,
@@ -206,12 +330,19 @@ impl SnailSwap {
                 .map(|amount| amount.into())
                 .collect(),
         );
+        self.assert_amounts_not_paused(pool.tokens(), &amounts);
 
         let tokens = pool.tokens();
         let mut deposits = self.internal_unwrap_or_default_account(&sender_id);
 
         for i in 0..tokens.len() {
-            deposits.deposit(&tokens[i], amounts[i]);
+            self.internal_deposit_pool_token(
+                &pool,
+                &sender_id,
+                &mut deposits,
+                &tokens[i],
+                amounts[i],
+            );
         }
 
         // Freed up storage balance from LP tokens will be returned to near_balance.
@@ -226,7 +357,7 @@ impl SnailSwap {
                 .unwrap();
         }
 
-        self.transfer_admin_fees(&tokens, &admin_fees);
+        self.transfer_admin_fees(&pool, &admin_fees);
         self.internal_save_account(&sender_id, deposits);
         self.pools.replace(pool_id, &pool);
         self.internal_check_storage(prev_storage);
@@ -245,12 +376,13 @@ impl SnailSwap {
         self.assert_contract_running();
         let prev_storage = env::storage_usage();
         let sender_id = env::predecessor_account_id();
-        let mut pool = self.pools.get(pool_id).expect("ERR_NO_POOL");
+        let mut pool = self.internal_get_pool(pool_id);
 
         let remove_coin_amount: Vec<Balance> = remove_coin_amount
             .into_iter()
             .map(|amount| amount.into())
             .collect();
+        self.assert_amounts_not_paused(pool.tokens(), &remove_coin_amount);
 
         let (removed_lp, admin_fees) =
             pool.remove_liquidity_imbalance(&sender_id, &remove_coin_amount);
@@ -263,7 +395,13 @@ impl SnailSwap {
         let mut deposits = self.internal_unwrap_or_default_account(&sender_id);
 
         for i in 0..tokens.len() {
-            deposits.deposit(&tokens[i], remove_coin_amount[i]);
+            self.internal_deposit_pool_token(
+                &pool,
+                &sender_id,
+                &mut deposits,
+                &tokens[i],
+                remove_coin_amount[i],
+            );
         }
 
         // Freed up storage balance from LP tokens will be returned to near_balance.
@@ -278,12 +416,35 @@ impl SnailSwap {
                 .unwrap();
         }
 
-        self.transfer_admin_fees(&tokens, &admin_fees);
+        self.transfer_admin_fees(&pool, &admin_fees);
         self.internal_save_account(&sender_id, deposits);
         self.pools.replace(pool_id, &pool);
         self.internal_check_storage(prev_storage);
     }
 
+    /// Removes exactly `exact_out` of `token_out` for the LP it takes, the inverse framing of
+    /// `remove_liquidity_one_coin`: the LP burnt is bounded by `max_lp` instead of the output.
+    /// Goes through the imbalanced removal, which inverts the curve for a single coin.
+    #[payable]
+    pub fn remove_liquidity_to_exact_one_coin(
+        &mut self,
+        pool_id: u64,
+        token_out: AccountId,
+        exact_out: U128,
+        max_lp: U128,
+    ) {
+        assert!(exact_out.0 > 0, "ERR_ZERO_AMOUNT");
+        let pool = self.internal_get_pool(pool_id);
+        assert!(pool.tokens().contains(&token_out), "ERR_MISSING_TOKEN");
+        let amounts: Vec<U128> = pool
+            .tokens()
+            .iter()
+            .map(|id| if id == &token_out { exact_out } else { U128(0) })
+            .collect();
+        self.remove_liquidity_imbalance(pool_id, amounts, Some(max_lp));
+    }
+
+    /// Fails with ERR_VP_BELOW_MIN if given and the virtual price of the pool is below min_virtual_price.
     #[payable]
     pub fn remove_liquidity_one_coin(
         &mut self,
@@ -291,16 +452,19 @@ impl SnailSwap {
         token_out: AccountId,
         remove_lp_amount: U128,
         min_amount: U128,
+        min_virtual_price: Option<U128>,
     ) {
         assert_one_yocto();
         self.assert_contract_running();
         let prev_storage = env::storage_usage();
         let sender_id = env::predecessor_account_id();
-        let mut pool = self.pools.get(pool_id).expect("ERR_NO_POOL");
+        let mut pool = self.internal_get_pool(pool_id);
+        pool.assert_min_virtual_price(min_virtual_price);
+        self.assert_token_not_paused(&token_out);
 
         let (amounts, admin_fees) = pool.remove_liquidity_one_coin(
             &sender_id,
-            token_out.into(),
+            &token_out,
             remove_lp_amount.into(),
             min_amount.into(),
         );
@@ -309,7 +473,13 @@ impl SnailSwap {
         let mut deposits = self.internal_unwrap_or_default_account(&sender_id);
 
         for i in 0..tokens.len() {
-            deposits.deposit(&tokens[i], amounts[i]);
+            self.internal_deposit_pool_token(
+                &pool,
+                &sender_id,
+                &mut deposits,
+                &tokens[i],
+                amounts[i],
+            );
         }
 
         // Freed up storage balance from LP tokens will be returned to near_balance.
@@ -324,7 +494,7 @@ impl SnailSwap {
                 .unwrap();
         }
 
-        self.transfer_admin_fees(&tokens, &admin_fees);
+        self.transfer_admin_fees(&pool, &admin_fees);
         self.internal_save_account(&sender_id, deposits);
         self.pools.replace(pool_id, &pool);
         self.internal_check_storage(prev_storage);
@@ -332,26 +502,185 @@ impl SnailSwap {
 
     fn swap_core(
         &mut self,
+        sender_id: &AccountId,
         pool_id: u64,
         token_in: &AccountId,
         amount_in: Balance,
         token_out: &AccountId,
         minimum_amount_out: Balance,
+        referral_id: &Option<AccountId>,
     ) -> Balance {
         self.assert_contract_running();
-
-        let mut pool = self.pools.get(pool_id).expect("ERR_NO_POOL");
-
-        let (amount_out, admin_fee) = pool.swap(token_in, amount_in, token_out, minimum_amount_out);
+        assert_swap_valid(token_in, amount_in, token_out);
+        self.assert_token_not_paused(token_in);
+        self.assert_token_not_paused(token_out);
+
+        let mut pool = self.internal_get_pool(pool_id);
+
+        let (mut amount_out, mut admin_fee, total_fee) =
+            pool.swap(token_in, amount_in, token_out, minimum_amount_out);
+        // share holders get part of the fee back, carved out of the admin fee like the referral fee.
+        let discount = pool.lp_fee_discount(sender_id, total_fee).min(admin_fee);
+        if discount > 0 {
+            admin_fee -= discount;
+            amount_out += discount;
+            env::log_str(
+                format!(
+                    "LP fee discount {} {} to {}",
+                    discount, token_out, sender_id
+                )
+                .as_str(),
+            );
+        }
+        // referral fee is carved out of what is left of the admin fee after the LP share.
+        let referral_fee = pool
+            .fees_info()
+            .referral_fee(total_fee)
+            .expect("ERR_REFERRAL_FEE")
+            .min(admin_fee);
         self.pools.replace(pool_id, &pool);
+        self.internal_record_account_volume(
+            sender_id, &pool, token_in, amount_in, token_out, amount_out,
+        );
         //allocate fees
-        let mut exchange_account = self.internal_unwrap_account(&self.owner_id);
-        exchange_account.deposit(token_out, admin_fee);
-        self.internal_save_account(&self.owner_id.clone(), exchange_account);
+        if let Some(referral_id) = referral_id {
+            if referral_fee > 0
+                && pool.base_pool_of(token_out).is_none()
+                && self.internal_deposit_referral_fee(referral_id, token_out, referral_fee)
+            {
+                admin_fee = admin_fee.checked_sub(referral_fee).unwrap_or_default();
+            }
+        }
+        let owner_id = self.owner_id.clone();
+        let mut exchange_account = self.internal_unwrap_account(&owner_id);
+        self.internal_deposit_pool_token(
+            &pool,
+            &owner_id,
+            &mut exchange_account,
+            token_out,
+            admin_fee,
+        );
+        self.internal_save_account(&owner_id, exchange_account);
 
         amount_out.into()
     }
 
+    /// Returns the token of `acceptable_outs` giving the most for `amount_in` of `token_in`.
+    fn internal_best_output(
+        &self,
+        pool_id: u64,
+        token_in: &AccountId,
+        amount_in: Balance,
+        acceptable_outs: &[AccountId],
+    ) -> AccountId {
+        let pool = self.internal_get_pool(pool_id);
+        let rates = pool.get_rates();
+        acceptable_outs
+            .iter()
+            .filter(|token_out| *token_out != token_in)
+            .map(|token_out| {
+                let index = pool
+                    .tokens()
+                    .iter()
+                    .position(|id| id == token_out)
+                    .expect("ERR_MISSING_TOKEN");
+                let amount_out = pool.get_return(token_in, amount_in, token_out);
+                (token_out, amount_out * rates[index])
+            })
+            .max_by_key(|(_, normalized_out)| *normalized_out)
+            .expect("ERR_NO_ACCEPTABLE_OUT")
+            .0
+            .clone()
+    }
+
+    /// Makes sure the account can receive token_out of the pool.
+    /// Base LP of a metapool is received as shares, so it needs no token registration.
+    fn internal_register_out_token(
+        &self,
+        pool_id: u64,
+        account: &mut Account,
+        token_out: &AccountId,
+        auto_register: bool,
+    ) {
+        let pool = self.internal_get_pool(pool_id);
+        if pool.base_pool_of(token_out).is_some() || account.get_balance(token_out).is_some() {
+            return;
+        }
+        assert!(auto_register, "ERR_OUT_TOKEN_NOT_REGISTERED");
+        account.near_amount = account
+            .near_amount
+            .checked_add(env::attached_deposit())
+            .unwrap();
+        account.register(&vec![token_out.clone()]);
+    }
+
+    /// Adds a swap to the volume of the account, amounts are normalized to 24 decimals
+    /// by the pool rates so volumes in different tokens add up.
+    fn internal_record_account_volume(
+        &mut self,
+        account_id: &AccountId,
+        pool: &Pool,
+        token_in: &AccountId,
+        amount_in: Balance,
+        token_out: &AccountId,
+        amount_out: Balance,
+    ) {
+        let rates = pool.get_rates();
+        let normalized = |token_id: &AccountId, amount: Balance| {
+            let idx = pool
+                .tokens()
+                .iter()
+                .position(|id| id == token_id)
+                .expect("ERR_MISSING_TOKEN");
+            amount.checked_mul(rates[idx]).unwrap()
+        };
+        let mut volume = self.account_volumes.get(account_id).unwrap_or_default();
+        volume.input.0 = volume
+            .input
+            .0
+            .checked_add(normalized(token_in, amount_in))
+            .unwrap();
+        volume.output.0 = volume
+            .output
+            .0
+            .checked_add(normalized(token_out, amount_out))
+            .unwrap();
+        self.account_volumes.insert(account_id, &volume);
+    }
+
+    /// Deposits referral fee into the referrer's internal account.
+    /// Returns false if referrer is not registered or has no storage for the token,
+    /// in which case the fee stays with the admin fee.
+    fn internal_deposit_referral_fee(
+        &mut self,
+        referral_id: &AccountId,
+        token_id: &AccountId,
+        amount: Balance,
+    ) -> bool {
+        if let Some(mut account) = self.internal_get_account(referral_id) {
+            if account.deposit_with_storage_check(token_id, amount) {
+                self.internal_save_account(referral_id, account);
+                env::log_str(
+                    format!(
+                        "Referral {} got {} {} of fee",
+                        referral_id, amount, token_id
+                    )
+                    .as_str(),
+                );
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Swaps from the sender's deposits.
+    /// token_out should be registered in the sender's deposits, unless `auto_register`
+    /// is set, then it is registered with the attached deposit added to sender's storage.
+    /// Fails with ERR_PRICE_IMPACT_TOO_HIGH if the swap gets more than `max_price_impact_bps`
+    /// below the spot rate of the pool.
+    /// If `recipient` is given, token_out is credited to its deposits instead of the sender's.
+    /// The recipient must be registered, token_out registration and `auto_register`
+    /// then apply to the recipient's deposits.
     #[payable]
     pub fn swap(
         &mut self,
@@ -360,32 +689,257 @@ impl SnailSwap {
         amount_in: U128,
         token_out: AccountId,
         minimum_amount_out: U128,
+        referral_id: Option<AccountId>,
+        auto_register: Option<bool>,
+        max_price_impact_bps: Option<u32>,
+        recipient: Option<AccountId>,
     ) -> U128 {
+        assert_swap_valid(&token_in, amount_in.0, &token_out);
         let sender_id = env::predecessor_account_id();
+        // spot rate is quoted before the swap moves it.
+        let pre_swap_pool = max_price_impact_bps.map(|_| self.internal_get_pool(pool_id));
+        // self referral is ignored, the account below would overwrite it anyway.
+        let referral_id = referral_id.filter(|referral_id| referral_id != &sender_id);
         let mut account = self.internal_unwrap_account(&sender_id);
+        let mut recipient = recipient
+            .filter(|recipient_id| recipient_id != &sender_id)
+            .map(|recipient_id| {
+                let recipient_account = self.internal_unwrap_account(&recipient_id);
+                (recipient_id, recipient_account)
+            });
+        self.internal_register_out_token(
+            pool_id,
+            recipient
+                .as_mut()
+                .map(|(_, recipient_account)| recipient_account)
+                .unwrap_or(&mut account),
+            &token_out,
+            auto_register.unwrap_or(false),
+        );
 
         let amount_out = self.swap_core(
+            &sender_id,
             pool_id,
             &token_in,
             amount_in.0,
             &token_out,
             minimum_amount_out.0,
+            &referral_id,
         );
-        account.withdraw(&token_in, amount_in.0);
-
-        account.deposit(&token_out, amount_out);
+        if let Some(pre_swap_pool) = pre_swap_pool {
+            pre_swap_pool.assert_max_price_impact(
+                &token_in,
+                amount_in.0,
+                &token_out,
+                amount_out,
+                max_price_impact_bps,
+            );
+        }
+        let pool = self.internal_get_pool(pool_id);
+        self.internal_withdraw_pool_token(&pool, &sender_id, &mut account, &token_in, amount_in.0);
+        match recipient {
+            Some((recipient_id, mut recipient_account)) => {
+                self.internal_deposit_pool_token(
+                    &pool,
+                    &recipient_id,
+                    &mut recipient_account,
+                    &token_out,
+                    amount_out,
+                );
+                self.internal_save_account(&recipient_id, recipient_account);
+                env::log_str(
+                    format!(
+                        "Swap output {} {} to {}",
+                        amount_out, token_out, recipient_id
+                    )
+                    .as_str(),
+                );
+            }
+            None => self.internal_deposit_pool_token(
+                &pool,
+                &sender_id,
+                &mut account,
+                &token_out,
+                amount_out,
+            ),
+        }
         self.internal_save_account(&sender_id, account);
 
         amount_out.into()
     }
 
-    pub fn change_fees_setting(&mut self, pool_id: u64, fees: Fees) {
+    /// Wraps the attached NEAR on the wrap contract, swaps it for `token_out`
+    /// and sends the output to the caller.
+    /// If wrapping fails the attached NEAR is refunded.
+    #[payable]
+    pub fn swap_near(
+        &mut self,
+        pool_id: u64,
+        token_out: AccountId,
+        min_amount_out: U128,
+    ) -> Promise {
+        self.assert_contract_running();
+        let wrap_near_id = self.wrap_near_id.clone().expect("ERR_NO_WRAP_NEAR");
+        let amount = env::attached_deposit();
+        assert!(amount > 0, "ERR_ZERO_AMOUNT");
+        let pool = self.internal_get_pool(pool_id);
+        assert!(
+            pool.base_pool_of(&token_out).is_none(),
+            "ERR_INVALID_TOKEN_OUT"
+        );
+        assert!(
+            pool.get_return(&wrap_near_id, amount, &token_out) >= min_amount_out.0,
+            "ERR_MIN_AMOUNT"
+        );
+
+        ext_wrap_near::near_deposit(wrap_near_id, amount, GAS_FOR_NEAR_DEPOSIT).then(
+            ext_self::exchange_callback_post_wrap_near(
+                pool_id,
+                env::predecessor_account_id(),
+                U128(amount),
+                token_out,
+                min_amount_out,
+                env::current_account_id(),
+                0,
+                GAS_FOR_SWAP_NEAR_CALLBACK,
+            ),
+        )
+    }
+
+    /// Swaps to whichever of `acceptable_outs` returns the most for `amount_in`,
+    /// returns are compared in 24 decimals. `minimum_amount_out` is in the chosen token.
+    #[payable]
+    pub fn swap_to_any(
+        &mut self,
+        pool_id: u64,
+        token_in: AccountId,
+        amount_in: U128,
+        acceptable_outs: Vec<AccountId>,
+        minimum_amount_out: U128,
+    ) -> U128 {
+        let token_out =
+            self.internal_best_output(pool_id, &token_in, amount_in.0, &acceptable_outs);
+        env::log_str(format!("Swap to any picked {}", token_out).as_str());
+        self.swap(
+            pool_id,
+            token_in,
+            amount_in,
+            token_out,
+            minimum_amount_out,
+            None,
+            None,
+            None,
+            None,
+        )
+    }
+
+    /// Swaps `amount_in` along the given route from the sender's deposits,
+    /// each hop swapping the whole output of the previous one.
+    /// The route runs synchronously inside this call, token movement is internal to deposits
+    /// and no promise is made mid-route, so a failing hop reverts the whole route.
+    /// Every token_out of the route should be registered in the sender's deposits.
+    pub fn swap_route(
+        &mut self,
+        actions: Vec<SwapAction>,
+        amount_in: U128,
+        referral_id: Option<AccountId>,
+    ) -> U128 {
+        self.assert_contract_running();
+        assert!(!actions.is_empty(), "ERR_EMPTY_ROUTE");
+        assert!(actions.len() <= MAX_ROUTE_LEN, "ERR_ROUTE_TOO_LONG");
+        assert!(
+            env::prepaid_gas() >= Gas(GAS_FOR_SWAP_HOP.0 * actions.len() as u64),
+            "ERR_NOT_ENOUGH_GAS_FOR_ROUTE"
+        );
+        let sender_id = env::predecessor_account_id();
+        let referral_id = referral_id.filter(|referral_id| referral_id != &sender_id);
+        let mut account = self.internal_unwrap_account(&sender_id);
+
+        let mut amount = amount_in.0;
+        for (i, action) in actions.iter().enumerate() {
+            if i > 0 {
+                assert_eq!(
+                    action.token_in,
+                    actions[i - 1].token_out,
+                    "ERR_ROUTE_NOT_CONNECTED"
+                );
+            }
+            self.internal_register_out_token(
+                action.pool_id,
+                &mut account,
+                &action.token_out,
+                false,
+            );
+            let amount_out = self.swap_core(
+                &sender_id,
+                action.pool_id,
+                &action.token_in,
+                amount,
+                &action.token_out,
+                action.min_amount_out.0,
+                &referral_id,
+            );
+            let pool = self.internal_get_pool(action.pool_id);
+            self.internal_withdraw_pool_token(
+                &pool,
+                &sender_id,
+                &mut account,
+                &action.token_in,
+                amount,
+            );
+            self.internal_deposit_pool_token(
+                &pool,
+                &sender_id,
+                &mut account,
+                &action.token_out,
+                amount_out,
+            );
+            amount = amount_out;
+        }
+        self.internal_save_account(&sender_id, account);
+
+        amount.into()
+    }
+
+    /// Commits new fees for the pool, they can be applied from `apply_at_sec` on.
+    /// `apply_at_sec` should be at least `MIN_FEE_CHANGE_DELAY` from now.
+    pub fn commit_new_fees(&mut self, pool_id: u64, fees: Fees, apply_at_sec: u64) {
         self.assert_owner();
         assert_fees_info_valid(&fees);
 
-        let mut pool = self.pools.get(pool_id).expect("ERR_NO_POOL");
+        let mut pool = self.internal_get_pool(pool_id);
+        let now = (near_sdk::env::block_timestamp() as u64) / (1e9 as u64);
+        assert!(
+            apply_at_sec >= now + MIN_FEE_CHANGE_DELAY,
+            "ERR_FEE_DELAY_TOO_SHORT"
+        );
+
+        pool.commit_new_fees(fees, apply_at_sec);
+        self.pools.replace(pool_id, &pool);
+        env::log_str(&format!(
+            "Pool {} new fees committed, applicable at {}",
+            pool_id, apply_at_sec
+        ));
+    }
+
+    /// Commits the fees of `source_pool_id` as new fees of `target_pool_id`,
+    /// applicable after `MIN_FEE_CHANGE_DELAY` like any other fee change.
+    pub fn copy_fees_from(&mut self, target_pool_id: u64, source_pool_id: u64) {
+        let fees = self
+            .pools
+            .get(source_pool_id)
+            .expect("ERR_NO_POOL")
+            .fees_info();
+        let now = (near_sdk::env::block_timestamp() as u64) / (1e9 as u64);
+        self.commit_new_fees(target_pool_id, fees, now + MIN_FEE_CHANGE_DELAY);
+    }
 
-        pool.change_fees_setting(fees);
+    /// Applies the committed fees of the pool once their delay has passed.
+    pub fn apply_new_fees(&mut self, pool_id: u64) {
+        self.assert_owner();
+
+        let mut pool = self.internal_get_pool(pool_id);
+        pool.apply_new_fees();
         self.pools.replace(pool_id, &pool);
     }
 
@@ -398,18 +952,188 @@ impl SnailSwap {
     ) {
         self.assert_owner();
 
-        let mut pool = self.pools.get(pool_id).expect("ERR_NO_POOL");
+        let mut pool = self.internal_get_pool(pool_id);
         let start_ramp_ts = (near_sdk::env::block_timestamp() as u64) / (1e9 as u64);
-
-        pool.set_amp_params(
+        let amp = AmpParams {
             initial_amp_factor,
             target_amp_factor,
-            start_ramp_ts,
             stop_ramp_ts,
+        };
+        amp.assert_valid(start_ramp_ts, pool.get_amp_factor() as u64);
+
+        pool.set_amp_params(
+            amp.initial_amp_factor,
+            amp.target_amp_factor,
+            start_ramp_ts,
+            amp.stop_ramp_ts,
+        );
+        self.pools.replace(pool_id, &pool);
+    }
+
+    /// Commits new fees and starts a new amp ramp of the pool in one call,
+    /// both are validated before the pool is changed.
+    /// Fees are applicable after `MIN_FEE_CHANGE_DELAY` like any other fee change.
+    /// Only can be called by owner.
+    pub fn update_pool_params(&mut self, pool_id: u64, fees: Option<Fees>, amp: Option<AmpParams>) {
+        self.assert_owner();
+        assert!(fees.is_some() || amp.is_some(), "ERR_NOTHING_TO_UPDATE");
+
+        let mut pool = self.internal_get_pool(pool_id);
+        let now = (near_sdk::env::block_timestamp() as u64) / (1e9 as u64);
+        if let Some(fees) = &fees {
+            assert_fees_info_valid(fees);
+        }
+        if let Some(amp) = &amp {
+            amp.assert_valid(now, pool.get_amp_factor() as u64);
+        }
+
+        if let Some(fees) = fees {
+            pool.commit_new_fees(fees, now + MIN_FEE_CHANGE_DELAY);
+            env::log_str(&format!(
+                "Pool {} new fees committed, applicable at {}",
+                pool_id,
+                now + MIN_FEE_CHANGE_DELAY
+            ));
+        }
+        if let Some(amp) = amp {
+            pool.set_amp_params(
+                amp.initial_amp_factor,
+                amp.target_amp_factor,
+                now,
+                amp.stop_ramp_ts,
+            );
+        }
+        self.pools.replace(pool_id, &pool);
+    }
+
+    /// Stops an in-progress amp ramp, pinning A at its current value.
+    /// Only can be called by owner.
+    pub fn stop_ramp_amp(&mut self, pool_id: u64) {
+        self.assert_owner();
+
+        let mut pool = self.internal_get_pool(pool_id);
+        let amp_factor = pool.get_amp_factor() as u64;
+        let now_ts = (near_sdk::env::block_timestamp() as u64) / (1e9 as u64);
+
+        pool.set_amp_params(amp_factor, amp_factor, now_ts, now_ts);
+        self.pools.replace(pool_id, &pool);
+
+        env::log_str(format!("Pool {} amp factor frozen at {}", pool_id, amp_factor).as_str());
+    }
+
+    /// Sets whether adding liquidity and imbalanced removals are rejected while A is ramping,
+    /// balanced removals stay allowed. Only can be called by owner.
+    pub fn set_block_liquidity_during_ramp(&mut self, pool_id: u64, block: bool) {
+        self.assert_owner();
+
+        let mut pool = self.internal_get_pool(pool_id);
+        pool.set_block_liquidity_during_ramp(block);
+        self.pools.replace(pool_id, &pool);
+    }
+
+    /// Sets the account allowed to update the pool rates besides the owner.
+    /// Only can be called by owner.
+    pub fn set_rate_provider(&mut self, pool_id: u64, rate_provider: Option<AccountId>) {
+        self.assert_owner();
+
+        let mut pool = self.internal_get_pool(pool_id);
+        pool.set_rate_provider(rate_provider);
+        self.pools.replace(pool_id, &pool);
+    }
+
+    /// Stores rates of the pool used instead of the decimal scaling, for tokens not worth
+    /// the same such as liquid staked NEAR. `rates[i]` brings an amount of the i-th token
+    /// to its value in 24 decimals. Rates must be refreshed before large swaps.
+    /// Not available for metapools. Only can be called by owner or the rate provider of the pool.
+    pub fn set_pool_rates(&mut self, pool_id: u64, rates: Vec<U128>) {
+        let mut pool = self.internal_get_pool(pool_id);
+        // metapool rates follow the base pool virtual price.
+        assert!(matches!(pool, Pool::SimplePool(_)), "ERR_META_POOL_RATES");
+        let predecessor_id = env::predecessor_account_id();
+        assert!(
+            predecessor_id == self.owner_id || Some(predecessor_id) == pool.rate_provider(),
+            "ERR_NOT_ALLOWED"
         );
+
+        pool.set_rates(rates.into_iter().map(|rate| rate.0).collect());
+        self.pools.replace(pool_id, &pool);
+    }
+
+    /// Sets the cap on the shares a single account can hold in the pool,
+    /// enforced when adding liquidity and on the receiver of share transfers.
+    /// None for unlimited. Only can be called by owner.
+    pub fn set_max_share_per_account(&mut self, pool_id: u64, max_share: Option<U128>) {
+        self.assert_owner();
+
+        let mut pool = self.internal_get_pool(pool_id);
+        pool.set_max_share_per_account(max_share.map(|m| m.0));
+        self.pools.replace(pool_id, &pool);
+    }
+
+    /// Sets whether the D and y solvers of the pool stop within a thousandth of one unit
+    /// of the coin with the fewest decimals instead of within 1 in 24 decimals,
+    /// saving gas on pools of low decimal coins. Only can be called by owner.
+    pub fn set_rate_scaled_epsilon(&mut self, pool_id: u64, enabled: bool) {
+        self.assert_owner();
+
+        let mut pool = self.internal_get_pool(pool_id);
+        pool.set_rate_scaled_epsilon(enabled);
+        self.pools.replace(pool_id, &pool);
+    }
+
+    /// Sets the minimal amount of LP a single one-coin removal from the pool has to burn.
+    /// Only can be called by owner.
+    pub fn set_min_one_coin_burn(&mut self, pool_id: u64, min_burn: U128) {
+        self.assert_owner();
+
+        let mut pool = self.internal_get_pool(pool_id);
+        pool.set_min_one_coin_burn(min_burn.0);
+        self.pools.replace(pool_id, &pool);
+    }
+
+    /// Sets the part of the trade fee, in bps, given back to swappers holding shares
+    /// of the pool, at most 10000. Only can be called by owner.
+    pub fn set_lp_fee_discount(&mut self, pool_id: u64, bps: u32) {
+        self.assert_owner();
+        assert!(bps as u128 <= BPS_DENOMINATOR, "ERR_INVALID_DISCOUNT");
+
+        let mut pool = self.internal_get_pool(pool_id);
+        pool.set_lp_fee_discount(bps);
+        self.pools.replace(pool_id, &pool);
+    }
+
+    /// Sets the cap on the balance of each token of the pool, 0 for unlimited.
+    /// Liquidity additions and swaps pushing a balance above its cap are rejected.
+    /// None removes the caps. Only can be called by owner.
+    pub fn set_balance_caps(&mut self, pool_id: u64, caps: Option<Vec<U128>>) {
+        self.assert_owner();
+
+        let mut pool = self.internal_get_pool(pool_id);
+        pool.set_balance_caps(caps.map(|caps| caps.into_iter().map(|cap| cap.0).collect()));
         self.pools.replace(pool_id, &pool);
     }
 
+    /// Shuts the pool down for good, only fee-free proportional removal of liquidity
+    /// is allowed from then on. Only can be called by owner or guardians.
+    #[payable]
+    pub fn shutdown_pool(&mut self, pool_id: u64) {
+        assert_one_yocto();
+        self.assert_owner_or_guardians();
+
+        let mut pool = self.internal_get_pool(pool_id);
+        pool.shutdown();
+        self.pools.replace(pool_id, &pool);
+
+        env::log_str(
+            format!(
+                "Pool {} shut down by {}",
+                pool_id,
+                env::predecessor_account_id()
+            )
+            .as_str(),
+        );
+    }
+
     /// Change state of contract, Only can be called by owner.
     #[payable]
     pub fn change_state(&mut self, state: RunningState) {
@@ -454,7 +1178,7 @@ impl SnailSwap {
 
         let refund = env::attached_deposit()
             .checked_sub(storage_cost)
-            .expect("ERR_STORAGE_DEPOSIT");
+            .unwrap_or_else(|| panic_storage_deposit(storage_cost, env::attached_deposit()));
         if refund > 0 {
             Promise::new(env::predecessor_account_id()).transfer(refund);
         }
@@ -469,6 +1193,37 @@ impl SnailSwap {
         };
     }
 
+    /// Token is accepted if it is whitelisted, tokens of the pools are whitelisted on creation.
+    fn is_token_accepted(&self, token_id: &AccountId) -> bool {
+        self.whitelisted_tokens.contains(token_id)
+    }
+
+    fn assert_token_accepted(&self, token_id: &AccountId) {
+        assert!(
+            self.is_token_accepted(token_id),
+            "{} [{}]",
+            TOKEN_NOT_WHITELISTED,
+            token_id
+        );
+    }
+
+    fn assert_token_not_paused(&self, token_id: &AccountId) {
+        assert!(
+            !self.paused_tokens.contains(token_id),
+            "ERR_TOKEN_PAUSED [{}]",
+            token_id
+        );
+    }
+
+    /// Panics if any of the tokens moved, with a non-zero amount, is paused.
+    fn assert_amounts_not_paused(&self, tokens: &[AccountId], amounts: &[Balance]) {
+        for (token_id, amount) in tokens.iter().zip(amounts.iter()) {
+            if *amount > 0 {
+                self.assert_token_not_paused(token_id);
+            }
+        }
+    }
+
     fn assert_owner(&self) {
         let sender_id = env::predecessor_account_id();
         assert!(
@@ -479,36 +1234,87 @@ impl SnailSwap {
         );
     }
 
+    fn assert_owner_or_guardians(&self) {
+        let sender_id = env::predecessor_account_id();
+        assert!(
+            self.owner_id == sender_id || self.guardians.contains(&sender_id),
+            "ERR_NOT_ALLOWED"
+        );
+    }
+
     /// Adds given pool to the list and returns it's id.
+    /// Tokens of the pool are added to the whitelist.
     /// If there is not enough attached balance to cover storage, fails.
     /// If too much attached - refunds it back.
     fn internal_add_pool(&mut self, pool: Pool) -> u64 {
         let prev_storage = env::storage_usage();
-        let id = self.pools.len() as u64;
-        self.pools.push(&pool);
+        let id = self.internal_push_pool(pool);
         self.internal_check_storage(prev_storage);
         id
     }
+
+    /// Adds the pool and whitelists its tokens, leaving the storage check to the caller.
+    fn internal_push_pool(&mut self, pool: Pool) -> u64 {
+        let id = self.pools.len() as u64;
+        for token in pool.tokens() {
+            self.whitelisted_tokens.insert(token);
+        }
+        self.pools.push(&pool);
+        id
+    }
+
+    /// Validates the arguments and builds the simple pool taking the next pool id.
+    fn internal_new_simple_pool(&self, args: SimplePoolArgs) -> Pool {
+        check_token_duplicates(&args.tokens);
+        assert!(
+            args.tokens.len() <= MAX_COINS as usize,
+            "ERR_TOO_MANY_COINS"
+        );
+        assert_eq!(
+            args.decimals.len(),
+            args.tokens.len(),
+            "ERR_WRONG_DECIMALS_NUM: one decimals per token expected"
+        );
+        assert_decimals_valid(&args.decimals);
+        assert_fees_info_valid(&args.fees);
+
+        Pool::SimplePool(SimplePool::new(
+            self.pools.len() as u32,
+            args.initial_amp_factor,
+            args.target_amp_factor,
+            args.start_ramp_ts,
+            args.stop_ramp_ts,
+            args.fees,
+            args.tokens,
+            args.decimals,
+        ))
+    }
 }
 
 #[near_bindgen]
 impl SnailSwap {
+    /// Settles the withdrawal of the given nonce, reverting exactly the amount
+    /// it debited if the transfer failed.
     #[private]
-    pub fn exchange_callback_post_withdraw(
-        &mut self,
-        token_id: AccountId,
-        sender_id: AccountId,
-        amount: U128,
-    ) {
+    pub fn exchange_callback_post_withdraw(&mut self, nonce: u64) {
         assert_eq!(
             env::promise_results_count(),
             1,
             "{}",
             CALLBACK_POST_WITHDRAW_INVALID
         );
+        let PendingWithdrawal {
+            sender_id,
+            token_id,
+            amount,
+        } = self
+            .pending_withdrawals
+            .remove(&nonce)
+            .expect(PENDING_WITHDRAWAL_NOT_FOUND);
         match env::promise_result(0) {
             PromiseResult::NotReady => unreachable!(),
             PromiseResult::Successful(_) => {
+                self.internal_sub_managed(&token_id, amount);
                 env::log_str(
                     format!("SnailSwap exchange_callback_post_withdraw success.").as_str(),
                 );
@@ -518,7 +1324,7 @@ impl SnailSwap {
                 // If account doesn't exit, deposits to the owner's account as lostfound.
                 let mut failed = false;
                 if let Some(mut account) = self.internal_get_account(&sender_id) {
-                    if account.deposit_with_storage_check(&token_id, amount.0) {
+                    if account.deposit_with_storage_check(&token_id, amount) {
                         // cause storage already checked, here can directly save
                         self.accounts.insert(&sender_id, &account.into());
                     } else {
@@ -545,22 +1351,109 @@ impl SnailSwap {
                     failed = true;
                 }
                 if failed {
-                    self.internal_lostfound(&token_id, amount.0);
+                    self.internal_lostfound(&token_id, amount);
+                }
+            }
+        }
+    }
+
+    #[private]
+    pub fn exchange_callback_post_wrap_near(
+        &mut self,
+        pool_id: u64,
+        sender_id: AccountId,
+        amount: U128,
+        token_out: AccountId,
+        min_amount_out: U128,
+    ) -> PromiseOrValue<U128> {
+        assert_eq!(
+            env::promise_results_count(),
+            1,
+            "{}",
+            CALLBACK_POST_WRAP_NEAR_INVALID
+        );
+        let wrap_near_id = self.wrap_near_id.clone().expect("ERR_NO_WRAP_NEAR");
+        match env::promise_result(0) {
+            PromiseResult::NotReady => unreachable!(),
+            PromiseResult::Failed => {
+                env::log_str(
+                    format!("SnailSwap swap_near wrap failed, refunding {}.", amount.0).as_str(),
+                );
+                Promise::new(sender_id).transfer(amount.0);
+                PromiseOrValue::Value(U128(0))
+            }
+            PromiseResult::Successful(_) => {
+                self.internal_add_managed(&wrap_near_id, amount.0);
+                // the wrapped NEAR is deposited for the sender before swapping, the swap runs
+                // in its own receipt so whatever makes it fail leaves the deposit to the sender.
+                if !self.internal_deposit_or_lostfound(&sender_id, &wrap_near_id, amount.0) {
+                    env::log_str(
+                        format!(
+                            "SnailSwap swap_near can't deposit for {}, {} wrapped to lostfound.",
+                            sender_id, amount.0
+                        )
+                        .as_str(),
+                    );
+                    return PromiseOrValue::Value(U128(0));
                 }
+                ext_self::exchange_swap_wrapped_near(
+                    pool_id,
+                    sender_id,
+                    amount,
+                    token_out,
+                    min_amount_out,
+                    env::current_account_id(),
+                    0,
+                    GAS_FOR_SWAP_WRAPPED_NEAR,
+                )
+                .into()
             }
         }
     }
+
+    /// Swaps wrapped NEAR deposited by swap_near for `token_out` and sends the output to the sender.
+    /// If the swap fails the wrapped NEAR stays in the sender's deposits.
+    #[private]
+    pub fn exchange_swap_wrapped_near(
+        &mut self,
+        pool_id: u64,
+        sender_id: AccountId,
+        amount: U128,
+        token_out: AccountId,
+        min_amount_out: U128,
+    ) -> U128 {
+        let wrap_near_id = self.wrap_near_id.clone().expect("ERR_NO_WRAP_NEAR");
+        let mut account = self.internal_unwrap_account(&sender_id);
+        account.withdraw(&wrap_near_id, amount.0);
+        self.internal_save_account(&sender_id, account);
+        let amount_out = self.swap_core(
+            &sender_id,
+            pool_id,
+            &wrap_near_id,
+            amount.0,
+            &token_out,
+            min_amount_out.0,
+            &None,
+        );
+        self.internal_send_tokens(&sender_id, &token_out, amount_out);
+        amount_out.into()
+    }
 }
 
 #[cfg(test)]
 mod tests {
 
     use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
-    use near_sdk::test_utils::{accounts, VMContextBuilder};
-    use near_sdk::{testing_env, Balance};
+    use near_sdk::test_utils::{accounts, get_logs, VMContextBuilder};
+    use near_sdk::{testing_env, Balance, PromiseOrValue};
     use near_sdk_sim::to_yocto;
 
     use super::*;
+    use crate::fees::FeesV1;
+    use crate::meta_pool::META_RATE_DENOMINATOR;
+    use crate::pool::PoolV1;
+    use crate::simple_pool::SimplePoolV1;
+    use crate::utils::{LOCKED_INITIAL_SHARES, PRECISION, PRECISION_DECIMALS};
 
     use near_sdk::serde::{Deserialize, Serialize};
     use near_sdk::serde_json;
@@ -577,6 +1470,10 @@ mod tests {
         let trade_fee_denominator: u64 = 1000;
         let withdraw_fee_numerator: u64 = 4;
         let withdraw_fee_denominator: u64 = 1000;
+        let referral_fee_numerator: u64 = 0;
+        let referral_fee_denominator: u64 = 1;
+        let lp_fee_share_numerator: u64 = 0;
+        let lp_fee_share_denominator: u64 = 1;
 
         Fees {
             admin_trade_fee_numerator,
@@ -587,6 +1484,10 @@ mod tests {
             trade_fee_denominator,
             withdraw_fee_numerator,
             withdraw_fee_denominator,
+            referral_fee_numerator,
+            referral_fee_denominator,
+            lp_fee_share_numerator,
+            lp_fee_share_denominator,
         }
     }
 
@@ -602,6 +1503,11 @@ mod tests {
             .attached_deposit(to_yocto("1"))
             .build());
         contract.storage_deposit(Some(accounts(0).clone()), None);
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.extend_whitelisted_tokens(vec![accounts(1), accounts(2), accounts(4)]);
+
+        testing_env!(context.attached_deposit(to_yocto("1")).build());
         (context, contract)
     }
 
@@ -694,6 +1600,7 @@ mod tests {
             pool_id,
             token_amounts.into_iter().map(|(_, x)| U128(x)).collect(),
             None,
+            None,
         );
 
         assert_eq!(contract.get_pool_shares(0, accounts(3)), expected_lp);
@@ -789,190 +1696,386 @@ mod tests {
         deposit_tokens(&mut context, &mut contract, accounts(1), vec![]);
     }
     #[test]
-    fn test_basics_two_coins() {
-        const COIN_NUM: usize = 2;
+    fn test_whitelisted_tokens() {
         let (mut context, mut contract) = setup_contract();
-        let token_decimals: [u32; COIN_NUM] = [18, 6];
-        let one_token_amount_0 = get_balance_with_decimals(1, token_decimals[0]);
-        let one_token_amount_1 = get_balance_with_decimals(1, token_decimals[1]);
+        assert_eq!(contract.get_whitelisted_tokens().len(), 3);
 
-        // add liquidity of (1,2) tokens
-        create_pool_with_liquidity(
-            &mut context,
-            &mut contract,
-            accounts(3),
-            vec![
-                (
-                    accounts(1),
-                    get_balance_with_decimals(10, token_decimals[0]),
-                ),
-                (
-                    accounts(2),
-                    get_balance_with_decimals(10, token_decimals[1]),
-                ),
-            ],
-            vec![token_decimals[0].into(), token_decimals[1].into()],
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(1)
+            .build());
+        contract.remove_whitelisted_tokens(vec![accounts(4)]);
+        assert_eq!(
+            contract.get_whitelisted_tokens(),
+            vec![accounts(1), accounts(2)]
         );
 
         deposit_tokens(
             &mut context,
             &mut contract,
             accounts(3),
-            vec![
-                (
-                    accounts(1),
-                    get_balance_with_decimals(100, token_decimals[0]),
-                ),
-                (
-                    accounts(2),
-                    get_balance_with_decimals(100, token_decimals[1]),
-                ),
-            ],
-        );
-
-        deposit_tokens(&mut context, &mut contract, accounts(1), vec![]);
-
-        assert_eq!(
-            contract.get_deposit(accounts(3), accounts(1)),
-            get_balance_with_decimals(100, token_decimals[0]).into()
+            vec![(accounts(1), to_yocto("1")), (accounts(2), to_yocto("1"))],
         );
         assert_eq!(
-            contract.get_deposit(accounts(3), accounts(2)),
-            get_balance_with_decimals(100, token_decimals[1]).into()
+            contract.get_deposit(accounts(3), accounts(1)).0,
+            to_yocto("1")
         );
+    }
 
-        let lp_decimals: u32 = 24;
-        assert_eq!(
-            contract.get_pool_total_shares(0).0,
-            get_balance_with_decimals(20, lp_decimals).into()
+    #[test]
+    #[should_panic(expected = "Token not whitelisted")]
+    fn test_deposit_not_whitelisted_token() {
+        let (mut context, mut contract) = setup_contract();
+        let token: AccountId = "unknown.near".parse().unwrap();
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(token, to_yocto("1"))],
         );
+    }
 
-        let get_amount_ret = contract.get_return(
-            0,
-            accounts(1).into(),
-            one_token_amount_0.into(),
-            accounts(2).into(),
+    #[test]
+    #[should_panic(expected = "Token not whitelisted")]
+    fn test_deposit_pool_token_removed_from_whitelist() {
+        let (mut context, mut contract) = setup_contract();
+        create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), to_yocto("1")), (accounts(2), to_yocto("1"))],
+            vec![24, 24],
         );
-
         testing_env!(context
-            .predecessor_account_id(accounts(3))
+            .predecessor_account_id(accounts(0))
             .attached_deposit(1)
             .build());
-        let amount_out = contract.swap(
-            0,
-            accounts(1).into(),
-            one_token_amount_0.into(),
-            accounts(2).into(),
-            0.into(),
+        contract.remove_whitelisted_tokens(vec![accounts(1)]);
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), to_yocto("1"))],
         );
+    }
 
-        assert_eq!(get_amount_ret, amount_out);
+    #[test]
+    fn test_migrate() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        let fees = FeesV1 {
+            admin_trade_fee_numerator: 50,
+            admin_trade_fee_denominator: 100,
+            admin_withdraw_fee_numerator: 40,
+            admin_withdraw_fee_denominator: 100,
+            trade_fee_numerator: 3,
+            trade_fee_denominator: 1000,
+            withdraw_fee_numerator: 4,
+            withdraw_fee_denominator: 1000,
+        };
+        let mut pools = Vector::new(StorageKey::Pools);
+        pools.push(&PoolV1::SimplePool(SimplePoolV1 {
+            token_account_ids: vec![accounts(1), accounts(2)],
+            token_decimals: vec![6, 6],
+            amounts: vec![100, 200],
+            volumes: vec![SwapVolume::default(); 2],
+            total_fees: vec![0, 0],
+            admin_fees: vec![0, 0],
+            shares: LookupMap::new(StorageKey::Shares { pool_id: 0 }),
+            shares_total_supply: 0,
+            initial_amp_factor: 100,
+            target_amp_factor: 100,
+            start_ramp_ts: 0,
+            stop_ramp_ts: 0,
+            fees,
+            apply_new_fee_ts: 0,
+            new_fees: fees,
+        }));
+        let mut old_accounts = LookupMap::new(StorageKey::Accounts);
+        for (account_id, amount) in [(accounts(0), 5), (accounts(3), 20), (accounts(4), 30)] {
+            let mut account = Account::new(&account_id);
+            account.deposit(&accounts(1), amount);
+            old_accounts.insert(&account_id, &VAccount::Current(account));
+        }
+        env::state_write(&owner::OldSnailSwap {
+            owner_id: accounts(0),
+            pools,
+            state: RunningState::Running,
+            accounts: old_accounts,
+        });
 
+        // accounts(4) is not listed, its deposit is not counted.
+        let contract = SnailSwap::migrate(vec![accounts(3), accounts(3)]);
+        assert_eq!(contract.metadata().owner, accounts(0));
+        assert_eq!(contract.get_number_of_pools(), 1);
         assert_eq!(
-            contract.get_deposit(accounts(3), accounts(1)).0,
-            99 * one_token_amount_0
+            contract.get_whitelisted_tokens(),
+            vec![accounts(1), accounts(2)]
         );
+        assert!(contract.get_guardians().is_empty());
+        assert_eq!(contract.get_pool_fee_info(0).fees, setup_fee());
+        assert_eq!(contract.get_pool_rate_denominator(0).0, 1);
+        assert_eq!(contract.get_managed_balance(accounts(1)).0, 125);
+        assert_eq!(contract.get_managed_balance(accounts(2)).0, 200);
+    }
 
-        // transfer some of token_id 2 from acc 3 to acc 1.
-        testing_env!(context.predecessor_account_id(accounts(3)).build());
-        contract.mft_transfer(
-            accounts(2).to_string(),
-            accounts(1),
-            U128(one_token_amount_1),
-            None,
-        );
-        assert_eq!(
-            contract.get_deposit(accounts(3), accounts(2)).0,
-            99 * one_token_amount_1 + amount_out.0
-        );
-        assert_eq!(
-            contract.get_deposit(accounts(1), accounts(2)).0,
-            one_token_amount_1
+    #[test]
+    fn test_pause_tokens() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(10, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
         );
-
         testing_env!(context
-            .predecessor_account_id(accounts(3))
-            .attached_deposit(to_yocto("0.0067"))
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(1)
             .build());
-        contract.mft_register(":0".to_string(), accounts(1));
+        contract.pause_tokens(vec![accounts(1)]);
+        assert!(contract.is_token_paused(accounts(1)));
+        assert!(!contract.is_token_paused(accounts(2)));
+
+        // removing only the other token is fine.
         testing_env!(context
             .predecessor_account_id(accounts(3))
             .attached_deposit(1)
             .build());
-        // transfer 1m shares in pool 0 to acc 1.
-        contract.mft_transfer(":0".to_string(), accounts(1), U128(1_000_000), None);
-
-        let pool_id: u64 = 0;
-        let remove_lp = contract.get_pool_shares(0, accounts(3));
-        testing_env!(context.predecessor_account_id(accounts(3)).build());
-
-        let expect_balances = contract.try_remove_liquidity(pool_id, remove_lp);
-
-        let deposit1 = contract.get_deposit(accounts(3), accounts(1)).0;
-        let deposit2 = contract.get_deposit(accounts(3), accounts(2)).0;
-
-        contract.remove_liquidity(pool_id, remove_lp, vec![1.into(), 2.into()]);
-
-        assert_eq!(
-            contract.get_deposit(accounts(3), accounts(1)).0 - deposit1,
-            expect_balances[0].0
+        contract.remove_liquidity_one_coin(
+            pool_id,
+            accounts(2),
+            U128(get_balance_with_decimals(1, PRECISION_DECIMALS)),
+            U128(0),
+            None,
         );
 
-        assert_eq!(
-            contract.get_deposit(accounts(3), accounts(2)).0 - deposit2,
-            expect_balances[1].0
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(1)
+            .build());
+        contract.resume_tokens(vec![accounts(1)]);
+        assert!(!contract.is_token_paused(accounts(1)));
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount)],
         );
+    }
 
-        // Exchange fees left in the pool as liquidity + 1m from transfer.
-
-        contract.withdraw(
-            accounts(1),
-            contract.get_deposit(accounts(3), accounts(1)),
-            None,
+    #[test]
+    #[should_panic(expected = "ERR_TOKEN_PAUSED")]
+    fn test_deposit_paused_token() {
+        let (mut context, mut contract) = setup_contract();
+        testing_env!(context.attached_deposit(1).build());
+        contract.pause_tokens(vec![accounts(1)]);
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), to_yocto("1"))],
         );
-        assert_eq!(contract.get_deposit(accounts(3), accounts(1)).0, 0);
-
-        //check fees
-        let total_admin_fees = contract.get_pool_admin_fee(0);
-
-        let deposit1 = contract.get_deposit(accounts(0), accounts(1)).0;
-        let deposit2 = contract.get_deposit(accounts(0), accounts(2)).0;
-
-        assert_eq!(total_admin_fees[0], deposit1);
-        assert_eq!(total_admin_fees[1], deposit2);
     }
 
     #[test]
-    fn test_basics_three_coins() {
+    #[should_panic(expected = "ERR_TOKEN_PAUSED")]
+    fn test_withdraw_paused_token() {
         let (mut context, mut contract) = setup_contract();
-        let token_decimals: [u32; 3] = [18, 6, 10];
-        let one_token_amount_0 = get_balance_with_decimals(1, token_decimals[0]);
-        let one_token_amount_1 = get_balance_with_decimals(1, token_decimals[1]);
-
-        // add liquidity of (1,2) tokens
-        create_pool_with_liquidity(
+        deposit_tokens(
             &mut context,
             &mut contract,
             accounts(3),
-            vec![
-                (
-                    accounts(1),
-                    get_balance_with_decimals(10, token_decimals[0]),
+            vec![(accounts(1), to_yocto("1"))],
+        );
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(1)
+            .build());
+        contract.pause_tokens(vec![accounts(1)]);
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        contract.withdraw(accounts(1), U128(to_yocto("1")), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_TOKEN_PAUSED")]
+    fn test_swap_paused_token() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(10, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount)],
+        );
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(1)
+            .build());
+        contract.pause_tokens(vec![accounts(2)]);
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        contract.swap(
+            pool_id,
+            accounts(1),
+            U128(amount),
+            accounts(2),
+            U128(0),
+            None,
+            Some(true),
+            None,
+            None,
+        );
+    }
+
+    #[test]
+    fn test_add_liquidity_takes_amounts_as_given() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(10, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+
+        // an imbalanced deposit is not rebalanced, it only mints fewer shares.
+        let amounts = vec![U128(amount), U128(amount / 10)];
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount / 10)],
+        );
+        let shares_before = contract.get_pool_shares(pool_id, accounts(3)).0;
+        let expected_lp = contract.try_add_liquidity(pool_id, amounts.clone()).0;
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(to_yocto("0.008"))
+            .build());
+        let lp = contract.add_liquidity(pool_id, amounts, None, None);
+        assert_eq!(lp, expected_lp);
+        assert_eq!(
+            contract.get_pool_shares(pool_id, accounts(3)).0,
+            shares_before + lp
+        );
+        assert_eq!(contract.get_deposit(accounts(3), accounts(1)).0, 0);
+        assert_eq!(contract.get_deposit(accounts(3), accounts(2)).0, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_TOKEN_PAUSED")]
+    fn test_add_liquidity_paused_token() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(10, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(1)
+            .build());
+        contract.pause_tokens(vec![accounts(1)]);
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(to_yocto("0.008"))
+            .build());
+        contract.add_liquidity(pool_id, vec![U128(amount), U128(amount)], None, None);
+    }
+
+    #[test]
+    fn test_unregister_tokens_with_dust() {
+        let (mut context, mut contract) = setup_contract();
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), 1)],
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(1)
+            .build());
+        contract.set_dust_tolerance(U128(1));
+        assert_eq!(contract.get_dust_tolerance(), U128(1));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        contract.unregister_tokens(vec![accounts(1)]);
+        assert!(contract
+            .get_deposits(accounts(3))
+            .get(&accounts(1))
+            .is_none());
+        assert_eq!(contract.get_deposit(accounts(0), accounts(1)).0, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Non-zero token balance")]
+    fn test_unregister_tokens_with_dust_above_tolerance() {
+        let (mut context, mut contract) = setup_contract();
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), 1)],
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        contract.unregister_tokens(vec![accounts(1)]);
+    }
+
+    #[test]
+    fn test_basics_two_coins() {
+        const COIN_NUM: usize = 2;
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: [u32; COIN_NUM] = [18, 6];
+        let one_token_amount_0 = get_balance_with_decimals(1, token_decimals[0]);
+        let one_token_amount_1 = get_balance_with_decimals(1, token_decimals[1]);
+
+        // add liquidity of (1,2) tokens
+        create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![
+                (
+                    accounts(1),
+                    get_balance_with_decimals(10, token_decimals[0]),
                 ),
                 (
                     accounts(2),
                     get_balance_with_decimals(10, token_decimals[1]),
                 ),
-                (
-                    accounts(4),
-                    get_balance_with_decimals(10, token_decimals[2]),
-                ),
-            ],
-            vec![
-                token_decimals[0].into(),
-                token_decimals[1].into(),
-                token_decimals[2].into(),
             ],
+            vec![token_decimals[0].into(), token_decimals[1].into()],
         );
 
         deposit_tokens(
@@ -988,10 +2091,6 @@ mod tests {
                     accounts(2),
                     get_balance_with_decimals(100, token_decimals[1]),
                 ),
-                (
-                    accounts(4),
-                    get_balance_with_decimals(100, token_decimals[2]),
-                ),
             ],
         );
 
@@ -1005,15 +2104,11 @@ mod tests {
             contract.get_deposit(accounts(3), accounts(2)),
             get_balance_with_decimals(100, token_decimals[1]).into()
         );
-        assert_eq!(
-            contract.get_deposit(accounts(3), accounts(4)),
-            get_balance_with_decimals(100, token_decimals[2]).into()
-        );
 
-        let lp_decimals: u32 = 24;
+        let lp_decimals = PRECISION_DECIMALS;
         assert_eq!(
             contract.get_pool_total_shares(0).0,
-            get_balance_with_decimals(30, lp_decimals).into()
+            get_balance_with_decimals(20, lp_decimals).into()
         );
 
         let get_amount_ret = contract.get_return(
@@ -1033,6 +2128,10 @@ mod tests {
             one_token_amount_0.into(),
             accounts(2).into(),
             0.into(),
+            None,
+            None,
+            None,
+            None,
         );
 
         assert_eq!(get_amount_ret, amount_out);
@@ -1076,12 +2175,19 @@ mod tests {
         testing_env!(context.predecessor_account_id(accounts(3)).build());
 
         let expect_balances = contract.try_remove_liquidity(pool_id, remove_lp);
+        assert_eq!(
+            contract.get_lp_redeemable(pool_id, accounts(3)),
+            expect_balances
+        );
 
         let deposit1 = contract.get_deposit(accounts(3), accounts(1)).0;
         let deposit2 = contract.get_deposit(accounts(3), accounts(2)).0;
-        let deposit3 = contract.get_deposit(accounts(3), accounts(4)).0;
 
-        contract.remove_liquidity(pool_id, remove_lp, vec![1.into(), 2.into(), 3.into()]);
+        contract.remove_liquidity(pool_id, remove_lp, vec![1.into(), 2.into()], None);
+        assert_eq!(
+            contract.get_lp_redeemable(pool_id, accounts(3)),
+            vec![U128(0), U128(0)]
+        );
 
         assert_eq!(
             contract.get_deposit(accounts(3), accounts(1)).0 - deposit1,
@@ -1092,10 +2198,6 @@ mod tests {
             contract.get_deposit(accounts(3), accounts(2)).0 - deposit2,
             expect_balances[1].0
         );
-        assert_eq!(
-            contract.get_deposit(accounts(3), accounts(4)).0 - deposit3,
-            expect_balances[2].0
-        );
 
         // Exchange fees left in the pool as liquidity + 1m from transfer.
 
@@ -1111,85 +2213,263 @@ mod tests {
 
         let deposit1 = contract.get_deposit(accounts(0), accounts(1)).0;
         let deposit2 = contract.get_deposit(accounts(0), accounts(2)).0;
-        let deposit3 = contract.get_deposit(accounts(0), accounts(4)).0;
 
         assert_eq!(total_admin_fees[0], deposit1);
         assert_eq!(total_admin_fees[1], deposit2);
-        assert_eq!(total_admin_fees[2], deposit3);
     }
 
-    /// Test liquidity management.
     #[test]
-    fn test_liquidity_basic() {
-        let token_decimals: u32 = 6;
-
+    fn test_basics_three_coins() {
         let (mut context, mut contract) = setup_contract();
+        let token_decimals: [u32; 3] = [18, 6, 10];
+        let one_token_amount_0 = get_balance_with_decimals(1, token_decimals[0]);
+        let one_token_amount_1 = get_balance_with_decimals(1, token_decimals[1]);
 
-        deposit_tokens(
+        // add liquidity of (1,2) tokens
+        create_pool_with_liquidity(
             &mut context,
             &mut contract,
-            accounts(0),
+            accounts(3),
             vec![
-                (accounts(1), to_yocto("0")),
-                (accounts(2), to_yocto("0")),
-                (accounts(4), to_yocto("0")),
+                (
+                    accounts(1),
+                    get_balance_with_decimals(10, token_decimals[0]),
+                ),
+                (
+                    accounts(2),
+                    get_balance_with_decimals(10, token_decimals[1]),
+                ),
+                (
+                    accounts(4),
+                    get_balance_with_decimals(10, token_decimals[2]),
+                ),
+            ],
+            vec![
+                token_decimals[0].into(),
+                token_decimals[1].into(),
+                token_decimals[2].into(),
             ],
-        );
-        testing_env!(context.predecessor_account_id(accounts(0)).build());
-        testing_env!(context
-            .predecessor_account_id(accounts(0))
-            .attached_deposit(env::storage_byte_cost() * 5500)
-            .build());
-
-        let initial_amp_factor: u64 = 100;
-        let target_amp_factor: u64 = 500;
-        let start_ramp_ts: u64 = 0;
-        let stop_ramp_ts: u64 = 0;
-        let fees: Fees = setup_fee();
-
-        let id = contract.add_simple_pool(
-            vec![accounts(1), accounts(2), accounts(4)],
-            vec![6, 6, 6],
-            initial_amp_factor,
-            target_amp_factor,
-            start_ramp_ts,
-            stop_ramp_ts,
-            fees,
         );
 
-        testing_env!(context.predecessor_account_id(accounts(3)).build());
         deposit_tokens(
             &mut context,
             &mut contract,
             accounts(3),
             vec![
-                (accounts(1), get_balance_with_decimals(100, token_decimals)),
-                (accounts(2), get_balance_with_decimals(100, token_decimals)),
-                (accounts(4), get_balance_with_decimals(100, token_decimals)),
+                (
+                    accounts(1),
+                    get_balance_with_decimals(100, token_decimals[0]),
+                ),
+                (
+                    accounts(2),
+                    get_balance_with_decimals(100, token_decimals[1]),
+                ),
+                (
+                    accounts(4),
+                    get_balance_with_decimals(100, token_decimals[2]),
+                ),
             ],
         );
 
-        testing_env!(context.predecessor_account_id(accounts(3)).build());
-
-        let deposit1 = contract.get_deposit(accounts(0), accounts(1)).0;
-        let deposit2 = contract.get_deposit(accounts(0), accounts(2)).0;
-        let deposit3 = contract.get_deposit(accounts(0), accounts(4)).0;
-        assert_eq!(deposit1, 0);
-        assert_eq!(deposit2, 0);
-        assert_eq!(deposit3, 0);
-
-        testing_env!(context.attached_deposit(to_yocto("0.008")).build());
+        deposit_tokens(&mut context, &mut contract, accounts(1), vec![]);
 
-        let expected_lp = contract.try_add_liquidity(
-            id,
-            vec![
-                U128(get_balance_with_decimals(50, token_decimals)),
-                U128(get_balance_with_decimals(10, token_decimals)),
-                U128(get_balance_with_decimals(20, token_decimals)),
-            ],
+        assert_eq!(
+            contract.get_deposit(accounts(3), accounts(1)),
+            get_balance_with_decimals(100, token_decimals[0]).into()
         );
-
-        let before_add_lp = contract.get_pool_shares(0, accounts(3));
+        assert_eq!(
+            contract.get_deposit(accounts(3), accounts(2)),
+            get_balance_with_decimals(100, token_decimals[1]).into()
+        );
+        assert_eq!(
+            contract.get_deposit(accounts(3), accounts(4)),
+            get_balance_with_decimals(100, token_decimals[2]).into()
+        );
+
+        let lp_decimals = PRECISION_DECIMALS;
+        assert_eq!(
+            contract.get_pool_total_shares(0).0,
+            get_balance_with_decimals(30, lp_decimals).into()
+        );
+
+        let get_amount_ret = contract.get_return(
+            0,
+            accounts(1).into(),
+            one_token_amount_0.into(),
+            accounts(2).into(),
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        let amount_out = contract.swap(
+            0,
+            accounts(1).into(),
+            one_token_amount_0.into(),
+            accounts(2).into(),
+            0.into(),
+            None,
+            None,
+            None,
+            None,
+        );
+
+        assert_eq!(get_amount_ret, amount_out);
+
+        assert_eq!(
+            contract.get_deposit(accounts(3), accounts(1)).0,
+            99 * one_token_amount_0
+        );
+
+        // transfer some of token_id 2 from acc 3 to acc 1.
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.mft_transfer(
+            accounts(2).to_string(),
+            accounts(1),
+            U128(one_token_amount_1),
+            None,
+        );
+        assert_eq!(
+            contract.get_deposit(accounts(3), accounts(2)).0,
+            99 * one_token_amount_1 + amount_out.0
+        );
+        assert_eq!(
+            contract.get_deposit(accounts(1), accounts(2)).0,
+            one_token_amount_1
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(to_yocto("0.0067"))
+            .build());
+        contract.mft_register(":0".to_string(), accounts(1));
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        // transfer 1m shares in pool 0 to acc 1.
+        contract.mft_transfer(":0".to_string(), accounts(1), U128(1_000_000), None);
+
+        let pool_id: u64 = 0;
+        let remove_lp = contract.get_pool_shares(0, accounts(3));
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+
+        let expect_balances = contract.try_remove_liquidity(pool_id, remove_lp);
+
+        let deposit1 = contract.get_deposit(accounts(3), accounts(1)).0;
+        let deposit2 = contract.get_deposit(accounts(3), accounts(2)).0;
+        let deposit3 = contract.get_deposit(accounts(3), accounts(4)).0;
+
+        contract.remove_liquidity(pool_id, remove_lp, vec![1.into(), 2.into(), 3.into()], None);
+
+        assert_eq!(
+            contract.get_deposit(accounts(3), accounts(1)).0 - deposit1,
+            expect_balances[0].0
+        );
+
+        assert_eq!(
+            contract.get_deposit(accounts(3), accounts(2)).0 - deposit2,
+            expect_balances[1].0
+        );
+        assert_eq!(
+            contract.get_deposit(accounts(3), accounts(4)).0 - deposit3,
+            expect_balances[2].0
+        );
+
+        // Exchange fees left in the pool as liquidity + 1m from transfer.
+
+        contract.withdraw(
+            accounts(1),
+            contract.get_deposit(accounts(3), accounts(1)),
+            None,
+        );
+        assert_eq!(contract.get_deposit(accounts(3), accounts(1)).0, 0);
+
+        //check fees
+        let total_admin_fees = contract.get_pool_admin_fee(0);
+
+        let deposit1 = contract.get_deposit(accounts(0), accounts(1)).0;
+        let deposit2 = contract.get_deposit(accounts(0), accounts(2)).0;
+        let deposit3 = contract.get_deposit(accounts(0), accounts(4)).0;
+
+        assert_eq!(total_admin_fees[0], deposit1);
+        assert_eq!(total_admin_fees[1], deposit2);
+        assert_eq!(total_admin_fees[2], deposit3);
+    }
+
+    /// Test liquidity management.
+    #[test]
+    fn test_liquidity_basic() {
+        let token_decimals: u32 = 6;
+
+        let (mut context, mut contract) = setup_contract();
+
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(0),
+            vec![
+                (accounts(1), to_yocto("0")),
+                (accounts(2), to_yocto("0")),
+                (accounts(4), to_yocto("0")),
+            ],
+        );
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(env::storage_byte_cost() * 5500)
+            .build());
+
+        let initial_amp_factor: u64 = 100;
+        let target_amp_factor: u64 = 500;
+        let start_ramp_ts: u64 = 0;
+        let stop_ramp_ts: u64 = 0;
+        let fees: Fees = setup_fee();
+
+        let id = contract.add_simple_pool(
+            vec![accounts(1), accounts(2), accounts(4)],
+            vec![6, 6, 6],
+            initial_amp_factor,
+            target_amp_factor,
+            start_ramp_ts,
+            stop_ramp_ts,
+            fees,
+        );
+
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![
+                (accounts(1), get_balance_with_decimals(100, token_decimals)),
+                (accounts(2), get_balance_with_decimals(100, token_decimals)),
+                (accounts(4), get_balance_with_decimals(100, token_decimals)),
+            ],
+        );
+
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+
+        let deposit1 = contract.get_deposit(accounts(0), accounts(1)).0;
+        let deposit2 = contract.get_deposit(accounts(0), accounts(2)).0;
+        let deposit3 = contract.get_deposit(accounts(0), accounts(4)).0;
+        assert_eq!(deposit1, 0);
+        assert_eq!(deposit2, 0);
+        assert_eq!(deposit3, 0);
+
+        testing_env!(context.attached_deposit(to_yocto("0.008")).build());
+
+        let expected_lp = contract.try_add_liquidity(
+            id,
+            vec![
+                U128(get_balance_with_decimals(50, token_decimals)),
+                U128(get_balance_with_decimals(10, token_decimals)),
+                U128(get_balance_with_decimals(20, token_decimals)),
+            ],
+        );
+
+        let before_add_lp = contract.get_pool_shares(0, accounts(3));
         contract.add_liquidity(
             id,
             vec![
@@ -1198,6 +2478,7 @@ mod tests {
                 U128(get_balance_with_decimals(20, token_decimals)),
             ],
             None,
+            None,
         );
 
         assert_eq!(
@@ -1222,6 +2503,7 @@ mod tests {
                 U128(get_balance_with_decimals(20, token_decimals)),
             ],
             None,
+            None,
         );
         assert_eq!(
             contract.get_pool_shares(0, accounts(3)).0 - before_add_lp.0,
@@ -1257,6 +2539,7 @@ mod tests {
             id,
             U128(get_balance_with_decimals(1, token_decimals)),
             vec![U128(0), U128(0), U128(0)],
+            None,
         );
 
         assert_eq!(
@@ -1374,6 +2657,7 @@ mod tests {
                 U128(deposit_amount),
             ],
             None,
+            None,
         );
         assert_eq!(
             contract.get_pool_shares(0, accounts(3)).0 - before_add_lp.0,
@@ -1401,19 +2685,21 @@ mod tests {
             (deposit_amount * 3).into()
         );
 
+        // the first deposit leaves the locked shares to no one.
         let all_lp_shares = contract.get_pool_total_shares(0).0;
+        let lp_shares = contract.get_pool_shares(id, accounts(3)).0;
         assert_ne!(all_lp_shares, to_yocto("0").into());
-        assert_eq!(all_lp_shares, contract.get_pool_shares(id, accounts(3)).0);
+        assert_eq!(all_lp_shares, lp_shares + LOCKED_INITIAL_SHARES);
 
         testing_env!(context.attached_deposit(1).build());
 
-        let expect_balances = contract.try_remove_liquidity(id, U128(all_lp_shares));
+        let expect_balances = contract.try_remove_liquidity(id, U128(lp_shares));
 
         let deposit1 = contract.get_deposit(accounts(3), accounts(1)).0;
         let deposit2 = contract.get_deposit(accounts(3), accounts(2)).0;
         let deposit3 = contract.get_deposit(accounts(3), accounts(4)).0;
 
-        contract.remove_liquidity(id, U128(all_lp_shares), vec![U128(0), U128(0), U128(0)]);
+        contract.remove_liquidity(id, U128(lp_shares), vec![U128(0), U128(0), U128(0)], None);
 
         assert_eq!(
             contract.get_deposit(accounts(3), accounts(1)).0 - deposit1,
@@ -1428,9 +2714,8 @@ mod tests {
             contract.get_deposit(accounts(3), accounts(4)).0 - deposit3,
             expect_balances[2].0
         );
-        let all_lp_shares = contract.get_pool_total_shares(0).0;
-        assert_eq!(all_lp_shares, to_yocto("0").into());
-        assert_eq!(all_lp_shares, contract.get_pool_shares(id, accounts(3)).0);
+        assert_eq!(contract.get_pool_total_shares(0).0, LOCKED_INITIAL_SHARES);
+        assert_eq!(contract.get_pool_shares(id, accounts(3)).0, 0);
 
         let deposit1_3 = contract.get_deposit(accounts(3), accounts(1)).0;
         let deposit2_3 = contract.get_deposit(accounts(3), accounts(2)).0;
@@ -1448,67 +2733,247 @@ mod tests {
 
         assert_eq!(account_0_amount, admin_fee);
 
-        let total_tokens = account_3_amount + admin_fee;
+        // what backs the locked shares stays in the pool.
+        let amounts = contract.get_pool(id).amounts;
+        let pool_amounts = amounts[0].0 + amounts[1].0 + amounts[2].0;
+        assert!(pool_amounts > 0);
 
-        assert_eq!(total_tokens, (deposit_amount * 3).into());
+        let total_tokens = account_3_amount + admin_fee + pool_amounts;
 
-        let amounts = contract.get_pool(id).amounts;
-        assert_eq!(amounts[0].0, 0);
-        assert_eq!(amounts[1].0, 0);
-        assert_eq!(amounts[2].0, 0);
+        assert_eq!(total_tokens, (deposit_amount * 3).into());
     }
 
-    fn set_up_liquidity(
-        token_decimals: u32,
-        common_deposit_amount: u32,
-    ) -> (VMContextBuilder, SnailSwap, Balance) {
+    #[test]
+    fn test_initial_shares_locked() {
         let (mut context, mut contract) = setup_contract();
+        let amount = get_balance_with_decimals(10, 6);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![6, 6],
+        );
+        let first_shares = contract.get_pool_shares(pool_id, accounts(3)).0;
+        assert_eq!(
+            contract.get_pool_total_shares(pool_id).0,
+            first_shares + LOCKED_INITIAL_SHARES
+        );
 
-        let lp_token_decimals = 24;
-
+        // the same balanced deposit mints the second depositor at least as many shares.
         deposit_tokens(
             &mut context,
             &mut contract,
-            accounts(0),
-            vec![
-                (accounts(1), to_yocto("0")),
-                (accounts(2), to_yocto("0")),
-                (accounts(4), to_yocto("0")),
-            ],
+            accounts(5),
+            vec![(accounts(1), amount), (accounts(2), amount)],
         );
-        testing_env!(context.predecessor_account_id(accounts(0)).build());
         testing_env!(context
-            .predecessor_account_id(accounts(0))
-            .attached_deposit(env::storage_byte_cost() * 5500)
+            .predecessor_account_id(accounts(5))
+            .attached_deposit(to_yocto("0.008"))
             .build());
+        contract.add_liquidity(pool_id, vec![U128(amount), U128(amount)], None, None);
+        let second_shares = contract.get_pool_shares(pool_id, accounts(5)).0;
+        assert!(second_shares >= first_shares);
 
-        let initial_amp_factor: u64 = 100;
-        let target_amp_factor: u64 = 500;
-        let start_ramp_ts: u64 = 0;
-        let stop_ramp_ts: u64 = 0;
-        let fees: Fees = setup_fee();
-
-        let id = contract.add_simple_pool(
-            vec![accounts(1), accounts(2), accounts(4)],
-            vec![
-                token_decimals as u64,
-                token_decimals as u64,
-                token_decimals as u64,
-            ],
-            initial_amp_factor,
-            target_amp_factor,
-            start_ramp_ts,
-            stop_ramp_ts,
-            fees,
-        );
+        // once the first depositor left, the second one still redeems what it put in.
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        contract.remove_liquidity(pool_id, U128(first_shares), vec![U128(0), U128(0)], None);
+        let redeemable = contract.try_remove_liquidity(pool_id, U128(second_shares));
+        for amount_out in redeemable {
+            assert!(amount_out.0 >= amount * 99 / 100);
+        }
+    }
 
-        testing_env!(context.predecessor_account_id(accounts(3)).build());
-        deposit_tokens(
+    #[test]
+    #[should_panic(expected = "ERR_INITIAL_DEPOSIT_TOO_SMALL")]
+    fn test_initial_deposit_too_small() {
+        let (mut context, mut contract) = setup_contract();
+        create_pool_with_liquidity(
             &mut context,
             &mut contract,
             accounts(3),
-            vec![
-                (accounts(1), get_balance_with_decimals(100, token_decimals)),
+            vec![(accounts(1), 100), (accounts(2), 100)],
+            vec![6, 6],
+        );
+    }
+
+    #[test]
+    fn test_add_liquidity_share_receiver() {
+        let (mut context, mut contract) = setup_contract();
+        let amount = get_balance_with_decimals(10, 6);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![6, 6],
+        );
+        let shares = contract.get_pool_shares(pool_id, accounts(3)).0;
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+        );
+        testing_env!(context
+            .predecessor_account_id(accounts(5))
+            .attached_deposit(to_yocto("0.01"))
+            .build());
+        contract.mft_register(format!(":{}", pool_id), accounts(5));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(to_yocto("0.008"))
+            .build());
+        let lp = contract.add_liquidity(
+            pool_id,
+            vec![U128(amount), U128(amount)],
+            None,
+            Some(accounts(5)),
+        );
+        assert!(lp > 0);
+        assert_eq!(contract.get_pool_shares(pool_id, accounts(5)).0, lp);
+        assert_eq!(contract.get_pool_shares(pool_id, accounts(3)).0, shares);
+        assert_eq!(contract.get_deposit(accounts(3), accounts(1)).0, 0);
+        assert_eq!(contract.get_deposit(accounts(3), accounts(2)).0, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "LP not registered")]
+    fn test_add_liquidity_share_receiver_not_registered() {
+        let (mut context, mut contract) = setup_contract();
+        let amount = get_balance_with_decimals(10, 6);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![6, 6],
+        );
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+        );
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(to_yocto("0.008"))
+            .build());
+        contract.add_liquidity(
+            pool_id,
+            vec![U128(amount), U128(amount)],
+            None,
+            Some(accounts(5)),
+        );
+    }
+
+    #[test]
+    fn test_remove_liquidity_to_exact_one_coin() {
+        let token_decimals: u32 = 6;
+        let id: u64 = 0;
+        let (mut context, mut contract, lp_amount) = set_up_liquidity(token_decimals, 100);
+        let exact_out = get_balance_with_decimals(10, token_decimals);
+        let expected_lp =
+            contract.try_remove_liquidity_imbalance(id, vec![U128(0), U128(exact_out), U128(0)]);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        let deposit_before = contract.get_deposit(accounts(3), accounts(2)).0;
+        contract.remove_liquidity_to_exact_one_coin(
+            id,
+            accounts(2),
+            U128(exact_out),
+            U128(expected_lp),
+        );
+        assert_eq!(
+            contract.get_deposit(accounts(3), accounts(2)).0 - deposit_before,
+            exact_out
+        );
+        assert_eq!(
+            contract.get_pool_shares(id, accounts(3)).0,
+            lp_amount - expected_lp
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_EXCEED_MAX_AMOUNT_LP_INPUT")]
+    fn test_remove_liquidity_to_exact_one_coin_max_lp() {
+        let token_decimals: u32 = 6;
+        let id: u64 = 0;
+        let (mut context, mut contract, _lp_amount) = set_up_liquidity(token_decimals, 100);
+        let exact_out = get_balance_with_decimals(10, token_decimals);
+        let expected_lp =
+            contract.try_remove_liquidity_imbalance(id, vec![U128(0), U128(exact_out), U128(0)]);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        contract.remove_liquidity_to_exact_one_coin(
+            id,
+            accounts(2),
+            U128(exact_out),
+            U128(expected_lp - 1),
+        );
+    }
+
+    fn set_up_liquidity(
+        token_decimals: u32,
+        common_deposit_amount: u32,
+    ) -> (VMContextBuilder, SnailSwap, Balance) {
+        let (mut context, mut contract) = setup_contract();
+
+        let lp_token_decimals = PRECISION_DECIMALS;
+
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(0),
+            vec![
+                (accounts(1), to_yocto("0")),
+                (accounts(2), to_yocto("0")),
+                (accounts(4), to_yocto("0")),
+            ],
+        );
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(env::storage_byte_cost() * 5500)
+            .build());
+
+        let initial_amp_factor: u64 = 100;
+        let target_amp_factor: u64 = 500;
+        let start_ramp_ts: u64 = 0;
+        let stop_ramp_ts: u64 = 0;
+        let fees: Fees = setup_fee();
+
+        let id = contract.add_simple_pool(
+            vec![accounts(1), accounts(2), accounts(4)],
+            vec![
+                token_decimals as u64,
+                token_decimals as u64,
+                token_decimals as u64,
+            ],
+            initial_amp_factor,
+            target_amp_factor,
+            start_ramp_ts,
+            stop_ramp_ts,
+            fees,
+        );
+
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![
+                (accounts(1), get_balance_with_decimals(100, token_decimals)),
                 (accounts(2), get_balance_with_decimals(100, token_decimals)),
                 (accounts(4), get_balance_with_decimals(100, token_decimals)),
             ],
@@ -1560,6 +3025,7 @@ mod tests {
                 )),
             ],
             None,
+            None,
         );
         assert_eq!(
             contract.get_pool_shares(0, accounts(3)).0 - before_add_lp.0,
@@ -1567,7 +3033,8 @@ mod tests {
         );
         let lp_token: Balance = contract.get_pool_shares(0, accounts(3)).0;
         assert_eq!(
-            get_balance_with_decimals(common_deposit_amount as u128 * 3, lp_token_decimals,),
+            get_balance_with_decimals(common_deposit_amount as u128 * 3, lp_token_decimals,)
+                - LOCKED_INITIAL_SHARES,
             contract.get_pool_shares(0, accounts(3)).0
         );
 
@@ -1590,6 +3057,7 @@ mod tests {
             id,
             U128(lp_amount),
             vec![U128(lp_amount), U128(lp_amount), U128(lp_amount)],
+            None,
         );
     }
 
@@ -1627,7 +3095,7 @@ mod tests {
             expected_remove_lp
         );
 
-        let lp_token_decimals: u32 = 24;
+        let lp_token_decimals = PRECISION_DECIMALS;
         let lp_amount: Balance = contract.get_pool_shares(0, accounts(3)).0;
 
         assert!(lp_amount < get_balance_with_decimals(300 - 80, lp_token_decimals));
@@ -1694,14 +3162,18 @@ mod tests {
             set_up_liquidity(token_decimals, common_deposit_amount);
 
         testing_env!(context.attached_deposit(1).build());
-        let lp_decimals: u32 = 24;
+        let lp_decimals = PRECISION_DECIMALS;
         let remove_lp_amount = get_balance_with_decimals(99 as u128, lp_decimals);
 
         let expected_received_token =
             contract.try_remove_liquidity_one_coin(id, &accounts(1), U128(remove_lp_amount));
+        assert_eq!(
+            contract.calc_remove_liquidity_one_coin(id, accounts(1), U128(remove_lp_amount)),
+            expected_received_token
+        );
 
         let token_before_remove = contract.get_deposit(accounts(3), accounts(1));
-        contract.remove_liquidity_one_coin(id, accounts(1), U128(remove_lp_amount), U128(0));
+        contract.remove_liquidity_one_coin(id, accounts(1), U128(remove_lp_amount), U128(0), None);
         let token_after_remove = contract.get_deposit(accounts(3), accounts(1));
         assert_eq!(
             token_after_remove.0 - token_before_remove.0,
@@ -1709,6 +3181,147 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_get_share_price() {
+        let token_decimals: u32 = 6;
+        let (_context, contract, _lp_amount) = set_up_liquidity(token_decimals, 100);
+        let one_lp = get_balance_with_decimals(1, PRECISION_DECIMALS);
+
+        let share_price = contract.get_share_price(0, accounts(1));
+        assert_eq!(
+            share_price,
+            contract.try_remove_liquidity_one_coin(0, &accounts(1), U128(one_lp))
+        );
+        // a balanced pool at par redeems about one token per share, less the fee.
+        let one_token = get_balance_with_decimals(1, token_decimals);
+        assert!(share_price.0 < one_token && share_price.0 > one_token * 99 / 100);
+    }
+
+    /// Creates a base pool of accounts(1) and accounts(2) with liquidity of `amount` each,
+    /// and a metapool pairing accounts(4) against its LP.
+    fn setup_meta_pool(
+        context: &mut VMContextBuilder,
+        contract: &mut SnailSwap,
+        amount: Balance,
+    ) -> (u64, u64) {
+        let base_pool_id = create_pool_with_liquidity(
+            context,
+            contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![18, 18],
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(env::storage_byte_cost() * 5500)
+            .build());
+        let meta_pool_id =
+            contract.add_meta_pool(accounts(4), 6, base_pool_id, 100, 100, 0, 0, setup_fee());
+        (base_pool_id, meta_pool_id)
+    }
+
+    #[test]
+    fn test_meta_pool_base_lp_rate() {
+        let (mut context, mut contract) = setup_contract();
+        let amount = get_balance_with_decimals(100_000, 18);
+        let (base_pool_id, meta_pool_id) = setup_meta_pool(&mut context, &mut contract, amount);
+        assert_eq!(
+            contract.get_pool_rate_denominator(meta_pool_id).0,
+            META_RATE_DENOMINATOR
+        );
+
+        // trade fees left in the base pool raise its virtual price above par.
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+        );
+        for (token_in, token_out) in [(accounts(1), accounts(2)), (accounts(2), accounts(1))] {
+            testing_env!(context
+                .predecessor_account_id(accounts(3))
+                .attached_deposit(1)
+                .build());
+            contract.swap(
+                base_pool_id,
+                token_in,
+                U128(amount / 2),
+                token_out,
+                U128(0),
+                None,
+                None,
+                None,
+                None,
+            );
+        }
+        let virtual_price = contract.get_virtual_price(base_pool_id).0;
+        assert!(virtual_price > PRECISION);
+
+        let rates = contract.get_pool_rates(meta_pool_id);
+        assert_eq!(rates[0].0, 10u128.pow(18) * META_RATE_DENOMINATOR);
+        assert_eq!(
+            rates[1].0,
+            virtual_price / (PRECISION / META_RATE_DENOMINATOR)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Requires attached deposit of exactly 1 yoctoNEAR")]
+    fn test_swap_underlying_requires_one_yocto() {
+        let (mut context, mut contract) = setup_contract();
+        let amount = get_balance_with_decimals(100, 18);
+        let (_, meta_pool_id) = setup_meta_pool(&mut context, &mut contract, amount);
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount)],
+        );
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(0)
+            .build());
+        contract.swap_underlying(meta_pool_id, accounts(1), U128(10), accounts(4), U128(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_ZERO_AMOUNT")]
+    fn test_swap_underlying_zero_amount() {
+        let (mut context, mut contract) = setup_contract();
+        let amount = get_balance_with_decimals(100, 18);
+        let (_, meta_pool_id) = setup_meta_pool(&mut context, &mut contract, amount);
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        contract.swap_underlying(meta_pool_id, accounts(1), U128(0), accounts(4), U128(0));
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_TOKEN_PAUSED")]
+    fn test_swap_underlying_paused_token() {
+        let (mut context, mut contract) = setup_contract();
+        let amount = get_balance_with_decimals(100, 18);
+        let (_, meta_pool_id) = setup_meta_pool(&mut context, &mut contract, amount);
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount)],
+        );
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(1)
+            .build());
+        contract.pause_tokens(vec![accounts(1)]);
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        contract.swap_underlying(meta_pool_id, accounts(1), U128(10), accounts(4), U128(1));
+    }
+
     #[test]
     #[should_panic(expected = "ERR_EXCEED_MIN_AMOUNT")]
     fn test_remove_liquidity_onecoin_could_exceed_one_coin_balance() {
@@ -1720,7 +3333,7 @@ mod tests {
 
         testing_env!(context.attached_deposit(1).build());
 
-        let lp_decimals: u32 = 24;
+        let lp_decimals = PRECISION_DECIMALS;
         let remove_lp_amount = get_balance_with_decimals(200 as u128, lp_decimals);
 
         let expected_received_token =
@@ -1733,6 +3346,7 @@ mod tests {
             accounts(1),
             U128(remove_lp_amount),
             U128(get_balance_with_decimals(200 as u128, lp_decimals)),
+            None,
         );
         let token_after_remove = contract.get_deposit(accounts(3), accounts(1));
 
@@ -1752,7 +3366,7 @@ mod tests {
 
         testing_env!(context.attached_deposit(1).build());
 
-        let lp_decimals: u32 = 24;
+        let lp_decimals = PRECISION_DECIMALS;
         let remove_lp_amount = get_balance_with_decimals(200 as u128, lp_decimals);
 
         let expected_received_token =
@@ -1760,7 +3374,7 @@ mod tests {
 
         let token_before_remove = contract.get_deposit(accounts(3), accounts(1));
 
-        contract.remove_liquidity_one_coin(id, accounts(1), U128(remove_lp_amount), U128(0));
+        contract.remove_liquidity_one_coin(id, accounts(1), U128(remove_lp_amount), U128(0), None);
 
         let token_after_remove = contract.get_deposit(accounts(3), accounts(1));
 
@@ -1770,41 +3384,3596 @@ mod tests {
         );
     }
 
-    /// Test fee info change.
     #[test]
-    fn test_fees_info_change() {
-        let (_context, mut contract) = setup_contract();
-        let initial_amp_factor: u64 = 100;
-        let target_amp_factor: u64 = 500;
-        let start_ramp_ts: u64 = 0;
-        let stop_ramp_ts: u64 = 0;
-        let mut fees: Fees = setup_fee();
+    fn test_metadata() {
+        let (mut context, mut contract) = setup_contract();
+        testing_env!(context.attached_deposit(1).build());
+        contract.extend_guardians(vec![accounts(5)]);
+
+        let metadata = contract.metadata();
+        assert_eq!(metadata.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(metadata.version, contract.version());
+        assert_eq!(metadata.owner, accounts(0));
+        assert_eq!(metadata.pool_count, 0);
+        assert_eq!(metadata.state, RunningState::Running);
+        assert_eq!(metadata.guardians, vec![accounts(5)]);
+        assert_eq!(metadata.wrap_near_id, None);
+    }
 
-        let id = contract.add_simple_pool(
-            vec![accounts(1), accounts(2), accounts(4)],
-            vec![6, 6, 6],
-            initial_amp_factor,
-            target_amp_factor,
-            start_ramp_ts,
-            stop_ramp_ts,
-            fees,
-        );
+    #[test]
+    fn test_guardians_events() {
+        let (mut context, mut contract) = setup_contract();
+        let event_logs = || -> Vec<serde_json::Value> {
+            get_logs()
+                .iter()
+                .filter_map(|log| log.strip_prefix("EVENT_JSON:"))
+                .map(|event| serde_json::from_str(event).unwrap())
+                .collect()
+        };
 
-        assert_eq!(fees, contract.fees_info(id));
+        testing_env!(context.attached_deposit(1).build());
+        contract.extend_guardians(vec![accounts(4), accounts(5)]);
+        assert_eq!(contract.get_guardians(), vec![accounts(4), accounts(5)]);
+        let events = event_logs();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0]["standard"], "snails_exchange");
+        assert_eq!(events[0]["event"], "extend_guardians");
+        assert_eq!(events[0]["data"][0]["owner_id"], accounts(0).to_string());
+        assert_eq!(
+            events[0]["data"][0]["guardians"],
+            serde_json::json!([accounts(4), accounts(5)])
+        );
 
-        fees.admin_trade_fee_numerator = 1 as u64;
-        fees.admin_trade_fee_denominator = 2 as u64;
-        fees.admin_withdraw_fee_numerator = 3 as u64;
-        fees.admin_withdraw_fee_denominator = 3 as u64;
-        fees.trade_fee_numerator = 123 as u64;
-        fees.trade_fee_denominator = 431 as u64;
-        fees.withdraw_fee_numerator = 153 as u64;
-        fees.withdraw_fee_denominator = 431 as u64;
+        // already a guardian, left out of the event.
+        testing_env!(context.attached_deposit(1).build());
+        contract.extend_guardians(vec![accounts(5)]);
+        let events = event_logs();
+        assert_eq!(events[0]["data"][0]["guardians"], serde_json::json!([]));
 
-        assert_ne!(fees, contract.fees_info(id));
+        testing_env!(context.attached_deposit(1).build());
+        contract.remove_guardians(vec![accounts(4)]);
+        assert_eq!(contract.get_guardians(), vec![accounts(5)]);
+        let events = event_logs();
+        assert_eq!(events[0]["event"], "remove_guardians");
+        assert_eq!(
+            events[0]["data"][0]["guardians"],
+            serde_json::json!([accounts(4)])
+        );
+    }
 
-        contract.change_fees_setting(id, fees);
+    #[test]
+    fn test_get_storage_report() {
+        let (mut context, mut contract) = setup_contract();
+        // the owner registered in setup.
+        let report = contract.get_storage_report();
+        assert_eq!(report.account_count, 1);
+        assert_eq!(report.pool_count, 0);
+        assert_eq!(report.total_bytes, env::storage_usage());
 
-        assert_eq!(fees, contract.fees_info(id));
+        create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![
+                (accounts(1), get_balance_with_decimals(10, 6)),
+                (accounts(2), get_balance_with_decimals(10, 6)),
+            ],
+            vec![6, 6],
+        );
+        let new_report = contract.get_storage_report();
+        assert_eq!(new_report.account_count, 2);
+        assert_eq!(new_report.pool_count, 1);
+        assert!(new_report.total_bytes > report.total_bytes);
+    }
+
+    fn setup_wrap_near_pool(context: &mut VMContextBuilder, contract: &mut SnailSwap) -> u64 {
+        let amount = get_balance_with_decimals(100, 24);
+        let pool_id = create_pool_with_liquidity(
+            context,
+            contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![24, 24],
+        );
+        // accounts(1) plays the wrap contract.
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(1)
+            .build());
+        contract.set_wrap_near_id(Some(accounts(1)));
+        assert_eq!(contract.metadata().wrap_near_id, Some(accounts(1)));
+        pool_id
+    }
+
+    #[test]
+    fn test_withdraw_callbacks_out_of_order() {
+        let (mut context, mut contract) = setup_contract();
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), 100)],
+        );
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        contract.withdraw(accounts(1), U128(30), None);
+        contract.withdraw(accounts(1), U128(40), None);
+        assert_eq!(contract.get_deposit(accounts(3), accounts(1)).0, 30);
+
+        // the second withdrawal fails first, only its own debit is reverted.
+        testing_env!(
+            context
+                .predecessor_account_id(accounts(0))
+                .attached_deposit(0)
+                .build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed],
+        );
+        contract.exchange_callback_post_withdraw(1);
+        assert_eq!(contract.get_deposit(accounts(3), accounts(1)).0, 70);
+
+        testing_env!(
+            context.build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(vec![])],
+        );
+        contract.exchange_callback_post_withdraw(0);
+        assert_eq!(contract.get_deposit(accounts(3), accounts(1)).0, 70);
+    }
+
+    #[test]
+    #[should_panic(expected = "Pending withdrawal not found")]
+    fn test_withdraw_callback_settled_twice() {
+        let (mut context, mut contract) = setup_contract();
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), 100)],
+        );
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        contract.withdraw(accounts(1), U128(30), None);
+
+        testing_env!(
+            context
+                .predecessor_account_id(accounts(0))
+                .attached_deposit(0)
+                .build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed],
+        );
+        contract.exchange_callback_post_withdraw(0);
+        contract.exchange_callback_post_withdraw(0);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NO_WRAP_NEAR")]
+    fn test_swap_near_without_wrap_near() {
+        let (mut context, mut contract) = setup_contract();
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(to_yocto("1"))
+            .build());
+        contract.swap_near(0, accounts(2), U128(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_MIN_AMOUNT")]
+    fn test_swap_near_min_amount() {
+        let (mut context, mut contract) = setup_contract();
+        let pool_id = setup_wrap_near_pool(&mut context, &mut contract);
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(to_yocto("1"))
+            .build());
+        contract.swap_near(pool_id, accounts(2), U128(to_yocto("1")));
+    }
+
+    #[test]
+    fn test_swap_near_callback() {
+        let (mut context, mut contract) = setup_contract();
+        let pool_id = setup_wrap_near_pool(&mut context, &mut contract);
+        let amounts_before = contract.get_pool(pool_id).amounts;
+
+        // callbacks are called by the contract itself, accounts(0) in tests.
+        testing_env!(
+            context
+                .predecessor_account_id(accounts(0))
+                .attached_deposit(0)
+                .build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed],
+        );
+        let result = contract.exchange_callback_post_wrap_near(
+            pool_id,
+            accounts(3),
+            U128(to_yocto("1")),
+            accounts(2),
+            U128(1),
+        );
+        assert!(matches!(result, PromiseOrValue::Value(U128(0))));
+        assert_eq!(contract.get_pool(pool_id).amounts, amounts_before);
+
+        // the wrapped NEAR is deposited for the sender, then swapped in its own receipt.
+        testing_env!(
+            context.build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(vec![])],
+        );
+        let result = contract.exchange_callback_post_wrap_near(
+            pool_id,
+            accounts(3),
+            U128(to_yocto("1")),
+            accounts(2),
+            U128(1),
+        );
+        assert!(matches!(result, PromiseOrValue::Promise(_)));
+        assert_eq!(
+            contract.get_deposit(accounts(3), accounts(1)).0,
+            to_yocto("1")
+        );
+        assert_eq!(contract.get_pool(pool_id).amounts, amounts_before);
+
+        let amount_out = contract.exchange_swap_wrapped_near(
+            pool_id,
+            accounts(3),
+            U128(to_yocto("1")),
+            accounts(2),
+            U128(1),
+        );
+        assert!(amount_out.0 > to_yocto("0.99") && amount_out.0 < to_yocto("1"));
+        assert_eq!(contract.get_deposit(accounts(3), accounts(1)).0, 0);
+        let amounts = contract.get_pool(pool_id).amounts;
+        assert_eq!(amounts[0].0, amounts_before[0].0 + to_yocto("1"));
+        assert!(amounts[1].0 <= amounts_before[1].0 - amount_out.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_TOKEN_PAUSED")]
+    fn test_swap_near_paused_token_keeps_deposit() {
+        let (mut context, mut contract) = setup_contract();
+        let pool_id = setup_wrap_near_pool(&mut context, &mut contract);
+
+        testing_env!(
+            context
+                .predecessor_account_id(accounts(0))
+                .attached_deposit(0)
+                .build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(vec![])],
+        );
+        contract.exchange_callback_post_wrap_near(
+            pool_id,
+            accounts(3),
+            U128(to_yocto("1")),
+            accounts(2),
+            U128(1),
+        );
+        assert_eq!(
+            contract.get_deposit(accounts(3), accounts(1)).0,
+            to_yocto("1")
+        );
+
+        // token_out gets paused before the swap receipt runs, only that receipt fails
+        // and the wrapped NEAR deposited above stays with the sender.
+        testing_env!(context.attached_deposit(1).build());
+        contract.pause_tokens(vec![accounts(2)]);
+        testing_env!(context.attached_deposit(0).build());
+        contract.exchange_swap_wrapped_near(
+            pool_id,
+            accounts(3),
+            U128(to_yocto("1")),
+            accounts(2),
+            U128(1),
+        );
+    }
+
+    #[test]
+    fn test_managed_balance_tracking() {
+        let (mut context, mut contract) = setup_contract();
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), 100)],
+        );
+        assert_eq!(contract.get_managed_balance(accounts(1)).0, 100);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        contract.withdraw(accounts(1), U128(30), None);
+        contract.withdraw(accounts(1), U128(40), None);
+        // sent tokens are managed until their transfer settles.
+        assert_eq!(contract.get_managed_balance(accounts(1)).0, 100);
+
+        testing_env!(
+            context
+                .predecessor_account_id(accounts(0))
+                .attached_deposit(0)
+                .build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(vec![])],
+        );
+        contract.exchange_callback_post_withdraw(0);
+        assert_eq!(contract.get_managed_balance(accounts(1)).0, 70);
+
+        testing_env!(
+            context.build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed],
+        );
+        contract.exchange_callback_post_withdraw(1);
+        assert_eq!(contract.get_managed_balance(accounts(1)).0, 70);
+    }
+
+    #[test]
+    fn test_retrieve_unmanaged_tokens() {
+        let (mut context, mut contract) = setup_contract();
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), 100)],
+        );
+
+        // 50 more were sent to the contract without `ft_transfer_call`.
+        let balance_of = serde_json::to_vec(&U128(150)).unwrap();
+        testing_env!(
+            context
+                .predecessor_account_id(accounts(0))
+                .attached_deposit(0)
+                .build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(balance_of)],
+        );
+        contract.exchange_callback_retrieve_unmanaged(accounts(1), U128(50), accounts(2));
+        // reserved while the transfer is in flight.
+        assert_eq!(contract.get_managed_balance(accounts(1)).0, 150);
+
+        testing_env!(
+            context.build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(vec![])],
+        );
+        contract.exchange_callback_post_retrieve(accounts(1), U128(50));
+        assert_eq!(contract.get_managed_balance(accounts(1)).0, 100);
+        assert_eq!(contract.get_deposit(accounts(3), accounts(1)).0, 100);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NOT_ENOUGH_UNMANAGED")]
+    fn test_retrieve_unmanaged_tokens_managed_amount() {
+        let (mut context, mut contract) = setup_contract();
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), 100)],
+        );
+
+        let balance_of = serde_json::to_vec(&U128(150)).unwrap();
+        testing_env!(
+            context
+                .predecessor_account_id(accounts(0))
+                .attached_deposit(0)
+                .build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(balance_of)],
+        );
+        contract.exchange_callback_retrieve_unmanaged(accounts(1), U128(51), accounts(2));
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_TOKEN_NOT_MANAGED")]
+    fn test_retrieve_unmanaged_tokens_not_managed() {
+        let (mut context, mut contract) = setup_contract();
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(1)
+            .build());
+        contract.retrieve_unmanaged_tokens(accounts(1), U128(50), accounts(2));
+    }
+
+    #[test]
+    fn test_get_math_constants() {
+        let (_, contract) = setup_contract();
+        let constants = contract.get_math_constants();
+        assert_eq!(constants.precision.0, crate::utils::PRECISION);
+        assert_eq!(constants.max_coins, MAX_COINS);
+        assert_eq!(constants.iterations, crate::utils::MAX_SOLVER_ITERATIONS);
+    }
+
+    #[test]
+    fn test_get_deposits_paged() {
+        let (mut context, mut contract) = setup_contract();
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), 100), (accounts(2), 200), (accounts(4), 300)],
+        );
+
+        let first_page = contract.get_deposits_paged(accounts(3), 0, 2);
+        assert_eq!(first_page.len(), 2);
+        let second_page = contract.get_deposits_paged(accounts(3), 2, 2);
+        assert_eq!(second_page.len(), 1);
+        let deposits = contract.get_deposits(accounts(3));
+        for (token_id, balance) in first_page.iter().chain(second_page.iter()) {
+            assert_eq!(deposits.get(token_id), Some(balance));
+        }
+        assert!(contract.get_deposits_paged(accounts(3), 3, 2).is_empty());
+        assert!(contract.get_deposits_paged(accounts(5), 0, 2).is_empty());
+    }
+
+    #[test]
+    fn test_get_deposit_shortfall() {
+        let (mut context, mut contract) = setup_contract();
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), 100)],
+        );
+        assert_eq!(
+            contract.get_deposit_shortfall(accounts(3), accounts(1), U128(150)),
+            U128(50)
+        );
+        assert_eq!(
+            contract.get_deposit_shortfall(accounts(3), accounts(1), U128(100)),
+            U128(0)
+        );
+        assert_eq!(
+            contract.get_deposit_shortfall(accounts(3), accounts(2), U128(10)),
+            U128(10)
+        );
+    }
+
+    #[test]
+    fn test_get_owner_pool_token_deposits() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let pool_0 = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![
+                (accounts(1), get_balance_with_decimals(100, token_decimals)),
+                (accounts(2), get_balance_with_decimals(100, token_decimals)),
+            ],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+        let pool_1 = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![
+                (accounts(1), get_balance_with_decimals(100, token_decimals)),
+                (accounts(4), get_balance_with_decimals(100, token_decimals)),
+            ],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![
+                (accounts(2), get_balance_with_decimals(10, token_decimals)),
+                (accounts(4), get_balance_with_decimals(10, token_decimals)),
+            ],
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        for (pool_id, token_in) in [(pool_0, accounts(2)), (pool_1, accounts(4))] {
+            contract.swap(
+                pool_id,
+                token_in,
+                get_balance_with_decimals(10, token_decimals).into(),
+                accounts(1),
+                0.into(),
+                None,
+                None,
+                None,
+                None,
+            );
+        }
+
+        let fee_0 = contract.get_pool_admin_fee(pool_0)[0];
+        let fee_1 = contract.get_pool_admin_fee(pool_1)[0];
+        assert!(fee_0 > 0 && fee_1 > 0);
+        assert_eq!(
+            contract.get_owner_pool_token_deposits(),
+            vec![
+                (accounts(1), U128(fee_0 + fee_1)),
+                (accounts(2), U128(0)),
+                (accounts(4), U128(0)),
+            ]
+        );
+
+        // deposits the owner made itself are listed along with the fees.
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(0),
+            vec![(accounts(2), 5)],
+        );
+        assert_eq!(
+            contract.get_owner_pool_token_deposits()[1],
+            (accounts(2), U128(5))
+        );
+
+        // withdrawn fees are no longer deposited, though the pools still report them.
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(1)
+            .build());
+        contract.withdraw(accounts(1), U128(fee_0), None);
+        assert_eq!(contract.get_pool_admin_fee(pool_0)[0], fee_0);
+        assert_eq!(
+            contract.get_owner_pool_token_deposits()[0],
+            (accounts(1), U128(fee_1))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_POOL_RESERVE_TOO_LOW")]
+    fn test_swap_drain_reserve() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(10, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+        // swapping in far more than the pool holds takes nearly the whole other reserve.
+        let amount_in = get_balance_with_decimals(1000, token_decimals);
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount_in)],
+        );
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        contract.swap(
+            pool_id,
+            accounts(1),
+            U128(amount_in),
+            accounts(2),
+            U128(0),
+            None,
+            Some(true),
+            None,
+            None,
+        );
+    }
+
+    #[test]
+    fn test_get_pool_tvl() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(10, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+
+        let tvl = contract.get_pool_tvl(pool_id, accounts(1)).0;
+        let value_of_second = contract
+            .get_return(pool_id, accounts(2), U128(amount), accounts(1))
+            .0;
+        assert_eq!(tvl, amount + value_of_second);
+        assert!(tvl < 2 * amount);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_GET_RETURN_FAILED: ERR_MATH_OVERFLOW")]
+    fn test_get_return_math_error() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(10, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+
+        contract.get_return(pool_id, accounts(1), U128(u128::MAX), accounts(2));
+    }
+
+    #[test]
+    fn test_get_lp_price_in_coins() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(10, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+
+        let pool_info = contract.get_pool(pool_id);
+        let supply = pool_info.shares_total_supply.0;
+        let one_lp = get_balance_with_decimals(1, PRECISION_DECIMALS);
+        let prices = contract.get_lp_price_in_coins(pool_id);
+        assert_eq!(prices.len(), 2);
+        for (price, amount) in prices.iter().zip(pool_info.amounts.iter()) {
+            assert_eq!(price.0, amount.0 * one_lp / supply);
+        }
+        // balanced pool of 20 coins backing about 20 LP, each LP redeems about half a coin of each.
+        assert!(prices[0].0 > get_balance_with_decimals(49, token_decimals) / 100);
+        assert!(prices[0].0 <= get_balance_with_decimals(50, token_decimals) / 100);
+        assert_eq!(prices[0], prices[1]);
+    }
+
+    #[test]
+    fn test_get_effective_fee_bps() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(10, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+
+        // trade fee is 3/1000, charged on the output, so the bps stay nominal
+        // whatever the price impact of the swap.
+        let small = contract.get_effective_fee_bps(
+            pool_id,
+            accounts(1),
+            U128(get_balance_with_decimals(1, token_decimals)),
+            accounts(2),
+        );
+        assert!((29..=30).contains(&small));
+        let large = contract.get_effective_fee_bps(
+            pool_id,
+            accounts(1),
+            U128(get_balance_with_decimals(9, token_decimals)),
+            accounts(2),
+        );
+        assert!((29..=30).contains(&large));
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_MISSING_TOKEN")]
+    fn test_get_pool_tvl_reference_not_in_pool() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(10, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+        contract.get_pool_tvl(pool_id, accounts(4));
+    }
+
+    #[test]
+    fn test_swap_auto_register_out_token() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(10, token_decimals);
+        // deep enough for the swap to leave the out token above the minimal reserve.
+        let liquidity = get_balance_with_decimals(100, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), liquidity), (accounts(2), liquidity)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(5),
+            vec![(accounts(1), amount)],
+        );
+        let prev_storage = contract.storage_balance_of(accounts(5)).unwrap().total.0;
+
+        testing_env!(context
+            .predecessor_account_id(accounts(5))
+            .attached_deposit(to_yocto("0.01"))
+            .build());
+        let amount_out = contract.swap(
+            pool_id,
+            accounts(1),
+            U128(amount),
+            accounts(2),
+            U128(0),
+            None,
+            Some(true),
+            None,
+            None,
+        );
+        assert!(amount_out.0 > 0);
+        assert_eq!(contract.get_deposit(accounts(5), accounts(2)), amount_out);
+        assert_eq!(
+            contract.storage_balance_of(accounts(5)).unwrap().total.0,
+            prev_storage + to_yocto("0.01")
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_OUT_TOKEN_NOT_REGISTERED")]
+    fn test_swap_out_token_not_registered() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(10, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(5),
+            vec![(accounts(1), amount)],
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(5))
+            .attached_deposit(1)
+            .build());
+        contract.swap(
+            pool_id,
+            accounts(1),
+            U128(amount),
+            accounts(2),
+            U128(0),
+            None,
+            None,
+            None,
+            None,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_ZERO_AMOUNT")]
+    fn test_swap_zero_amount() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(10, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(5),
+            vec![(accounts(1), amount)],
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(5))
+            .attached_deposit(1)
+            .build());
+        contract.swap(
+            pool_id,
+            accounts(1),
+            U128(0),
+            accounts(2),
+            U128(0),
+            None,
+            None,
+            None,
+            None,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_SAME_TOKEN")]
+    fn test_swap_same_token() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(10, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(5),
+            vec![(accounts(1), amount)],
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(5))
+            .attached_deposit(1)
+            .build());
+        contract.swap(
+            pool_id,
+            accounts(1),
+            U128(amount),
+            accounts(1),
+            U128(0),
+            None,
+            None,
+            None,
+            None,
+        );
+    }
+
+    #[test]
+    fn test_swap_to_recipient() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(10, token_decimals);
+        // deep enough for the swap to leave the out token above the minimal reserve.
+        let liquidity = get_balance_with_decimals(100, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), liquidity), (accounts(2), liquidity)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(5),
+            vec![(accounts(1), amount)],
+        );
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(4),
+            vec![(accounts(2), amount)],
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(5))
+            .attached_deposit(1)
+            .build());
+        let amount_out = contract.swap(
+            pool_id,
+            accounts(1),
+            U128(amount),
+            accounts(2),
+            U128(0),
+            None,
+            None,
+            None,
+            Some(accounts(4)),
+        );
+        assert!(amount_out.0 > 0);
+        assert_eq!(contract.get_deposit(accounts(5), accounts(1)).0, 0);
+        assert_eq!(contract.get_deposit(accounts(5), accounts(2)).0, 0);
+        assert_eq!(
+            contract.get_deposit(accounts(4), accounts(2)).0,
+            amount + amount_out.0
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "not registered")]
+    fn test_swap_to_unregistered_recipient() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(10, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(5),
+            vec![(accounts(1), amount)],
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(5))
+            .attached_deposit(1)
+            .build());
+        contract.swap(
+            pool_id,
+            accounts(1),
+            U128(amount),
+            accounts(2),
+            U128(0),
+            None,
+            None,
+            None,
+            Some(accounts(4)),
+        );
+    }
+
+    #[test]
+    fn test_swap_to_any() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![
+                (accounts(1), get_balance_with_decimals(100, token_decimals)),
+                (accounts(2), get_balance_with_decimals(50, token_decimals)),
+                (accounts(4), get_balance_with_decimals(150, token_decimals)),
+            ],
+            vec![
+                token_decimals.into(),
+                token_decimals.into(),
+                token_decimals.into(),
+            ],
+        );
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), get_balance_with_decimals(10, token_decimals))],
+        );
+
+        let amount_in = get_balance_with_decimals(10, token_decimals);
+        let return_2 = contract.get_return(pool_id, accounts(1), U128(amount_in), accounts(2));
+        let return_4 = contract.get_return(pool_id, accounts(1), U128(amount_in), accounts(4));
+        // accounts(4) is the more abundant coin, so it is the better priced output.
+        assert!(return_4.0 > return_2.0);
+
+        let deposit_2 = contract.get_deposit(accounts(3), accounts(2)).0;
+        let deposit_4 = contract.get_deposit(accounts(3), accounts(4)).0;
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        let amount_out = contract.swap_to_any(
+            pool_id,
+            accounts(1),
+            U128(amount_in),
+            vec![accounts(2), accounts(4)],
+            U128(1),
+        );
+        assert_eq!(amount_out, return_4);
+        assert_eq!(contract.get_deposit(accounts(3), accounts(2)).0, deposit_2);
+        assert_eq!(
+            contract.get_deposit(accounts(3), accounts(4)).0,
+            deposit_4 + return_4.0
+        );
+    }
+
+    fn setup_empty_pool(
+        context: &mut VMContextBuilder,
+        contract: &mut SnailSwap,
+        decimals: Vec<u64>,
+    ) -> u64 {
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(env::storage_byte_cost() * 5500)
+            .build());
+        contract.add_simple_pool(
+            vec![accounts(1), accounts(2)],
+            decimals,
+            100,
+            100,
+            0,
+            0,
+            setup_fee(),
+        )
+    }
+
+    #[test]
+    fn test_seed_pool() {
+        let (mut context, mut contract) = setup_contract();
+        let pool_id = setup_empty_pool(&mut context, &mut contract, vec![6, 18]);
+        let amounts = vec![
+            get_balance_with_decimals(100, 6),
+            get_balance_with_decimals(100, 18),
+        ];
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(0),
+            vec![(accounts(1), amounts[0]), (accounts(2), amounts[1])],
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(to_yocto("0.01"))
+            .build());
+        let lp = contract.seed_pool(pool_id, amounts.iter().map(|a| U128(*a)).collect());
+        assert_eq!(
+            lp.0,
+            get_balance_with_decimals(200, PRECISION_DECIMALS) - LOCKED_INITIAL_SHARES
+        );
+        assert_eq!(contract.get_pool_shares(pool_id, accounts(0)), lp);
+        assert_eq!(contract.get_deposit(accounts(0), accounts(1)).0, 0);
+    }
+
+    #[test]
+    fn test_get_pools() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(10, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+        let empty_pool_id = setup_empty_pool(&mut context, &mut contract, vec![6, 6]);
+        assert_eq!(contract.get_number_of_pools(), 2);
+
+        let pools = contract.get_pools(0, 100);
+        assert_eq!(pools.len(), 2);
+        assert_eq!(pools[0].pool_id, pool_id);
+        assert_eq!(pools[0].amp_factor, contract.get_pool_amp_factor(pool_id));
+        assert_eq!(pools[0].virtual_price, contract.get_virtual_price(pool_id));
+        assert_eq!(pools[1].pool_id, empty_pool_id);
+        assert_eq!(pools[1].virtual_price.0, 0);
+        assert_eq!(pools[1], contract.get_pool(empty_pool_id));
+
+        assert_eq!(contract.get_pools(1, 1)[0].pool_id, empty_pool_id);
+        assert!(contract.get_pools(2, 100).is_empty());
+        assert_eq!(contract.get_pools(0, u64::MAX).len(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_SEED_IMBALANCED")]
+    fn test_seed_pool_wrong_decimals() {
+        let (mut context, mut contract) = setup_contract();
+        let pool_id = setup_empty_pool(&mut context, &mut contract, vec![6, 18]);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(to_yocto("0.01"))
+            .build());
+        // first amount given at 18 decimals instead of 6.
+        contract.seed_pool(
+            pool_id,
+            vec![
+                U128(get_balance_with_decimals(100, 18)),
+                U128(get_balance_with_decimals(100, 18)),
+            ],
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_SEED_BELOW_ONE_TOKEN")]
+    fn test_seed_pool_below_one_token() {
+        let (mut context, mut contract) = setup_contract();
+        let pool_id = setup_empty_pool(&mut context, &mut contract, vec![6, 18]);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(to_yocto("0.01"))
+            .build());
+        contract.seed_pool(pool_id, vec![U128(1), U128(1)]);
+    }
+
+    #[test]
+    fn test_try_swap_route() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(100, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![
+                (accounts(1), amount),
+                (accounts(2), amount),
+                (accounts(4), amount),
+            ],
+            vec![
+                token_decimals.into(),
+                token_decimals.into(),
+                token_decimals.into(),
+            ],
+        );
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), get_balance_with_decimals(10, token_decimals))],
+        );
+        let amount_in = get_balance_with_decimals(10, token_decimals);
+        let route = vec![
+            SwapAction {
+                pool_id,
+                token_in: accounts(1),
+                token_out: accounts(2),
+                min_amount_out: U128(1),
+            },
+            SwapAction {
+                pool_id,
+                token_in: accounts(2),
+                token_out: accounts(4),
+                min_amount_out: U128(1),
+            },
+        ];
+
+        let amounts_before = contract.get_pool(pool_id).amounts;
+        let deposits_before = contract.get_deposits(accounts(3));
+        let amounts_out = contract.try_swap_route(route.clone(), U128(amount_in));
+        assert_eq!(amounts_out.len(), 2);
+        assert_eq!(
+            amounts_out[0],
+            contract.get_return(pool_id, accounts(1), U128(amount_in), accounts(2))
+        );
+        assert_eq!(contract.get_pool(pool_id).amounts, amounts_before);
+        assert_eq!(contract.get_deposits(accounts(3)), deposits_before);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        let amount_out = contract.swap_route(route, U128(amount_in), None);
+        assert_eq!(amount_out, amounts_out[1]);
+    }
+
+    #[test]
+    fn test_quote_route() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(100, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![
+                (accounts(1), amount),
+                (accounts(2), amount),
+                (accounts(4), amount),
+            ],
+            vec![
+                token_decimals.into(),
+                token_decimals.into(),
+                token_decimals.into(),
+            ],
+        );
+        let amount_in = get_balance_with_decimals(10, token_decimals);
+        let route = vec![
+            SwapAction {
+                pool_id,
+                token_in: accounts(1),
+                token_out: accounts(2),
+                min_amount_out: U128(1),
+            },
+            SwapAction {
+                pool_id,
+                token_in: accounts(2),
+                token_out: accounts(4),
+                min_amount_out: U128(1),
+            },
+        ];
+
+        let quote = contract.quote_route(route, U128(amount_in));
+        assert_eq!(quote.per_hop_fees.len(), 2);
+        assert!(quote.per_hop_fees.iter().all(|fee| fee.0 > 0));
+        // the second hop gets the first hop output, after its fee.
+        let first_out = contract.get_return(pool_id, accounts(1), U128(amount_in), accounts(2));
+        assert_eq!(
+            quote.final_out,
+            contract.get_return(pool_id, accounts(2), first_out, accounts(4))
+        );
+        assert!(quote.final_out < first_out);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_MISSING_TOKEN")]
+    fn test_try_swap_route_missing_token() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(100, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+        contract.try_swap_route(
+            vec![SwapAction {
+                pool_id,
+                token_in: accounts(1),
+                token_out: accounts(4),
+                min_amount_out: U128(1),
+            }],
+            U128(amount),
+        );
+    }
+
+    #[test]
+    fn test_get_account_volume() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![
+                (accounts(1), get_balance_with_decimals(100, token_decimals)),
+                (accounts(2), get_balance_with_decimals(100, token_decimals)),
+            ],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), get_balance_with_decimals(10, token_decimals))],
+        );
+        assert_eq!(contract.get_account_volume(accounts(3)).input.0, 0);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        let amount_in = get_balance_with_decimals(5, token_decimals);
+        let first_out = contract.swap(
+            pool_id,
+            accounts(1),
+            U128(amount_in),
+            accounts(2),
+            U128(1),
+            None,
+            None,
+            None,
+            None,
+        );
+        let second_out = contract.swap(
+            pool_id,
+            accounts(1),
+            U128(amount_in),
+            accounts(2),
+            U128(1),
+            None,
+            None,
+            None,
+            None,
+        );
+
+        // 6 decimals amounts are normalized to 24 decimals.
+        let rate = 10u128.pow(PRECISION_DECIMALS - token_decimals);
+        let volume = contract.get_account_volume(accounts(3));
+        assert_eq!(volume.input.0, 2 * amount_in * rate);
+        assert_eq!(volume.output.0, (first_out.0 + second_out.0) * rate);
+        assert_eq!(contract.get_account_volume(accounts(4)).output.0, 0);
+    }
+
+    #[test]
+    fn test_direct_swap_unknown_pool_refunds() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(10, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+        let amounts_before = contract.get_pool(pool_id).amounts;
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
+            .build());
+        for (bad_pool_id, token_out) in [(pool_id + 1, accounts(2)), (pool_id, accounts(4))] {
+            let msg = format!(
+                "{{\"pool_id\": {}, \"token_out\": \"{}\", \"min_amount_out\": \"1\"}}",
+                bad_pool_id, token_out
+            );
+            match contract.ft_on_transfer(accounts(3), U128(1_000_000), msg) {
+                PromiseOrValue::Value(unused) => assert_eq!(unused.0, 1_000_000),
+                _ => panic!("expected the whole amount refunded"),
+            }
+        }
+        assert_eq!(contract.get_pool(pool_id).amounts, amounts_before);
+    }
+
+    #[test]
+    fn test_swap_admin_fee_leaves_pool() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 18;
+        let amount = get_balance_with_decimals(100_000, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+        let amount_in = get_balance_with_decimals(1_000, token_decimals);
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount_in)],
+        );
+        let pool_amounts = contract.get_pool(pool_id).amounts;
+        let owner_deposit = contract.get_deposit(accounts(0), accounts(2)).0;
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        let amount_out = contract
+            .swap(
+                pool_id,
+                accounts(1),
+                U128(amount_in),
+                accounts(2),
+                U128(0),
+                None,
+                None,
+                None,
+                None,
+            )
+            .0;
+
+        // the admin fee is paid to the owner out of the pool reserve, not on top of it.
+        let admin_fee = contract.get_deposit(accounts(0), accounts(2)).0 - owner_deposit;
+        assert!(admin_fee > 0);
+        assert_eq!(contract.get_pool_admin_fee(pool_id)[1], admin_fee);
+        let new_amounts = contract.get_pool(pool_id).amounts;
+        assert_eq!(new_amounts[0].0, pool_amounts[0].0 + amount_in);
+        assert_eq!(new_amounts[1].0, pool_amounts[1].0 - amount_out - admin_fee);
+    }
+
+    #[test]
+    fn test_swap_with_referral() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![
+                (accounts(1), get_balance_with_decimals(100, token_decimals)),
+                (accounts(2), get_balance_with_decimals(100, token_decimals)),
+            ],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+
+        let mut fees = setup_fee();
+        fees.referral_fee_numerator = 1;
+        fees.referral_fee_denominator = 10;
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .block_timestamp(0)
+            .build());
+        contract.commit_new_fees(pool_id, fees, MIN_FEE_CHANGE_DELAY);
+        testing_env!(context
+            .block_timestamp(MIN_FEE_CHANGE_DELAY * 1_000_000_000)
+            .build());
+        contract.apply_new_fees(pool_id);
+
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(5),
+            vec![(accounts(2), 0)],
+        );
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), get_balance_with_decimals(10, token_decimals))],
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        contract.swap(
+            pool_id,
+            accounts(1),
+            get_balance_with_decimals(10, token_decimals).into(),
+            accounts(2),
+            0.into(),
+            Some(accounts(5)),
+            None,
+            None,
+            None,
+        );
+
+        let total_fee = contract.get_pool_fee(pool_id)[1];
+        let admin_fee = contract.get_pool_admin_fee(pool_id)[1];
+        let referral_fee = contract.get_deposit(accounts(5), accounts(2)).0;
+        assert_eq!(referral_fee, total_fee / 10);
+        assert_eq!(
+            contract.get_deposit(accounts(0), accounts(2)).0,
+            admin_fee - referral_fee
+        );
+    }
+
+    #[test]
+    fn test_remove_liquidity_imbalance_nearly_all() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(10, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+
+        let mut fees = setup_fee();
+        fees.withdraw_fee_numerator = 0;
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .block_timestamp(0)
+            .build());
+        contract.commit_new_fees(pool_id, fees, MIN_FEE_CHANGE_DELAY);
+        testing_env!(context
+            .block_timestamp(MIN_FEE_CHANGE_DELAY * 1_000_000_000)
+            .build());
+        contract.apply_new_fees(pool_id);
+
+        let shares = contract.get_pool_shares(pool_id, accounts(3)).0;
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        contract.remove_liquidity_imbalance(
+            pool_id,
+            vec![U128(amount - 1), U128(amount - 1)],
+            Some(U128(shares)),
+        );
+        assert_eq!(contract.get_deposit(accounts(3), accounts(1)).0, amount - 1);
+        assert_eq!(contract.get_deposit(accounts(3), accounts(2)).0, amount - 1);
+        assert!(contract.get_pool_shares(pool_id, accounts(3)).0 < shares / 1_000_000);
+    }
+
+    #[test]
+    fn test_get_pool_fee_info() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(10, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount / 10)],
+        );
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        contract.swap(
+            pool_id,
+            accounts(1),
+            U128(amount / 10),
+            accounts(2),
+            U128(0),
+            None,
+            None,
+            None,
+            None,
+        );
+
+        let fee_info = contract.get_pool_fee_info(pool_id);
+        assert_eq!(fee_info.fees, contract.fees_info(pool_id));
+        assert_eq!(
+            fee_info.total_fees,
+            contract
+                .get_pool_fee(pool_id)
+                .into_iter()
+                .map(U128)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            fee_info.admin_fees,
+            contract
+                .get_pool_admin_fee(pool_id)
+                .into_iter()
+                .map(U128)
+                .collect::<Vec<_>>()
+        );
+        assert!(fee_info.total_fees[1].0 > 0);
+    }
+
+    #[test]
+    fn test_get_fee_destination_for_swap() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(10, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+        assert_eq!(
+            contract.get_fee_destination_for_swap(pool_id, accounts(2)),
+            accounts(0)
+        );
+
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount / 10)],
+        );
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        contract.swap(
+            pool_id,
+            accounts(1),
+            U128(amount / 10),
+            accounts(2),
+            U128(0),
+            None,
+            None,
+            None,
+            None,
+        );
+        let destination = contract.get_fee_destination_for_swap(pool_id, accounts(2));
+        assert_eq!(
+            contract.get_deposit(destination, accounts(2)).0,
+            contract.get_pool_admin_fee(pool_id)[1]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_MISSING_TOKEN")]
+    fn test_get_fee_destination_for_swap_missing_token() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(10, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+        contract.get_fee_destination_for_swap(pool_id, accounts(4));
+    }
+
+    #[test]
+    fn test_lp_fee_share() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![
+                (accounts(1), get_balance_with_decimals(100, token_decimals)),
+                (accounts(2), get_balance_with_decimals(100, token_decimals)),
+            ],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+        assert_eq!(contract.get_admin_fee_split(pool_id), (0, 10_000));
+
+        let mut fees = setup_fee();
+        fees.lp_fee_share_numerator = 1;
+        fees.lp_fee_share_denominator = 4;
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .block_timestamp(0)
+            .build());
+        contract.commit_new_fees(pool_id, fees, MIN_FEE_CHANGE_DELAY);
+        testing_env!(context
+            .block_timestamp(MIN_FEE_CHANGE_DELAY * 1_000_000_000)
+            .build());
+        contract.apply_new_fees(pool_id);
+        assert_eq!(contract.get_admin_fee_split(pool_id), (2_500, 7_500));
+
+        let amount_in = get_balance_with_decimals(10, token_decimals);
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount_in)],
+        );
+        let balance_before = contract.get_pool(pool_id).amounts[1].0;
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        let amount_out = contract
+            .swap(
+                pool_id,
+                accounts(1),
+                amount_in.into(),
+                accounts(2),
+                0.into(),
+                None,
+                None,
+                None,
+                None,
+            )
+            .0;
+
+        // admin fee is half the total fee, a quarter of it is left to LPs.
+        let total_fee = contract.get_pool_fee(pool_id)[1];
+        let owner_fee = contract.get_deposit(accounts(0), accounts(2)).0;
+        assert_eq!(owner_fee, contract.get_pool_admin_fee(pool_id)[1]);
+        assert!(owner_fee < total_fee / 2);
+        assert!(owner_fee + 1 >= total_fee * 3 / 8);
+        // LP share of the admin fee stays in the pool.
+        assert_eq!(
+            contract.get_pool(pool_id).amounts[1].0,
+            balance_before - amount_out - owner_fee
+        );
+    }
+
+    #[test]
+    fn test_fees_json_defaults() {
+        let fees: Fees = serde_json::from_str(
+            r#"{
+                "admin_trade_fee_numerator": 50,
+                "admin_trade_fee_denominator": 100,
+                "admin_withdraw_fee_numerator": 40,
+                "admin_withdraw_fee_denominator": 100,
+                "trade_fee_numerator": 3,
+                "trade_fee_denominator": 1000,
+                "withdraw_fee_numerator": 4,
+                "withdraw_fee_denominator": 1000
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(fees, setup_fee());
+    }
+
+    #[test]
+    fn test_swap_with_referral_and_lp_fee_share() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![
+                (accounts(1), get_balance_with_decimals(100, token_decimals)),
+                (accounts(2), get_balance_with_decimals(100, token_decimals)),
+            ],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+
+        // referral fee is a quarter of the total fee, more than the admin fee left after the LP share.
+        let mut fees = setup_fee();
+        fees.referral_fee_numerator = 1;
+        fees.referral_fee_denominator = 4;
+        fees.lp_fee_share_numerator = 3;
+        fees.lp_fee_share_denominator = 4;
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .block_timestamp(0)
+            .build());
+        contract.commit_new_fees(pool_id, fees, MIN_FEE_CHANGE_DELAY);
+        testing_env!(context
+            .block_timestamp(MIN_FEE_CHANGE_DELAY * 1_000_000_000)
+            .build());
+        contract.apply_new_fees(pool_id);
+
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(5),
+            vec![(accounts(2), 0)],
+        );
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), get_balance_with_decimals(10, token_decimals))],
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        contract.swap(
+            pool_id,
+            accounts(1),
+            get_balance_with_decimals(10, token_decimals).into(),
+            accounts(2),
+            0.into(),
+            Some(accounts(5)),
+            None,
+            None,
+            None,
+        );
+
+        // the referrer gets at most what is left of the admin fee, nothing goes to the owner.
+        let total_fee = contract.get_pool_fee(pool_id)[1];
+        let admin_fee = contract.get_pool_admin_fee(pool_id)[1];
+        let referral_fee = contract.get_deposit(accounts(5), accounts(2)).0;
+        assert!(admin_fee > 0);
+        assert!(admin_fee < total_fee / 4);
+        assert_eq!(referral_fee, admin_fee);
+        assert_eq!(contract.get_deposit(accounts(0), accounts(2)).0, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_lp_fee_share_exceed_admin_fee")]
+    fn test_lp_fee_share_exceed_admin_fee() {
+        let (_context, mut contract) = setup_contract();
+        let mut fees = setup_fee();
+        fees.lp_fee_share_numerator = 2;
+        fees.lp_fee_share_denominator = 1;
+        contract.add_simple_pool(
+            vec![accounts(1), accounts(2)],
+            vec![6, 6],
+            100,
+            500,
+            0,
+            0,
+            fees,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_referral_fee_exceed_admin_fee")]
+    fn test_referral_fee_exceed_admin_fee() {
+        let (_context, mut contract) = setup_contract();
+        let mut fees = setup_fee();
+        fees.referral_fee_numerator = 6;
+        fees.referral_fee_denominator = 10;
+        contract.add_simple_pool(
+            vec![accounts(1), accounts(2)],
+            vec![6, 6],
+            100,
+            500,
+            0,
+            0,
+            fees,
+        );
+    }
+
+    /// Adds a 6 decimals pool of accounts(1) and accounts(2) with the given fees.
+    fn add_pool_with_fees(fees: Fees) -> u64 {
+        let (_context, mut contract) = setup_contract();
+        contract.add_simple_pool(
+            vec![accounts(1), accounts(2)],
+            vec![6, 6],
+            100,
+            500,
+            0,
+            0,
+            fees,
+        )
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_admin_trade_fee_denominator")]
+    fn test_admin_trade_fee_zero_denominator() {
+        let mut fees = setup_fee();
+        fees.admin_trade_fee_denominator = 0;
+        add_pool_with_fees(fees);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_admin_withdraw_fee_denominator")]
+    fn test_admin_withdraw_fee_zero_denominator() {
+        let mut fees = setup_fee();
+        fees.admin_withdraw_fee_denominator = 0;
+        add_pool_with_fees(fees);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_trade_fee_denominator")]
+    fn test_trade_fee_zero_denominator() {
+        let mut fees = setup_fee();
+        fees.trade_fee_denominator = 0;
+        add_pool_with_fees(fees);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_withdraw_fee_denominator")]
+    fn test_withdraw_fee_zero_denominator() {
+        let mut fees = setup_fee();
+        fees.withdraw_fee_denominator = 0;
+        add_pool_with_fees(fees);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_referral_fee_denominator")]
+    fn test_referral_fee_zero_denominator() {
+        let mut fees = setup_fee();
+        fees.referral_fee_denominator = 0;
+        add_pool_with_fees(fees);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_lp_fee_share_denominator")]
+    fn test_lp_fee_share_zero_denominator() {
+        let mut fees = setup_fee();
+        fees.lp_fee_share_denominator = 0;
+        add_pool_with_fees(fees);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_trade_fee_exceed_100_percent")]
+    fn test_trade_fee_exceed_100_percent() {
+        let mut fees = setup_fee();
+        fees.trade_fee_numerator = 1001;
+        add_pool_with_fees(fees);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_withdraw_fee_exceed_100_percent")]
+    fn test_withdraw_fee_exceed_100_percent() {
+        let mut fees = setup_fee();
+        fees.withdraw_fee_numerator = 1001;
+        add_pool_with_fees(fees);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_admin_trade_fee_exceed_trade_fee")]
+    fn test_admin_trade_fee_exceed_trade_fee() {
+        let mut fees = setup_fee();
+        fees.admin_trade_fee_numerator = 101;
+        add_pool_with_fees(fees);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_admin_withdraw_fee_exceed_withdraw_fee")]
+    fn test_admin_withdraw_fee_exceed_withdraw_fee() {
+        let mut fees = setup_fee();
+        fees.admin_withdraw_fee_numerator = 101;
+        add_pool_with_fees(fees);
+    }
+
+    #[test]
+    fn test_pool_with_future_ramp() {
+        let (mut context, mut contract) = setup_contract();
+        let amount = get_balance_with_decimals(10, 6);
+        // the ramp starts a day after the block timestamp.
+        let pool_id = contract.add_simple_pool(
+            vec![accounts(1), accounts(2)],
+            vec![6, 6],
+            100,
+            200,
+            86400,
+            2 * 86400,
+            setup_fee(),
+        );
+        assert_eq!(contract.get_pool_amp_factor(pool_id), U128(100));
+
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+        );
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(to_yocto("0.008"))
+            .build());
+        contract.add_liquidity(pool_id, vec![U128(amount), U128(amount)], None, None);
+        assert_eq!(
+            contract.get_virtual_price(pool_id),
+            U128(get_balance_with_decimals(1, PRECISION_DECIMALS))
+        );
+        assert!(
+            contract
+                .get_return(pool_id, accounts(1), U128(1_000_000), accounts(2))
+                .0
+                > 0
+        );
+    }
+
+    #[test]
+    fn test_stop_ramp_amp() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![
+                (accounts(1), get_balance_with_decimals(10, token_decimals)),
+                (accounts(2), get_balance_with_decimals(10, token_decimals)),
+            ],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .block_timestamp(0)
+            .build());
+        contract.set_amp_params(pool_id, 100, 500, 2 * 86400);
+
+        testing_env!(context.block_timestamp(86400 * 1_000_000_000).build());
+        assert_eq!(contract.get_amp_factor(pool_id).0, 300);
+        contract.stop_ramp_amp(pool_id);
+
+        testing_env!(context.block_timestamp(100000 * 1_000_000_000).build());
+        assert_eq!(contract.get_amp_factor(pool_id).0, 300);
+        let pool_info = contract.get_pool(pool_id);
+        assert_eq!(pool_info.initial_amp_factor.0, 300);
+        assert_eq!(pool_info.target_amp_factor.0, 300);
+        assert_eq!(pool_info.stop_ramp_ts.0, 86400);
+    }
+
+    #[test]
+    fn test_get_pool_amp_factor_during_ramp() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![
+                (accounts(1), get_balance_with_decimals(10, token_decimals)),
+                (accounts(2), get_balance_with_decimals(10, token_decimals)),
+            ],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .block_timestamp(0)
+            .build());
+        contract.set_amp_params(pool_id, 100, 500, 4 * 86400);
+        assert_eq!(contract.get_pool_amp_factor(pool_id).0, 100);
+
+        testing_env!(context.block_timestamp(86400 * 1_000_000_000).build());
+        assert_eq!(contract.get_pool_amp_factor(pool_id).0, 200);
+        assert_eq!(
+            contract.get_ramp_schedule(pool_id),
+            RampSchedule {
+                initial_amp: U128(100),
+                target_amp: U128(500),
+                start_ramp_ts: U128(0),
+                stop_ramp_ts: U128(4 * 86400),
+                current_amp: U128(200),
+            }
+        );
+
+        testing_env!(context.block_timestamp(5 * 86400 * 1_000_000_000).build());
+        assert_eq!(contract.get_pool_amp_factor(pool_id).0, 500);
+    }
+
+    #[test]
+    fn test_rate_scaled_epsilon() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![
+                (
+                    accounts(1),
+                    get_balance_with_decimals(1_000_000, token_decimals),
+                ),
+                (
+                    accounts(2),
+                    get_balance_with_decimals(1_000_000, token_decimals),
+                ),
+            ],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+        let amount_in = U128(get_balance_with_decimals(1_000, token_decimals));
+        let exact = contract.get_return(pool_id, accounts(1), amount_in, accounts(2));
+        assert!(!contract.get_pool(pool_id).rate_scaled_epsilon);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(0)
+            .build());
+        contract.set_rate_scaled_epsilon(pool_id, true);
+        assert!(contract.get_pool(pool_id).rate_scaled_epsilon);
+
+        // the solvers stop within a thousandth of a unit of the coins, the quote stays within one.
+        let scaled = contract.get_return(pool_id, accounts(1), amount_in, accounts(2));
+        assert!(scaled.0 + 1 >= exact.0 && scaled.0 <= exact.0 + 1);
+
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount_in.0)],
+        );
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        let amount_out = contract.swap(
+            pool_id,
+            accounts(1),
+            amount_in,
+            accounts(2),
+            scaled,
+            None,
+            None,
+            None,
+            None,
+        );
+        assert_eq!(amount_out, scaled);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_LIQUIDITY_BLOCKED_DURING_RAMP")]
+    fn test_block_liquidity_during_ramp() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![
+                (accounts(1), get_balance_with_decimals(10, token_decimals)),
+                (accounts(2), get_balance_with_decimals(10, token_decimals)),
+            ],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .block_timestamp(0)
+            .build());
+        contract.set_amp_params(pool_id, 100, 500, 2 * 86400);
+        contract.set_block_liquidity_during_ramp(pool_id, true);
+        assert!(contract.get_pool(pool_id).block_liquidity_during_ramp);
+
+        // balanced removal is still allowed
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .block_timestamp(86400 * 1_000_000_000)
+            .attached_deposit(1)
+            .build());
+        contract.remove_liquidity(
+            pool_id,
+            U128(get_balance_with_decimals(2, PRECISION_DECIMALS)),
+            vec![U128(0), U128(0)],
+            None,
+        );
+
+        testing_env!(context.attached_deposit(to_yocto("0.008")).build());
+        contract.add_liquidity(
+            pool_id,
+            vec![U128(get_balance_with_decimals(1, token_decimals)), U128(0)],
+            None,
+            None,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_POOL_SHUTDOWN")]
+    fn test_shutdown_pool() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(10, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(1)
+            .build());
+        contract.extend_guardians(vec![accounts(5)]);
+        testing_env!(context.predecessor_account_id(accounts(5)).build());
+        contract.shutdown_pool(pool_id);
+        assert!(contract.get_pool(pool_id).shutdown);
+
+        // proportional removal pays no withdraw fee,
+        // the total supply includes the locked initial shares.
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        let shares = contract.get_pool_shares(pool_id, accounts(3)).0;
+        let total_shares = contract.get_pool_total_shares(pool_id).0;
+        assert_eq!(total_shares, shares + LOCKED_INITIAL_SHARES);
+        let deposit_before = contract.get_deposit(accounts(3), accounts(1)).0;
+        contract.remove_liquidity(pool_id, U128(shares / 2), vec![U128(0), U128(0)], None);
+        assert_eq!(
+            contract.get_deposit(accounts(3), accounts(1)).0 - deposit_before,
+            amount * (shares / 2) / total_shares
+        );
+
+        contract.remove_liquidity_one_coin(pool_id, accounts(1), U128(shares / 4), U128(0), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_POOL_SHUTDOWN")]
+    fn test_shutdown_pool_blocks_swap() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(10, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(1)
+            .build());
+        contract.shutdown_pool(pool_id);
+
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.swap(
+            pool_id,
+            accounts(1),
+            U128(amount),
+            accounts(2),
+            U128(0),
+            None,
+            None,
+            None,
+            None,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NOT_ALLOWED")]
+    fn test_shutdown_pool_not_allowed() {
+        let (mut context, mut contract) = setup_contract();
+        contract.add_simple_pool(
+            vec![accounts(1), accounts(2)],
+            vec![6, 6],
+            100,
+            100,
+            0,
+            0,
+            setup_fee(),
+        );
+        testing_env!(context
+            .predecessor_account_id(accounts(5))
+            .attached_deposit(1)
+            .build());
+        contract.shutdown_pool(0);
+    }
+
+    #[test]
+    fn test_mft_transfer_all() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(10, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+        let token_id = format!(":{}", pool_id);
+        testing_env!(context
+            .predecessor_account_id(accounts(5))
+            .attached_deposit(to_yocto("0.01"))
+            .build());
+        contract.mft_register(token_id.clone(), accounts(5));
+
+        let shares = contract.get_pool_shares(pool_id, accounts(3));
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        contract.mft_transfer_all(token_id.clone(), accounts(5), None);
+        assert_eq!(contract.get_pool_shares(pool_id, accounts(3)).0, 0);
+        assert_eq!(contract.get_pool_shares(pool_id, accounts(5)), shares);
+
+        // nothing left, nothing moves.
+        contract.mft_transfer_all(token_id, accounts(5), None);
+        assert_eq!(contract.get_pool_shares(pool_id, accounts(5)), shares);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_RECEIVER_NOT_REGISTERED")]
+    fn test_mft_transfer_all_receiver_not_registered() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(10, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        contract.mft_transfer_all(format!(":{}", pool_id), accounts(5), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_RECEIVER_NOT_REGISTERED")]
+    fn test_mft_transfer_call_shares_receiver_not_registered() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(10, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        contract.mft_transfer_call(
+            format!(":{}", pool_id),
+            accounts(5),
+            U128(1_000_000),
+            None,
+            "".to_string(),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_RECEIVER_NOT_REGISTERED")]
+    fn test_mft_transfer_call_token_receiver_not_registered() {
+        let (mut context, mut contract) = setup_contract();
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), 1_000_000)],
+        );
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(5),
+            vec![(accounts(2), 1_000_000)],
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        contract.mft_transfer_call(
+            accounts(1).to_string(),
+            accounts(5),
+            U128(1_000),
+            None,
+            "".to_string(),
+        );
+    }
+
+    #[test]
+    fn test_lp_holder_count() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(10, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+        assert_eq!(contract.get_lp_holder_count(pool_id), 1);
+
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(5),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+        );
+        testing_env!(context
+            .predecessor_account_id(accounts(5))
+            .attached_deposit(to_yocto("0.008"))
+            .build());
+        contract.add_liquidity(pool_id, vec![U128(amount), U128(amount)], None, None);
+        assert_eq!(contract.get_lp_holder_count(pool_id), 2);
+
+        testing_env!(context.attached_deposit(1).build());
+        let shares = contract.get_pool_shares(pool_id, accounts(5));
+        contract.remove_liquidity(pool_id, shares, vec![U128(0), U128(0)], None);
+        assert_eq!(contract.get_lp_holder_count(pool_id), 1);
+    }
+
+    #[test]
+    fn test_get_account_lp_positions() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(10, token_decimals);
+        let first_pool = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+        let second_pool = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(4), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+
+        assert_eq!(
+            contract.get_account_lp_positions(accounts(3)),
+            vec![
+                (
+                    first_pool,
+                    contract.get_pool_shares(first_pool, accounts(3))
+                ),
+                (
+                    second_pool,
+                    contract.get_pool_shares(second_pool, accounts(3))
+                ),
+            ]
+        );
+
+        assert!(contract.get_account_lp_positions(accounts(5)).is_empty());
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(5),
+            vec![(accounts(1), amount), (accounts(4), amount)],
+        );
+        testing_env!(context
+            .predecessor_account_id(accounts(5))
+            .attached_deposit(to_yocto("0.008"))
+            .build());
+        let shares =
+            contract.add_liquidity(second_pool, vec![U128(amount), U128(amount)], None, None);
+        assert_eq!(
+            contract.get_account_lp_positions(accounts(5)),
+            vec![(second_pool, U128(shares))]
+        );
+    }
+
+    #[test]
+    fn test_lp_fee_discount() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(100, token_decimals);
+        let amount_in = get_balance_with_decimals(10, token_decimals);
+        // the same swap on two identical pools, by a share holder and by a non holder.
+        let mut outs = vec![];
+        for swapper in [accounts(3), accounts(5)] {
+            let pool_id = create_pool_with_liquidity(
+                &mut context,
+                &mut contract,
+                accounts(3),
+                vec![(accounts(1), amount), (accounts(2), amount)],
+                vec![token_decimals.into(), token_decimals.into()],
+            );
+            testing_env!(context.predecessor_account_id(accounts(0)).build());
+            contract.set_lp_fee_discount(pool_id, 2000);
+            assert_eq!(contract.get_pool(pool_id).lp_fee_discount_bps, 2000);
+            deposit_tokens(
+                &mut context,
+                &mut contract,
+                swapper.clone(),
+                vec![(accounts(1), amount_in)],
+            );
+
+            let owner_fee_before = contract.get_deposit(accounts(0), accounts(2)).0;
+            testing_env!(context
+                .predecessor_account_id(swapper.clone())
+                .attached_deposit(1)
+                .build());
+            let amount_out = contract.swap(
+                pool_id,
+                accounts(1),
+                U128(amount_in),
+                accounts(2),
+                U128(0),
+                None,
+                Some(true),
+                None,
+                None,
+            );
+            outs.push((
+                amount_out.0,
+                contract.get_deposit(accounts(0), accounts(2)).0 - owner_fee_before,
+            ));
+        }
+        let (holder_out, holder_admin_fee) = outs[0];
+        let (other_out, other_admin_fee) = outs[1];
+        assert!(holder_out > other_out);
+        // the discount only moves part of the admin fee to the holder.
+        assert_eq!(holder_out + holder_admin_fee, other_out + other_admin_fee);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_INVALID_DISCOUNT")]
+    fn test_lp_fee_discount_too_high() {
+        let (mut context, mut contract) = setup_contract();
+        let pool_id = contract.add_simple_pool(
+            vec![accounts(1), accounts(2)],
+            vec![6, 6],
+            100,
+            100,
+            0,
+            0,
+            setup_fee(),
+        );
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        contract.set_lp_fee_discount(pool_id, 10_001);
+    }
+
+    #[test]
+    fn test_balance_caps() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(100, token_decimals);
+        let extra = get_balance_with_decimals(5, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        contract.set_balance_caps(pool_id, Some(vec![U128(amount + extra), U128(0)]));
+        assert_eq!(
+            contract.get_balance_caps(pool_id),
+            vec![
+                BalanceCap {
+                    cap: U128(amount + extra),
+                    headroom: Some(U128(extra)),
+                },
+                BalanceCap {
+                    cap: U128(0),
+                    headroom: None,
+                },
+            ]
+        );
+
+        // up to the cap is fine.
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), extra), (accounts(2), extra)],
+        );
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(to_yocto("0.008"))
+            .build());
+        contract.add_liquidity(pool_id, vec![U128(extra), U128(extra)], None, None);
+        let caps = contract.get_balance_caps(pool_id);
+        assert_eq!(
+            caps[0].headroom,
+            Some(U128(
+                amount + extra - contract.get_pool(pool_id).amounts[0].0
+            ))
+        );
+        assert_eq!(caps[1].headroom, None);
+
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        contract.set_balance_caps(pool_id, None);
+        assert_eq!(contract.get_balance_caps(pool_id)[0].cap, U128(0));
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_BALANCE_CAP_EXCEEDED")]
+    fn test_balance_cap_exceeded_by_swap() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(100, token_decimals);
+        let amount_in = get_balance_with_decimals(10, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        contract.set_balance_caps(pool_id, Some(vec![U128(amount + amount_in - 1), U128(0)]));
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount_in)],
+        );
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        contract.swap(
+            pool_id,
+            accounts(1),
+            U128(amount_in),
+            accounts(2),
+            U128(0),
+            None,
+            Some(true),
+            None,
+            None,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "param_num should equal to coin num")]
+    fn test_balance_caps_wrong_len() {
+        let (mut context, mut contract) = setup_contract();
+        let pool_id = contract.add_simple_pool(
+            vec![accounts(1), accounts(2)],
+            vec![6, 6],
+            100,
+            100,
+            0,
+            0,
+            setup_fee(),
+        );
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        contract.set_balance_caps(pool_id, Some(vec![U128(1)]));
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_BURN_TOO_SMALL")]
+    fn test_min_one_coin_burn() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(10, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+        assert_eq!(
+            contract.get_pool(pool_id).min_one_coin_burn.0,
+            crate::utils::MIN_ONE_COIN_BURN
+        );
+
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        contract.set_min_one_coin_burn(pool_id, U128(1_000_000_000));
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        contract.remove_liquidity_one_coin(pool_id, accounts(1), U128(999_999_999), U128(0), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_BURN_TOO_SMALL")]
+    fn test_calc_remove_liquidity_one_coin_burn_too_small() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(10, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        contract.set_min_one_coin_burn(pool_id, U128(1_000_000_000));
+        contract.calc_remove_liquidity_one_coin(pool_id, accounts(1), U128(999_999_999));
+    }
+
+    #[test]
+    fn test_calc_single_deposit_impact() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        // the pool is short of accounts(2).
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![
+                (accounts(1), get_balance_with_decimals(100, token_decimals)),
+                (accounts(2), get_balance_with_decimals(10, token_decimals)),
+            ],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+        let amount = get_balance_with_decimals(1, token_decimals);
+
+        let rich = contract.calc_single_deposit_impact(pool_id, accounts(1), U128(amount));
+        assert_eq!(
+            rich.lp_minted,
+            contract.try_add_liquidity(pool_id, vec![U128(amount), U128(0)])
+        );
+        assert!(rich.bonus_or_penalty_bps < 0);
+
+        let short = contract.calc_single_deposit_impact(pool_id, accounts(2), U128(amount));
+        assert_eq!(
+            short.lp_minted,
+            contract.try_add_liquidity(pool_id, vec![U128(0), U128(amount)])
+        );
+        assert!(short.bonus_or_penalty_bps > 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_MISSING_TOKEN")]
+    fn test_calc_single_deposit_impact_missing_token() {
+        let (mut context, mut contract) = setup_contract();
+        let amount = get_balance_with_decimals(10, 6);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![6, 6],
+        );
+        contract.calc_single_deposit_impact(pool_id, accounts(4), U128(amount));
+    }
+
+    #[test]
+    fn test_set_pool_rates() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(10, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+        let decimal_rate = 10u128.pow(PRECISION_DECIMALS - token_decimals);
+        assert_eq!(
+            contract.get_pool_rates(pool_id),
+            vec![U128(decimal_rate), U128(decimal_rate)]
+        );
+        let one_token = get_balance_with_decimals(1, token_decimals) / 100;
+        let return_at_par = contract
+            .get_return(pool_id, accounts(1), U128(one_token), accounts(2))
+            .0;
+
+        // first token worth 1.05 of the second, e.g. a staked token accruing rewards.
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        contract.set_rate_provider(pool_id, Some(accounts(5)));
+        assert_eq!(contract.get_pool(pool_id).rate_provider, Some(accounts(5)));
+        testing_env!(context.predecessor_account_id(accounts(5)).build());
+        contract.set_pool_rates(
+            pool_id,
+            vec![U128(decimal_rate * 105 / 100), U128(decimal_rate)],
+        );
+        assert_eq!(
+            contract.get_pool_rates(pool_id)[0].0,
+            decimal_rate * 105 / 100
+        );
+        let return_at_rate = contract
+            .get_return(pool_id, accounts(1), U128(one_token), accounts(2))
+            .0;
+        assert!(return_at_rate > return_at_par);
+        assert!(return_at_rate < one_token * 105 / 100);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NOT_ALLOWED")]
+    fn test_set_pool_rates_not_allowed() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(10, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+
+        testing_env!(context.predecessor_account_id(accounts(5)).build());
+        contract.set_pool_rates(pool_id, vec![U128(1), U128(1)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_SHARE_CAP")]
+    fn test_share_cap_add_liquidity() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(10, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        let shares = contract.get_pool_shares(pool_id, accounts(3));
+        contract.set_max_share_per_account(pool_id, Some(U128(shares.0 + 1)));
+        assert_eq!(
+            contract.get_pool(pool_id).max_share_per_account,
+            Some(U128(shares.0 + 1))
+        );
+
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+        );
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(to_yocto("0.008"))
+            .build());
+        contract.add_liquidity(pool_id, vec![U128(amount), U128(amount)], None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_SHARE_CAP")]
+    fn test_share_cap_mft_transfer() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(10, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+        let token_id = format!(":{}", pool_id);
+
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        contract.set_max_share_per_account(pool_id, Some(U128(1_000_000)));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(to_yocto("0.0067"))
+            .build());
+        contract.mft_register(token_id.clone(), accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        contract.mft_transfer(token_id.clone(), accounts(1), U128(1_000_000), None);
+        assert_eq!(contract.get_pool_shares(pool_id, accounts(1)).0, 1_000_000);
+
+        contract.mft_transfer(token_id, accounts(1), U128(1), None);
+    }
+
+    #[test]
+    fn test_mft_unregister() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(10, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        let shares = contract.get_pool_shares(pool_id, accounts(3));
+        contract.remove_liquidity(pool_id, shares, vec![U128(0), U128(0)], None);
+        let token_id = format!(":{}", pool_id);
+        assert!(contract.is_lp_token_registered(token_id.clone(), accounts(3)));
+        contract.mft_unregister(token_id.clone());
+        assert!(!contract.is_lp_token_registered(token_id, accounts(3)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Non-zero shares balance")]
+    fn test_mft_unregister_with_shares() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(10, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        contract.mft_unregister(format!(":{}", pool_id));
+    }
+
+    #[test]
+    fn test_remove_liquidity_min_virtual_price() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(10, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+        let shares = contract.get_pool_shares(pool_id, accounts(3)).0;
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        let virtual_price = contract.get_virtual_price(pool_id);
+        contract.remove_liquidity(
+            pool_id,
+            U128(shares / 2),
+            vec![U128(0), U128(0)],
+            Some(virtual_price),
+        );
+        assert_eq!(
+            contract.get_pool_shares(pool_id, accounts(3)).0,
+            shares - shares / 2
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_VP_BELOW_MIN")]
+    fn test_remove_liquidity_one_coin_below_min_virtual_price() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(10, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+        let shares = contract.get_pool_shares(pool_id, accounts(3)).0;
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        let virtual_price = contract.get_virtual_price(pool_id).0;
+        contract.remove_liquidity_one_coin(
+            pool_id,
+            accounts(1),
+            U128(shares / 2),
+            U128(0),
+            Some(U128(virtual_price + 1)),
+        );
+    }
+
+    #[test]
+    fn test_swap_max_price_impact() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(100, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount)],
+        );
+        let pool = contract.pools.get(pool_id).unwrap();
+        let small_in = get_balance_with_decimals(1, token_decimals);
+        let small_out = pool.get_return(&accounts(1), small_in, &accounts(2));
+        assert_eq!(
+            pool.get_price_impact_bps(&accounts(1), small_in, &accounts(2), small_out),
+            0
+        );
+        let large_in = get_balance_with_decimals(90, token_decimals);
+        let large_out = pool.get_return(&accounts(1), large_in, &accounts(2));
+        assert!(pool.get_price_impact_bps(&accounts(1), large_in, &accounts(2), large_out) > 10);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        let amount_out = contract.swap(
+            pool_id,
+            accounts(1),
+            U128(small_in),
+            accounts(2),
+            U128(0),
+            None,
+            None,
+            Some(10),
+            None,
+        );
+        assert_eq!(amount_out.0, small_out);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_PRICE_IMPACT_TOO_HIGH")]
+    fn test_swap_price_impact_too_high() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(100, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount)],
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        contract.swap(
+            pool_id,
+            accounts(1),
+            U128(get_balance_with_decimals(90, token_decimals)),
+            accounts(2),
+            U128(0),
+            None,
+            None,
+            Some(10),
+            None,
+        );
+    }
+
+    #[test]
+    fn test_add_simple_pools() {
+        let (mut context, mut contract) = setup_contract();
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(env::storage_byte_cost() * 11000)
+            .build());
+        let ids = contract.add_simple_pools(vec![
+            SimplePoolArgs {
+                tokens: vec![accounts(1), accounts(2)],
+                decimals: vec![6, 6],
+                initial_amp_factor: 100,
+                target_amp_factor: 100,
+                start_ramp_ts: 0,
+                stop_ramp_ts: 0,
+                fees: setup_fee(),
+            },
+            SimplePoolArgs {
+                tokens: vec![accounts(1), accounts(4)],
+                decimals: vec![6, 6],
+                initial_amp_factor: 100,
+                target_amp_factor: 100,
+                start_ramp_ts: 0,
+                stop_ramp_ts: 0,
+                fees: setup_fee(),
+            },
+        ]);
+        assert_eq!(ids, vec![0, 1]);
+        assert_eq!(contract.get_number_of_pools(), 2);
+        assert_eq!(
+            contract.get_pool(1).token_account_ids,
+            vec![accounts(1), accounts(4)]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_TOKEN_DUPLICATES")]
+    fn test_add_simple_pools_duplicate_tokens() {
+        let (mut context, mut contract) = setup_contract();
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(env::storage_byte_cost() * 11000)
+            .build());
+        contract.add_simple_pools(vec![
+            SimplePoolArgs {
+                tokens: vec![accounts(1), accounts(2)],
+                decimals: vec![6, 6],
+                initial_amp_factor: 100,
+                target_amp_factor: 100,
+                start_ramp_ts: 0,
+                stop_ramp_ts: 0,
+                fees: setup_fee(),
+            },
+            SimplePoolArgs {
+                tokens: vec![accounts(4), accounts(4)],
+                decimals: vec![6, 6],
+                initial_amp_factor: 100,
+                target_amp_factor: 100,
+                start_ramp_ts: 0,
+                stop_ramp_ts: 0,
+                fees: setup_fee(),
+            },
+        ]);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_WRONG_DECIMALS_NUM")]
+    fn test_add_simple_pool_wrong_decimals_num() {
+        let (mut context, mut contract) = setup_contract();
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(env::storage_byte_cost() * 5500)
+            .build());
+        contract.add_simple_pool(
+            vec![accounts(1), accounts(2)],
+            vec![6],
+            100,
+            500,
+            0,
+            0,
+            setup_fee(),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_DECIMALS_TOO_HIGH")]
+    fn test_add_simple_pool_decimals_too_high() {
+        let (mut context, mut contract) = setup_contract();
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(env::storage_byte_cost() * 5500)
+            .build());
+        contract.add_simple_pool(
+            vec![accounts(1), accounts(2)],
+            vec![24, 25],
+            100,
+            500,
+            0,
+            0,
+            setup_fee(),
+        );
+    }
+
+    #[test]
+    fn test_storage_unregister() {
+        let (mut context, mut contract) = setup_contract();
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), 100)],
+        );
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        contract.withdraw(accounts(1), U128(100), None);
+
+        assert_eq!(contract.get_storage_report().account_count, 2);
+        assert!(contract.storage_unregister(None));
+        assert!(contract.storage_balance_of(accounts(3)).is_none());
+        assert!(!contract.storage_unregister(None));
+        assert_eq!(contract.get_storage_report().account_count, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_STORAGE_UNREGISTER_TOKENS_NOT_EMPTY")]
+    fn test_storage_unregister_tokens_not_empty() {
+        let (mut context, mut contract) = setup_contract();
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), 100)],
+        );
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        contract.storage_unregister(None);
+    }
+
+    #[test]
+    fn test_storage_unregister_force() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(10, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), 100)],
+        );
+        let shares = contract.get_pool_shares(pool_id, accounts(3)).0;
+        let owner_shares = contract.get_pool_shares(pool_id, accounts(0)).0;
+        let owner_deposit = contract.get_deposit(accounts(0), accounts(1)).0;
+        assert!(shares > 0);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        assert!(contract.storage_unregister(Some(true)));
+        assert!(contract.storage_balance_of(accounts(3)).is_none());
+        assert_eq!(contract.get_pool_shares(pool_id, accounts(3)).0, 0);
+        assert_eq!(
+            contract.get_pool_shares(pool_id, accounts(0)).0,
+            owner_shares + shares
+        );
+        assert_eq!(
+            contract.get_deposit(accounts(0), accounts(1)).0,
+            owner_deposit + 100
+        );
+    }
+
+    #[test]
+    fn test_withdraw_all() {
+        let (mut context, mut contract) = setup_contract();
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), 1_000_000), (accounts(2), 2_000_000)],
+        );
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        contract.register_tokens(vec![accounts(4)]);
+        assert_eq!(contract.get_deposits(accounts(3)).len(), 3);
+        let mut balances = contract.get_balances(accounts(3));
+        balances.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            balances,
+            vec![
+                (accounts(1), U128(1_000_000)),
+                (accounts(2), U128(2_000_000)),
+                (accounts(4), U128(0))
+            ]
+        );
+
+        let mut withdrawn = contract.withdraw_all();
+        withdrawn.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            withdrawn,
+            vec![
+                (accounts(1), U128(1_000_000)),
+                (accounts(2), U128(2_000_000))
+            ]
+        );
+        // tokens with a zero balance are unregistered as well.
+        assert!(contract.get_deposits(accounts(3)).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_STORAGE_DEPOSIT: needed")]
+    fn test_add_liquidity_storage_deposit_short() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(10, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+        deposit_tokens(
+            &mut context,
+            &mut contract,
+            accounts(5),
+            vec![(accounts(1), amount), (accounts(2), amount)],
+        );
+
+        // the new LP registration of accounts(5) can't be paid by 1 yocto.
+        testing_env!(context
+            .predecessor_account_id(accounts(5))
+            .attached_deposit(1)
+            .build());
+        contract.add_liquidity(pool_id, vec![U128(amount), U128(amount)], None, None);
+    }
+
+    #[test]
+    fn test_register_for_pool() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let amount = get_balance_with_decimals(10, token_decimals);
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![
+                (accounts(1), amount),
+                (accounts(2), amount),
+                (accounts(4), amount),
+            ],
+            vec![
+                token_decimals.into(),
+                token_decimals.into(),
+                token_decimals.into(),
+            ],
+        );
+        let token_id = format!(":{}", pool_id);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(5))
+            .attached_deposit(to_yocto("1"))
+            .build());
+        contract.storage_deposit(None, Some(true));
+        assert!(contract.get_deposits(accounts(5)).is_empty());
+        assert!(!contract.is_lp_token_registered(token_id.clone(), accounts(5)));
+
+        testing_env!(context.attached_deposit(to_yocto("0.1")).build());
+        contract.register_for_pool(pool_id);
+        let deposits = contract.get_deposits(accounts(5));
+        assert_eq!(deposits.len(), 3);
+        for token in [accounts(1), accounts(2), accounts(4)].iter() {
+            assert_eq!(deposits.get(token), Some(&U128(0)));
+        }
+        assert!(contract.is_lp_token_registered(token_id.clone(), accounts(5)));
+        assert_eq!(contract.get_pool_shares(pool_id, accounts(5)).0, 0);
+
+        // registering again only refunds.
+        contract.register_for_pool(pool_id);
+        assert_eq!(contract.get_deposits(accounts(5)).len(), 3);
+    }
+
+    #[test]
+    fn test_mft_metadata() {
+        let (_, mut contract) = setup_contract();
+        contract.add_simple_pool(
+            vec![accounts(1), accounts(2)],
+            vec![6, 6],
+            100,
+            100,
+            0,
+            0,
+            setup_fee(),
+        );
+        let metadata = contract.mft_metadata(":0".to_string());
+        assert_eq!(metadata.symbol, "SNAIL-LP-0");
+        assert_eq!(
+            metadata.name,
+            format!("Snails LP {}-{}", accounts(1), accounts(2))
+        );
+        assert_eq!(metadata.decimals as u32, PRECISION_DECIMALS);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NO_METADATA")]
+    fn test_mft_metadata_of_token() {
+        let (_, contract) = setup_contract();
+        contract.mft_metadata(accounts(1).to_string());
+    }
+
+    #[test]
+    fn test_pool_created_at() {
+        let (mut context, mut contract) = setup_contract();
+        testing_env!(context.block_timestamp(1000 * 1_000_000_000).build());
+        let id = contract.add_simple_pool(
+            vec![accounts(1), accounts(2)],
+            vec![6, 6],
+            100,
+            100,
+            0,
+            0,
+            setup_fee(),
+        );
+
+        testing_env!(context.block_timestamp(2000 * 1_000_000_000).build());
+        assert_eq!(contract.get_pool(id).created_at.0, 1000);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_RAMP_TOO_SHORT")]
+    fn test_set_amp_params_ramp_too_short() {
+        let (mut context, mut contract) = setup_contract();
+        contract.add_simple_pool(
+            vec![accounts(1), accounts(2)],
+            vec![6, 6],
+            100,
+            100,
+            0,
+            0,
+            setup_fee(),
+        );
+        testing_env!(context.block_timestamp(1000 * 1_000_000_000).build());
+        contract.set_amp_params(0, 100, 200, 1000 + 86399);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_RAMP_TOO_FAST")]
+    fn test_set_amp_params_ramp_too_fast() {
+        let (mut context, mut contract) = setup_contract();
+        contract.add_simple_pool(
+            vec![accounts(1), accounts(2)],
+            vec![6, 6],
+            100,
+            100,
+            0,
+            0,
+            setup_fee(),
+        );
+        testing_env!(context.block_timestamp(1000 * 1_000_000_000).build());
+        contract.set_amp_params(0, 100, 1001, 1000 + 86400);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_RAMP_TOO_FAST")]
+    fn test_set_amp_params_too_far_from_current() {
+        let (mut context, mut contract) = setup_contract();
+        contract.add_simple_pool(
+            vec![accounts(1), accounts(2)],
+            vec![6, 6],
+            100,
+            100,
+            0,
+            0,
+            setup_fee(),
+        );
+        testing_env!(context.block_timestamp(1000 * 1_000_000_000).build());
+        // a ramp within bounds of the given initial A can't jump away from the current one.
+        contract.set_amp_params(0, 1001, 1001, 1000 + 86400);
+    }
+
+    #[test]
+    fn test_update_pool_params() {
+        let (mut context, mut contract) = setup_contract();
+        let mut fees = setup_fee();
+        let id = contract.add_simple_pool(
+            vec![accounts(1), accounts(2)],
+            vec![6, 6],
+            100,
+            100,
+            0,
+            0,
+            fees,
+        );
+        fees.trade_fee_numerator = 1;
+
+        testing_env!(context.block_timestamp(0).build());
+        contract.update_pool_params(
+            id,
+            Some(fees),
+            Some(AmpParams {
+                initial_amp_factor: 100,
+                target_amp_factor: 500,
+                stop_ramp_ts: 2 * 86400,
+            }),
+        );
+        let pool_info = contract.get_pool(id);
+        assert_eq!(pool_info.target_amp_factor.0, 500);
+        assert_eq!(pool_info.stop_ramp_ts.0, 2 * 86400);
+        assert_ne!(fees, contract.fees_info(id));
+
+        testing_env!(context
+            .block_timestamp(MIN_FEE_CHANGE_DELAY * 1_000_000_000)
+            .build());
+        contract.apply_new_fees(id);
+        assert_eq!(fees, contract.fees_info(id));
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_RAMP_TOO_FAST")]
+    fn test_update_pool_params_invalid_amp() {
+        let (mut context, mut contract) = setup_contract();
+        let fees = setup_fee();
+        let id = contract.add_simple_pool(
+            vec![accounts(1), accounts(2)],
+            vec![6, 6],
+            100,
+            100,
+            0,
+            0,
+            fees,
+        );
+
+        testing_env!(context.block_timestamp(0).build());
+        contract.update_pool_params(
+            id,
+            Some(fees),
+            Some(AmpParams {
+                initial_amp_factor: 100,
+                target_amp_factor: 1001,
+                stop_ramp_ts: 86400,
+            }),
+        );
+    }
+
+    /// Test fee info change.
+    #[test]
+    fn test_fees_info_change() {
+        let (mut context, mut contract) = setup_contract();
+        let initial_amp_factor: u64 = 100;
+        let target_amp_factor: u64 = 500;
+        let start_ramp_ts: u64 = 0;
+        let stop_ramp_ts: u64 = 0;
+        let mut fees: Fees = setup_fee();
+
+        let id = contract.add_simple_pool(
+            vec![accounts(1), accounts(2), accounts(4)],
+            vec![6, 6, 6],
+            initial_amp_factor,
+            target_amp_factor,
+            start_ramp_ts,
+            stop_ramp_ts,
+            fees,
+        );
+
+        assert_eq!(fees, contract.fees_info(id));
+
+        fees.admin_trade_fee_numerator = 1 as u64;
+        fees.admin_trade_fee_denominator = 2 as u64;
+        fees.admin_withdraw_fee_numerator = 3 as u64;
+        fees.admin_withdraw_fee_denominator = 3 as u64;
+        fees.trade_fee_numerator = 123 as u64;
+        fees.trade_fee_denominator = 431 as u64;
+        fees.withdraw_fee_numerator = 153 as u64;
+        fees.withdraw_fee_denominator = 431 as u64;
+
+        assert_ne!(fees, contract.fees_info(id));
+
+        contract.commit_new_fees(id, fees, MIN_FEE_CHANGE_DELAY);
+        assert_ne!(fees, contract.fees_info(id));
+
+        testing_env!(context
+            .block_timestamp(MIN_FEE_CHANGE_DELAY * 1_000_000_000)
+            .build());
+        contract.apply_new_fees(id);
+
+        assert_eq!(fees, contract.fees_info(id));
+    }
+
+    #[test]
+    fn test_get_max_output() {
+        let (mut context, mut contract) = setup_contract();
+        let token_decimals: u32 = 6;
+        let pool_id = create_pool_with_liquidity(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            vec![
+                (accounts(1), get_balance_with_decimals(10, token_decimals)),
+                (accounts(2), get_balance_with_decimals(10, token_decimals)),
+            ],
+            vec![token_decimals.into(), token_decimals.into()],
+        );
+
+        let balance = contract.get_pool(pool_id).amounts[1].0;
+        let max_output = contract.get_max_output(pool_id, accounts(2)).0;
+        assert!(max_output < balance);
+        assert_eq!(
+            max_output,
+            balance - get_balance_with_decimals(1, token_decimals)
+        );
+    }
+
+    #[test]
+    fn test_copy_fees_from() {
+        let (mut context, mut contract) = setup_contract();
+        let source_fees = setup_fee();
+        let mut target_fees = setup_fee();
+        target_fees.trade_fee_numerator = 1;
+        let source_id = contract.add_simple_pool(
+            vec![accounts(1), accounts(2)],
+            vec![6, 6],
+            100,
+            100,
+            0,
+            0,
+            source_fees,
+        );
+        let target_id = contract.add_simple_pool(
+            vec![accounts(1), accounts(4)],
+            vec![6, 6],
+            100,
+            100,
+            0,
+            0,
+            target_fees,
+        );
+
+        contract.copy_fees_from(target_id, source_id);
+        assert_eq!(target_fees, contract.fees_info(target_id));
+
+        testing_env!(context
+            .block_timestamp(MIN_FEE_CHANGE_DELAY * 1_000_000_000)
+            .build());
+        contract.apply_new_fees(target_id);
+        assert_eq!(contract.fees_info(source_id), contract.fees_info(target_id));
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_FEES_TIMELOCKED")]
+    fn test_apply_new_fees_before_delay() {
+        let (mut context, mut contract) = setup_contract();
+        let id = contract.add_simple_pool(
+            vec![accounts(1), accounts(2)],
+            vec![6, 6],
+            100,
+            100,
+            0,
+            0,
+            setup_fee(),
+        );
+        contract.commit_new_fees(id, setup_fee(), MIN_FEE_CHANGE_DELAY);
+
+        testing_env!(context
+            .block_timestamp((MIN_FEE_CHANGE_DELAY - 1) * 1_000_000_000)
+            .build());
+        contract.apply_new_fees(id);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_FEE_DELAY_TOO_SHORT")]
+    fn test_commit_new_fees_delay_too_short() {
+        let (mut context, mut contract) = setup_contract();
+        let id = contract.add_simple_pool(
+            vec![accounts(1), accounts(2)],
+            vec![6, 6],
+            100,
+            100,
+            0,
+            0,
+            setup_fee(),
+        );
+        testing_env!(context.block_timestamp(1000 * 1_000_000_000).build());
+        contract.commit_new_fees(id, setup_fee(), MIN_FEE_CHANGE_DELAY);
     }
 }
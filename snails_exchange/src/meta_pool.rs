@@ -0,0 +1,403 @@
+//! Metapool: a two coin pool pairing a coin against the LP token of a base pool.
+//!
+//! The coin/LP leg is a regular `SimplePool`, so direct swaps (coin <-> LP) and
+//! liquidity management reuse the same `SnailStableSwap` math.
+//! The LP leg is booked under this contract's account id and the actual LP is held
+//! as shares of this contract in the base pool.
+//! Underlying swaps (coin <-> base coin) route through the base pool.
+//! The base LP is valued at the base pool virtual price, refreshed every time
+//! the metapool is loaded.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId, Balance};
+
+use crate::simple_pool::decimals_to_rates;
+use crate::utils::{assert_swap_valid, PRECISION, PRECISION_DECIMALS};
+use crate::*;
+
+/// Index of the base pool LP in the metapool coins.
+pub const BASE_LP_INDEX: usize = 1;
+/// LP tokens of the pools have as many decimals as amounts are normalized to.
+pub const LP_DECIMALS: u64 = PRECISION_DECIMALS as u64;
+/// Denominator of the metapool rates, so the base LP rate keeps 12 digits of the virtual price
+/// while the rate of a coin with few decimals still fits in u128.
+pub const META_RATE_DENOMINATOR: u128 = 1_000_000_000_000;
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct MetaPool {
+    /// Pool whose LP token is paired against the coin.
+    pub base_pool_id: u64,
+    /// Tokens of the base pool, reachable through underlying swaps.
+    pub base_token_account_ids: Vec<AccountId>,
+    /// Coin and base LP pool.
+    pub pool: SimplePool,
+}
+
+impl MetaPool {
+    pub fn new(
+        id: u32,
+        base_pool_id: u64,
+        base_token_account_ids: Vec<AccountId>,
+        initial_amp_factor: u64,
+        target_amp_factor: u64,
+        start_ramp_ts: u64,
+        stop_ramp_ts: u64,
+        fees: Fees,
+        token_account_id: AccountId,
+        decimals: u64,
+    ) -> Self {
+        let mut meta_pool = Self {
+            base_pool_id,
+            base_token_account_ids,
+            pool: SimplePool::new(
+                id,
+                initial_amp_factor,
+                target_amp_factor,
+                start_ramp_ts,
+                stop_ramp_ts,
+                fees,
+                vec![token_account_id, env::current_account_id()],
+                vec![decimals, LP_DECIMALS],
+            ),
+        };
+        meta_pool.set_base_virtual_price(PRECISION);
+        meta_pool
+    }
+
+    /// Prices the base LP at `virtual_price` of the base pool, scaled by PRECISION.
+    pub fn set_base_virtual_price(&mut self, virtual_price: u128) {
+        let coin_rate = decimals_to_rates(&vec![self.pool.token_decimals[0]])[0];
+        let lp_rate = virtual_price / (PRECISION / META_RATE_DENOMINATOR);
+        assert!(lp_rate > 0, "ERR_ZERO_RATE");
+        self.pool.rates = Some(vec![coin_rate * META_RATE_DENOMINATOR, lp_rate]);
+        self.pool.rate_denominator = META_RATE_DENOMINATOR;
+    }
+
+    /// Returns the coin paired against the base LP.
+    pub fn coin(&self) -> &AccountId {
+        &self.pool.token_account_ids[0]
+    }
+
+    /// Returns the id the base LP is booked under in this pool.
+    pub fn lp_token_id(&self) -> &AccountId {
+        &self.pool.token_account_ids[BASE_LP_INDEX]
+    }
+
+    pub fn is_base_token(&self, token_id: &AccountId) -> bool {
+        self.base_token_account_ids.contains(token_id)
+    }
+}
+
+impl Pool {
+    /// Returns the base pool id if `token_id` is the base LP leg of a metapool.
+    pub fn base_pool_of(&self, token_id: &AccountId) -> Option<u64> {
+        match self {
+            Pool::MetaPool(pool) if pool.lp_token_id() == token_id => Some(pool.base_pool_id),
+            _ => None,
+        }
+    }
+}
+
+#[near_bindgen]
+impl SnailSwap {
+    /// Adds new metapool pairing `token` against the LP of `base_pool_id`.
+    /// Attached NEAR should be enough to cover the added storage.
+    #[payable]
+    pub fn add_meta_pool(
+        &mut self,
+        token: AccountId,
+        decimals: u64,
+        base_pool_id: u64,
+        initial_amp_factor: u64,
+        target_amp_factor: u64,
+        start_ramp_ts: u64,
+        stop_ramp_ts: u64,
+        fees: Fees,
+    ) -> u64 {
+        self.assert_owner();
+        self.assert_contract_running();
+        assert_fees_info_valid(&fees);
+        assert_decimals_valid(&[decimals]);
+
+        let prev_storage = env::storage_usage();
+        let mut base_pool = self.pools.get(base_pool_id).expect("ERR_NO_POOL");
+        let base_tokens = match &base_pool {
+            Pool::SimplePool(pool) => pool.tokens().to_vec(),
+            _ => env::panic_str("ERR_BASE_POOL_NOT_SIMPLE"),
+        };
+        let mut tokens = base_tokens.clone();
+        tokens.push(token.clone());
+        check_token_duplicates(&tokens);
+
+        // base LP of the metapool is held by this contract, admin fees in base LP go to the owner.
+        for account_id in [env::current_account_id(), self.owner_id.clone()].iter() {
+            if !base_pool.is_lp_token_registered(account_id) {
+                base_pool.share_register(account_id);
+            }
+        }
+        self.pools.replace(base_pool_id, &base_pool);
+
+        let id = self.pools.len() as u64;
+        self.pools.push(&Pool::MetaPool(MetaPool::new(
+            id as u32,
+            base_pool_id,
+            base_tokens,
+            initial_amp_factor,
+            target_amp_factor,
+            start_ramp_ts,
+            stop_ramp_ts,
+            fees,
+            token.clone(),
+            decimals,
+        )));
+        self.whitelisted_tokens.insert(&token);
+        self.internal_check_storage(prev_storage);
+        id
+    }
+
+    /// Swaps between the coin of a metapool and one of the base pool tokens,
+    /// routing through the base pool.
+    #[payable]
+    pub fn swap_underlying(
+        &mut self,
+        pool_id: u64,
+        token_in: AccountId,
+        amount_in: U128,
+        token_out: AccountId,
+        minimum_amount_out: U128,
+    ) -> U128 {
+        assert_one_yocto();
+        self.assert_contract_running();
+        assert_swap_valid(&token_in, amount_in.0, &token_out);
+        self.assert_token_not_paused(&token_in);
+        self.assert_token_not_paused(&token_out);
+        self.internal_unwrap_meta_pool(pool_id, &token_in, &token_out);
+        let sender_id = env::predecessor_account_id();
+        let mut account = self.internal_unwrap_account(&sender_id);
+        account.withdraw(&token_in, amount_in.0);
+
+        let amount_out = self.internal_swap_underlying(
+            &sender_id,
+            pool_id,
+            &token_in,
+            amount_in.0,
+            &token_out,
+            minimum_amount_out.0,
+        );
+
+        account.deposit(&token_out, amount_out);
+        self.internal_save_account(&sender_id, account);
+
+        amount_out.into()
+    }
+}
+
+impl SnailSwap {
+    /// Returns the pool, with the base LP of a metapool priced at the current
+    /// virtual price of its base pool.
+    pub(crate) fn internal_get_pool(&self, pool_id: u64) -> Pool {
+        let mut pool = self.pools.get(pool_id).expect("ERR_NO_POOL");
+        if let Pool::MetaPool(meta_pool) = &mut pool {
+            let base_pool = self.pools.get(meta_pool.base_pool_id).expect("ERR_NO_POOL");
+            // an empty base pool has no virtual price, its LP is minted at par.
+            let virtual_price = if base_pool.share_total_balance() == 0 {
+                PRECISION
+            } else {
+                base_pool.get_virtual_price()
+            };
+            meta_pool.set_base_virtual_price(virtual_price);
+        }
+        pool
+    }
+
+    /// Returns the metapool, panics if `token_in` -> `token_out` is not one of its underlying swaps.
+    fn internal_unwrap_meta_pool(
+        &self,
+        pool_id: u64,
+        token_in: &AccountId,
+        token_out: &AccountId,
+    ) -> MetaPool {
+        match self.internal_get_pool(pool_id) {
+            Pool::MetaPool(pool) => {
+                assert!(
+                    (pool.coin() == token_in && pool.is_base_token(token_out))
+                        || (pool.coin() == token_out && pool.is_base_token(token_in)),
+                    "ERR_NOT_UNDERLYING_SWAP"
+                );
+                pool
+            }
+            _ => env::panic_str("ERR_NOT_META_POOL"),
+        }
+    }
+
+    /// Quotes an underlying swap.
+    /// Returns amount out, fee charged by the metapool in its output token
+    /// and fee charged by the base pool in its output token.
+    pub(crate) fn internal_get_return_underlying(
+        &self,
+        pool_id: u64,
+        token_in: &AccountId,
+        amount_in: Balance,
+        token_out: &AccountId,
+    ) -> (Balance, Balance, Balance) {
+        let pool = self.internal_unwrap_meta_pool(pool_id, token_in, token_out);
+        let base_pool = match self.pools.get(pool.base_pool_id).expect("ERR_NO_POOL") {
+            Pool::SimplePool(base_pool) => base_pool,
+            _ => env::panic_str("ERR_BASE_POOL_NOT_SIMPLE"),
+        };
+
+        if token_in == pool.coin() {
+            let (lp_amount, meta_fee) =
+                pool.pool
+                    .get_return_and_fee(token_in, amount_in, pool.lp_token_id());
+            let (amount_out, base_fee) =
+                base_pool.try_remove_liquidity_one_coin_and_fee(token_out, lp_amount);
+            (amount_out, meta_fee, base_fee)
+        } else {
+            let amounts: Vec<Balance> = base_pool
+                .tokens()
+                .iter()
+                .map(|id| if id == token_in { amount_in } else { 0 })
+                .collect();
+            let (lp_amount, base_fee) = base_pool.try_add_liquidity_and_fee(&amounts);
+            let (amount_out, meta_fee) =
+                pool.pool
+                    .get_return_and_fee(pool.lp_token_id(), lp_amount, token_out);
+            (amount_out, meta_fee, base_fee)
+        }
+    }
+
+    /// Executes an underlying swap, the tokens are expected to be already taken from the sender.
+    pub(crate) fn internal_swap_underlying(
+        &mut self,
+        sender_id: &AccountId,
+        pool_id: u64,
+        token_in: &AccountId,
+        amount_in: Balance,
+        token_out: &AccountId,
+        minimum_amount_out: Balance,
+    ) -> Balance {
+        // the base pool leg doesn't go through `swap_core`.
+        self.assert_token_not_paused(token_in);
+        self.assert_token_not_paused(token_out);
+        let pool = self.internal_unwrap_meta_pool(pool_id, token_in, token_out);
+        let (coin, lp_token_id, base_pool_id) = (
+            pool.coin().clone(),
+            pool.lp_token_id().clone(),
+            pool.base_pool_id,
+        );
+        let contract_id = env::current_account_id();
+
+        if token_in == &coin {
+            // coin -> base LP in the metapool, then base LP -> token_out in the base pool.
+            // minimum_amount_out guards the route on the last leg,
+            // the first one only must not swallow the input.
+            let lp_amount = self.swap_core(
+                sender_id,
+                pool_id,
+                token_in,
+                amount_in,
+                &lp_token_id,
+                1,
+                &None,
+            );
+
+            let mut base_pool = self.pools.get(base_pool_id).expect("ERR_NO_POOL");
+            let (amounts, admin_fees) = base_pool.remove_liquidity_one_coin(
+                &contract_id,
+                token_out,
+                lp_amount,
+                minimum_amount_out,
+            );
+            self.transfer_admin_fees(&base_pool, &admin_fees);
+            self.pools.replace(base_pool_id, &base_pool);
+
+            let out_idx = base_pool
+                .tokens()
+                .iter()
+                .position(|id| id == token_out)
+                .expect("ERR_MISSING_TOKEN");
+            amounts[out_idx]
+        } else {
+            // token_in -> base LP in the base pool, then base LP -> coin in the metapool.
+            let mut base_pool = self.pools.get(base_pool_id).expect("ERR_NO_POOL");
+            let amounts: Vec<Balance> = base_pool
+                .tokens()
+                .iter()
+                .map(|id| if id == token_in { amount_in } else { 0 })
+                .collect();
+            let (lp_amount, admin_fees) = base_pool.add_liquidity(&contract_id, &amounts);
+            self.transfer_admin_fees(&base_pool, &admin_fees);
+            self.pools.replace(base_pool_id, &base_pool);
+
+            self.swap_core(
+                sender_id,
+                pool_id,
+                &lp_token_id,
+                lp_amount,
+                token_out,
+                minimum_amount_out,
+                &None,
+            )
+        }
+    }
+
+    /// Takes `amount` of `token_id` of the given pool from the sender's deposits.
+    /// The base LP of a metapool is taken from the sender's shares in the base pool.
+    pub(crate) fn internal_withdraw_pool_token(
+        &mut self,
+        pool: &Pool,
+        sender_id: &AccountId,
+        account: &mut Account,
+        token_id: &AccountId,
+        amount: Balance,
+    ) {
+        match pool.base_pool_of(token_id) {
+            Some(base_pool_id) => self.internal_transfer_base_shares(
+                base_pool_id,
+                sender_id,
+                &env::current_account_id(),
+                amount,
+            ),
+            None => account.withdraw(token_id, amount),
+        }
+    }
+
+    /// Gives `amount` of `token_id` of the given pool into the receiver's deposits.
+    /// The base LP of a metapool is given as shares in the base pool,
+    /// so receiver must be registered to the base pool LP token.
+    pub(crate) fn internal_deposit_pool_token(
+        &mut self,
+        pool: &Pool,
+        receiver_id: &AccountId,
+        account: &mut Account,
+        token_id: &AccountId,
+        amount: Balance,
+    ) {
+        match pool.base_pool_of(token_id) {
+            Some(base_pool_id) => self.internal_transfer_base_shares(
+                base_pool_id,
+                &env::current_account_id(),
+                receiver_id,
+                amount,
+            ),
+            None => account.deposit(token_id, amount),
+        }
+    }
+
+    fn internal_transfer_base_shares(
+        &mut self,
+        base_pool_id: u64,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+    ) {
+        if amount == 0 {
+            return;
+        }
+        let mut base_pool = self.pools.get(base_pool_id).expect("ERR_NO_POOL");
+        base_pool.share_transfer(sender_id, receiver_id, amount);
+        self.pools.replace(base_pool_id, &base_pool);
+    }
+}
@@ -2,6 +2,7 @@ use near_contract_standards::fungible_token::metadata::FungibleTokenMetadata;
 use near_sdk::json_types::U128;
 use near_sdk::{ext_contract, near_bindgen, Balance, PromiseOrValue};
 
+use crate::meta_pool::LP_DECIMALS;
 use crate::utils::{GAS_FOR_FT_TRANSFER_CALL, GAS_FOR_RESOLVE_TRANSFER, NO_DEPOSIT};
 use crate::*;
 
@@ -33,8 +34,11 @@ enum TokenOrPool {
 }
 
 /// This is used to parse token_id fields in mft protocol used in ref,
-/// So, if we choose #nn as a partern, should announce it in mft protocol.
-/// cause : is not allowed in a normal account id, it can be a partern leading char
+/// The LP token of a pool is identified as `:nn`, nn being the pool id, e.g. `:0`,
+/// any other token_id is the account id of a deposited token.
+/// cause : is not allowed in a normal account id, it can be a partern leading char.
+/// Receivers get the token_id as is in `mft_on_transfer`, so the farming contract
+/// turns `:nn` sent by this contract into the seed id `{this contract}@nn`.
 fn try_identify_pool_id(token_id: &String) -> Result<u64, &'static str> {
     if token_id.starts_with(":") {
         if let Ok(pool_id) = str::parse::<u64>(&token_id[1..token_id.len()]) {
@@ -69,7 +73,7 @@ impl SnailSwap {
         self.assert_contract_running();
         match parse_token_id(token_id) {
             TokenOrPool::Pool(pool_id) => {
-                let mut pool = self.pools.get(pool_id).expect("ERR_NO_POOL");
+                let mut pool = self.internal_get_pool(pool_id);
                 pool.share_transfer(sender_id, receiver_id, amount);
                 self.pools.replace(pool_id, &pool);
                 log!(
@@ -102,11 +106,27 @@ impl SnailSwap {
         }
     }
 
+    /// Panics if the receiver can't hold `token_id`: for pool shares it must be registered
+    /// to the LP token, for other tokens it must have the token registered in its account.
+    fn assert_receiver_registered(&self, token_id: String, receiver_id: &AccountId) {
+        let registered = match parse_token_id(token_id) {
+            TokenOrPool::Pool(pool_id) => {
+                let pool = self.internal_get_pool(pool_id);
+                pool.is_lp_token_registered(receiver_id)
+            }
+            TokenOrPool::Token(token_id) => self
+                .internal_get_account(receiver_id)
+                .map(|account| account.get_balance(&token_id).is_some())
+                .unwrap_or(false),
+        };
+        assert!(registered, "ERR_RECEIVER_NOT_REGISTERED");
+    }
+
     fn internal_mft_balance(&self, token_id: String, account_id: &AccountId) -> Balance {
         match parse_token_id(token_id) {
             TokenOrPool::Pool(pool_id) => {
-                let pool = self.pools.get(pool_id).expect("ERR_NO_POOL");
-                pool.share_balances(account_id)
+                let pool = self.internal_get_pool(pool_id);
+                pool.share_balance_of(account_id)
             }
             TokenOrPool::Token(token_id) => self.internal_get_deposit(account_id, &token_id),
         }
@@ -122,7 +142,7 @@ impl SnailSwap {
     pub fn mft_total_supply(&self, token_id: String) -> U128 {
         match parse_token_id(token_id) {
             TokenOrPool::Pool(pool_id) => {
-                let pool = self.pools.get(pool_id).expect("ERR_NO_POOL");
+                let pool = self.internal_get_pool(pool_id);
                 U128(pool.share_total_balance())
             }
             TokenOrPool::Token(_token_id) => unimplemented!(),
@@ -138,7 +158,7 @@ impl SnailSwap {
         match parse_token_id(token_id) {
             TokenOrPool::Token(_) => env::panic_str("ERR_INVALID_REGISTER"),
             TokenOrPool::Pool(pool_id) => {
-                let mut pool = self.pools.get(pool_id).expect("ERR_NO_POOL");
+                let mut pool = self.internal_get_pool(pool_id);
                 pool.share_register(&account_id);
                 self.pools.replace(pool_id, &pool);
                 self.internal_check_storage(prev_storage);
@@ -146,11 +166,35 @@ impl SnailSwap {
         }
     }
 
+    /// Unregister LP token of given pool for the caller and refund the freed storage.
+    /// Fails if token_id is not a pool or the caller still has shares in it.
+    #[payable]
+    pub fn mft_unregister(&mut self, token_id: String) {
+        assert_one_yocto();
+        self.assert_contract_running();
+        let account_id = env::predecessor_account_id();
+        let prev_storage = env::storage_usage();
+        match parse_token_id(token_id) {
+            TokenOrPool::Token(_) => env::panic_str("ERR_INVALID_UNREGISTER"),
+            TokenOrPool::Pool(pool_id) => {
+                let mut pool = self.internal_get_pool(pool_id);
+                pool.share_unregister(&account_id);
+                self.pools.replace(pool_id, &pool);
+            }
+        }
+        let refund = (prev_storage.saturating_sub(env::storage_usage()) as Balance)
+            .checked_mul(env::storage_byte_cost())
+            .unwrap();
+        if refund > 0 {
+            Promise::new(account_id).transfer(refund);
+        }
+    }
+
     pub fn is_lp_token_registered(&self, token_id: String, account_id: AccountId) -> bool {
         match parse_token_id(token_id) {
             TokenOrPool::Token(_) => env::panic_str("ERR_INVALID_REGISTER"),
             TokenOrPool::Pool(pool_id) => {
-                let pool = self.pools.get(pool_id).expect("ERR_NO_POOL");
+                let pool = self.internal_get_pool(pool_id);
                 pool.is_lp_token_registered(&account_id)
             }
         }
@@ -176,6 +220,30 @@ impl SnailSwap {
         );
     }
 
+    /// Transfers the caller's whole balance of LP tokens or deposited tokens,
+    /// does nothing if the balance is zero.
+    #[payable]
+    pub fn mft_transfer_all(
+        &mut self,
+        token_id: String,
+        receiver_id: AccountId,
+        memo: Option<String>,
+    ) {
+        assert_one_yocto();
+        self.assert_contract_running();
+        let sender_id = env::predecessor_account_id();
+        self.assert_receiver_registered(token_id.clone(), &receiver_id);
+        let amount = self.internal_mft_balance(token_id.clone(), &sender_id);
+        if amount == 0 {
+            log!("Nothing to transfer of {} from {}", token_id, sender_id);
+            return;
+        }
+        self.internal_mft_transfer(token_id, &sender_id, &receiver_id, amount, memo);
+    }
+
+    /// Transfers LP tokens or deposited tokens and calls `mft_on_transfer` on the receiver
+    /// with the same token_id and msg, e.g. to stake LP shares of a pool into a farm.
+    /// Unused amount returned by the receiver is refunded to the sender.
     #[payable]
     pub fn mft_transfer_call(
         &mut self,
@@ -188,6 +256,7 @@ impl SnailSwap {
         assert_one_yocto();
         self.assert_contract_running();
         let sender_id = env::predecessor_account_id();
+        self.assert_receiver_registered(token_id.clone(), &receiver_id);
         self.internal_mft_transfer(token_id.clone(), &sender_id, &receiver_id, amount.0, memo);
         assert!(
             env::prepaid_gas() >= GAS_FOR_FT_TRANSFER_CALL,
@@ -253,18 +322,24 @@ impl SnailSwap {
         U128(unused_amount)
     }
 
+    /// Returns metadata of the LP token of the given pool, named after the pool tokens.
+    /// Metadata of other tokens is not known by this contract, fails with ERR_NO_METADATA.
     pub fn mft_metadata(&self, token_id: String) -> FungibleTokenMetadata {
         match parse_token_id(token_id) {
-            TokenOrPool::Pool(pool_id) => FungibleTokenMetadata {
-                spec: "mft-1.0.0".to_string(),
-                name: format!("stableSwap-pool-{}", pool_id),
-                symbol: format!("STABLE-POOL-{}", pool_id),
-                icon: None,
-                reference: None,
-                reference_hash: None,
-                decimals: 24,
-            },
-            TokenOrPool::Token(_token_id) => unimplemented!(),
+            TokenOrPool::Pool(pool_id) => {
+                let pool = self.internal_get_pool(pool_id);
+                let symbols: Vec<&str> = pool.tokens().iter().map(|id| id.as_str()).collect();
+                FungibleTokenMetadata {
+                    spec: "mft-1.0.0".to_string(),
+                    name: format!("Snails LP {}", symbols.join("-")),
+                    symbol: format!("SNAIL-LP-{}", pool_id),
+                    icon: None,
+                    reference: None,
+                    reference_hash: None,
+                    decimals: LP_DECIMALS as u8,
+                }
+            }
+            TokenOrPool::Token(_token_id) => env::panic_str("ERR_NO_METADATA"),
         }
     }
 }
@@ -0,0 +1,257 @@
+//! Implement all the relevant logic for owner of this contract.
+
+use crate::events::{emit_guardians_extended, emit_guardians_removed};
+use crate::pool::PoolV1;
+use crate::utils::{GAS_FOR_FT_BALANCE_OF, GAS_FOR_RETRIEVE_CALLBACK};
+use crate::*;
+use std::collections::HashSet;
+
+/// Contract state before the whitelist and the later fields were added,
+/// read by `migrate`.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub(crate) struct OldSnailSwap {
+    pub owner_id: AccountId,
+    pub pools: Vector<PoolV1>,
+    pub state: RunningState,
+    pub accounts: LookupMap<AccountId, VAccount>,
+}
+
+#[near_bindgen]
+impl SnailSwap {
+    /// Extend whitelisted tokens with new tokens. Only can be called by owner.
+    #[payable]
+    pub fn extend_whitelisted_tokens(&mut self, tokens: Vec<AccountId>) {
+        assert_one_yocto();
+        self.assert_owner();
+        for token in tokens {
+            self.whitelisted_tokens.insert(&token);
+        }
+    }
+
+    /// Remove whitelisted token. Only can be called by owner.
+    #[payable]
+    pub fn remove_whitelisted_tokens(&mut self, tokens: Vec<AccountId>) {
+        assert_one_yocto();
+        self.assert_owner();
+        for token in tokens {
+            let exist = self.whitelisted_tokens.remove(&token);
+            assert!(exist, "ERR_TOKEN_NOT_IN_WHITELIST");
+        }
+    }
+
+    /// Extend guardians with new accounts. Only can be called by owner.
+    #[payable]
+    pub fn extend_guardians(&mut self, guardians: Vec<AccountId>) {
+        assert_one_yocto();
+        self.assert_owner();
+        let added: Vec<AccountId> = guardians
+            .into_iter()
+            .filter(|guardian| self.guardians.insert(guardian))
+            .collect();
+        emit_guardians_extended(&self.owner_id, &added);
+    }
+
+    /// Remove guardians. Only can be called by owner.
+    #[payable]
+    pub fn remove_guardians(&mut self, guardians: Vec<AccountId>) {
+        assert_one_yocto();
+        self.assert_owner();
+        for guardian in guardians.iter() {
+            let exist = self.guardians.remove(guardian);
+            assert!(exist, "ERR_GUARDIAN_NOT_IN_LIST");
+        }
+        emit_guardians_removed(&self.owner_id, &guardians);
+    }
+
+    /// Pause tokens: they can't be deposited, withdrawn, swapped
+    /// or moved in and out of pools. Only can be called by owner.
+    #[payable]
+    pub fn pause_tokens(&mut self, tokens: Vec<AccountId>) {
+        assert_one_yocto();
+        self.assert_owner();
+        for token in tokens {
+            self.paused_tokens.insert(&token);
+        }
+    }
+
+    /// Resume paused tokens. Only can be called by owner.
+    #[payable]
+    pub fn resume_tokens(&mut self, tokens: Vec<AccountId>) {
+        assert_one_yocto();
+        self.assert_owner();
+        for token in tokens {
+            let exist = self.paused_tokens.remove(&token);
+            assert!(exist, "ERR_TOKEN_NOT_PAUSED");
+        }
+    }
+
+    /// Set balance that can be left when unregistering a token. Only can be called by owner.
+    #[payable]
+    pub fn set_dust_tolerance(&mut self, dust_tolerance: U128) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.dust_tolerance = dust_tolerance.0;
+    }
+
+    /// Set the contract wrapping NEAR for `swap_near`, `None` disables it.
+    /// Only can be called by owner.
+    #[payable]
+    pub fn set_wrap_near_id(&mut self, wrap_near_id: Option<AccountId>) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.wrap_near_id = wrap_near_id;
+    }
+
+    /// Migration function from the state without the whitelist and the later fields.
+    /// Tokens of existing pools are whitelisted, accounts registered before
+    /// are not included in `account_count`. Pools get the new parameters at their defaults.
+    /// Managed balances start from the pool amounts and the deposits of the owner and `account_ids`,
+    /// every account holding deposits has to be listed, as accounts can't be enumerated.
+    #[init(ignore_state)]
+    #[private]
+    pub fn migrate(account_ids: Vec<AccountId>) -> Self {
+        let old: OldSnailSwap = env::state_read().expect("ERR_NOT_INITIALIZED");
+        // Same storage prefix, each pool is rewritten in place in the current layout.
+        let old_pools: Vec<PoolV1> = old.pools.iter().collect();
+        let mut pools = Vector::new(StorageKey::Pools);
+        let mut pool_amounts = vec![];
+        for pool in old_pools {
+            let PoolV1::SimplePool(simple_pool) = &pool;
+            pool_amounts.extend(
+                simple_pool
+                    .token_account_ids
+                    .iter()
+                    .cloned()
+                    .zip(simple_pool.amounts.iter().cloned()),
+            );
+            pools.push(&pool.into());
+        }
+        let mut contract = Self {
+            owner_id: old.owner_id,
+            pools,
+            state: old.state,
+            accounts: old.accounts,
+            whitelisted_tokens: UnorderedSet::new(StorageKey::Whitelist),
+            dust_tolerance: 0,
+            guardians: UnorderedSet::new(StorageKey::Guardian),
+            wrap_near_id: None,
+            account_volumes: UnorderedMap::new(StorageKey::AccountVolumes),
+            pending_withdrawals: LookupMap::new(StorageKey::PendingWithdrawals),
+            next_withdrawal_nonce: 0,
+            account_count: 0,
+            paused_tokens: UnorderedSet::new(StorageKey::PausedTokens),
+            managed_balances: LookupMap::new(StorageKey::ManagedBalances),
+        };
+        for pool in contract.pools.iter() {
+            for token in pool.tokens() {
+                contract.whitelisted_tokens.insert(token);
+            }
+        }
+        for (token, amount) in pool_amounts {
+            contract.internal_add_managed(&token, amount);
+        }
+        let mut counted = HashSet::new();
+        for account_id in std::iter::once(contract.owner_id.clone()).chain(account_ids) {
+            if !counted.insert(account_id.clone()) {
+                continue;
+            }
+            if let Some(account) = contract.internal_get_account(&account_id) {
+                for (token, amount) in account.tokens.iter() {
+                    contract.internal_add_managed(&token, amount);
+                }
+            }
+        }
+        contract
+    }
+
+    /// Sends `amount` of `token_id` held by the contract beyond what deposits and pools account for,
+    /// e.g. tokens sent with `ft_transfer` instead of `ft_transfer_call`. Only can be called by owner.
+    /// Tokens of a transfer whose `ft_on_transfer` has not run yet look unmanaged,
+    /// so the amount should be checked against the balance before calling.
+    /// Tokens without a managed balance, never deposited or not counted by `migrate`, are refused.
+    #[payable]
+    pub fn retrieve_unmanaged_tokens(
+        &mut self,
+        token_id: AccountId,
+        amount: U128,
+        receiver_id: AccountId,
+    ) -> Promise {
+        assert_one_yocto();
+        self.assert_owner();
+        assert!(amount.0 > 0, "ERR_ZERO_AMOUNT");
+        assert!(
+            self.managed_balances.contains_key(&token_id),
+            "ERR_TOKEN_NOT_MANAGED"
+        );
+        ext_fungible_token::ft_balance_of(
+            env::current_account_id(),
+            token_id.clone(),
+            0,
+            GAS_FOR_FT_BALANCE_OF,
+        )
+        .then(ext_self::exchange_callback_retrieve_unmanaged(
+            token_id,
+            amount,
+            receiver_id,
+            env::current_account_id(),
+            0,
+            GAS_FOR_RETRIEVE_CALLBACK,
+        ))
+    }
+
+    /// Sends the tokens if the contract balance covers them on top of the managed amount.
+    /// The amount counts as managed until the transfer settles, so it can't be retrieved twice.
+    #[private]
+    pub fn exchange_callback_retrieve_unmanaged(
+        &mut self,
+        token_id: AccountId,
+        amount: U128,
+        receiver_id: AccountId,
+    ) -> Promise {
+        let balance = match env::promise_result(0) {
+            PromiseResult::Successful(value) => {
+                near_sdk::serde_json::from_slice::<U128>(&value)
+                    .expect("ERR_WRONG_BALANCE_FORMAT")
+                    .0
+            }
+            _ => env::panic_str("ERR_BALANCE_OF_FAILED"),
+        };
+        let managed = self
+            .managed_balances
+            .get(&token_id)
+            .expect("ERR_TOKEN_NOT_MANAGED");
+        assert!(
+            amount.0 <= balance.saturating_sub(managed),
+            "ERR_NOT_ENOUGH_UNMANAGED"
+        );
+        self.internal_add_managed(&token_id, amount.0);
+        log!(
+            "Retrieve {} unmanaged {} to {}",
+            amount.0,
+            token_id,
+            receiver_id
+        );
+        ext_fungible_token::ft_transfer(
+            receiver_id,
+            amount,
+            None,
+            token_id.clone(),
+            1,
+            GAS_FOR_FT_TRANSFER,
+        )
+        .then(ext_self::exchange_callback_post_retrieve(
+            token_id,
+            amount,
+            env::current_account_id(),
+            0,
+            GAS_FOR_RESOLVE_TRANSFER,
+        ))
+    }
+
+    /// Releases the amount reserved by `exchange_callback_retrieve_unmanaged`,
+    /// it left the contract or is unmanaged again if the transfer failed.
+    #[private]
+    pub fn exchange_callback_post_retrieve(&mut self, token_id: AccountId, amount: U128) {
+        self.internal_sub_managed(&token_id, amount.0);
+    }
+}
@@ -1,208 +1,600 @@
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
 use near_sdk::{AccountId, Balance};
 
+use crate::bigint::U256;
 use crate::fees::Fees;
-use crate::simple_pool::SimplePool;
+use crate::meta_pool::MetaPool;
+use crate::simple_pool::{SimplePool, SimplePoolV1};
+use crate::utils::{BPS_DENOMINATOR, PRECISION};
+
+/// Behaviour shared by every kind of pool.
+/// A new pool type is added by implementing this trait and adding a variant to `Pool`.
+pub trait PoolTrait {
+    /// Adds liquidity into the pool.
+    /// Updates amounts to amount kept in the pool.
+    fn add_liquidity(
+        &mut self,
+        sender_id: &AccountId,
+        amounts: &Vec<Balance>,
+    ) -> (Balance, Vec<Balance>);
+
+    /// Removes liquidity from the pool.
+    fn remove_liquidity(
+        &mut self,
+        sender_id: &AccountId,
+        shares: Balance,
+        min_amounts: Vec<Balance>,
+    ) -> (Vec<Balance>, Vec<Balance>);
+
+    fn remove_liquidity_imbalance(
+        &mut self,
+        sender_id: &AccountId,
+        remove_coin_amount: &Vec<Balance>,
+    ) -> (u128, Vec<Balance>);
+
+    fn remove_liquidity_one_coin(
+        &mut self,
+        sender_id: &AccountId,
+        token_out: &AccountId,
+        remove_lp_amount: Balance,
+        min_amount: Balance,
+    ) -> (Vec<Balance>, Vec<Balance>);
+
+    fn get_virtual_price(&self) -> u128;
+
+    /// Returns the marginal price of `token_in` in `token_out` before fees, scaled by PRECISION.
+    fn get_spot_price(&self, token_in: &AccountId, token_out: &AccountId) -> u128;
+
+    fn get_amp_factor(&self) -> u128;
+
+    /// Stores fees to be applied once `apply_new_fee_ts` has passed.
+    fn commit_new_fees(&mut self, fees: Fees, apply_new_fee_ts: u64);
+
+    /// Replaces fees by the committed ones.
+    fn apply_new_fees(&mut self);
+
+    fn set_amp_params(
+        &mut self,
+        initial_amp_factor: u64,
+        target_amp_factor: u64,
+        start_ramp_ts: u64,
+        stop_ramp_ts: u64,
+    );
+
+    /// Returns true while A moves from the initial to the target amp factor.
+    fn is_ramping(&self) -> bool;
+
+    /// Sets whether imbalanced liquidity changes are rejected while A is ramping.
+    fn set_block_liquidity_during_ramp(&mut self, block: bool);
+
+    /// Sets whether the solvers stop within a fraction of one unit of the coin
+    /// with the fewest decimals instead of within 1 in 24 decimals.
+    fn set_rate_scaled_epsilon(&mut self, enabled: bool);
+
+    /// Sets the cap on the shares a single account can hold, None for unlimited.
+    fn set_max_share_per_account(&mut self, max_share: Option<Balance>);
+
+    /// Sets the minimal amount of LP a single one-coin removal has to burn.
+    fn set_min_one_coin_burn(&mut self, min_burn: Balance);
+
+    /// Sets the part of the trade fee, in bps, given back to swappers holding shares.
+    fn set_lp_fee_discount(&mut self, discount_bps: u32);
+
+    /// Sets the cap on the balance of each token, 0 for unlimited. None for no caps.
+    fn set_balance_caps(&mut self, caps: Option<Vec<Balance>>);
+
+    /// Returns the part of `total_fee` given back to `account_id` for holding shares of the pool.
+    fn lp_fee_discount(&self, account_id: &AccountId, total_fee: Balance) -> Balance;
+
+    fn is_shutdown(&self) -> bool;
+
+    /// Shuts the pool down, only proportional removal of liquidity is allowed from then on.
+    fn shutdown(&mut self);
+
+    /// Returns which tokens are in the pool.
+    fn tokens(&self) -> &[AccountId];
+
+    /// Returns given pool's total fee.
+    fn get_fee(&self) -> Vec<u128>;
+
+    fn get_admin_fee(&self) -> Vec<u128>;
+
+    fn fees_info(&self) -> Fees;
+
+    /// Returns how many tokens will one receive swapping given amount of token_in for token_out.
+    fn get_return(
+        &self,
+        token_in: &AccountId,
+        amount_in: Balance,
+        token_out: &AccountId,
+    ) -> Balance;
+
+    /// Returns the maximum amount of token_out that can be obtained from the pool.
+    fn get_max_output(&self, token_out: &AccountId) -> Balance;
+
+    /// Returns multipliers bringing each token amount to 24 decimals,
+    /// the stored rates if set, otherwise from the token decimals.
+    fn get_rates(&self) -> Vec<u128>;
+
+    /// Returns the denominator of the rates, 1 unless a rate is fractional.
+    fn get_rate_denominator(&self) -> u128;
+
+    /// Sets the account allowed to update the rates besides the owner.
+    fn set_rate_provider(&mut self, rate_provider: Option<AccountId>);
+
+    fn rate_provider(&self) -> Option<AccountId>;
+
+    /// Stores rates used instead of the decimal scaling.
+    fn set_rates(&mut self, rates: Vec<u128>);
+
+    fn share_total_balance(&self) -> Balance;
+
+    /// Returns number of accounts holding a non-zero amount of shares.
+    fn lp_holder_count(&self) -> u64;
+
+    fn share_balance_of(&self, account_id: &AccountId) -> Balance;
+
+    /// Swaps given number of token_in for token_out and returns received amount,
+    /// admin fee and total fee.
+    fn swap(
+        &mut self,
+        token_in: &AccountId,
+        amount_in: Balance,
+        token_out: &AccountId,
+        min_amount_out: Balance,
+    ) -> (Balance, Balance, Balance);
+
+    fn share_transfer(&mut self, sender_id: &AccountId, receiver_id: &AccountId, amount: u128);
+
+    fn share_register(&mut self, account_id: &AccountId);
+
+    /// Unregisters account with zero shares.
+    fn share_unregister(&mut self, account_id: &AccountId);
+
+    fn is_lp_token_registered(&self, account_id: &AccountId) -> bool;
+
+    fn try_remove_liquidity_one_coin(
+        &self,
+        token_out: &AccountId,
+        remove_lp_amount: Balance,
+    ) -> Balance;
+
+    fn try_remove_liquidity_imbalance(&self, remove_coin_amount: &Vec<Balance>) -> u128;
+
+    fn try_remove_liquidity(&self, shares: Balance) -> Vec<Balance>;
+
+    fn try_add_liquidity(&self, deposit_amounts: &Vec<Balance>) -> Balance;
+}
+
 /// Generic Pool, providing wrapper around different implementations of swap pools.
 /// Allows to add new types of pools just by adding extra item in the enum without needing to migrate the storage.
 #[derive(BorshSerialize, BorshDeserialize)]
 pub enum Pool {
     SimplePool(SimplePool),
+    MetaPool(MetaPool),
+}
+
+/// Pool before metapools and the later simple pool parameters were added, read by `migrate`.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub enum PoolV1 {
+    SimplePool(SimplePoolV1),
+}
+
+impl From<PoolV1> for Pool {
+    fn from(pool: PoolV1) -> Self {
+        match pool {
+            PoolV1::SimplePool(pool) => Pool::SimplePool(pool.into()),
+        }
+    }
 }
 
 impl Pool {
-    /// Adds liquidity into underlying pool.
-    /// Updates amounts to amount kept in the pool.
-    pub fn add_liquidity(
+    /// Returns the underlying pool as a trait object.
+    /// The coin/LP leg of a metapool is a simple pool.
+    pub fn as_trait(&self) -> &dyn PoolTrait {
+        match self {
+            Pool::SimplePool(pool) => pool,
+            Pool::MetaPool(pool) => &pool.pool,
+        }
+    }
+
+    /// Returns the underlying pool as a mutable trait object.
+    pub fn as_trait_mut(&mut self) -> &mut dyn PoolTrait {
+        match self {
+            Pool::SimplePool(pool) => pool,
+            Pool::MetaPool(pool) => &mut pool.pool,
+        }
+    }
+
+    /// Returns the kind of the pool.
+    pub fn kind(&self) -> String {
+        match self {
+            Pool::SimplePool(_) => "SIMPLE_POOL".to_string(),
+            Pool::MetaPool(_) => "META_POOL".to_string(),
+        }
+    }
+
+    /// Returns how much `token_out` removing `remove_lp_amount` of LP for it delivers,
+    /// the coin/LP leg for a metapool.
+    pub fn calc_remove_liquidity_one_coin(
+        &self,
+        token_out: &AccountId,
+        remove_lp_amount: Balance,
+    ) -> Balance {
+        match self {
+            Pool::SimplePool(pool) => {
+                pool.calc_remove_liquidity_one_coin(token_out, remove_lp_amount)
+            }
+            Pool::MetaPool(pool) => pool
+                .pool
+                .calc_remove_liquidity_one_coin(token_out, remove_lp_amount),
+        }
+    }
+
+    /// Panics if `min_virtual_price` is given and the virtual price is below it,
+    /// so LPs can refuse to exit a pool manipulated down.
+    pub fn assert_min_virtual_price(&self, min_virtual_price: Option<U128>) {
+        if let Some(min_virtual_price) = min_virtual_price {
+            assert!(
+                self.get_virtual_price() >= min_virtual_price.0,
+                "ERR_VP_BELOW_MIN"
+            );
+        }
+    }
+
+    /// Returns how much worse, in bps, getting `amount_out` for `amount_in` is
+    /// than the spot rate, quoted swapping at most one token of `token_in`.
+    /// Both rates include the fee, must be called on the pool before the swap.
+    pub fn get_price_impact_bps(
+        &self,
+        token_in: &AccountId,
+        amount_in: Balance,
+        token_out: &AccountId,
+        amount_out: Balance,
+    ) -> u128 {
+        let index = self
+            .tokens()
+            .iter()
+            .position(|id| id == token_in)
+            .expect("ERR_MISSING_TOKEN");
+        let one_token = std::cmp::max(PRECISION / self.get_rates()[index], 1);
+        let probe_in = std::cmp::min(amount_in, one_token);
+        let probe_out = self.get_return(token_in, probe_in, token_out);
+        // amount_out / amount_in against probe_out / probe_in
+        let executed = U256::from(amount_out) * U256::from(probe_in);
+        let spot = U256::from(probe_out) * U256::from(amount_in);
+        if executed >= spot {
+            0
+        } else {
+            ((spot - executed) * U256::from(BPS_DENOMINATOR) / spot).as_u128()
+        }
+    }
+
+    /// Panics if `max_price_impact_bps` is given and the price impact of the swap is above it.
+    pub fn assert_max_price_impact(
+        &self,
+        token_in: &AccountId,
+        amount_in: Balance,
+        token_out: &AccountId,
+        amount_out: Balance,
+        max_price_impact_bps: Option<u32>,
+    ) {
+        if let Some(max_price_impact_bps) = max_price_impact_bps {
+            assert!(
+                self.get_price_impact_bps(token_in, amount_in, token_out, amount_out)
+                    <= max_price_impact_bps as u128,
+                "ERR_PRICE_IMPACT_TOO_HIGH"
+            );
+        }
+    }
+}
+
+impl PoolTrait for Pool {
+    fn add_liquidity(
         &mut self,
         sender_id: &AccountId,
         amounts: &Vec<Balance>,
     ) -> (Balance, Vec<Balance>) {
-        match self {
-            Pool::SimplePool(pool) => pool.add_liquidity(sender_id, amounts),
-        }
+        self.as_trait_mut().add_liquidity(sender_id, amounts)
     }
 
-    /// Removes liquidity from underlying pool.
-    pub fn remove_liquidity(
+    fn remove_liquidity(
         &mut self,
         sender_id: &AccountId,
         shares: Balance,
         min_amounts: Vec<Balance>,
     ) -> (Vec<Balance>, Vec<Balance>) {
-        match self {
-            Pool::SimplePool(pool) => pool.remove_liquidity(sender_id, shares, min_amounts),
-        }
+        self.as_trait_mut()
+            .remove_liquidity(sender_id, shares, min_amounts)
     }
 
-    pub fn remove_liquidity_imbalance(
+    fn remove_liquidity_imbalance(
         &mut self,
         sender_id: &AccountId,
         remove_coin_amount: &Vec<Balance>,
     ) -> (u128, Vec<Balance>) {
-        match self {
-            Pool::SimplePool(pool) => {
-                pool.remove_liquidity_imbalance(sender_id, remove_coin_amount)
-            }
-        }
+        self.as_trait_mut()
+            .remove_liquidity_imbalance(sender_id, remove_coin_amount)
     }
 
-    pub fn remove_liquidity_one_coin(
+    fn remove_liquidity_one_coin(
         &mut self,
         sender_id: &AccountId,
-        token_out: AccountId,
+        token_out: &AccountId,
         remove_lp_amount: Balance,
         min_amount: Balance,
     ) -> (Vec<Balance>, Vec<Balance>) {
-        match self {
-            Pool::SimplePool(pool) => {
-                pool.remove_liquidity_one_coin(sender_id, &token_out, remove_lp_amount, min_amount)
-            }
-        }
+        self.as_trait_mut().remove_liquidity_one_coin(
+            sender_id,
+            token_out,
+            remove_lp_amount,
+            min_amount,
+        )
     }
 
-    pub fn get_virtual_price(&self) -> u128 {
-        match self {
-            Pool::SimplePool(pool) => pool.get_virtual_price(),
-        }
+    fn get_virtual_price(&self) -> u128 {
+        self.as_trait().get_virtual_price()
     }
 
-    pub fn get_amp_factor(&self) -> u128 {
-        match self {
-            Pool::SimplePool(pool) => pool.get_amp_factor(),
-        }
+    fn get_spot_price(&self, token_in: &AccountId, token_out: &AccountId) -> u128 {
+        self.as_trait().get_spot_price(token_in, token_out)
     }
 
-    pub fn change_fees_setting(&mut self, fees: Fees) {
-        match self {
-            Pool::SimplePool(pool) => pool.change_fees_setting(fees),
-        }
+    fn get_amp_factor(&self) -> u128 {
+        self.as_trait().get_amp_factor()
+    }
+
+    fn commit_new_fees(&mut self, fees: Fees, apply_new_fee_ts: u64) {
+        self.as_trait_mut().commit_new_fees(fees, apply_new_fee_ts)
+    }
+
+    fn apply_new_fees(&mut self) {
+        self.as_trait_mut().apply_new_fees()
     }
 
-    pub fn set_amp_params(
+    fn set_amp_params(
         &mut self,
         initial_amp_factor: u64,
         target_amp_factor: u64,
         start_ramp_ts: u64,
         stop_ramp_ts: u64,
     ) {
-        match self {
-            Pool::SimplePool(pool) => pool.set_amp_params(
-                initial_amp_factor,
-                target_amp_factor,
-                start_ramp_ts,
-                stop_ramp_ts,
-            ),
-        }
+        self.as_trait_mut().set_amp_params(
+            initial_amp_factor,
+            target_amp_factor,
+            start_ramp_ts,
+            stop_ramp_ts,
+        )
     }
 
-    /// Returns which tokens are in the underlying pool.
-    pub fn tokens(&self) -> &[AccountId] {
-        match self {
-            Pool::SimplePool(pool) => pool.tokens(),
-        }
+    fn is_ramping(&self) -> bool {
+        self.as_trait().is_ramping()
     }
 
-    /// Returns given pool's total fee.
-    pub fn get_fee(&self) -> Vec<u128> {
-        match self {
-            Pool::SimplePool(pool) => pool.get_fee(),
-        }
+    fn set_block_liquidity_during_ramp(&mut self, block: bool) {
+        self.as_trait_mut().set_block_liquidity_during_ramp(block)
     }
 
-    pub fn get_admin_fee(&self) -> Vec<u128> {
-        match self {
-            Pool::SimplePool(pool) => pool.get_admin_fee(),
-        }
+    fn set_rate_scaled_epsilon(&mut self, enabled: bool) {
+        self.as_trait_mut().set_rate_scaled_epsilon(enabled)
     }
 
-    pub fn fees_info(&self) -> Fees {
-        match self {
-            Pool::SimplePool(pool) => pool.fees_info(),
-        }
+    fn set_max_share_per_account(&mut self, max_share: Option<Balance>) {
+        self.as_trait_mut().set_max_share_per_account(max_share)
     }
 
-    /// Returns how many tokens will one receive swapping given amount of token_in for token_out.
-    pub fn get_return(
+    fn set_min_one_coin_burn(&mut self, min_burn: Balance) {
+        self.as_trait_mut().set_min_one_coin_burn(min_burn)
+    }
+
+    fn set_lp_fee_discount(&mut self, discount_bps: u32) {
+        self.as_trait_mut().set_lp_fee_discount(discount_bps)
+    }
+
+    fn set_balance_caps(&mut self, caps: Option<Vec<Balance>>) {
+        self.as_trait_mut().set_balance_caps(caps)
+    }
+
+    fn lp_fee_discount(&self, account_id: &AccountId, total_fee: Balance) -> Balance {
+        self.as_trait().lp_fee_discount(account_id, total_fee)
+    }
+
+    fn is_shutdown(&self) -> bool {
+        self.as_trait().is_shutdown()
+    }
+
+    fn shutdown(&mut self) {
+        self.as_trait_mut().shutdown()
+    }
+
+    fn tokens(&self) -> &[AccountId] {
+        self.as_trait().tokens()
+    }
+
+    fn get_fee(&self) -> Vec<u128> {
+        self.as_trait().get_fee()
+    }
+
+    fn get_admin_fee(&self) -> Vec<u128> {
+        self.as_trait().get_admin_fee()
+    }
+
+    fn fees_info(&self) -> Fees {
+        self.as_trait().fees_info()
+    }
+
+    fn get_return(
         &self,
         token_in: &AccountId,
         amount_in: Balance,
         token_out: &AccountId,
     ) -> Balance {
-        match self {
-            Pool::SimplePool(pool) => pool.get_return(token_in, amount_in, token_out),
-        }
+        self.as_trait().get_return(token_in, amount_in, token_out)
     }
 
-    pub fn share_total_balance(&self) -> Balance {
-        match self {
-            Pool::SimplePool(pool) => pool.share_total_balance(),
-        }
+    fn get_max_output(&self, token_out: &AccountId) -> Balance {
+        self.as_trait().get_max_output(token_out)
     }
 
-    pub fn share_balances(&self, account_id: &AccountId) -> Balance {
-        match self {
-            Pool::SimplePool(pool) => pool.share_balance_of(account_id),
-        }
+    fn get_rates(&self) -> Vec<u128> {
+        self.as_trait().get_rates()
+    }
+
+    fn get_rate_denominator(&self) -> u128 {
+        self.as_trait().get_rate_denominator()
+    }
+
+    fn set_rate_provider(&mut self, rate_provider: Option<AccountId>) {
+        self.as_trait_mut().set_rate_provider(rate_provider)
+    }
+
+    fn rate_provider(&self) -> Option<AccountId> {
+        self.as_trait().rate_provider()
     }
 
-    /// Swaps given number of token_in for token_out and returns received amount.
-    pub fn swap(
+    fn set_rates(&mut self, rates: Vec<u128>) {
+        self.as_trait_mut().set_rates(rates)
+    }
+
+    fn share_total_balance(&self) -> Balance {
+        self.as_trait().share_total_balance()
+    }
+
+    fn lp_holder_count(&self) -> u64 {
+        self.as_trait().lp_holder_count()
+    }
+
+    fn share_balance_of(&self, account_id: &AccountId) -> Balance {
+        self.as_trait().share_balance_of(account_id)
+    }
+
+    fn swap(
         &mut self,
         token_in: &AccountId,
         amount_in: Balance,
         token_out: &AccountId,
         min_amount_out: Balance,
-    ) -> (Balance, Balance) {
-        match self {
-            Pool::SimplePool(pool) => pool.swap(token_in, amount_in, token_out, min_amount_out),
-        }
+    ) -> (Balance, Balance, Balance) {
+        self.as_trait_mut()
+            .swap(token_in, amount_in, token_out, min_amount_out)
     }
-    pub fn share_transfer(&mut self, sender_id: &AccountId, receiver_id: &AccountId, amount: u128) {
-        match self {
-            Pool::SimplePool(pool) => pool.share_transfer(sender_id, receiver_id, amount),
-        }
+
+    fn share_transfer(&mut self, sender_id: &AccountId, receiver_id: &AccountId, amount: u128) {
+        self.as_trait_mut()
+            .share_transfer(sender_id, receiver_id, amount)
     }
 
-    pub fn share_register(&mut self, account_id: &AccountId) {
-        match self {
-            Pool::SimplePool(pool) => pool.share_register(account_id),
-        }
+    fn share_register(&mut self, account_id: &AccountId) {
+        self.as_trait_mut().share_register(account_id)
     }
 
-    pub fn is_lp_token_registered(&self, account_id: &AccountId) -> bool {
-        match self {
-            Pool::SimplePool(pool) => pool.is_lp_token_registered(account_id),
-        }
+    fn share_unregister(&mut self, account_id: &AccountId) {
+        self.as_trait_mut().share_unregister(account_id)
     }
 
-    pub fn try_remove_liquidity_one_coin(
+    fn is_lp_token_registered(&self, account_id: &AccountId) -> bool {
+        self.as_trait().is_lp_token_registered(account_id)
+    }
+
+    fn try_remove_liquidity_one_coin(
         &self,
         token_out: &AccountId,
         remove_lp_amount: Balance,
     ) -> Balance {
-        match self {
-            Pool::SimplePool(pool) => {
-                pool.try_remove_liquidity_one_coin(token_out, remove_lp_amount)
-            }
-        }
+        self.as_trait()
+            .try_remove_liquidity_one_coin(token_out, remove_lp_amount)
     }
 
-    pub fn try_remove_liquidity_imbalance(&self, remove_coin_amount: &Vec<Balance>) -> u128 {
-        match self {
-            Pool::SimplePool(pool) => pool.try_remove_liquidity_imbalance(remove_coin_amount),
-        }
+    fn try_remove_liquidity_imbalance(&self, remove_coin_amount: &Vec<Balance>) -> u128 {
+        self.as_trait()
+            .try_remove_liquidity_imbalance(remove_coin_amount)
     }
 
-    pub fn try_remove_liquidity(&self, shares: Balance) -> Vec<Balance> {
-        match self {
-            Pool::SimplePool(pool) => pool.try_remove_liquidity(shares),
+    fn try_remove_liquidity(&self, shares: Balance) -> Vec<Balance> {
+        self.as_trait().try_remove_liquidity(shares)
+    }
+
+    fn try_add_liquidity(&self, deposit_amounts: &Vec<Balance>) -> Balance {
+        self.as_trait().try_add_liquidity(deposit_amounts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    fn setup_fee() -> Fees {
+        Fees {
+            admin_trade_fee_numerator: 50,
+            admin_trade_fee_denominator: 100,
+            admin_withdraw_fee_numerator: 40,
+            admin_withdraw_fee_denominator: 100,
+            trade_fee_numerator: 3,
+            trade_fee_denominator: 1000,
+            withdraw_fee_numerator: 4,
+            withdraw_fee_denominator: 1000,
+            referral_fee_numerator: 0,
+            referral_fee_denominator: 1,
+            lp_fee_share_numerator: 0,
+            lp_fee_share_denominator: 1,
         }
     }
 
-    pub fn try_add_liquidity(&self, deposit_amounts: &Vec<Balance>) -> Balance {
-        match self {
-            Pool::SimplePool(pool) => pool.try_add_liquidity(deposit_amounts),
+    #[test]
+    fn test_trait_object_dispatch() {
+        testing_env!(VMContextBuilder::new().build());
+        let fees = setup_fee();
+        let amounts = vec![10_000_000, 20_000_000];
+        let mut simple_pool = SimplePool::new(
+            0,
+            100,
+            100,
+            0,
+            0,
+            fees,
+            vec![accounts(1), accounts(2)],
+            vec![6, 6],
+        );
+        simple_pool.add_liquidity(&accounts(3), &amounts);
+        let mut pool = Pool::SimplePool(simple_pool);
+
+        let direct = match &pool {
+            Pool::SimplePool(pool) => pool,
+            _ => unreachable!(),
+        };
+        let dispatched = pool.as_trait();
+        assert!(std::ptr::eq(
+            dispatched as *const dyn PoolTrait as *const u8,
+            direct as *const SimplePool as *const u8
+        ));
+        assert_eq!(dispatched.tokens(), direct.tokens());
+        assert_eq!(dispatched.fees_info(), direct.fees_info());
+        assert_eq!(dispatched.get_amp_factor(), direct.get_amp_factor());
+        assert_eq!(dispatched.get_virtual_price(), direct.get_virtual_price());
+        assert_eq!(
+            dispatched.share_total_balance(),
+            direct.share_total_balance()
+        );
+        assert_eq!(
+            dispatched.share_balance_of(&accounts(3)),
+            direct.share_balance_of(&accounts(3))
+        );
+        assert_eq!(
+            dispatched.get_return(&accounts(1), 1_000_000, &accounts(2)),
+            direct.get_return(&accounts(1), 1_000_000, &accounts(2))
+        );
+        assert_eq!(
+            dispatched.try_remove_liquidity(1_000),
+            direct.try_remove_liquidity(1_000)
+        );
+
+        pool.as_trait_mut().share_register(&accounts(4));
+        match &pool {
+            Pool::SimplePool(pool) => assert!(pool.is_lp_token_registered(&accounts(4))),
+            _ => unreachable!(),
         }
     }
 }
@@ -3,12 +3,22 @@ use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
 use near_sdk::collections::LookupMap;
 use near_sdk::{env, AccountId, Balance};
 
-use crate::error::{LP_ALREADY_REGISTERED, LP_NOT_REGISTERED, ZERO_SHARES};
+use crate::error::{LP_ALREADY_REGISTERED, LP_NOT_REGISTERED, NON_ZERO_SHARES, ZERO_SHARES};
 
-use crate::utils::{add_to_collection, SwapVolume};
+use crate::utils::{
+    add_to_collection, SwapVolume, BPS_DENOMINATOR, LOCKED_INITIAL_SHARES, MIN_INITIAL_SHARES,
+    MIN_ONE_COIN_BURN, MIN_RESERVE, PRECISION_DECIMALS,
+};
 
-use crate::fees::Fees;
-use crate::snails::{PoolStatus, SnailStableSwap};
+use crate::fees::{Fees, FeesV1};
+use crate::pool::PoolTrait;
+use crate::snails::{MathError, PoolStatus, SnailStableSwap};
+
+/// Panics with the failed operation followed by the math error behind it,
+/// e.g. "ERR_SWAP_FAILED: ERR_MATH_INSUFFICIENT_BALANCE".
+fn math_panic(context: &str, err: MathError) -> ! {
+    env::panic_str(format!("{}: {}", context, err).as_str())
+}
 
 /// Implementation of simple pool, that maintains constant product between balances of all the tokens.
 /// Similar in design to "Uniswap".
@@ -28,6 +38,8 @@ pub struct SimplePool {
     pub shares: LookupMap<AccountId, Balance>,
     /// Total number of shares.
     pub shares_total_supply: Balance,
+    /// Number of accounts holding a non-zero amount of shares.
+    pub lp_holder_count: u64,
 
     /// Initial amplification coefficient (A)
     pub initial_amp_factor: u64,
@@ -43,14 +55,114 @@ pub struct SimplePool {
     pub apply_new_fee_ts: u64,
 
     pub new_fees: Fees,
+
+    /// Rejects imbalanced liquidity changes while A is ramping.
+    pub block_liquidity_during_ramp: bool,
+
+    /// Cap on the shares a single account can hold, None for unlimited.
+    pub max_share_per_account: Option<Balance>,
+
+    /// Minimal amount of LP a single one-coin removal has to burn.
+    pub min_one_coin_burn: Balance,
+
+    /// Part of the trade fee, in bps, given back to swappers holding shares of the pool.
+    pub lp_fee_discount_bps: u32,
+
+    /// Cap on the balance of each token, 0 for unlimited. None for no caps.
+    pub balance_caps: Option<Vec<Balance>>,
+
+    /// Account allowed to update the rates besides the owner, e.g. a price feed.
+    pub rate_provider: Option<AccountId>,
+    /// Rates bringing each token amount to 24 decimals at its exchange rate,
+    /// used instead of the decimal scaling when set.
+    pub rates: Option<Vec<u128>>,
+    /// Denominator of `rates`, 1 unless a rate is fractional such as the base LP of a metapool.
+    pub rate_denominator: u128,
+    /// Whether the D and y solvers stop within a fraction of one unit of the coin with
+    /// the fewest decimals instead of within 1 in 24 decimals.
+    pub rate_scaled_epsilon: bool,
+
+    /// Once shut down, only fee-free proportional removal of liquidity is allowed.
+    pub shutdown: bool,
+
+    /// Unix timestamp in seconds the pool was created at.
+    pub created_at: u64,
+}
+
+/// Simple pool before the pool parameters after `new_fees` were added, read by `migrate`.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct SimplePoolV1 {
+    pub token_account_ids: Vec<AccountId>,
+    pub token_decimals: Vec<u64>,
+    pub amounts: Vec<Balance>,
+    pub volumes: Vec<SwapVolume>,
+    pub total_fees: Vec<Balance>,
+    pub admin_fees: Vec<Balance>,
+    pub shares: LookupMap<AccountId, Balance>,
+    pub shares_total_supply: Balance,
+    pub initial_amp_factor: u64,
+    pub target_amp_factor: u64,
+    pub start_ramp_ts: u64,
+    pub stop_ramp_ts: u64,
+    pub fees: FeesV1,
+    pub apply_new_fee_ts: u64,
+    pub new_fees: FeesV1,
+}
+
+impl From<SimplePoolV1> for SimplePool {
+    /// Holders were not counted before, `lp_holder_count` starts at 0 and never goes below it.
+    fn from(pool: SimplePoolV1) -> Self {
+        Self {
+            token_account_ids: pool.token_account_ids,
+            token_decimals: pool.token_decimals,
+            amounts: pool.amounts,
+            volumes: pool.volumes,
+            total_fees: pool.total_fees,
+            admin_fees: pool.admin_fees,
+            shares: pool.shares,
+            shares_total_supply: pool.shares_total_supply,
+            lp_holder_count: 0,
+            initial_amp_factor: pool.initial_amp_factor,
+            target_amp_factor: pool.target_amp_factor,
+            start_ramp_ts: pool.start_ramp_ts,
+            stop_ramp_ts: pool.stop_ramp_ts,
+            fees: pool.fees.into(),
+            apply_new_fee_ts: pool.apply_new_fee_ts,
+            new_fees: pool.new_fees.into(),
+            block_liquidity_during_ramp: false,
+            max_share_per_account: None,
+            min_one_coin_burn: MIN_ONE_COIN_BURN,
+            lp_fee_discount_bps: 0,
+            balance_caps: None,
+            rate_provider: None,
+            rates: None,
+            rate_denominator: 1,
+            rate_scaled_epsilon: false,
+            shutdown: false,
+            created_at: 0,
+        }
+    }
 }
 
 pub fn decimals_to_rates(vector: &Vec<u64>) -> Vec<u128> {
+    decimals_to_rates_with_precision(vector, PRECISION_DECIMALS)
+}
+
+/// Rates bringing amounts of the given decimals to `precision_decimals` decimals.
+/// A token with `precision_decimals` decimals gets rate 1,
+/// panics with ERR_DECIMALS_TOO_HIGH if any has more.
+pub(crate) fn decimals_to_rates_with_precision(
+    vector: &Vec<u64>,
+    precision_decimals: u32,
+) -> Vec<u128> {
     let mut arr = vec![0u128; vector.len()];
     let base: u128 = 10; // an explicit type is required
     for (place, element) in arr.iter_mut().zip(vector.iter()) {
-        assert!(24 >= *element, "invalid rates number");
-        *place = base.pow(24 as u32 - *element as u32) as u128;
+        assert!(
+            precision_decimals as u64 >= *element,
+            "ERR_DECIMALS_TOO_HIGH"
+        );
+        *place = base.pow(precision_decimals - *element as u32) as u128;
     }
     arr
 }
@@ -76,6 +188,7 @@ impl SimplePool {
             admin_fees: vec![0u128; token_account_ids.len()],
             shares: LookupMap::new(StorageKey::Shares { pool_id: id }),
             shares_total_supply: 0,
+            lp_holder_count: 0,
             initial_amp_factor: initial_amp_factor,
             target_amp_factor: target_amp_factor,
             start_ramp_ts: start_ramp_ts,
@@ -83,10 +196,367 @@ impl SimplePool {
             fees: fees,
             apply_new_fee_ts: 0,
             new_fees: fees,
+            block_liquidity_during_ramp: false,
+            max_share_per_account: None,
+            min_one_coin_burn: MIN_ONE_COIN_BURN,
+            lp_fee_discount_bps: 0,
+            balance_caps: None,
+            rate_provider: None,
+            rates: None,
+            rate_denominator: 1,
+            rate_scaled_epsilon: false,
+            shutdown: false,
+            created_at: env::block_timestamp() / 1_000_000_000,
+        }
+    }
+
+    pub fn coin_num(&self) -> usize {
+        self.token_account_ids.len()
+    }
+
+    /// Returns the invariant calculator of the pool at the given time and rates.
+    fn invariant(&self, unix_timestamp_s: u64, rates: Vec<u128>) -> SnailStableSwap {
+        let invariant = SnailStableSwap::new(
+            self.initial_amp_factor,
+            self.target_amp_factor,
+            unix_timestamp_s,
+            self.start_ramp_ts,
+            self.stop_ramp_ts,
+            rates,
+        )
+        .with_rate_denominator(self.rate_denominator);
+        if self.rate_scaled_epsilon {
+            invariant.with_rate_scaled_epsilon()
+        } else {
+            invariant
+        }
+    }
+
+    /// Returns the LP share of the given admin fee, which stays in the pool.
+    fn lp_fee_share(&self, admin_fee: Balance) -> Balance {
+        self.fees.lp_fee_share(admin_fee).expect("ERR_LP_FEE_SHARE")
+    }
+
+    /// Leaves the LP share of the admin fees in the pool, only the rest goes to the owner.
+    fn retain_lp_fee_share(&self, poolstatus: &mut PoolStatus) {
+        for i in 0..poolstatus.admin_fee_amount.len() {
+            let lp_share = self.lp_fee_share(poolstatus.admin_fee_amount[i]);
+            poolstatus.new_balances[i] = poolstatus.new_balances[i].checked_add(lp_share).unwrap();
+            poolstatus.admin_fee_amount[i] -= lp_share;
+        }
+    }
+
+    fn assert_not_shutdown(&self) {
+        assert!(!self.shutdown, "ERR_POOL_SHUTDOWN");
+    }
+
+    /// Panics if imbalanced liquidity changes are blocked and A is ramping.
+    fn assert_liquidity_not_blocked(&self) {
+        assert!(
+            !(self.block_liquidity_during_ramp && self.is_ramping()),
+            "ERR_LIQUIDITY_BLOCKED_DURING_RAMP"
+        );
+    }
+
+    /// Panics if removing `remove_lp_amount` of LP for a single coin is not allowed right now.
+    fn assert_one_coin_removal_allowed(&self, remove_lp_amount: Balance) {
+        self.assert_not_shutdown();
+        self.assert_liquidity_not_blocked();
+        assert!(
+            remove_lp_amount >= self.min_one_coin_burn,
+            "ERR_BURN_TOO_SMALL"
+        );
+    }
+
+    /// Returns how much `token_out` removing `remove_lp_amount` of LP for it delivers,
+    /// fee included, panicking where the removal itself would.
+    pub fn calc_remove_liquidity_one_coin(
+        &self,
+        token_out: &AccountId,
+        remove_lp_amount: Balance,
+    ) -> Balance {
+        self.assert_one_coin_removal_allowed(remove_lp_amount);
+        self.try_remove_liquidity_one_coin(token_out, remove_lp_amount)
+    }
+
+    /// Panics if the account holds more shares than the cap.
+    /// This contract is exempt, as it holds the base LP of metapools.
+    fn assert_share_cap(&self, account_id: &AccountId) {
+        if let Some(max_share) = self.max_share_per_account {
+            if account_id != &env::current_account_id() {
+                assert!(
+                    self.shares.get(account_id).unwrap_or_default() <= max_share,
+                    "ERR_SHARE_CAP"
+                );
+            }
+        }
+    }
+
+    /// Returns the balance of the coin at `index` a swap has to leave in the pool.
+    fn min_reserve(&self, index: usize) -> Balance {
+        MIN_RESERVE * 10u128.pow(self.token_decimals[index] as u32)
+    }
+
+    /// Panics if any balance of the pool is above its cap.
+    fn assert_balance_caps(&self) {
+        if let Some(caps) = &self.balance_caps {
+            for (amount, cap) in self.amounts.iter().zip(caps.iter()) {
+                assert!(*cap == 0 || amount <= cap, "ERR_BALANCE_CAP_EXCEEDED");
+            }
+        }
+    }
+
+    fn assert_param_num(&self, param_num: usize) {
+        assert_eq!(
+            self.coin_num(),
+            param_num,
+            "param_num should equal to coin num"
+        );
+    }
+
+    fn add_liquidity_impl(&self, deposit_amounts: &Vec<Balance>) -> PoolStatus {
+        self.add_liquidity_with_fees(deposit_amounts, &self.fees)
+    }
+
+    fn add_liquidity_with_fees(&self, deposit_amounts: &Vec<Balance>, fees: &Fees) -> PoolStatus {
+        self.assert_param_num(deposit_amounts.len());
+
+        let unix_timestamp_s = (near_sdk::env::block_timestamp() as u64) / (1e9 as u64);
+
+        let rates = self.get_rates();
+
+        let invariant = self.invariant(unix_timestamp_s, rates);
+
+        invariant
+            .add_liquidity(
+                deposit_amounts,
+                &self.amounts,
+                self.shares_total_supply,
+                fees,
+            )
+            .unwrap_or_else(|e| math_panic("ERR_ADD_LIQUIDITY_FAILED", e))
+    }
+
+    /// Returns shares minted for the deposit and the fee charged, in shares.
+    pub fn try_add_liquidity_and_fee(&self, deposit_amounts: &Vec<Balance>) -> (Balance, Balance) {
+        let mint_shares = self
+            .add_liquidity_with_fees(deposit_amounts, &self.fees)
+            .pool_lp_token_changed;
+        let no_fees = Fees {
+            trade_fee_numerator: 0,
+            ..self.fees
+        };
+        let mint_shares_without_fee = self
+            .add_liquidity_with_fees(deposit_amounts, &no_fees)
+            .pool_lp_token_changed;
+        (
+            mint_shares,
+            mint_shares_without_fee.saturating_sub(mint_shares),
+        )
+    }
+
+    /// Returns amount of `token_out` received burning `remove_lp_amount` and the fee charged in `token_out`.
+    pub fn try_remove_liquidity_one_coin_and_fee(
+        &self,
+        token_out: &AccountId,
+        remove_lp_amount: Balance,
+    ) -> (Balance, Balance) {
+        let token_index = self.token_index(token_out);
+        let poolstatus = self.remove_liquidity_one_coin_impl(token_index as u8, remove_lp_amount);
+        (
+            poolstatus.recieved_amount[token_index],
+            poolstatus.total_fee_amount[token_index],
+        )
+    }
+
+    /// Returns how much `token_out` is received swapping `amount_in` of `token_in` and the fee charged in `token_out`.
+    pub fn get_return_and_fee(
+        &self,
+        token_in: &AccountId,
+        amount_in: Balance,
+        token_out: &AccountId,
+    ) -> (Balance, Balance) {
+        self.internal_get_return(
+            self.token_index(token_in),
+            amount_in,
+            self.token_index(token_out),
+        )
+    }
+
+    fn remove_liquidity_impl(&self, shares: Balance) -> PoolStatus {
+        let unix_timestamp_s = (near_sdk::env::block_timestamp() as u64) / (1e9 as u64);
+        let rates = self.get_rates();
+        // LPs exit a shut down pool without withdraw fee
+        let fees = if self.shutdown {
+            Fees {
+                withdraw_fee_numerator: 0,
+                ..self.fees
+            }
+        } else {
+            self.fees
+        };
+
+        let invariant = self.invariant(unix_timestamp_s, rates);
+
+        invariant
+            .remove_liquidity(shares, &self.amounts, self.shares_total_supply, &fees)
+            .unwrap_or_else(|e| math_panic("ERR_REMOVE_LIQUIDITY_FAILED", e))
+    }
+
+    pub fn process_amount_and_fees(
+        &mut self,
+        sender_id: &AccountId,
+        prev_shares_amount: Balance,
+        poolstatus: &PoolStatus,
+    ) -> Vec<Balance> {
+        let burn_shares: Balance = poolstatus.pool_lp_token_changed.into();
+        assert!(poolstatus.pool_lp_changed_direction == false);
+
+        self.shares_total_supply = self.shares_total_supply.checked_sub(burn_shares).unwrap();
+
+        let mut result = vec![];
+        //update amounts
+        for i in 0..self.token_account_ids.len() {
+            self.amounts[i] = poolstatus.new_balances[i].into();
+
+            self.total_fees[i] = self.total_fees[i]
+                .checked_add(poolstatus.total_fee_amount[i])
+                .unwrap();
+
+            self.admin_fees[i] = self.admin_fees[i]
+                .checked_add(poolstatus.admin_fee_amount[i])
+                .unwrap();
+
+            result.push(poolstatus.recieved_amount[i] as u128);
+        }
+
+        if prev_shares_amount == burn_shares {
+            // never unregister a LP when he remove liqudity.
+            self.shares.insert(&sender_id, &0);
+            if burn_shares > 0 {
+                self.lp_holder_count = self.lp_holder_count.saturating_sub(1);
+            }
+        } else {
+            self.shares.insert(
+                &sender_id,
+                &(prev_shares_amount.checked_sub(burn_shares).unwrap()),
+            );
+        }
+
+        env::log_str(
+            format!(
+                "{} shares of liquidity removed: receive back {:?}",
+                burn_shares,
+                result
+                    .iter()
+                    .zip(self.token_account_ids.iter())
+                    .map(|(amount, token_id)| format!("{} {}", amount, token_id))
+                    .collect::<Vec<String>>(),
+            )
+            .as_str(),
+        );
+
+        result
+    }
+
+    fn remove_liquidity_imbalance_impl(&self, remove_coin_amount: &Vec<Balance>) -> PoolStatus {
+        self.assert_param_num(remove_coin_amount.len());
+        for i in 0..self.token_account_ids.len() {
+            //should not drain out any coin
+            assert!(
+                self.amounts[i] > remove_coin_amount[i],
+                "INVALID_INPUT_AMOUNT"
+            );
+        }
+
+        let unix_timestamp_s = (near_sdk::env::block_timestamp() as u64) / (1e9 as u64);
+        let rates = self.get_rates();
+
+        let invariant = self.invariant(unix_timestamp_s, rates);
+
+        invariant
+            .remove_liquidity_imbalance(
+                remove_coin_amount,
+                &self.amounts,
+                self.shares_total_supply,
+                &self.fees,
+            )
+            .unwrap_or_else(|e| math_panic("REMOVE_LIQUIDITY_IMBALANCE_FAILED", e))
+    }
+
+    fn remove_liquidity_one_coin_impl(
+        &self,
+        token_index: u8,
+        remove_lp_amount: Balance,
+    ) -> PoolStatus {
+        let unix_timestamp_s = (near_sdk::env::block_timestamp() as u64) / (1e9 as u64);
+        let rates = self.get_rates();
+
+        let invariant = self.invariant(unix_timestamp_s, rates);
+
+        invariant
+            .remove_liquidity_one_coin(
+                token_index,
+                remove_lp_amount,
+                &self.amounts,
+                self.shares_total_supply,
+                &self.fees,
+            )
+            .unwrap_or_else(|e| math_panic("ERR_CANT_REMOVE_LIQUIDITY_ONE_COIN", e))
+    }
+
+    /// Returns token index for given pool.
+    fn token_index(&self, token_id: &AccountId) -> usize {
+        self.token_account_ids
+            .iter()
+            .position(|id| id == token_id)
+            .expect("ERR_MISSING_TOKEN")
+    }
+
+    /// Returns number of tokens in outcome and the fee charged, given amount.
+    /// Tokens are provided as indexes into token list for given pool.
+    fn internal_get_return(
+        &self,
+        token_in: usize,
+        amount_in: Balance,
+        token_out: usize,
+    ) -> (Balance, Balance) {
+        let unix_timestamp_s = (near_sdk::env::block_timestamp() as u64) / (1e9 as u64);
+        let rates = self.get_rates();
+
+        let invariant = self.invariant(unix_timestamp_s, rates);
+
+        let in_idx = token_in;
+        let out_idx = token_out;
+
+        let result = invariant
+            .exchange(
+                in_idx as u8,
+                out_idx as u8,
+                amount_in,
+                &self.amounts,
+                &self.fees,
+            )
+            .unwrap_or_else(|e| math_panic("ERR_GET_RETURN_FAILED", e));
+
+        (result.amount_b, result.total_fee)
+    }
+
+    /// Mint new shares for given user.
+    fn mint_shares(&mut self, account_id: &AccountId, shares: Balance) {
+        if shares == 0 {
+            return;
         }
+        self.shares_total_supply = self.shares_total_supply.checked_add(shares).unwrap();
+        if self.shares.get(account_id).unwrap_or_default() == 0 {
+            self.lp_holder_count += 1;
+        }
+        add_to_collection(&mut self.shares, &account_id.to_string(), shares);
     }
+}
 
-    pub fn set_amp_params(
+impl PoolTrait for SimplePool {
+    fn set_amp_params(
         &mut self,
         initial_amp_factor: u64,
         target_amp_factor: u64,
@@ -99,34 +569,82 @@ impl SimplePool {
         self.stop_ramp_ts = stop_ramp_ts;
     }
 
-    pub fn coin_num(&self) -> usize {
-        self.token_account_ids.len()
+    fn is_ramping(&self) -> bool {
+        let unix_timestamp_s = (near_sdk::env::block_timestamp() as u64) / (1e9 as u64);
+        self.start_ramp_ts <= unix_timestamp_s && unix_timestamp_s < self.stop_ramp_ts
+    }
+
+    fn set_block_liquidity_during_ramp(&mut self, block: bool) {
+        self.block_liquidity_during_ramp = block;
+    }
+
+    fn set_rate_scaled_epsilon(&mut self, enabled: bool) {
+        self.rate_scaled_epsilon = enabled;
+    }
+
+    fn set_max_share_per_account(&mut self, max_share: Option<Balance>) {
+        self.max_share_per_account = max_share;
+    }
+
+    fn set_min_one_coin_burn(&mut self, min_burn: Balance) {
+        self.min_one_coin_burn = min_burn;
+    }
+
+    fn set_lp_fee_discount(&mut self, discount_bps: u32) {
+        self.lp_fee_discount_bps = discount_bps;
+    }
+
+    fn set_balance_caps(&mut self, caps: Option<Vec<Balance>>) {
+        if let Some(caps) = &caps {
+            self.assert_param_num(caps.len());
+        }
+        self.balance_caps = caps;
+    }
+
+    fn lp_fee_discount(&self, account_id: &AccountId, total_fee: Balance) -> Balance {
+        if self.lp_fee_discount_bps == 0 || self.share_balance_of(account_id) == 0 {
+            return 0;
+        }
+        total_fee * self.lp_fee_discount_bps as u128 / BPS_DENOMINATOR
+    }
+
+    fn is_shutdown(&self) -> bool {
+        self.shutdown
+    }
+
+    fn shutdown(&mut self) {
+        self.shutdown = true;
     }
 
     /// Returns given pool's total fee.
-    pub fn get_fee(&self) -> Vec<u128> {
+    fn get_fee(&self) -> Vec<u128> {
         self.total_fees.iter().map(|fee| (fee.clone())).collect()
     }
 
-    pub fn get_admin_fee(&self) -> Vec<u128> {
+    fn get_admin_fee(&self) -> Vec<u128> {
         self.admin_fees.iter().map(|fee| (fee.clone())).collect()
     }
+
     /// Returns balance of shares for given user.
-    pub fn share_balance_of(&self, account_id: &AccountId) -> Balance {
+    fn share_balance_of(&self, account_id: &AccountId) -> Balance {
         self.shares.get(account_id).unwrap_or_default()
     }
 
-    pub fn fees_info(&self) -> Fees {
+    fn fees_info(&self) -> Fees {
         self.fees
     }
 
     /// Returns total number of shares in this pool.
-    pub fn share_total_balance(&self) -> Balance {
+    fn share_total_balance(&self) -> Balance {
         self.shares_total_supply
     }
 
+    fn lp_holder_count(&self) -> u64 {
+        self.lp_holder_count
+    }
+
     /// Returns how much token you will receive if swap `token_amount_in` of `token_in` for `token_out`.
-    pub fn get_return(
+    fn get_return(
         &self,
         token_in: &AccountId,
         amount_in: Balance,
@@ -137,61 +655,75 @@ impl SimplePool {
             amount_in,
             self.token_index(token_out),
         )
+        .0
     }
 
-    fn assert_param_num(&self, param_num: usize) {
-        assert_eq!(
-            self.coin_num(),
-            param_num,
-            "param_num should equal to coin num"
-        );
+    /// Returns the maximum amount of `token_out` that can be taken out of the pool,
+    /// that is its whole balance except the reserve floor.
+    fn get_max_output(&self, token_out: &AccountId) -> Balance {
+        let index = self.token_index(token_out);
+        self.amounts[index].saturating_sub(self.min_reserve(index))
     }
 
-    fn add_liquidity_impl(&self, deposit_amounts: &Vec<Balance>) -> PoolStatus {
-        self.assert_param_num(deposit_amounts.len());
-
-        let unix_timestamp_s = (near_sdk::env::block_timestamp() as u64) / (1e9 as u64);
+    fn get_rates(&self) -> Vec<u128> {
+        self.rates
+            .clone()
+            .unwrap_or_else(|| decimals_to_rates(&self.token_decimals))
+    }
 
-        let rates = decimals_to_rates(&self.token_decimals);
+    fn get_rate_denominator(&self) -> u128 {
+        self.rate_denominator
+    }
 
-        let invariant = SnailStableSwap::new(
-            self.initial_amp_factor,
-            self.target_amp_factor,
-            unix_timestamp_s,
-            self.start_ramp_ts,
-            self.stop_ramp_ts,
-            rates,
-        );
+    fn set_rate_provider(&mut self, rate_provider: Option<AccountId>) {
+        self.rate_provider = rate_provider;
+    }
 
-        invariant
-            .add_liquidity(
-                deposit_amounts,
-                &self.amounts,
-                self.shares_total_supply,
-                &self.fees,
-            )
-            .expect("ERR_ADD_LIQUIDITY_FAILED")
+    fn rate_provider(&self) -> Option<AccountId> {
+        self.rate_provider.clone()
+    }
+
+    fn set_rates(&mut self, rates: Vec<u128>) {
+        self.assert_param_num(rates.len());
+        assert!(rates.iter().all(|rate| *rate > 0), "ERR_ZERO_RATE");
+        self.rates = Some(rates);
     }
-    pub fn try_add_liquidity(&self, deposit_amounts: &Vec<Balance>) -> Balance {
+
+    fn try_add_liquidity(&self, deposit_amounts: &Vec<Balance>) -> Balance {
         let poolstatus = self.add_liquidity_impl(deposit_amounts);
 
-        let mint_shares = poolstatus.pool_lp_token_changed;
+        let mut mint_shares = poolstatus.pool_lp_token_changed;
         assert!(poolstatus.pool_lp_changed_direction == true);
+        if self.shares_total_supply == 0 {
+            mint_shares = mint_shares.saturating_sub(LOCKED_INITIAL_SHARES);
+        }
 
         mint_shares.into()
     }
 
     /// Adds the amounts of tokens to liquidity pool and returns number of shares that this user receives.
     /// Updates amount to amount kept in the pool.
-    pub fn add_liquidity(
+    fn add_liquidity(
         &mut self,
         sender_id: &AccountId,
         deposit_amounts: &Vec<Balance>,
     ) -> (Balance, Vec<Balance>) {
-        let poolstatus = self.add_liquidity_impl(deposit_amounts);
+        self.assert_not_shutdown();
+        self.assert_liquidity_not_blocked();
+        let mut poolstatus = self.add_liquidity_impl(deposit_amounts);
+        self.retain_lp_fee_share(&mut poolstatus);
 
-        let mint_shares = poolstatus.pool_lp_token_changed;
+        let mut mint_shares = poolstatus.pool_lp_token_changed;
         assert!(poolstatus.pool_lp_changed_direction == true);
+        if self.shares_total_supply == 0 {
+            assert!(
+                mint_shares >= MIN_INITIAL_SHARES,
+                "ERR_INITIAL_DEPOSIT_TOO_SMALL"
+            );
+            // locked shares count in the supply but belong to no one, so they are never burnt.
+            self.shares_total_supply = LOCKED_INITIAL_SHARES;
+            mint_shares -= LOCKED_INITIAL_SHARES;
+        }
 
         //update amounts and fees
         for i in 0..self.token_account_ids.len() {
@@ -205,8 +737,10 @@ impl SimplePool {
                 .unwrap();
         }
 
+        self.assert_balance_caps();
         self.mint_shares(&sender_id, mint_shares.into());
         assert!(mint_shares > 0, "{}", ZERO_SHARES);
+        self.assert_share_cap(sender_id);
         env::log_str(
             format!(
                 "Liquidity added {:?}, minted {} shares, shares_total_supply {}",
@@ -223,25 +757,7 @@ impl SimplePool {
         (mint_shares.into(), poolstatus.admin_fee_amount)
     }
 
-    fn remove_liquidity_impl(&self, shares: Balance) -> PoolStatus {
-        let unix_timestamp_s = (near_sdk::env::block_timestamp() as u64) / (1e9 as u64);
-        let rates = decimals_to_rates(&self.token_decimals);
-
-        let invariant = SnailStableSwap::new(
-            self.initial_amp_factor,
-            self.target_amp_factor,
-            unix_timestamp_s,
-            self.start_ramp_ts,
-            self.stop_ramp_ts,
-            rates,
-        );
-
-        invariant
-            .remove_liquidity(shares, &self.amounts, self.shares_total_supply, &self.fees)
-            .expect("ERR_REMOVE_LIQUIDITY_FAILED")
-    }
-
-    pub fn try_remove_liquidity(&self, shares: Balance) -> Vec<Balance> {
+    fn try_remove_liquidity(&self, shares: Balance) -> Vec<Balance> {
         let poolstatus = self.remove_liquidity_impl(shares);
 
         poolstatus
@@ -250,14 +766,16 @@ impl SimplePool {
             .map(|amount| *amount)
             .collect()
     }
-    pub fn remove_liquidity(
+
+    fn remove_liquidity(
         &mut self,
         sender_id: &AccountId,
         shares: Balance,
         min_amounts: Vec<Balance>,
     ) -> (Vec<Balance>, Vec<Balance>) {
         self.assert_param_num(min_amounts.len());
-        let poolstatus = self.remove_liquidity_impl(shares);
+        let mut poolstatus = self.remove_liquidity_impl(shares);
+        self.retain_lp_fee_share(&mut poolstatus);
         let prev_shares_amount = self.shares.get(&sender_id).expect("ERR_NO_SHARES");
         let amounts = self.process_amount_and_fees(sender_id, prev_shares_amount, &poolstatus);
 
@@ -268,103 +786,26 @@ impl SimplePool {
         (amounts, poolstatus.admin_fee_amount)
     }
 
-    pub fn process_amount_and_fees(
-        &mut self,
-        sender_id: &AccountId,
-        prev_shares_amount: Balance,
-        poolstatus: &PoolStatus,
-    ) -> Vec<Balance> {
-        let burn_shares: Balance = poolstatus.pool_lp_token_changed.into();
-        assert!(poolstatus.pool_lp_changed_direction == false);
-
-        self.shares_total_supply = self.shares_total_supply.checked_sub(burn_shares).unwrap();
-
-        let mut result = vec![];
-        //update amounts
-        for i in 0..self.token_account_ids.len() {
-            self.amounts[i] = poolstatus.new_balances[i].into();
-
-            self.total_fees[i] = self.total_fees[i]
-                .checked_add(poolstatus.total_fee_amount[i])
-                .unwrap();
-
-            self.admin_fees[i] = self.admin_fees[i]
-                .checked_add(poolstatus.admin_fee_amount[i])
-                .unwrap();
-
-            result.push(poolstatus.recieved_amount[i] as u128);
-        }
-
-        if prev_shares_amount == burn_shares {
-            // never unregister a LP when he remove liqudity.
-            self.shares.insert(&sender_id, &0);
-        } else {
-            self.shares.insert(
-                &sender_id,
-                &(prev_shares_amount.checked_sub(burn_shares).unwrap()),
-            );
-        }
-
-        env::log_str(
-            format!(
-                "{} shares of liquidity removed: receive back {:?}",
-                burn_shares,
-                result
-                    .iter()
-                    .zip(self.token_account_ids.iter())
-                    .map(|(amount, token_id)| format!("{} {}", amount, token_id))
-                    .collect::<Vec<String>>(),
-            )
-            .as_str(),
-        );
-
-        result
-    }
-    fn remove_liquidity_imbalance_impl(&self, remove_coin_amount: &Vec<Balance>) -> PoolStatus {
-        self.assert_param_num(remove_coin_amount.len());
-        for i in 0..self.token_account_ids.len() {
-            //should not drain out any coin
-            assert!(
-                self.amounts[i] > remove_coin_amount[i],
-                "INVALID_INPUT_AMOUNT"
-            );
-        }
-
-        let unix_timestamp_s = (near_sdk::env::block_timestamp() as u64) / (1e9 as u64);
-        let rates = decimals_to_rates(&self.token_decimals);
-
-        let invariant = SnailStableSwap::new(
-            self.initial_amp_factor,
-            self.target_amp_factor,
-            unix_timestamp_s,
-            self.start_ramp_ts,
-            self.stop_ramp_ts,
-            rates,
-        );
-
-        invariant
-            .remove_liquidity_imbalance(
-                remove_coin_amount,
-                &self.amounts,
-                self.shares_total_supply,
-                &self.fees,
-            )
-            .expect("REMOVE_LIQUIDITY_IMBALANCE_FAILED")
-    }
-
-    pub fn try_remove_liquidity_imbalance(&self, remove_coin_amount: &Vec<Balance>) -> u128 {
+    fn try_remove_liquidity_imbalance(&self, remove_coin_amount: &Vec<Balance>) -> u128 {
         let poolstatus = self.remove_liquidity_imbalance_impl(remove_coin_amount);
         poolstatus.pool_lp_token_changed.into()
     }
 
-    pub fn remove_liquidity_imbalance(
+    fn remove_liquidity_imbalance(
         &mut self,
         sender_id: &AccountId,
         remove_coin_amount: &Vec<Balance>,
     ) -> (u128, Vec<Balance>) {
-        let poolstatus = self.remove_liquidity_imbalance_impl(remove_coin_amount);
+        self.assert_not_shutdown();
+        self.assert_liquidity_not_blocked();
+        let mut poolstatus = self.remove_liquidity_imbalance_impl(remove_coin_amount);
+        self.retain_lp_fee_share(&mut poolstatus);
 
         let prev_shares_amount = self.shares.get(&sender_id).expect("ERR_NO_SHARES");
+        // the +1 rounding the burn up never takes more than the sender holds
+        if poolstatus.pool_lp_token_changed == prev_shares_amount + 1 {
+            poolstatus.pool_lp_token_changed = prev_shares_amount;
+        }
         let amounts = self.process_amount_and_fees(sender_id, prev_shares_amount, &poolstatus);
 
         for i in 0..self.token_account_ids.len() {
@@ -377,35 +818,7 @@ impl SimplePool {
         )
     }
 
-    fn remove_liquidity_one_coin_impl(
-        &self,
-        token_index: u8,
-        remove_lp_amount: Balance,
-    ) -> PoolStatus {
-        let unix_timestamp_s = (near_sdk::env::block_timestamp() as u64) / (1e9 as u64);
-        let rates = decimals_to_rates(&self.token_decimals);
-
-        let invariant = SnailStableSwap::new(
-            self.initial_amp_factor,
-            self.target_amp_factor,
-            unix_timestamp_s,
-            self.start_ramp_ts,
-            self.stop_ramp_ts,
-            rates,
-        );
-
-        invariant
-            .remove_liquidity_one_coin(
-                token_index,
-                remove_lp_amount,
-                &self.amounts,
-                self.shares_total_supply,
-                &self.fees,
-            )
-            .expect("ERR_CANT_REMOVE_LIQUIDITY_ONE_COIN")
-    }
-
-    pub fn try_remove_liquidity_one_coin(
+    fn try_remove_liquidity_one_coin(
         &self,
         token_out: &AccountId,
         remove_lp_amount: Balance,
@@ -415,15 +828,17 @@ impl SimplePool {
         poolstatus.recieved_amount[token_index as usize]
     }
 
-    pub fn remove_liquidity_one_coin(
+    fn remove_liquidity_one_coin(
         &mut self,
         sender_id: &AccountId,
         token_out: &AccountId,
         remove_lp_amount: Balance,
         min_amount: Balance,
     ) -> (Vec<Balance>, Vec<Balance>) {
+        self.assert_one_coin_removal_allowed(remove_lp_amount);
         let token_index = self.token_index(token_out) as u8;
-        let poolstatus = self.remove_liquidity_one_coin_impl(token_index, remove_lp_amount);
+        let mut poolstatus = self.remove_liquidity_one_coin_impl(token_index, remove_lp_amount);
+        self.retain_lp_fee_share(&mut poolstatus);
         let prev_shares_amount = self.shares.get(&sender_id).expect("ERR_NO_SHARES");
         let amounts = self.process_amount_and_fees(sender_id, prev_shares_amount, &poolstatus);
         assert!(
@@ -434,26 +849,21 @@ impl SimplePool {
         (amounts, poolstatus.admin_fee_amount)
     }
 
-    /// Swap `token_amount_in` of `token_in` token into `token_out` and return how much was received.
+    /// Swap `token_amount_in` of `token_in` token into `token_out` and return how much was received,
+    /// together with the admin fee and the total fee charged in `token_out`.
     /// Assuming that `token_amount_in` was already received from `sender_id`.
-    pub fn swap(
+    fn swap(
         &mut self,
         token_in: &AccountId,
         amount_in: Balance,
         token_out: &AccountId,
         min_amount_out: Balance,
-    ) -> (Balance, Balance) {
+    ) -> (Balance, Balance, Balance) {
+        self.assert_not_shutdown();
         let unix_timestamp_s = (near_sdk::env::block_timestamp() as u64) / (1e9 as u64);
-        let rates = decimals_to_rates(&self.token_decimals);
+        let rates = self.get_rates();
 
-        let invariant = SnailStableSwap::new(
-            self.initial_amp_factor,
-            self.target_amp_factor,
-            unix_timestamp_s,
-            self.start_ramp_ts,
-            self.stop_ramp_ts,
-            rates,
-        );
+        let invariant = self.invariant(unix_timestamp_s, rates);
 
         let in_idx = self.token_index(token_in);
         let out_idx = self.token_index(token_out);
@@ -466,17 +876,29 @@ impl SimplePool {
                 &self.amounts,
                 &self.fees,
             )
-            .expect("ERR_SWAP_FAILED");
+            .unwrap_or_else(|e| math_panic("ERR_SWAP_FAILED", e));
 
         let amount_out: Balance = (result.amount_b as u128).into();
         assert!(amount_out >= min_amount_out, "ERR_MIN_AMOUNT");
 
         self.amounts[in_idx] = self.amounts[in_idx].checked_add(amount_in).unwrap();
 
-        self.amounts[out_idx] = self.amounts[out_idx].checked_sub(amount_out).unwrap();
-
         let total_fee_amount: Balance = (result.total_fee as u128).into();
         let admin_fee_amount: Balance = (result.admin_fee as u128).into();
+        // LP share of the admin fee stays in the pool
+        let admin_fee_amount = admin_fee_amount - self.lp_fee_share(admin_fee_amount);
+
+        // admin fee leaves the pool as well
+        self.amounts[out_idx] = self.amounts[out_idx]
+            .checked_sub(amount_out)
+            .unwrap()
+            .checked_sub(admin_fee_amount)
+            .unwrap();
+        assert!(
+            self.amounts[out_idx] >= self.min_reserve(out_idx),
+            "ERR_POOL_RESERVE_TOO_LOW"
+        );
+        self.assert_balance_caps();
 
         self.total_fees[out_idx] = self.total_fees[out_idx]
             .checked_add(total_fee_amount)
@@ -500,133 +922,109 @@ impl SimplePool {
         env::log_str(
             format!(
                 "Swapped {} {} for {} {} with admin fee {} total_fee {}",
-                amount_in, token_in, amount_out, token_out, result.admin_fee, result.total_fee
+                amount_in, token_in, amount_out, token_out, admin_fee_amount, result.total_fee
             )
             .as_str(),
         );
 
-        (amount_out, admin_fee_amount)
+        (amount_out, admin_fee_amount, total_fee_amount)
     }
 
-    pub fn change_fees_setting(&mut self, fees: Fees) {
-        self.fees = fees
-    }
-
-    /// Returns token index for given pool.
-    fn token_index(&self, token_id: &AccountId) -> usize {
-        self.token_account_ids
-            .iter()
-            .position(|id| id == token_id)
-            .expect("ERR_MISSING_TOKEN")
+    fn commit_new_fees(&mut self, fees: Fees, apply_new_fee_ts: u64) {
+        self.new_fees = fees;
+        self.apply_new_fee_ts = apply_new_fee_ts;
     }
 
-    /// Returns number of tokens in outcome, given amount.
-    /// Tokens are provided as indexes into token list for given pool.
-    fn internal_get_return(
-        &self,
-        token_in: usize,
-        amount_in: Balance,
-        token_out: usize,
-    ) -> Balance {
+    fn apply_new_fees(&mut self) {
+        assert!(self.apply_new_fee_ts != 0, "ERR_NO_PENDING_FEES");
         let unix_timestamp_s = (near_sdk::env::block_timestamp() as u64) / (1e9 as u64);
-        let rates = decimals_to_rates(&self.token_decimals);
-
-        let invariant = SnailStableSwap::new(
-            self.initial_amp_factor,
-            self.target_amp_factor,
-            unix_timestamp_s,
-            self.start_ramp_ts,
-            self.stop_ramp_ts,
-            rates,
+        assert!(
+            unix_timestamp_s >= self.apply_new_fee_ts,
+            "ERR_FEES_TIMELOCKED"
         );
-
-        let in_idx = token_in;
-        let out_idx = token_out;
-
-        let result = invariant
-            .exchange(
-                in_idx as u8,
-                out_idx as u8,
-                amount_in,
-                &self.amounts,
-                &self.fees,
-            )
-            .expect("ERR_GET_RETURN_FAILED");
-
-        result.amount_b
-    }
-
-    /// Mint new shares for given user.
-    fn mint_shares(&mut self, account_id: &AccountId, shares: Balance) {
-        if shares == 0 {
-            return;
-        }
-        self.shares_total_supply = self.shares_total_supply.checked_add(shares).unwrap();
-        add_to_collection(&mut self.shares, &account_id.to_string(), shares);
+        self.fees = self.new_fees;
+        self.apply_new_fee_ts = 0;
     }
 
-    pub fn tokens(&self) -> &[AccountId] {
+    fn tokens(&self) -> &[AccountId] {
         &self.token_account_ids
     }
 
     /// Transfers shares from predecessor to receiver.
-    pub fn share_transfer(&mut self, sender_id: &AccountId, receiver_id: &AccountId, amount: u128) {
+    fn share_transfer(&mut self, sender_id: &AccountId, receiver_id: &AccountId, amount: u128) {
         let balance = self.shares.get(&sender_id).expect("ERR_NO_SHARES");
         if let Some(new_balance) = balance.checked_sub(amount) {
             self.shares.insert(&sender_id, &new_balance);
+            if new_balance == 0 && amount > 0 {
+                self.lp_holder_count = self.lp_holder_count.saturating_sub(1);
+            }
         } else {
             env::panic_str("ERR_NOT_ENOUGH_SHARES");
         }
         let balance_out = self.shares.get(&receiver_id).expect(LP_NOT_REGISTERED);
+        if balance_out == 0 && amount > 0 {
+            self.lp_holder_count += 1;
+        }
         self.shares
             .insert(&receiver_id, &(balance_out.checked_add(amount).unwrap()));
+        self.assert_share_cap(receiver_id);
     }
 
     /// Register given account with 0 balance in shares.
     /// Storage payment should be checked by caller.
-    pub fn share_register(&mut self, account_id: &AccountId) {
+    fn share_register(&mut self, account_id: &AccountId) {
         if self.shares.contains_key(account_id) {
             env::panic_str(LP_ALREADY_REGISTERED);
         }
         self.shares.insert(account_id, &0);
     }
 
-    pub fn is_lp_token_registered(&self, account_id: &AccountId) -> bool {
+    /// Unregister given account from shares, its balance should be 0.
+    fn share_unregister(&mut self, account_id: &AccountId) {
+        let balance = self.shares.get(account_id).expect(LP_NOT_REGISTERED);
+        assert_eq!(balance, 0, "{}", NON_ZERO_SHARES);
+        self.shares.remove(account_id);
+    }
+
+    fn is_lp_token_registered(&self, account_id: &AccountId) -> bool {
         self.shares.contains_key(account_id)
     }
 
-    pub fn get_virtual_price(&self) -> u128 {
+    fn get_virtual_price(&self) -> u128 {
         let unix_timestamp_s = (near_sdk::env::block_timestamp() as u64) / (1e9 as u64);
-        let rates = decimals_to_rates(&self.token_decimals);
+        let rates = self.get_rates();
 
-        let invariant = SnailStableSwap::new(
-            self.initial_amp_factor,
-            self.target_amp_factor,
-            unix_timestamp_s,
-            self.start_ramp_ts,
-            self.stop_ramp_ts,
-            rates,
-        );
+        let invariant = self.invariant(unix_timestamp_s, rates);
 
         invariant
             .get_virtual_price(&self.amounts, self.shares_total_supply)
-            .expect("ERR_INVALID_VIRUTAL_PRICE")
+            .unwrap_or_else(|e| math_panic("ERR_INVALID_VIRUTAL_PRICE", e))
     }
 
-    pub fn get_amp_factor(&self) -> u128 {
+    fn get_spot_price(&self, token_in: &AccountId, token_out: &AccountId) -> u128 {
         let unix_timestamp_s = (near_sdk::env::block_timestamp() as u64) / (1e9 as u64);
-        let rates = decimals_to_rates(&self.token_decimals);
+        let rates = self.get_rates();
 
-        let invariant = SnailStableSwap::new(
-            self.initial_amp_factor,
-            self.target_amp_factor,
-            unix_timestamp_s,
-            self.start_ramp_ts,
-            self.stop_ramp_ts,
-            rates,
-        );
+        let invariant = self.invariant(unix_timestamp_s, rates);
+
+        invariant
+            .get_spot_price(
+                self.token_index(token_in) as u8,
+                self.token_index(token_out) as u8,
+                &self.amounts,
+            )
+            .unwrap_or_else(|e| math_panic("ERR_SPOT_PRICE_FAILED", e))
+    }
+
+    fn get_amp_factor(&self) -> u128 {
+        let unix_timestamp_s = (near_sdk::env::block_timestamp() as u64) / (1e9 as u64);
+        let rates = self.get_rates();
 
-        invariant.compute_amp_factor().expect("ERR_amp_factor") as u128
+        let invariant = self.invariant(unix_timestamp_s, rates);
+
+        invariant
+            .compute_amp_factor()
+            .unwrap_or_else(|e| math_panic("ERR_amp_factor", e)) as u128
     }
 }
 
@@ -636,11 +1034,48 @@ mod tests {
 
     #[test]
     fn test_decimals_to_rates() {
-        const RATES: [u128; 3 as usize] = [1000000, 1000000000000000000, 1000000000000000000];
+        const RATES: [u128; 3 as usize] = [
+            10u128.pow(PRECISION_DECIMALS - 18),
+            10u128.pow(PRECISION_DECIMALS - 6),
+            10u128.pow(PRECISION_DECIMALS - 6),
+        ];
         let decimals: Vec<u64> = vec![18, 6, 6];
         let rates = decimals_to_rates(&decimals);
         for i in 0..rates.len() {
             assert_eq!(rates[i], RATES[i]);
         }
     }
+
+    #[test]
+    fn test_decimals_to_rates_with_precision() {
+        let decimals: Vec<u64> = vec![18, 6, 6, 0];
+        assert_eq!(
+            decimals_to_rates(&decimals),
+            decimals_to_rates_with_precision(&decimals, PRECISION_DECIMALS)
+        );
+        for precision_decimals in [18u32, 24, 30] {
+            let rates = decimals_to_rates_with_precision(&decimals, precision_decimals);
+            for (rate, decimal) in rates.iter().zip(decimals.iter()) {
+                assert_eq!(
+                    rate * 10u128.pow(*decimal as u32),
+                    10u128.pow(precision_decimals)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_decimals_to_rates_boundary() {
+        let decimals: Vec<u64> = vec![PRECISION_DECIMALS as u64, 0];
+        assert_eq!(
+            decimals_to_rates(&decimals),
+            vec![1, 10u128.pow(PRECISION_DECIMALS)]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_DECIMALS_TOO_HIGH")]
+    fn test_decimals_to_rates_too_high() {
+        decimals_to_rates(&vec![18, PRECISION_DECIMALS as u64 + 1]);
+    }
 }
@@ -2,7 +2,38 @@
 
 use crate::bigint::{U192, U256, U576};
 use crate::fees::Fees;
-use crate::utils::PRECISION;
+use crate::utils::{CONVERGENCE_EPSILON_DIVISOR, MAX_COINS, MAX_SOLVER_ITERATIONS, PRECISION};
+use std::fmt;
+
+use MathError::*;
+
+/// Failures of the invariant math, surfaced to the contract instead of a bare None.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MathError {
+    /// An intermediate value doesn't fit its integer type.
+    Overflow,
+    /// Newton iterations for D didn't converge within the iteration cap.
+    DNotConverged,
+    /// Newton iterations for y didn't converge within the iteration cap.
+    YNotConverged,
+    /// Division by a zero supply, invariant or denominator.
+    DivideByZero,
+    /// Subtracting more than the pool or user balance holds.
+    InsufficientBalance,
+}
+
+impl fmt::Display for MathError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match self {
+            Overflow => "ERR_MATH_OVERFLOW",
+            DNotConverged => "ERR_D_NOT_CONVERGED",
+            YNotConverged => "ERR_Y_NOT_CONVERGED",
+            DivideByZero => "ERR_MATH_DIVIDE_BY_ZERO",
+            InsufficientBalance => "ERR_MATH_INSUFFICIENT_BALANCE",
+        };
+        write!(f, "{}", msg)
+    }
+}
 
 /// Encodes all results of swapping from a source token to a destination token
 pub struct SwapResult {
@@ -55,7 +86,14 @@ pub struct SnailStableSwap {
     stop_ramp_ts: u64,
 
     rates: Vec<u128>,
+    /// Denominator of the rates, 1 unless a rate is fractional such as a base LP
+    /// priced at its virtual price.
+    rate_denominator: u128,
     coin_num: u64,
+    /// Newton iterations allowed for D and y before failing as not converged.
+    max_iterations: u32,
+    /// D and y solvers stop once successive iterates differ by at most this, in 24 decimals.
+    convergence_epsilon: u128,
 }
 
 impl SnailStableSwap {
@@ -69,7 +107,10 @@ impl SnailStableSwap {
         rates: Vec<u128>,
     ) -> Self {
         let coin_num = rates.len();
-        assert!((coin_num <= 3 && coin_num >= 2), "2 <= coin_num <= 3");
+        assert!(
+            (coin_num <= MAX_COINS as usize && coin_num >= 2),
+            "2 <= coin_num <= 3"
+        );
         Self {
             initial_amp_factor,
             target_amp_factor,
@@ -77,17 +118,74 @@ impl SnailStableSwap {
             start_ramp_ts,
             stop_ramp_ts,
             rates,
+            rate_denominator: 1,
             coin_num: coin_num as u64,
+            max_iterations: MAX_SOLVER_ITERATIONS,
+            convergence_epsilon: 1,
+        }
+    }
+
+    /// Overrides the iteration cap of the D and y solvers.
+    #[cfg(test)]
+    pub fn with_max_iterations(mut self, max_iterations: u32) -> Self {
+        self.max_iterations = max_iterations;
+        self
+    }
+
+    /// Overrides the convergence target of the D and y solvers, 1 by default.
+    pub fn with_convergence_epsilon(mut self, convergence_epsilon: u128) -> Self {
+        self.convergence_epsilon = convergence_epsilon.max(1);
+        self
+    }
+
+    /// Overrides the denominator of the rates, 1 by default.
+    pub fn with_rate_denominator(mut self, rate_denominator: u128) -> Self {
+        self.rate_denominator = rate_denominator.max(1);
+        self
+    }
+
+    /// Scales the convergence target to the largest rate, i.e. the coin with the fewest decimals:
+    /// one of its units is `rate` in 24 decimals, so iterating below a fraction of it only wastes gas.
+    pub fn with_rate_scaled_epsilon(self) -> Self {
+        let max_rate = self.rates.iter().copied().max().unwrap_or(1);
+        let epsilon = max_rate / self.rate_denominator / CONVERGENCE_EPSILON_DIVISOR;
+        self.with_convergence_epsilon(epsilon)
+    }
+
+    /// Brings an amount of the i-th coin to 24 decimals.
+    fn to_precision(&self, amount: u128, i: usize) -> Result<u128, MathError> {
+        if self.rate_denominator == 1 {
+            return amount.checked_mul(self.rates[i]).ok_or(Overflow);
+        }
+        U256::from(amount)
+            .checked_mul(self.rates[i].into())
+            .ok_or(Overflow)?
+            .checked_div(self.rate_denominator.into())
+            .ok_or(DivideByZero)?
+            .to_u128()
+            .ok_or(Overflow)
+    }
+
+    /// Brings an amount in 24 decimals back to the i-th coin, rounding down.
+    fn from_precision(&self, p_amount: u128, i: usize) -> Result<u128, MathError> {
+        if self.rate_denominator == 1 {
+            return p_amount.checked_div(self.rates[i]).ok_or(DivideByZero);
         }
+        U256::from(p_amount)
+            .checked_mul(self.rate_denominator.into())
+            .ok_or(Overflow)?
+            .checked_div(self.rates[i].into())
+            .ok_or(DivideByZero)?
+            .to_u128()
+            .ok_or(Overflow)
     }
 
-    fn p_balances_convert(&self, balances: &Vec<u128>) -> Option<Vec<u128>> {
+    fn p_balances_convert(&self, balances: &Vec<u128>) -> Result<Vec<u128>, MathError> {
         let mut p_balances = balances.clone();
         for i in 0..balances.len() {
-            p_balances[i] = balances[i].checked_mul(self.rates[i])?;
+            p_balances[i] = self.to_precision(balances[i], i)?;
         }
-        // None if overflow
-        Some(p_balances)
+        Ok(p_balances)
     }
 
     fn compute_next_d(
@@ -96,90 +194,126 @@ impl SnailStableSwap {
         d_init: U576,
         d_prod: U576,
         sum_x: U192,
-    ) -> Option<U576> {
+    ) -> Result<U576, MathError> {
         assert!(amp_factor != 0, "amp_factor == 0");
-        let ann = (amp_factor as u128).checked_mul(self.coin_num.into())?;
-        let leverage = U576::from(sum_x).checked_mul(ann.into())?;
-        let numerator = d_init.checked_mul(
-            d_prod
-                .checked_mul(self.coin_num.into())?
-                .checked_add(leverage.into())?,
-        )?;
+        let ann = (amp_factor as u128)
+            .checked_mul(self.coin_num.into())
+            .ok_or(Overflow)?;
+        let leverage = U576::from(sum_x).checked_mul(ann.into()).ok_or(Overflow)?;
+        let numerator = d_init
+            .checked_mul(
+                d_prod
+                    .checked_mul(self.coin_num.into())
+                    .ok_or(Overflow)?
+                    .checked_add(leverage.into())
+                    .ok_or(Overflow)?,
+            )
+            .ok_or(Overflow)?;
         assert!(ann > 1, "ann {} ", ann);
         let denominator = d_init
-            .checked_mul(ann.checked_sub(1)?.into())?
-            .checked_add(d_prod.checked_mul((self.coin_num.checked_add(1).unwrap()).into())?)?;
+            .checked_mul(ann.checked_sub(1).ok_or(Overflow)?.into())
+            .ok_or(Overflow)?
+            .checked_add(
+                d_prod
+                    .checked_mul((self.coin_num.checked_add(1).unwrap()).into())
+                    .ok_or(Overflow)?,
+            )
+            .ok_or(Overflow)?;
 
-        numerator.checked_div(denominator)
+        numerator.checked_div(denominator).ok_or(DivideByZero)
     }
 
     /// Compute the amplification coefficient (A)
-    pub fn compute_amp_factor(&self) -> Option<u64> {
-        assert!(self.current_ts >= self.start_ramp_ts);
-        if self.current_ts < self.stop_ramp_ts {
-            let time_range = self.stop_ramp_ts.checked_sub(self.start_ramp_ts)?;
-            let time_delta = self.current_ts.checked_sub(self.start_ramp_ts)?;
+    pub fn compute_amp_factor(&self) -> Result<u64, MathError> {
+        if self.current_ts < self.start_ramp_ts {
+            // ramp scheduled in the future hasn't started yet
+            Ok(self.initial_amp_factor)
+        } else if self.current_ts < self.stop_ramp_ts {
+            let time_range = self
+                .stop_ramp_ts
+                .checked_sub(self.start_ramp_ts)
+                .ok_or(Overflow)?;
+            let time_delta = self
+                .current_ts
+                .checked_sub(self.start_ramp_ts)
+                .ok_or(Overflow)?;
 
             // Compute amp factor based on ramp time
             if self.target_amp_factor >= self.initial_amp_factor {
                 // Ramp up
                 let amp_range = self
                     .target_amp_factor
-                    .checked_sub(self.initial_amp_factor)?;
+                    .checked_sub(self.initial_amp_factor)
+                    .ok_or(Overflow)?;
                 let amp_delta = (amp_range as u128)
-                    .checked_mul(time_delta as u128)?
-                    .checked_div(time_range as u128)? as u64;
-
-                self.initial_amp_factor.checked_add(amp_delta)
+                    .checked_mul(time_delta as u128)
+                    .ok_or(Overflow)?
+                    .checked_div(time_range as u128)
+                    .ok_or(DivideByZero)? as u64;
+
+                self.initial_amp_factor
+                    .checked_add(amp_delta)
+                    .ok_or(Overflow)
             } else {
                 // Ramp down
                 let amp_range = self
                     .initial_amp_factor
-                    .checked_sub(self.target_amp_factor)?;
+                    .checked_sub(self.target_amp_factor)
+                    .ok_or(Overflow)?;
                 let amp_delta = (amp_range as u128)
-                    .checked_mul(time_delta as u128)?
-                    .checked_div(time_range as u128)? as u64;
-                self.initial_amp_factor.checked_sub(amp_delta)
+                    .checked_mul(time_delta as u128)
+                    .ok_or(Overflow)?
+                    .checked_div(time_range as u128)
+                    .ok_or(DivideByZero)? as u64;
+                self.initial_amp_factor
+                    .checked_sub(amp_delta)
+                    .ok_or(Overflow)
             }
         } else {
             // when stop_ramp_ts == 0 or current_ts >= stop_ramp_ts
-            Some(self.target_amp_factor)
+            Ok(self.target_amp_factor)
         }
     }
 
     /// Compute stable swap invariant (D)
-    fn get_d(&self, p_balances: &Vec<u128>) -> Option<U576> {
+    fn get_d(&self, p_balances: &Vec<u128>) -> Result<U576, MathError> {
         let mut sum_x = U192::from(0);
         for &i in p_balances.iter() {
-            sum_x = sum_x.checked_add(i.into())?;
+            sum_x = sum_x.checked_add(i.into()).ok_or(Overflow)?;
         }
         if sum_x == 0.into() {
-            Some(0.into())
+            Ok(0.into())
         } else {
             let amp_factor = self.compute_amp_factor()?;
 
             let mut d_prev: U576;
             let mut d: U576 = sum_x.into();
 
-            for _ in 0..256 {
+            for _ in 0..self.max_iterations {
                 let mut d_prod = d;
                 for &_x in p_balances.iter() {
-                    let x_times_coins = U192::from(_x).checked_mul(self.coin_num.into())?;
-
-                    d_prod = d_prod.checked_mul(d)?.checked_div(x_times_coins.into())?;
+                    let x_times_coins = U192::from(_x)
+                        .checked_mul(self.coin_num.into())
+                        .ok_or(Overflow)?;
+
+                    d_prod = d_prod
+                        .checked_mul(d)
+                        .ok_or(Overflow)?
+                        .checked_div(x_times_coins.into())
+                        .ok_or(DivideByZero)?;
                 }
                 d_prev = d;
 
                 d = self.compute_next_d(amp_factor, d, d_prod, sum_x)?;
                 if d > d_prev {
-                    if d.checked_sub(d_prev)? <= 1.into() {
-                        break;
+                    if d - d_prev <= self.convergence_epsilon.into() {
+                        return Ok(d);
                     }
-                } else if d_prev.checked_sub(d)? <= 1.into() {
-                    break;
+                } else if d_prev - d <= self.convergence_epsilon.into() {
+                    return Ok(d);
                 }
             }
-            Some(d)
+            Err(DNotConverged)
         }
     }
 
@@ -187,14 +321,68 @@ impl SnailStableSwap {
         &self,
         balances: &Vec<u128>,
         total_token_supply: u128,
-    ) -> Option<u128> {
-        let p_balances = self.p_balances_convert(balances).unwrap();
+    ) -> Result<u128, MathError> {
+        let p_balances = self.p_balances_convert(balances)?;
+        let d = self.get_d(&p_balances)?;
+        Ok(d.checked_mul(PRECISION.into())
+            .ok_or(Overflow)?
+            .checked_div(total_token_supply.into())
+            .ok_or(DivideByZero)?
+            .to_u128()
+            .ok_or(Overflow)?)
+    }
+
+    /// Compute the marginal price of coin `i` in coin `j` at the given balances, before fees:
+    /// amount of `j` an infinitesimal amount of `i` swaps into, scaled by PRECISION,
+    /// each amount in its own decimals.
+    pub fn get_spot_price(&self, i: u8, j: u8, balances: &Vec<u128>) -> Result<u128, MathError> {
+        let p_balances = self.p_balances_convert(balances)?;
         let d = self.get_d(&p_balances)?;
-        Some(
-            d.checked_mul(PRECISION.into())?
-                .checked_div(total_token_supply.into())?
-                .to_u128()?,
-        )
+        let amp_factor = self.compute_amp_factor()?;
+        let ann = U576::from(amp_factor as u128)
+            .checked_mul(self.coin_num.into())
+            .ok_or(Overflow)?;
+        // D_P = D^(n+1) / (n^n * prod(x))
+        let mut d_prod = d;
+        for &x in p_balances.iter() {
+            let x_times_coins = U192::from(x)
+                .checked_mul(self.coin_num.into())
+                .ok_or(Overflow)?;
+            d_prod = d_prod
+                .checked_mul(d)
+                .ok_or(Overflow)?
+                .checked_div(x_times_coins.into())
+                .ok_or(DivideByZero)?;
+        }
+        // dx_j / dx_i is the ratio of the partial derivatives of the invariant,
+        // Ann + D_P / x_k for coin k.
+        let x_i = U576::from(p_balances[i as usize]);
+        let x_j = U576::from(p_balances[j as usize]);
+        let numerator = ann
+            .checked_mul(x_i)
+            .ok_or(Overflow)?
+            .checked_add(d_prod)
+            .ok_or(Overflow)?
+            .checked_mul(x_j)
+            .ok_or(Overflow)?
+            .checked_mul(self.rates[i as usize].into())
+            .ok_or(Overflow)?
+            .checked_mul(PRECISION.into())
+            .ok_or(Overflow)?;
+        let denominator = ann
+            .checked_mul(x_j)
+            .ok_or(Overflow)?
+            .checked_add(d_prod)
+            .ok_or(Overflow)?
+            .checked_mul(x_i)
+            .ok_or(Overflow)?
+            .checked_mul(self.rates[j as usize].into())
+            .ok_or(Overflow)?;
+        numerator
+            .checked_div(denominator)
+            .ok_or(DivideByZero)?
+            .to_u128()
+            .ok_or(Overflow)
     }
 
     /// Compute the amount of pool tokens to mint after a deposit
@@ -204,7 +392,7 @@ impl SnailStableSwap {
         balances: &Vec<u128>,
         total_token_supply: u128,
         fees: &Fees,
-    ) -> Option<PoolStatus> {
+    ) -> Result<PoolStatus, MathError> {
         let mut new_balances = balances.clone();
         let mut new_balances_d = balances.clone();
         let mut total_fee_amount = vec![0 as u128; self.coin_num as usize];
@@ -212,17 +400,19 @@ impl SnailStableSwap {
 
         let mut d_0: U576 = 0.into();
         if total_token_supply > 0 {
-            let p_balances = self.p_balances_convert(balances).unwrap();
+            let p_balances = self.p_balances_convert(balances)?;
             d_0 = self.get_d(&p_balances)?;
         }
         for i in 0..new_balances.len() {
             if total_token_supply == 0 {
                 assert!(deposit_amounts[i] > 0); // initial deposit requires depositing all coins
             }
-            new_balances[i] = new_balances[i].checked_add(deposit_amounts[i])?;
+            new_balances[i] = new_balances[i]
+                .checked_add(deposit_amounts[i])
+                .ok_or(Overflow)?;
         }
         // Invariant after change
-        let p_balances_new_balance = self.p_balances_convert(&new_balances).unwrap();
+        let p_balances_new_balance = self.p_balances_convert(&new_balances)?;
         let d_1 = self.get_d(&p_balances_new_balance)?;
         assert!(d_1 > d_0, "d_1 {} > d_0 {}", d_1, d_0);
 
@@ -230,41 +420,56 @@ impl SnailStableSwap {
         if total_token_supply > 0 {
             // Recalculate the invariant accounting for fees
             for i in 0..new_balances.len() {
-                assert!(d_0 != 0.into(), "d_0 == 0");
                 let ideal_balance: U192 = d_1
-                    .checked_mul(balances[i].into())?
-                    .checked_div(d_0)?
-                    .to_u192()?;
+                    .checked_mul(balances[i].into())
+                    .ok_or(Overflow)?
+                    .checked_div(d_0)
+                    .ok_or(DivideByZero)?
+                    .to_u192()
+                    .ok_or(Overflow)?;
 
                 let difference = if ideal_balance > new_balances[i].into() {
-                    ideal_balance.checked_sub(new_balances[i].into())?
+                    ideal_balance
+                        .checked_sub(new_balances[i].into())
+                        .ok_or(InsufficientBalance)?
                 } else {
-                    U192::from(new_balances[i]).checked_sub(ideal_balance)?
+                    U192::from(new_balances[i])
+                        .checked_sub(ideal_balance)
+                        .ok_or(InsufficientBalance)?
                 };
 
-                let diff_u128 = difference.to_u128()?;
-                total_fee_amount[i] = fees.normalized_trade_fee(self.coin_num.into(), diff_u128)?;
-                admin_fee_amount[i] = fees.admin_trade_fee(total_fee_amount[i])?;
+                let diff_u128 = difference.to_u128().ok_or(Overflow)?;
+                total_fee_amount[i] = fees
+                    .normalized_trade_fee(self.coin_num.into(), diff_u128)
+                    .ok_or(Overflow)?;
+                admin_fee_amount[i] = fees.admin_trade_fee(total_fee_amount[i]).ok_or(Overflow)?;
 
-                new_balances[i] = new_balances[i].checked_sub(admin_fee_amount[i])?;
+                new_balances[i] = new_balances[i]
+                    .checked_sub(admin_fee_amount[i])
+                    .ok_or(InsufficientBalance)?;
                 new_balances_d[i] = new_balances[i]
-                    .checked_add(admin_fee_amount[i])?
-                    .checked_sub(total_fee_amount[i])?;
+                    .checked_add(admin_fee_amount[i])
+                    .ok_or(Overflow)?
+                    .checked_sub(total_fee_amount[i])
+                    .ok_or(InsufficientBalance)?;
             }
-            let p_balances = self.p_balances_convert(&new_balances_d).unwrap();
+            let p_balances = self.p_balances_convert(&new_balances_d)?;
             d_2 = self.get_d(&p_balances)?;
         }
         // else. new_balances = old_balances
         // calculate how many tokens to be mint
         let mint_lp_amount = if total_token_supply == 0 {
-            d_1.to_u128()?
+            d_1.to_u128().ok_or(Overflow)?
         } else {
             U576::from(total_token_supply)
-                .checked_mul(d_2.checked_sub(d_0)?)?
-                .checked_div(d_0)?
-                .to_u128()?
+                .checked_mul(d_2.checked_sub(d_0).ok_or(InsufficientBalance)?)
+                .ok_or(Overflow)?
+                .checked_div(d_0)
+                .ok_or(DivideByZero)?
+                .to_u128()
+                .ok_or(Overflow)?
         };
-        Some(PoolStatus {
+        Ok(PoolStatus {
             pool_lp_token_changed: mint_lp_amount,     // calculated
             pool_lp_changed_direction: true,           // false = pool lp increase
             recieved_amount: deposit_amounts.to_vec(), // input parameter
@@ -274,14 +479,16 @@ impl SnailStableSwap {
         })
     }
 
-    fn get_y_raw(&self, i: u8, j: u8, x: u128, balances: &Vec<u128>) -> Option<U576> {
+    fn get_y_raw(&self, i: u8, j: u8, x: u128, balances: &Vec<u128>) -> Result<U576, MathError> {
         assert_ne!(i, j);
         assert!(i < (self.coin_num as u8));
         assert!(j < (self.coin_num as u8));
 
         // c =  D ** (n + 1) / (n ** (2 * n) * prod' * A)
         let amp_factor = self.compute_amp_factor()?;
-        let ann = (amp_factor as u128).checked_mul(self.coin_num.into())?; // A * n ** n
+        let ann = (amp_factor as u128)
+            .checked_mul(self.coin_num.into())
+            .ok_or(Overflow)?; // A * n ** n
         let d = self.get_d(balances)?;
         let mut c = d;
         let mut sum_: U192 = 0.into(); //avoid sum overflow
@@ -294,42 +501,69 @@ impl SnailStableSwap {
             } else {
                 continue;
             }
-            sum_ = sum_.checked_add(_x.into())?;
+            sum_ = sum_.checked_add(_x.into()).ok_or(Overflow)?;
 
             c = c
-                .checked_mul(d)?
-                .checked_div(U192::from(_x).checked_mul(self.coin_num.into())?.into())?;
+                .checked_mul(d)
+                .ok_or(Overflow)?
+                .checked_div(
+                    U192::from(_x)
+                        .checked_mul(self.coin_num.into())
+                        .ok_or(Overflow)?
+                        .into(),
+                )
+                .ok_or(DivideByZero)?;
         }
 
         c = c
-            .checked_mul(d)?
-            .checked_div(ann.checked_mul(self.coin_num.into())?.into())?;
+            .checked_mul(d)
+            .ok_or(Overflow)?
+            .checked_div(
+                ann.checked_mul(self.coin_num.into())
+                    .ok_or(Overflow)?
+                    .into(),
+            )
+            .ok_or(DivideByZero)?;
         // b = sum' - (A*n**n - 1) * D / (A * n**n)
-        let b = d.checked_div(ann.into())?.checked_add(sum_.into())?;
+        let b = d
+            .checked_div(ann.into())
+            .ok_or(DivideByZero)?
+            .checked_add(sum_.into())
+            .ok_or(Overflow)?;
 
         // y approximating: y**2 + b*y = c
         let mut y_prev: U576;
         let mut y = d;
-        for _ in 0..256 {
+        for _ in 0..self.max_iterations {
             y_prev = y;
-            let y_numerator = y.checked_pow(2.into())?.checked_add(c)?;
-            let y_denominator = y.checked_mul(2.into())?.checked_add(b)?.checked_sub(d)?;
-
-            y = y_numerator.checked_div(y_denominator)?;
+            let y_numerator = y
+                .checked_pow(2.into())
+                .ok_or(Overflow)?
+                .checked_add(c)
+                .ok_or(Overflow)?;
+            let y_denominator = y
+                .checked_mul(2.into())
+                .ok_or(Overflow)?
+                .checked_add(b)
+                .ok_or(Overflow)?
+                .checked_sub(d)
+                .ok_or(InsufficientBalance)?;
+
+            y = y_numerator.checked_div(y_denominator).ok_or(DivideByZero)?;
 
             if y > y_prev {
-                if y.checked_sub(y_prev)? <= 1.into() {
-                    break;
+                if y - y_prev <= self.convergence_epsilon.into() {
+                    return Ok(y);
                 }
-            } else if y_prev.checked_sub(y)? <= 1.into() {
-                break;
+            } else if y_prev - y <= self.convergence_epsilon.into() {
+                return Ok(y);
             }
         }
-        Some(y)
+        Err(YNotConverged)
     }
 
-    fn get_y(&self, i: u8, j: u8, x: u128, balances: &Vec<u128>) -> Option<u128> {
-        self.get_y_raw(i, j, x, balances)?.to_u128()
+    fn get_y(&self, i: u8, j: u8, x: u128, balances: &Vec<u128>) -> Result<u128, MathError> {
+        self.get_y_raw(i, j, x, balances)?.to_u128().ok_or(Overflow)
     }
 
     pub fn exchange(
@@ -339,7 +573,7 @@ impl SnailStableSwap {
         dx: u128,
         balances: &Vec<u128>,
         fees: &Fees,
-    ) -> Option<SwapResult> {
+    ) -> Result<SwapResult, MathError> {
         self.exchange_impl(i, j, dx, balances, fees)
     }
 
@@ -350,30 +584,41 @@ impl SnailStableSwap {
         dx: u128,
         balances: &Vec<u128>,
         fees: &Fees,
-    ) -> Option<SwapResult> {
+    ) -> Result<SwapResult, MathError> {
         let ii: usize = i as usize;
         let jj: usize = j as usize;
         let p_balances = self.p_balances_convert(balances)?;
         // overflow checked_add here, make sure x + dx u128
-        let p_x = p_balances[ii].checked_add(dx.checked_mul(self.rates[ii])?)?;
+        let p_x = p_balances[ii]
+            .checked_add(self.to_precision(dx, ii)?)
+            .ok_or(Overflow)?;
         let p_y = self.get_y(i, j, p_x, &p_balances)?;
 
         // -1 to just in case there were some rounding errors
-        let p_dy1 = p_balances[jj].checked_sub(p_y)?.checked_sub(1u128)?;
-        let p_dy_fee = fees.trade_fee(p_dy1)?;
-        let p_admin_fee = fees.admin_trade_fee(p_dy_fee)?;
-        let dy_fee = p_dy_fee.checked_div(self.rates[jj])?;
-        let admin_fee = p_admin_fee.checked_div(self.rates[jj])?;
+        let p_dy1 = p_balances[jj]
+            .checked_sub(p_y)
+            .ok_or(InsufficientBalance)?
+            .checked_sub(1u128)
+            .ok_or(InsufficientBalance)?;
+        let p_dy_fee = fees.trade_fee(p_dy1).ok_or(Overflow)?;
+        let p_admin_fee = fees.admin_trade_fee(p_dy_fee).ok_or(Overflow)?;
+        let dy_fee = self.from_precision(p_dy_fee, jj)?;
+        let admin_fee = self.from_precision(p_admin_fee, jj)?;
 
         // final swapped y amount considering all fees now
         // remove precision
-        let dy = (p_dy1.checked_sub(p_dy_fee)?).checked_div(self.rates[jj])?;
+        let dy =
+            self.from_precision(p_dy1.checked_sub(p_dy_fee).ok_or(InsufficientBalance)?, jj)?;
 
         let mut new_balances = balances.clone();
-        new_balances[ii] = balances[ii].checked_add(dx)?;
-        new_balances[jj] = balances[jj].checked_sub(dy)?.checked_sub(admin_fee)?;
-
-        Some(SwapResult {
+        new_balances[ii] = balances[ii].checked_add(dx).ok_or(Overflow)?;
+        new_balances[jj] = balances[jj]
+            .checked_sub(dy)
+            .ok_or(InsufficientBalance)?
+            .checked_sub(admin_fee)
+            .ok_or(InsufficientBalance)?;
+
+        Ok(SwapResult {
             i_a: i as i8,
             i_b: j as i8,
             amount_a: dx,
@@ -391,7 +636,7 @@ impl SnailStableSwap {
         balances: &Vec<u128>,
         total_token_supply: u128,
         fees: &Fees,
-    ) -> Option<PoolStatus> {
+    ) -> Result<PoolStatus, MathError> {
         return self.remove_liquidity_impl(removed_lp_amount, balances, total_token_supply, fees);
     }
 
@@ -402,41 +647,53 @@ impl SnailStableSwap {
         balances: &Vec<u128>,
         total_token_supply: u128,
         fees: &Fees,
-    ) -> Option<PoolStatus> {
+    ) -> Result<PoolStatus, MathError> {
         let mut recieved_amount = vec![0 as u128; self.coin_num as usize];
         let mut total_fee_amount = vec![0 as u128; self.coin_num as usize];
         let mut admin_fee_amount = vec![0 as u128; self.coin_num as usize];
         let mut new_balances = balances.clone();
 
-        assert!(total_token_supply != 0);
-        assert!(
-            total_token_supply >= removed_lp_amount,
-            "remove lp > total lp"
-        );
+        if total_token_supply == 0 {
+            return Err(DivideByZero);
+        }
+        if removed_lp_amount > total_token_supply {
+            return Err(InsufficientBalance);
+        }
 
         for i in 0..balances.len() {
             let value = U256::from(balances[i])
-                .checked_mul(removed_lp_amount.into())?
-                .checked_div(total_token_supply.into())?
-                .to_u128()?;
-
-            total_fee_amount[i] = fees.withdraw_fee(value)?;
-            admin_fee_amount[i] = fees.admin_withdraw_fee(total_fee_amount[i])?;
+                .checked_mul(removed_lp_amount.into())
+                .ok_or(Overflow)?
+                .checked_div(total_token_supply.into())
+                .ok_or(DivideByZero)?
+                .to_u128()
+                .ok_or(Overflow)?;
+
+            total_fee_amount[i] = fees.withdraw_fee(value).ok_or(Overflow)?;
+            admin_fee_amount[i] = fees
+                .admin_withdraw_fee(total_fee_amount[i])
+                .ok_or(Overflow)?;
 
             // remove patial / remove all
             // if remove all, all LP fees should be recieved to user
             if total_token_supply > removed_lp_amount {
-                recieved_amount[i] = value.checked_sub(total_fee_amount[i])?;
+                recieved_amount[i] = value
+                    .checked_sub(total_fee_amount[i])
+                    .ok_or(InsufficientBalance)?;
             } else {
                 // remove all here
-                recieved_amount[i] = value.checked_sub(admin_fee_amount[i])?;
+                recieved_amount[i] = value
+                    .checked_sub(admin_fee_amount[i])
+                    .ok_or(InsufficientBalance)?;
             }
             new_balances[i] = balances[i]
-                .checked_sub(recieved_amount[i])?
-                .checked_sub(admin_fee_amount[i])?;
+                .checked_sub(recieved_amount[i])
+                .ok_or(InsufficientBalance)?
+                .checked_sub(admin_fee_amount[i])
+                .ok_or(InsufficientBalance)?;
         }
 
-        Some(PoolStatus {
+        Ok(PoolStatus {
             pool_lp_token_changed: removed_lp_amount,  // input parameter
             pool_lp_changed_direction: false,          // false = lp decrease
             recieved_amount: recieved_amount.to_vec(), // calculated
@@ -454,7 +711,7 @@ impl SnailStableSwap {
         balances: &Vec<u128>,
         total_token_supply: u128,
         fees: &Fees,
-    ) -> Option<PoolStatus> {
+    ) -> Result<PoolStatus, MathError> {
         //assert!(remove_coin_amount[i] >= 0);
 
         let mut final_remove_coin_amount = remove_coin_amount.clone();
@@ -470,25 +727,38 @@ impl SnailStableSwap {
         let p_balances = self.p_balances_convert(balances)?;
         let d_0 = self.get_d(&p_balances)?;
         for i in 0..new_balances.len() {
-            new_balances[i] = new_balances[i].checked_sub(remove_coin_amount[i])?;
-            new_balances_d[i] = new_balances_d[i].checked_sub(remove_coin_amount[i])?;
+            new_balances[i] = new_balances[i]
+                .checked_sub(remove_coin_amount[i])
+                .ok_or(InsufficientBalance)?;
+            new_balances_d[i] = new_balances_d[i]
+                .checked_sub(remove_coin_amount[i])
+                .ok_or(InsufficientBalance)?;
         }
         let p_balances = self.p_balances_convert(&new_balances)?;
         let d_1 = self.get_d(&p_balances)?;
 
         for i in 0..new_balances.len() {
             let ideal_balance = U576::from(balances[i])
-                .checked_mul(d_1)?
-                .checked_div(d_0)?
-                .to_u128()?;
+                .checked_mul(d_1)
+                .ok_or(Overflow)?
+                .checked_div(d_0)
+                .ok_or(DivideByZero)?
+                .to_u128()
+                .ok_or(Overflow)?;
             let difference = if ideal_balance > new_balances[i] {
-                ideal_balance.checked_sub(new_balances[i])?
+                ideal_balance
+                    .checked_sub(new_balances[i])
+                    .ok_or(InsufficientBalance)?
             } else {
-                new_balances[i].checked_sub(ideal_balance)?
+                new_balances[i]
+                    .checked_sub(ideal_balance)
+                    .ok_or(InsufficientBalance)?
             };
             // Fee1: trade_fee from difference
-            total_fee_amount[i] = fees.normalized_trade_fee(self.coin_num.into(), difference)?;
-            admin_fee_amount[i] = fees.admin_trade_fee(total_fee_amount[i])?;
+            total_fee_amount[i] = fees
+                .normalized_trade_fee(self.coin_num.into(), difference)
+                .ok_or(Overflow)?;
+            admin_fee_amount[i] = fees.admin_trade_fee(total_fee_amount[i]).ok_or(Overflow)?;
             if total_fee_amount[i] > 0 {
                 assert!(
                     admin_fee_amount[i] < total_fee_amount[i],
@@ -503,8 +773,10 @@ impl SnailStableSwap {
                 "remaining balance not enough for trade fee"
             );
             // Fee2: withdraw_fee from withdraw amounts, usually zero ..
-            withdraw_fee_amount[i] = fees.withdraw_fee(remove_coin_amount[i])?;
-            admin_withdraw_fee_amount[i] = fees.admin_withdraw_fee(withdraw_fee_amount[i])?;
+            withdraw_fee_amount[i] = fees.withdraw_fee(remove_coin_amount[i]).ok_or(Overflow)?;
+            admin_withdraw_fee_amount[i] = fees
+                .admin_withdraw_fee(withdraw_fee_amount[i])
+                .ok_or(Overflow)?;
             if withdraw_fee_amount[i] > 0 {
                 assert!(
                     admin_withdraw_fee_amount[i] < withdraw_fee_amount[i],
@@ -518,42 +790,62 @@ impl SnailStableSwap {
             }
 
             // fees = trade_fee + withdraw_fee
-            total_fee_amount[i] = total_fee_amount[i].checked_add(withdraw_fee_amount[i])?;
-            admin_fee_amount[i] = admin_fee_amount[i].checked_add(admin_withdraw_fee_amount[i])?;
+            total_fee_amount[i] = total_fee_amount[i]
+                .checked_add(withdraw_fee_amount[i])
+                .ok_or(Overflow)?;
+            admin_fee_amount[i] = admin_fee_amount[i]
+                .checked_add(admin_withdraw_fee_amount[i])
+                .ok_or(Overflow)?;
 
             assert!(
                 new_balances[i] > total_fee_amount[i],
                 "remaining balance not enough for withdraw fee"
             );
 
-            new_balances[i] = new_balances[i].checked_sub(admin_fee_amount[i])?;
+            new_balances[i] = new_balances[i]
+                .checked_sub(admin_fee_amount[i])
+                .ok_or(InsufficientBalance)?;
 
             //new_balance_d is used to compute_d, total fees are concluded.
-            new_balances_d[i] = new_balances_d[i].checked_sub(total_fee_amount[i])?;
+            new_balances_d[i] = new_balances_d[i]
+                .checked_sub(total_fee_amount[i])
+                .ok_or(InsufficientBalance)?;
         }
         let p_new_balances_d = self.p_balances_convert(&new_balances_d)?;
         let d_2 = self.get_d(&p_new_balances_d)?;
 
-        let mut burn_token_amount = (d_0.checked_sub(d_2)?)
-            .checked_mul(U576::from(total_token_supply))?
-            .checked_div(d_0)?
-            .to_u128()?;
-
-        burn_token_amount = burn_token_amount.checked_add(1)?; // +1 in case of rounding errors
+        let mut burn_token_amount = (d_0.checked_sub(d_2).ok_or(InsufficientBalance)?)
+            .checked_mul(U576::from(total_token_supply))
+            .ok_or(Overflow)?
+            .checked_div(d_0)
+            .ok_or(DivideByZero)?
+            .to_u128()
+            .ok_or(Overflow)?;
+
+        // +1 in case of rounding errors, never more than the whole supply
+        burn_token_amount = std::cmp::min(
+            burn_token_amount.checked_add(1).ok_or(Overflow)?,
+            total_token_supply,
+        );
         assert!(burn_token_amount > 0);
         // remove all. LP fees should be withdraw to final user
         if d_2 == 0.into() {
             for j in 0..new_balances.len() {
-                let lp_fee_amount = total_fee_amount[j].checked_sub(admin_fee_amount[j])?;
-                final_remove_coin_amount[j] =
-                    final_remove_coin_amount[j].checked_add(lp_fee_amount)?; // LP fee
-                new_balances[j] = new_balances[j].checked_sub(lp_fee_amount)?;
+                let lp_fee_amount = total_fee_amount[j]
+                    .checked_sub(admin_fee_amount[j])
+                    .ok_or(InsufficientBalance)?;
+                final_remove_coin_amount[j] = final_remove_coin_amount[j]
+                    .checked_add(lp_fee_amount)
+                    .ok_or(Overflow)?; // LP fee
+                new_balances[j] = new_balances[j]
+                    .checked_sub(lp_fee_amount)
+                    .ok_or(InsufficientBalance)?;
                 assert_eq!(new_balances[j], 0u128);
                 total_fee_amount[j] = admin_fee_amount[j];
             }
         }
 
-        Some(PoolStatus {
+        Ok(PoolStatus {
             pool_lp_token_changed: burn_token_amount, // calculated
             pool_lp_changed_direction: false,         // false = lp decrease
             recieved_amount: final_remove_coin_amount.to_vec(), // input parameter
@@ -563,12 +855,14 @@ impl SnailStableSwap {
         })
     }
 
-    fn get_y_d_raw(&self, i: u8, balances: &Vec<u128>, d: U576) -> Option<U576> {
+    fn get_y_d_raw(&self, i: u8, balances: &Vec<u128>, d: U576) -> Result<U576, MathError> {
         assert!(i < self.coin_num as u8);
 
         // c =  D ** (n + 1) / (n ** (2 * n) * prod' * A)
         let amp_factor = self.compute_amp_factor()?;
-        let ann = (amp_factor as u128).checked_mul(self.coin_num.into())?; // A * n ** n
+        let ann = (amp_factor as u128)
+            .checked_mul(self.coin_num.into())
+            .ok_or(Overflow)?; // A * n ** n
         let mut c = d;
         let mut sum_: U192 = 0.into();
         let mut _x: u128 = 0;
@@ -578,41 +872,68 @@ impl SnailStableSwap {
             } else {
                 continue;
             }
-            sum_ = sum_.checked_add(_x.into())?;
+            sum_ = sum_.checked_add(_x.into()).ok_or(Overflow)?;
             c = c
-                .checked_mul(d)?
-                .checked_div(U192::from(_x).checked_mul(self.coin_num.into())?.into())?;
+                .checked_mul(d)
+                .ok_or(Overflow)?
+                .checked_div(
+                    U192::from(_x)
+                        .checked_mul(self.coin_num.into())
+                        .ok_or(Overflow)?
+                        .into(),
+                )
+                .ok_or(DivideByZero)?;
         }
         c = c
-            .checked_mul(d)?
-            .checked_div(ann.checked_mul(self.coin_num.into())?.into())?;
+            .checked_mul(d)
+            .ok_or(Overflow)?
+            .checked_div(
+                ann.checked_mul(self.coin_num.into())
+                    .ok_or(Overflow)?
+                    .into(),
+            )
+            .ok_or(DivideByZero)?;
 
         // b = sum' - (A*n**n - 1) * D / (A * n**n)
-        let b = d.checked_div(ann.into())?.checked_add(sum_.into())?;
+        let b = d
+            .checked_div(ann.into())
+            .ok_or(DivideByZero)?
+            .checked_add(sum_.into())
+            .ok_or(Overflow)?;
 
         // y approximating: y**2 + b*y = c
         let mut y_prev: U576;
         let mut y = d;
-        for _ in 0..256 {
+        for _ in 0..self.max_iterations {
             y_prev = y;
             // y = (y * y + c) / (2 * y + b - d);
-            let y_numerator = y.checked_pow(2.into())?.checked_add(c)?;
-            let y_denominator = y.checked_mul(2.into())?.checked_add(b)?.checked_sub(d)?;
-            y = y_numerator.checked_div(y_denominator)?;
+            let y_numerator = y
+                .checked_pow(2.into())
+                .ok_or(Overflow)?
+                .checked_add(c)
+                .ok_or(Overflow)?;
+            let y_denominator = y
+                .checked_mul(2.into())
+                .ok_or(Overflow)?
+                .checked_add(b)
+                .ok_or(Overflow)?
+                .checked_sub(d)
+                .ok_or(InsufficientBalance)?;
+            y = y_numerator.checked_div(y_denominator).ok_or(DivideByZero)?;
 
             if y > y_prev {
-                if y.checked_sub(y_prev)? <= 1.into() {
-                    break;
+                if y - y_prev <= self.convergence_epsilon.into() {
+                    return Ok(y);
                 }
-            } else if y_prev.checked_sub(y)? <= 1.into() {
-                break;
+            } else if y_prev - y <= self.convergence_epsilon.into() {
+                return Ok(y);
             }
         }
-        Some(y)
+        Err(YNotConverged)
     }
 
-    fn get_y_d(&self, i: u8, balances: &Vec<u128>, d: U576) -> Option<u128> {
-        self.get_y_d_raw(i, balances, d)?.to_u128()
+    fn get_y_d(&self, i: u8, balances: &Vec<u128>, d: U576) -> Result<u128, MathError> {
+        self.get_y_d_raw(i, balances, d)?.to_u128().ok_or(Overflow)
     }
 
     pub fn remove_liquidity_one_coin(
@@ -622,7 +943,7 @@ impl SnailStableSwap {
         balances: &Vec<u128>,
         total_token_supply: u128,
         fees: &Fees,
-    ) -> Option<PoolStatus> {
+    ) -> Result<PoolStatus, MathError> {
         self.remove_liquidity_one_coin_impl(i, remove_lp_amount, balances, total_token_supply, fees)
     }
 
@@ -634,46 +955,66 @@ impl SnailStableSwap {
         balances: &Vec<u128>,
         total_token_supply: u128,
         fees: &Fees,
-    ) -> Option<PoolStatus> {
-        assert!(
-            remove_lp_amount <= total_token_supply,
-            "remove lp > total lp"
-        );
+    ) -> Result<PoolStatus, MathError> {
+        if remove_lp_amount > total_token_supply {
+            return Err(InsufficientBalance);
+        }
         let ii = i as usize; // for index i as type of usize
         let p_balances = self.p_balances_convert(balances)?;
         let d_0 = self.get_d(&p_balances)?;
-        let d_1 = d_0.checked_sub(
-            U576::from(remove_lp_amount)
-                .checked_mul(d_0)?
-                .checked_div(total_token_supply.into())?,
-        )?;
+        let d_1 = d_0
+            .checked_sub(
+                U576::from(remove_lp_amount)
+                    .checked_mul(d_0)
+                    .ok_or(Overflow)?
+                    .checked_div(total_token_supply.into())
+                    .ok_or(DivideByZero)?,
+            )
+            .ok_or(InsufficientBalance)?;
 
         let p_new_y = self.get_y_d(i, &p_balances, d_1)?;
-        let p_dy_0 = p_balances[ii].checked_sub(p_new_y)?; // expected p_dy without considering fees
-                                                           //let dy_0 = p_dy_0.checked_div(self.rates[ii])?; // expected dy without considering fees
+        let p_dy_0 = p_balances[ii]
+            .checked_sub(p_new_y)
+            .ok_or(InsufficientBalance)?; // expected p_dy without considering fees
+                                          //let dy_0 = p_dy_0.checked_div(self.rates[ii]).ok_or(DivideByZero)?; // expected dy without considering fees
         let mut p_balances_reduce_fees = p_balances.clone();
         for j in 0..p_balances.len() {
             let p_dx_expected = if j == ii {
                 U576::from(p_balances[j])
-                    .checked_mul(d_1)?
-                    .checked_div(d_0)?
-                    .to_u128()?
-                    .checked_sub(p_new_y)?
+                    .checked_mul(d_1)
+                    .ok_or(Overflow)?
+                    .checked_div(d_0)
+                    .ok_or(DivideByZero)?
+                    .to_u128()
+                    .ok_or(Overflow)?
+                    .checked_sub(p_new_y)
+                    .ok_or(InsufficientBalance)?
             } else {
-                p_balances[j].checked_sub(
-                    U576::from(p_balances[j])
-                        .checked_mul(d_1)?
-                        .checked_div(d_0)?
-                        .to_u128()?,
-                )?
+                p_balances[j]
+                    .checked_sub(
+                        U576::from(p_balances[j])
+                            .checked_mul(d_1)
+                            .ok_or(Overflow)?
+                            .checked_div(d_0)
+                            .ok_or(DivideByZero)?
+                            .to_u128()
+                            .ok_or(Overflow)?,
+                    )
+                    .ok_or(InsufficientBalance)?
             };
             p_balances_reduce_fees[j] = p_balances_reduce_fees[j]
-                .checked_sub(fees.normalized_trade_fee(self.coin_num.into(), p_dx_expected)?)?;
+                .checked_sub(
+                    fees.normalized_trade_fee(self.coin_num.into(), p_dx_expected)
+                        .ok_or(Overflow)?,
+                )
+                .ok_or(InsufficientBalance)?;
         }
         let p_dy = p_balances_reduce_fees[ii]
-            .checked_sub(self.get_y_d(i, &p_balances_reduce_fees, d_1)?)?
-            .checked_sub(1)?; // Withdraw less 1 to account for rounding errors
-                              //let dy = p_dy.checked_div(self.rates[ii])?;
+            .checked_sub(self.get_y_d(i, &p_balances_reduce_fees, d_1)?)
+            .ok_or(InsufficientBalance)?
+            .checked_sub(1)
+            .ok_or(InsufficientBalance)?; // Withdraw less 1 to account for rounding errors
+                                          //let dy = p_dy.checked_div(self.rates[ii]).ok_or(DivideByZero)?;
 
         // preparing output
         let mut recieved_amount = vec![0 as u128; self.coin_num as usize];
@@ -681,35 +1022,43 @@ impl SnailStableSwap {
         let mut admin_fee_amount = vec![0 as u128; self.coin_num as usize];
         let mut new_balances = balances.clone();
         //trade_fee calculate
-        let mut p_total_fee_amount = p_dy_0.checked_sub(p_dy)?;
-        let mut p_admin_fee_amount = fees.admin_trade_fee(p_total_fee_amount)?;
+        let mut p_total_fee_amount = p_dy_0.checked_sub(p_dy).ok_or(InsufficientBalance)?;
+        let mut p_admin_fee_amount = fees.admin_trade_fee(p_total_fee_amount).ok_or(Overflow)?;
         assert!(p_total_fee_amount >= p_admin_fee_amount, "trade_fee error!");
         // withdraw fee calculate
-        let p_withdraw_fee_amount = fees.withdraw_fee(p_dy)?;
-        let p_admin_withdraw_fee_amount = fees.admin_withdraw_fee(p_withdraw_fee_amount)?;
+        let p_withdraw_fee_amount = fees.withdraw_fee(p_dy).ok_or(Overflow)?;
+        let p_admin_withdraw_fee_amount = fees
+            .admin_withdraw_fee(p_withdraw_fee_amount)
+            .ok_or(Overflow)?;
         assert!(
             p_withdraw_fee_amount >= p_admin_withdraw_fee_amount,
             "withdraw_fee error!"
         );
         //total fees = trade_fee + withdraw_fee
-        p_total_fee_amount = p_total_fee_amount.checked_add(p_withdraw_fee_amount)?;
-        p_admin_fee_amount = p_admin_fee_amount.checked_add(p_admin_withdraw_fee_amount)?;
+        p_total_fee_amount = p_total_fee_amount
+            .checked_add(p_withdraw_fee_amount)
+            .ok_or(Overflow)?;
+        p_admin_fee_amount = p_admin_fee_amount
+            .checked_add(p_admin_withdraw_fee_amount)
+            .ok_or(Overflow)?;
         // remove precision
-        total_fee_amount[ii] = p_total_fee_amount.checked_div(self.rates[ii])?;
-        admin_fee_amount[ii] = p_admin_fee_amount.checked_div(self.rates[ii])?;
-
-        recieved_amount[ii] =
-            (p_dy.checked_sub(p_withdraw_fee_amount)?).checked_div(self.rates[ii])?;
-        // new_balance = balance - dy - admin_trade_fee + (withdraw_fee - admin_withdraw_fee)
-        //              = balance - dy - admin_total_fee + withdraw_fee
-        new_balances[ii] = (U192::from(p_balances[ii])
-            .checked_add(p_withdraw_fee_amount.into())?
-            .checked_sub(p_dy.into())?
-            .checked_sub(p_admin_fee_amount.into())?
-            .to_u128()?)
-        .checked_div(self.rates[ii])?; //withdraw_fee. firstly add to avoid overflow
-
-        Some(PoolStatus {
+        total_fee_amount[ii] = self.from_precision(p_total_fee_amount, ii)?;
+        admin_fee_amount[ii] = self.from_precision(p_admin_fee_amount, ii)?;
+
+        recieved_amount[ii] = self.from_precision(
+            p_dy.checked_sub(p_withdraw_fee_amount)
+                .ok_or(InsufficientBalance)?,
+            ii,
+        )?;
+        // new_balance = balance - received - admin_total_fee, both rounded down
+        // so the rounding dust stays in the pool.
+        new_balances[ii] = balances[ii]
+            .checked_sub(recieved_amount[ii])
+            .ok_or(InsufficientBalance)?
+            .checked_sub(admin_fee_amount[ii])
+            .ok_or(InsufficientBalance)?;
+
+        Ok(PoolStatus {
             pool_lp_token_changed: remove_lp_amount,
             pool_lp_changed_direction: false,
             recieved_amount: recieved_amount.to_vec(),
@@ -723,6 +1072,7 @@ impl SnailStableSwap {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::utils::PRECISION_DECIMALS;
     use proptest::prelude::*;
     use rand::Rng;
     use sim::Model;
@@ -746,7 +1096,11 @@ mod tests {
     //pub const MAX_NEAR_INPUT: u128 = 340282366920938463463374607431768211455 >> 4;
 
     /// decimal to 1e24
-    const TEST_RATES: [u128; 3 as usize] = [1000000, 1000000000000000000, 1000000000000000000];
+    const TEST_RATES: [u128; 3 as usize] = [
+        10u128.pow(PRECISION_DECIMALS - 18),
+        10u128.pow(PRECISION_DECIMALS - 6),
+        10u128.pow(PRECISION_DECIMALS - 6),
+    ];
     const TEST_TRADE_FEE: u128 = 4000000;
     const TEST_WITHDRAW_FEE: u128 = 3000000;
     const TEST_FEE_DENOMINATOR: u128 = 10000000000;
@@ -765,6 +1119,10 @@ mod tests {
         trade_fee_denominator: 10000000000,
         withdraw_fee_numerator: 0,
         withdraw_fee_denominator: 10000000000,
+        referral_fee_numerator: 0,
+        referral_fee_denominator: 10000000000,
+        lp_fee_share_numerator: 0,
+        lp_fee_share_denominator: 10000000000,
     };
     //initial Fees with withdraw_fee
     const TEST_FEES_WITH_WITHDRAW_FEE: Fees = Fees {
@@ -776,6 +1134,10 @@ mod tests {
         trade_fee_denominator: 10000000000,
         withdraw_fee_numerator: 3000000,
         withdraw_fee_denominator: 10000000000,
+        referral_fee_numerator: 0,
+        referral_fee_denominator: 10000000000,
+        lp_fee_share_numerator: 0,
+        lp_fee_share_denominator: 10000000000,
     };
     #[test]
     fn test_ramp_amp_up() {
@@ -808,6 +1170,28 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_amp_before_ramp_start() {
+        let initial_amp_factor = 100;
+        let target_amp_factor = initial_amp_factor * 2;
+        let start_ramp_ts = MIN_RAMP_DURATION;
+        let stop_ramp_ts = start_ramp_ts + MIN_RAMP_DURATION;
+        for current_ts in [ZERO_TS, start_ramp_ts - 1] {
+            let snails_swap = SnailStableSwap::new(
+                initial_amp_factor,
+                target_amp_factor,
+                current_ts,
+                start_ramp_ts,
+                stop_ramp_ts,
+                TEST_RATES.to_vec(),
+            );
+            assert_eq!(
+                snails_swap.compute_amp_factor().unwrap(),
+                initial_amp_factor
+            );
+        }
+    }
+
     #[test]
     fn test_ramp_amp_down() {
         let mut rng = rand::thread_rng();
@@ -840,6 +1224,158 @@ mod tests {
         }
     }
 
+    fn setup_math_error_swap() -> SnailStableSwap {
+        SnailStableSwap::new(100, 100, ZERO_TS, ZERO_TS, ZERO_TS, TEST_RATES.to_vec())
+    }
+
+    #[test]
+    fn test_math_error_overflow() {
+        let snails_swap = setup_math_error_swap();
+        let balances = vec![1000, 1000, 1000];
+        let result =
+            snails_swap.exchange(0, 1, u128::MAX, &balances, &TEST_FEES_WITHOUT_WITHDRAW_FEE);
+        assert_eq!(result.err(), Some(MathError::Overflow));
+        assert_eq!(MathError::Overflow.to_string(), "ERR_MATH_OVERFLOW");
+    }
+
+    #[test]
+    fn test_math_error_divide_by_zero() {
+        let snails_swap = setup_math_error_swap();
+        let balances = vec![1000, 1000, 1000];
+        let result = snails_swap.get_virtual_price(&balances, 0);
+        assert_eq!(result.err(), Some(MathError::DivideByZero));
+        assert_eq!(
+            MathError::DivideByZero.to_string(),
+            "ERR_MATH_DIVIDE_BY_ZERO"
+        );
+    }
+
+    #[test]
+    fn test_spot_price() {
+        let snails_swap = setup_math_error_swap();
+        // same value of each coin, in their decimals.
+        let balances = vec![
+            1000 * 10u128.pow(18),
+            1000 * 10u128.pow(6),
+            1000 * 10u128.pow(6),
+        ];
+        assert_eq!(
+            snails_swap.get_spot_price(1, 2, &balances).unwrap(),
+            PRECISION
+        );
+        // 1 DAI is worth 1 USDT, in USDT decimals.
+        assert_eq!(
+            snails_swap.get_spot_price(0, 1, &balances).unwrap(),
+            PRECISION / 10u128.pow(12)
+        );
+
+        // coin 2 is short, so worth more than coin 1.
+        let balances = vec![
+            1000 * 10u128.pow(18),
+            1500 * 10u128.pow(6),
+            500 * 10u128.pow(6),
+        ];
+        let spot = snails_swap.get_spot_price(2, 1, &balances).unwrap();
+        assert!(spot > PRECISION);
+        let reverse = snails_swap.get_spot_price(1, 2, &balances).unwrap();
+        assert!(reverse < PRECISION);
+        // a small swap without fees executes close to the spot price.
+        let dx = 10u128.pow(4);
+        let p_balances = snails_swap.p_balances_convert(&balances).unwrap();
+        let p_y = snails_swap
+            .get_y(2, 1, p_balances[2] + dx * TEST_RATES[2], &p_balances)
+            .unwrap();
+        let dy = (p_balances[1] - p_y) / TEST_RATES[1];
+        let executed = dy * PRECISION / dx;
+        assert!(executed <= spot && executed * 1001 / 1000 >= spot);
+
+        let balances = vec![1000 * 10u128.pow(18), 0, 1000 * 10u128.pow(6)];
+        assert_eq!(
+            snails_swap.get_spot_price(1, 2, &balances).err(),
+            Some(MathError::DivideByZero)
+        );
+    }
+
+    #[test]
+    fn test_math_error_insufficient_balance() {
+        let snails_swap = setup_math_error_swap();
+        let balances = vec![1000, 1000, 1000];
+        let result = snails_swap.remove_liquidity_imbalance(
+            &vec![2000, 0, 0],
+            &balances,
+            3000,
+            &TEST_FEES_WITHOUT_WITHDRAW_FEE,
+        );
+        assert_eq!(result.err(), Some(MathError::InsufficientBalance));
+        assert_eq!(
+            MathError::InsufficientBalance.to_string(),
+            "ERR_MATH_INSUFFICIENT_BALANCE"
+        );
+    }
+
+    #[test]
+    fn test_math_error_remove_more_than_supply() {
+        let snails_swap = setup_math_error_swap();
+        let balances = vec![1000, 1000, 1000];
+        assert_eq!(
+            snails_swap
+                .remove_liquidity(3001, &balances, 3000, &TEST_FEES_WITHOUT_WITHDRAW_FEE)
+                .err(),
+            Some(MathError::InsufficientBalance)
+        );
+        assert_eq!(
+            snails_swap
+                .remove_liquidity(0, &balances, 0, &TEST_FEES_WITHOUT_WITHDRAW_FEE)
+                .err(),
+            Some(MathError::DivideByZero)
+        );
+        assert_eq!(
+            snails_swap
+                .remove_liquidity_one_coin(
+                    0,
+                    3001,
+                    &balances,
+                    3000,
+                    &TEST_FEES_WITHOUT_WITHDRAW_FEE
+                )
+                .err(),
+            Some(MathError::InsufficientBalance)
+        );
+    }
+
+    #[test]
+    fn test_d_not_converged() {
+        let snails_swap =
+            SnailStableSwap::new(1000, 1000, ZERO_TS, ZERO_TS, ZERO_TS, TEST_RATES.to_vec());
+        // highly imbalanced pool, Newton steps for D start far from the solution.
+        let p_balances = vec![PRECISION, 10u128.pow(33), 10u128.pow(33)];
+        assert!(snails_swap.get_d(&p_balances).is_ok());
+
+        let capped_swap = snails_swap.with_max_iterations(2);
+        assert_eq!(
+            capped_swap.get_d(&p_balances).err(),
+            Some(MathError::DNotConverged)
+        );
+        assert_eq!(MathError::DNotConverged.to_string(), "ERR_D_NOT_CONVERGED");
+    }
+
+    #[test]
+    fn test_y_not_converged() {
+        // D of a balanced pool converges on the first step, y needs more.
+        let snails_swap =
+            SnailStableSwap::new(1000, 1000, ZERO_TS, ZERO_TS, ZERO_TS, TEST_RATES.to_vec())
+                .with_max_iterations(1);
+        let p_balances = vec![10u128.pow(30), 10u128.pow(30), 10u128.pow(30)];
+        assert!(snails_swap.get_d(&p_balances).is_ok());
+        assert_eq!(
+            snails_swap
+                .get_y_raw(0, 1, 10u128.pow(32), &p_balances)
+                .err(),
+            Some(MathError::YNotConverged)
+        );
+        assert_eq!(MathError::YNotConverged.to_string(), "ERR_Y_NOT_CONVERGED");
+    }
+
     proptest! {
         #[test]
         fn test_random_p_balances(
@@ -940,6 +1476,70 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_rate_scaled_epsilon() {
+        let snails_swap =
+            SnailStableSwap::new(100, 100, ZERO_TS, ZERO_TS, ZERO_TS, TEST_RATES.to_vec());
+        assert_eq!(snails_swap.convergence_epsilon, 1);
+        // a thousandth of a 6 decimals unit.
+        let snails_swap = snails_swap.with_rate_scaled_epsilon();
+        assert_eq!(
+            snails_swap.convergence_epsilon,
+            10u128.pow(PRECISION_DECIMALS - 6) / CONVERGENCE_EPSILON_DIVISOR
+        );
+        // 24 decimals coins keep the default.
+        let snails_swap = SnailStableSwap::new(100, 100, ZERO_TS, ZERO_TS, ZERO_TS, vec![1, 1])
+            .with_rate_scaled_epsilon();
+        assert_eq!(snails_swap.convergence_epsilon, 1);
+    }
+
+    proptest! {
+        #[test]
+        fn test_rate_scaled_epsilon_parity(
+            amp_factor in MIN_AMP..MAX_AMP,
+            b0 in 1..MAX_DAI_INPUT,
+            b1 in 1..MAX_USDT_INPUT,
+            b2 in 1..MAX_USDC_INPUT,
+            x in 1..TEST_MAX_DX_WITHOUT_DECIMAL,
+        ) {
+            let balances = [b0, b1, b2];
+            let model = Model::new(
+                amp_factor.into(),
+                balances.to_vec(),
+                TEST_N_COIN,
+                TEST_RATES.to_vec(),
+                TEST_TRADE_FEE,
+                TEST_WITHDRAW_FEE,
+                0
+            );
+            let snails_swap = SnailStableSwap::new(
+                amp_factor,
+                amp_factor,
+                ZERO_TS,
+                ZERO_TS,
+                ZERO_TS,
+                TEST_RATES.to_vec(),
+            )
+            .with_rate_scaled_epsilon();
+            let epsilon = U576::from(snails_swap.convergence_epsilon);
+            let p_balances = snails_swap.p_balances_convert(&balances.to_vec()).unwrap();
+
+            // stopping earlier stays within the epsilon of the model, which stops within 1.
+            let d = snails_swap.get_d(&p_balances).unwrap();
+            let d_python = U576::from_dec_str(&model.sim_d().to_string()).unwrap();
+            let d_diff = if d > d_python { d - d_python } else { d_python - d };
+            assert!(d_diff <= epsilon + 1);
+
+            // y also carries the difference of D, in units of the coin it stays within rounding.
+            let p_x = x.checked_mul(TEST_RATES[1]).unwrap();
+            let y = snails_swap.get_y_raw(1, 2, p_x, &p_balances).unwrap() / TEST_RATES[2];
+            let y_python = U576::from_dec_str(&model.sim_y(1, 2, p_x.into()).to_string()).unwrap()
+                / TEST_RATES[2];
+            let y_diff = if y > y_python { y - y_python } else { y_python - y };
+            assert!(y_diff <= 1.into());
+        }
+    }
+
     fn check_y(
         model: &Model,
         i: u8,
@@ -1906,6 +2506,49 @@ mod tests {
         }
     }
 
+    proptest! {
+        #[test]
+        fn test_tiny_remove_one_coin_never_decreases_virtual_price_proptest(
+            amp_factor in MIN_AMP..MAX_AMP,
+            x0 in 1_000..1_000_000u128,
+            x1 in 1_000..1_000_000u128,
+            x2 in 1_000..1_000_000u128,
+            i in 0..TEST_N_COIN,
+            remove_lp in 1..1_000_000_000u128,
+        ) {
+            let snails_swap = SnailStableSwap::new(
+                amp_factor,
+                amp_factor,
+                ZERO_TS,
+                ZERO_TS,
+                ZERO_TS,
+                TEST_RATES.to_vec(),
+            );
+            let mut balances = vec![x0 * 10u128.pow(18), x1 * 10u128.pow(6), x2 * 10u128.pow(6)];
+            let p_balances = snails_swap.p_balances_convert(&balances).unwrap();
+            let mut total_token_supply = snails_swap.get_d(&p_balances).unwrap().to_u128().unwrap();
+            let mut virtual_price = snails_swap.get_virtual_price(&balances, total_token_supply).unwrap();
+
+            for _ in 0..20 {
+                // removals too small to withdraw anything are rejected.
+                if let Ok(pool_status) = snails_swap.remove_liquidity_one_coin(
+                    i,
+                    remove_lp,
+                    &balances,
+                    total_token_supply,
+                    &TEST_FEES_WITH_WITHDRAW_FEE,
+                ) {
+                    balances = pool_status.new_balances;
+                    total_token_supply -= remove_lp;
+                    let new_virtual_price =
+                        snails_swap.get_virtual_price(&balances, total_token_supply).unwrap();
+                    prop_assert!(new_virtual_price >= virtual_price);
+                    virtual_price = new_virtual_price;
+                }
+            }
+        }
+    }
+
     #[test]
     fn test_snails_remove_one_coin_with_random_inputs() {
         for _ in 0..200 {
@@ -54,19 +54,36 @@ impl StorageManagement for SnailSwap {
             .unwrap()
     }
 
-    #[allow(unused_variables)]
+    /// Closes the caller's account and refunds its whole storage deposit.
+    /// Token balances and LP shares should be zero, unless `force` is set,
+    /// then they are forfeited to lostfound.
     #[payable]
     fn storage_unregister(&mut self, force: Option<bool>) -> bool {
         assert_one_yocto();
         self.assert_contract_running();
         let account_id = env::predecessor_account_id();
-        if let Some(account_deposit) = self.internal_get_account(&account_id) {
-            // TODO: figure out force option logic.
-            assert!(
-                account_deposit.tokens.is_empty(),
-                "ERR_STORAGE_UNREGISTER_TOKENS_NOT_EMPTY"
-            );
+        let force = force.unwrap_or(false);
+        if let Some(mut account_deposit) = self.internal_get_account(&account_id) {
+            let balances = account_deposit.get_balances();
+            if force {
+                // lostfound is the owner's account, it can't forfeit to itself.
+                assert_ne!(account_id, self.owner_id, "ERR_OWNER_FORCE_UNREGISTER");
+            } else {
+                assert!(
+                    balances.iter().all(|(_, amount)| *amount == 0),
+                    "ERR_STORAGE_UNREGISTER_TOKENS_NOT_EMPTY"
+                );
+            }
+            self.internal_unregister_lp_shares(&account_id, force);
+            for (token_id, amount) in balances {
+                if amount > 0 {
+                    self.internal_lostfound(&token_id, amount);
+                }
+            }
+            account_deposit.tokens.clear();
             self.accounts.remove(&account_id);
+            // accounts registered before `migrate` are not counted
+            self.account_count = self.account_count.saturating_sub(1);
             Promise::new(account_id.clone()).transfer(account_deposit.near_amount);
             true
         } else {
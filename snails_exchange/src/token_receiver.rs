@@ -19,22 +19,97 @@ enum TokenReceiverMessage {
         token_out: AccountId,
         /// Required minimum amount of token_out.
         min_amount_out: U128,
+        /// Account which receives the referral part of the fee.
+        referral_id: Option<AccountId>,
+    },
+    /// Alternative to deposit + add_liquidity call, the received token is the only one added.
+    AddLiquidity {
+        /// Pool to add the token to.
+        pool_id: u64,
+        /// Required minimum amount of LP shares minted.
+        min_mint_amount: U128,
     },
 }
 
 impl SnailSwap {
+    /// Pool of a direct swap must exist and hold both tokens,
+    /// otherwise the received tokens are refunded rather than panicking mid-callback.
+    fn is_direct_swap_valid(
+        &self,
+        pool_id: u64,
+        token_in: &AccountId,
+        token_out: &AccountId,
+    ) -> bool {
+        if pool_id >= self.pools.len() {
+            return false;
+        }
+        let pool = self.internal_get_pool(pool_id);
+        pool.tokens().contains(token_in) && pool.tokens().contains(token_out)
+    }
+
     fn direct_swap(
         &mut self,
+        sender_id: &AccountId,
         pool_id: u64,
         token_in: &AccountId,
         token_out: &AccountId,
         amount_in: Balance,
         min_amount_out: Balance,
+        referral_id: &Option<AccountId>,
     ) -> Balance {
-        let amount_out = self.swap_core(pool_id, token_in, amount_in, token_out, min_amount_out);
+        // base LP of a metapool can not be sent out as a fungible token.
+        assert!(
+            self.pools
+                .get(pool_id)
+                .expect("ERR_NO_POOL")
+                .base_pool_of(token_out)
+                .is_none(),
+            "ERR_BASE_LP_NOT_TRANSFERABLE"
+        );
+        let amount_out = self.swap_core(
+            sender_id,
+            pool_id,
+            token_in,
+            amount_in,
+            token_out,
+            min_amount_out,
+            referral_id,
+        );
 
         amount_out.into()
     }
+
+    /// Adds `amount` of `token_in` alone to the pool, going through the imbalanced deposit path.
+    /// Sender must be registered to the pool LP token, as storage can't be paid here.
+    fn direct_add_liquidity(
+        &mut self,
+        sender_id: &AccountId,
+        pool_id: u64,
+        token_in: &AccountId,
+        amount: Balance,
+        min_mint_amount: Balance,
+    ) -> Balance {
+        let mut pool = self.internal_get_pool(pool_id);
+        assert!(
+            pool.is_lp_token_registered(sender_id),
+            "{}",
+            LP_NOT_REGISTERED
+        );
+        let amounts: Vec<Balance> = pool
+            .tokens()
+            .iter()
+            .map(|id| if id == token_in { amount } else { 0 })
+            .collect();
+        assert!(amounts.iter().any(|a| *a > 0), "ERR_MISSING_TOKEN");
+
+        let (lp_shares, admin_fees) = pool.add_liquidity(sender_id, &amounts);
+        assert!(lp_shares >= min_mint_amount, "ERR_MIN_MINT_AMOUNT");
+
+        self.transfer_admin_fees(&pool, &admin_fees);
+        self.pools.replace(pool_id, &pool);
+
+        lp_shares
+    }
 }
 #[near_bindgen]
 impl FungibleTokenReceiver for SnailSwap {
@@ -48,6 +123,8 @@ impl FungibleTokenReceiver for SnailSwap {
     ) -> PromiseOrValue<U128> {
         self.assert_contract_running();
         let token_in = env::predecessor_account_id();
+        self.assert_token_accepted(&token_in);
+        self.assert_token_not_paused(&token_in);
 
         env::log_str(
             format!(
@@ -61,6 +138,7 @@ impl FungibleTokenReceiver for SnailSwap {
 
         if msg.is_empty() {
             // Simple deposit.
+            self.internal_add_managed(&token_in, amount.0);
             self.internal_deposit(&sender_id, &token_in, amount.into());
             PromiseOrValue::Value(U128(0))
         } else {
@@ -72,13 +150,27 @@ impl FungibleTokenReceiver for SnailSwap {
                     pool_id,
                     token_out,
                     min_amount_out,
+                    referral_id,
                 } => {
+                    if !self.is_direct_swap_valid(pool_id, &token_in, &token_out) {
+                        env::log_str(
+                            format!(
+                                "{}: pool {} token_in {} token_out {}, refunding {}",
+                                INVALID_DIRECT_SWAP, pool_id, token_in, token_out, amount.0
+                            )
+                            .as_str(),
+                        );
+                        return PromiseOrValue::Value(amount);
+                    }
+                    self.internal_add_managed(&token_in, amount.0);
                     let amount_out = self.direct_swap(
+                        &sender_id,
                         pool_id,
                         &token_in,
                         &token_out,
                         amount.0,
                         min_amount_out.0,
+                        &referral_id,
                     );
 
                     env::log_str(format!("Direct swap from sender {} pool {} token_in {} amount {} for token_out {} min_amount {}  ", 
@@ -89,6 +181,29 @@ impl FungibleTokenReceiver for SnailSwap {
                     // Even if send tokens fails, we don't return funds back to sender.
                     PromiseOrValue::Value(U128(0))
                 }
+                TokenReceiverMessage::AddLiquidity {
+                    pool_id,
+                    min_mint_amount,
+                } => {
+                    self.internal_add_managed(&token_in, amount.0);
+                    let lp_shares = self.direct_add_liquidity(
+                        &sender_id,
+                        pool_id,
+                        &token_in,
+                        amount.0,
+                        min_mint_amount.0,
+                    );
+
+                    env::log_str(
+                        format!(
+                            "Direct add liquidity from sender {} pool {} token_in {} amount {} minted {}",
+                            sender_id, pool_id, token_in, amount.0, lp_shares
+                        )
+                        .as_str(),
+                    );
+
+                    PromiseOrValue::Value(U128(0))
+                }
             }
         }
     }
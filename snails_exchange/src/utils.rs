@@ -5,7 +5,7 @@ use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
 use near_sdk::collections::LookupMap;
 use near_sdk::json_types::U128;
 use near_sdk::serde::{Deserialize, Serialize};
-use near_sdk::{ext_contract, AccountId, Balance, Gas};
+use near_sdk::{env, ext_contract, AccountId, Balance, Gas};
 /// Attach no deposit.
 pub const NO_DEPOSIT: u128 = 0;
 /// hotfix_insuffient_gas_for_mft_resolve_transfer, increase from 5T to 20T
@@ -16,8 +16,144 @@ pub const GAS_FOR_FT_TRANSFER_CALL: Gas = Gas(25_000_000_000_000 + GAS_FOR_RESOL
 /// Amount of gas for fungible token transfers.
 pub const GAS_FOR_FT_TRANSFER: Gas = Gas(10_000_000_000_000);
 
-/// 1e24
-pub const PRECISION: u128 = 1_000_000_000_000_000_000_000_000;
+/// Decimals every token amount is normalized to by the rates, LP tokens have as many decimals.
+/// Fixed at build time, defaults to 24.
+pub const PRECISION_DECIMALS: u32 = 24;
+
+/// 10^PRECISION_DECIMALS, 1e24 by default
+pub const PRECISION: u128 = 10u128.pow(PRECISION_DECIMALS);
+
+/// Maximal number of coins in a pool supported by the invariant math.
+pub const MAX_COINS: u8 = 3;
+
+/// Newton iterations allowed for the D and y solvers before failing as not converged.
+pub const MAX_SOLVER_ITERATIONS: u32 = 256;
+
+/// Fraction of the smallest unit of the coarsest coin the rate scaled solvers stop within,
+/// far below what rounding to that coin's decimals can see.
+pub const CONVERGENCE_EPSILON_DIVISOR: u128 = 1_000;
+
+/// Minimal duration of an amp ramp in seconds.
+pub const MIN_RAMP_DURATION: u64 = 86400;
+/// Maximal factor an amp ramp can change A by.
+pub const MAX_AMP_CHANGE: u64 = 10;
+
+/// Minimal delay in seconds before committed fees can be applied.
+pub const MIN_FEE_CHANGE_DELAY: u64 = 3 * 86400;
+
+/// Factor the seed amounts of a pool, in 24 decimals, must stay below of each other.
+/// Amounts given at the wrong decimals are off by a factor 10 at least.
+pub const MAX_SEED_IMBALANCE: u128 = 10;
+
+/// Denominator of amounts given in basis points.
+pub const BPS_DENOMINATOR: u128 = 10_000;
+
+/// Minimal amount of each coin, in whole tokens, a swap has to leave in the pool,
+/// so it can never be drained into states the invariant math can't handle.
+pub const MIN_RESERVE: u128 = 1;
+
+/// Default minimal amount of LP burnt by a single one-coin removal,
+/// so repeated dust removals can't add up the rounding in their favor.
+pub const MIN_ONE_COIN_BURN: u128 = 1_000_000;
+
+/// Minimal amount of LP the first deposit of a pool has to mint, 0.001 LP.
+pub const MIN_INITIAL_SHARES: u128 = PRECISION / 1_000;
+
+/// LP taken out of the first deposit of a pool and locked forever, 0.000001 LP.
+/// Pool supply never goes back to zero, so no later depositor can set the share price from scratch.
+pub const LOCKED_INITIAL_SHARES: u128 = MIN_INITIAL_SHARES / 1_000;
+
+/// Maximal number of pools returned by a single `get_pools` call, keeps the view within gas.
+pub const MAX_POOLS_PER_VIEW: u64 = 100;
+
+/// Maximal number of transfers made by a single `withdraw_all` call,
+/// each one takes GAS_FOR_FT_TRANSFER and GAS_FOR_RESOLVE_TRANSFER.
+pub const MAX_WITHDRAW_ALL_TRANSFERS: usize = 6;
+
+/// Gas budgeted for a single hop of a routed swap.
+pub const GAS_FOR_SWAP_HOP: Gas = Gas(25_000_000_000_000);
+/// Maximal number of hops in a routed swap, keeps the route well below the 300 Tgas call limit.
+pub const MAX_ROUTE_LEN: usize = 8;
+
+/// Amount of gas for wrapping NEAR on the wrap contract.
+pub const GAS_FOR_NEAR_DEPOSIT: Gas = Gas(10_000_000_000_000);
+/// Amount of gas for swapping the NEAR wrapped by `swap_near` and sending the output.
+pub const GAS_FOR_SWAP_WRAPPED_NEAR: Gas =
+    Gas(GAS_FOR_SWAP_HOP.0 + GAS_FOR_FT_TRANSFER.0 + GAS_FOR_RESOLVE_TRANSFER.0);
+/// Amount of gas for the callback of `swap_near`, which deposits the wrapped NEAR
+/// and schedules the swap.
+pub const GAS_FOR_SWAP_NEAR_CALLBACK: Gas = Gas(10_000_000_000_000 + GAS_FOR_SWAP_WRAPPED_NEAR.0);
+/// Amount of gas for reading the contract's own token balance.
+pub const GAS_FOR_FT_BALANCE_OF: Gas = Gas(5_000_000_000_000);
+/// Amount of gas for the callback of `retrieve_unmanaged_tokens`, which sends the tokens out.
+pub const GAS_FOR_RETRIEVE_CALLBACK: Gas =
+    Gas(10_000_000_000_000 + GAS_FOR_FT_TRANSFER.0 + GAS_FOR_RESOLVE_TRANSFER.0);
+
+/// Single hop of a routed swap.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub struct SwapAction {
+    /// Pool which should be used for swapping.
+    pub pool_id: u64,
+    /// Token to swap from, the token_out of the previous hop.
+    pub token_in: AccountId,
+    /// Token to swap into.
+    pub token_out: AccountId,
+    /// Required minimum amount of token_out of this hop.
+    pub min_amount_out: U128,
+}
+
+/// Arguments of a simple pool, as taken by `add_simple_pool`.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub struct SimplePoolArgs {
+    pub tokens: Vec<AccountId>,
+    pub decimals: Vec<u64>,
+    pub initial_amp_factor: u64,
+    pub target_amp_factor: u64,
+    pub start_ramp_ts: u64,
+    pub stop_ramp_ts: u64,
+    pub fees: Fees,
+}
+
+/// Amp ramp of a pool, starting now.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub struct AmpParams {
+    pub initial_amp_factor: u64,
+    pub target_amp_factor: u64,
+    /// Unix timestamp in seconds the ramp ends at.
+    pub stop_ramp_ts: u64,
+}
+
+impl AmpParams {
+    /// Checks the ramp starting at `start_ramp_ts` lasts at least `MIN_RAMP_DURATION`
+    /// and moves A by at most `MAX_AMP_CHANGE` times from `current_amp_factor`,
+    /// the A of the pool when the ramp starts.
+    pub fn assert_valid(&self, start_ramp_ts: u64, current_amp_factor: u64) {
+        assert!(
+            self.stop_ramp_ts >= start_ramp_ts + MIN_RAMP_DURATION,
+            "ERR_RAMP_TOO_SHORT"
+        );
+        assert!(
+            amp_change_valid(current_amp_factor, self.initial_amp_factor)
+                && amp_change_valid(current_amp_factor, self.target_amp_factor),
+            "ERR_RAMP_TOO_FAST"
+        );
+    }
+}
+
+/// Whether A moves from `from` to `to` by at most `MAX_AMP_CHANGE` times either way.
+fn amp_change_valid(from: u64, to: u64) -> bool {
+    from.checked_mul(MAX_AMP_CHANGE)
+        .map_or(true, |max| to <= max)
+        && to
+            .checked_mul(MAX_AMP_CHANGE)
+            .map_or(true, |max| from <= max)
+}
 
 /// Volume of swap on the given token.
 #[derive(Clone, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
@@ -42,6 +178,22 @@ pub fn check_token_duplicates(tokens: &[AccountId]) {
     assert_eq!(token_set.len(), tokens.len(), "ERR_TOKEN_DUPLICATES");
 }
 
+/// Checks every token has at most as many decimals as amounts are normalized to,
+/// so it can be given a rate.
+pub fn assert_decimals_valid(decimals: &[u64]) {
+    assert!(
+        decimals.iter().all(|d| *d <= PRECISION_DECIMALS as u64),
+        "ERR_DECIMALS_TOO_HIGH"
+    );
+}
+
+/// Checks a swap moves a non zero amount between two different tokens,
+/// before it gets to the pool math.
+pub fn assert_swap_valid(token_in: &AccountId, amount_in: Balance, token_out: &AccountId) {
+    assert!(amount_in > 0, "ERR_ZERO_AMOUNT");
+    assert_ne!(token_in, token_out, "ERR_SAME_TOKEN");
+}
+
 pub fn assert_fees_info_valid(fees: &Fees) {
     assert!(
         fees.admin_trade_fee_denominator != 0 as u64,
@@ -59,6 +211,54 @@ pub fn assert_fees_info_valid(fees: &Fees) {
         fees.withdraw_fee_denominator != 0 as u64,
         "ERR_withdraw_fee_denominator"
     );
+    assert!(
+        fees.referral_fee_denominator != 0 as u64,
+        "ERR_referral_fee_denominator"
+    );
+    assert!(
+        fees.lp_fee_share_denominator != 0 as u64,
+        "ERR_lp_fee_share_denominator"
+    );
+    assert!(
+        fees.trade_fee_numerator <= fees.trade_fee_denominator,
+        "ERR_trade_fee_exceed_100_percent"
+    );
+    assert!(
+        fees.withdraw_fee_numerator <= fees.withdraw_fee_denominator,
+        "ERR_withdraw_fee_exceed_100_percent"
+    );
+    // admin fees are parts of the trade and withdraw fees.
+    assert!(
+        fees.admin_trade_fee_numerator <= fees.admin_trade_fee_denominator,
+        "ERR_admin_trade_fee_exceed_trade_fee"
+    );
+    assert!(
+        fees.admin_withdraw_fee_numerator <= fees.admin_withdraw_fee_denominator,
+        "ERR_admin_withdraw_fee_exceed_withdraw_fee"
+    );
+    assert!(
+        fees.lp_fee_share_numerator <= fees.lp_fee_share_denominator,
+        "ERR_lp_fee_share_exceed_admin_fee"
+    );
+    // referral_fee / referral_denominator <= admin_trade_fee / admin_trade_denominator
+    assert!(
+        (fees.referral_fee_numerator as u128) * (fees.admin_trade_fee_denominator as u128)
+            <= (fees.admin_trade_fee_numerator as u128) * (fees.referral_fee_denominator as u128),
+        "ERR_referral_fee_exceed_admin_fee"
+    );
+}
+
+/// Panics with the storage cost needed, the deposit attached and the shortfall.
+pub fn panic_storage_deposit(needed: Balance, attached: Balance) -> ! {
+    env::panic_str(
+        format!(
+            "ERR_STORAGE_DEPOSIT: needed {}, attached {}, short of {}",
+            needed,
+            attached,
+            needed.saturating_sub(attached)
+        )
+        .as_str(),
+    )
 }
 
 /// Adds given value to item stored in the given key in the LookupMap collection.
@@ -70,10 +270,37 @@ pub fn add_to_collection(c: &mut LookupMap<AccountId, Balance>, key: &String, va
 
 #[ext_contract(ext_self)]
 pub trait SnailExchange {
-    fn exchange_callback_post_withdraw(
+    fn exchange_callback_post_withdraw(&mut self, nonce: u64);
+
+    fn exchange_callback_post_wrap_near(
         &mut self,
-        token_id: AccountId,
+        pool_id: u64,
         sender_id: AccountId,
         amount: U128,
-    );
+        token_out: AccountId,
+        min_amount_out: U128,
+    ) -> near_sdk::PromiseOrValue<U128>;
+
+    fn exchange_swap_wrapped_near(
+        &mut self,
+        pool_id: u64,
+        sender_id: AccountId,
+        amount: U128,
+        token_out: AccountId,
+        min_amount_out: U128,
+    ) -> U128;
+
+    fn exchange_callback_retrieve_unmanaged(
+        &mut self,
+        token_id: AccountId,
+        amount: U128,
+        receiver_id: AccountId,
+    ) -> near_sdk::Promise;
+
+    fn exchange_callback_post_retrieve(&mut self, token_id: AccountId, amount: U128);
+}
+
+#[ext_contract(ext_wrap_near)]
+pub trait WrapNear {
+    fn near_deposit(&mut self);
 }
@@ -2,8 +2,13 @@
 
 use std::collections::HashMap;
 
+use crate::bigint::U256;
+use crate::meta_pool::LP_DECIMALS;
+use crate::utils::{
+    BPS_DENOMINATOR, MAX_COINS, MAX_POOLS_PER_VIEW, MAX_ROUTE_LEN, MAX_SOLVER_ITERATIONS, PRECISION,
+};
 use crate::*;
-use near_sdk::json_types::U128;
+use near_sdk::json_types::{U128, U64};
 use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::{near_bindgen, AccountId};
 
@@ -15,6 +20,33 @@ pub struct ContractMetadata {
     pub owner: AccountId,
     pub pool_count: u64,
     pub state: RunningState,
+    pub guardians: Vec<AccountId>,
+    pub wrap_near_id: Option<AccountId>,
+}
+
+/// Constants of the stable swap math, for clients mirroring it off-chain.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Deserialize, Debug))]
+pub struct MathConstants {
+    /// Precision amounts are normalized to by the rates.
+    pub precision: U128,
+    /// Maximal number of coins in a pool.
+    pub max_coins: u8,
+    /// Newton iterations allowed for the D and y solvers.
+    pub iterations: u32,
+}
+
+/// Storage used by the contract, to forecast the storage staking.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Deserialize, Debug))]
+pub struct StorageReport {
+    /// Bytes used by the contract account.
+    pub total_bytes: u64,
+    /// Number of registered accounts.
+    pub account_count: u64,
+    pub pool_count: u64,
 }
 
 #[derive(Serialize, Deserialize, PartialEq)]
@@ -29,6 +61,12 @@ pub struct RefStorageState {
 #[serde(crate = "near_sdk::serde")]
 #[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq))]
 pub struct PoolInfo {
+    /// Index of the pool.
+    pub pool_id: u64,
+    /// Pool kind.
+    pub pool_kind: String,
+    /// Base pool of a metapool.
+    pub base_pool_id: Option<u64>,
     /// List of tokens in the pool.
     pub token_account_ids: Vec<AccountId>,
     pub token_decimals: Vec<u64>,
@@ -45,21 +83,134 @@ pub struct PoolInfo {
     pub start_ramp_ts: U128,
     /// Ramp A stop timestamp
     pub stop_ramp_ts: U128,
+    /// Current amplification coefficient, interpolated while A is ramping.
+    pub amp_factor: U128,
+    /// Current virtual price, 0 for a pool without liquidity.
+    pub virtual_price: U128,
+    /// Whether imbalanced liquidity changes are rejected while A is ramping.
+    pub block_liquidity_during_ramp: bool,
+    /// Whether the solvers stop within a fraction of one unit of the coin with the fewest decimals.
+    pub rate_scaled_epsilon: bool,
+    /// Cap on the shares a single account can hold, None for unlimited.
+    pub max_share_per_account: Option<U128>,
+    /// Minimal amount of LP a single one-coin removal has to burn.
+    pub min_one_coin_burn: U128,
+    /// Part of the trade fee, in bps, given back to swappers holding shares of the pool.
+    pub lp_fee_discount_bps: u32,
+    /// Account allowed to update the rates besides the owner.
+    pub rate_provider: Option<AccountId>,
+    /// Whether the pool is shut down, only allowing proportional removal of liquidity.
+    pub shutdown: bool,
+    /// Unix timestamp in seconds the pool was created at.
+    pub created_at: U64,
+}
+
+/// Amp factor ramp of a pool, timestamps in seconds.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq))]
+pub struct RampSchedule {
+    pub initial_amp: U128,
+    pub target_amp: U128,
+    pub start_ramp_ts: U128,
+    pub stop_ramp_ts: U128,
+    /// Amp factor at the block timestamp.
+    pub current_amp: U128,
+}
+
+/// Quote of an underlying metapool swap.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq))]
+pub struct UnderlyingReturn {
+    pub amount_out: U128,
+    /// Fee charged by the metapool, in its output token.
+    pub meta_fee: U128,
+    /// Fee charged by the base pool, in its output token.
+    pub base_fee: U128,
+}
+
+/// Quote of a deposit of a single token.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq))]
+pub struct SingleDepositImpact {
+    /// Shares minted for the deposit, after fees.
+    pub lp_minted: U128,
+    /// Minted shares against the shares the deposit is worth at the virtual price, in bps.
+    /// Positive if the pool is short of the token, negative for the penalty otherwise.
+    pub bonus_or_penalty_bps: i32,
+}
+
+/// Balance cap of a token of a pool.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq))]
+pub struct BalanceCap {
+    /// 0 for unlimited.
+    pub cap: U128,
+    /// Amount the balance can still grow by, None if unlimited.
+    pub headroom: Option<U128>,
+}
+
+/// Fee-inclusive quote of a routed swap.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq))]
+pub struct RouteQuote {
+    /// Output of the last hop.
+    pub final_out: U128,
+    /// Fee charged by each hop, in its token_out.
+    pub per_hop_fees: Vec<U128>,
+}
+
+/// Fees of a pool together with the fees it accrued.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq))]
+pub struct PoolFeeInfo {
+    pub fees: Fees,
+    /// Total fees accrued, per token of the pool.
+    pub total_fees: Vec<U128>,
+    /// Admin fees accrued, per token of the pool.
+    pub admin_fees: Vec<U128>,
 }
 
-impl From<Pool> for PoolInfo {
-    fn from(pool: Pool) -> Self {
-        match pool {
-            Pool::SimplePool(pool) => Self {
-                token_account_ids: pool.token_account_ids,
-                token_decimals: pool.token_decimals,
-                amounts: pool.amounts.into_iter().map(|a| U128(a)).collect(),
-                shares_total_supply: U128(pool.shares_total_supply),
-                initial_amp_factor: U128(pool.initial_amp_factor.into()),
-                target_amp_factor: U128(pool.target_amp_factor.into()),
-                start_ramp_ts: U128(pool.start_ramp_ts.into()),
-                stop_ramp_ts: U128(pool.stop_ramp_ts.into()),
-            },
+impl PoolInfo {
+    pub fn new(pool_id: u64, pool: Pool) -> Self {
+        let pool_kind = pool.kind();
+        let amp_factor = pool.get_amp_factor();
+        let virtual_price = if pool.share_total_balance() > 0 {
+            pool.get_virtual_price()
+        } else {
+            0
+        };
+        let (pool, base_pool_id) = match pool {
+            Pool::SimplePool(pool) => (pool, None),
+            Pool::MetaPool(pool) => (pool.pool, Some(pool.base_pool_id)),
+        };
+        Self {
+            pool_id,
+            pool_kind,
+            base_pool_id,
+            token_account_ids: pool.token_account_ids,
+            token_decimals: pool.token_decimals,
+            amounts: pool.amounts.into_iter().map(|a| U128(a)).collect(),
+            shares_total_supply: U128(pool.shares_total_supply),
+            initial_amp_factor: U128(pool.initial_amp_factor.into()),
+            target_amp_factor: U128(pool.target_amp_factor.into()),
+            start_ramp_ts: U128(pool.start_ramp_ts.into()),
+            stop_ramp_ts: U128(pool.stop_ramp_ts.into()),
+            amp_factor: U128(amp_factor),
+            virtual_price: U128(virtual_price),
+            block_liquidity_during_ramp: pool.block_liquidity_during_ramp,
+            rate_scaled_epsilon: pool.rate_scaled_epsilon,
+            max_share_per_account: pool.max_share_per_account.map(U128),
+            min_one_coin_burn: U128(pool.min_one_coin_burn),
+            lp_fee_discount_bps: pool.lp_fee_discount_bps,
+            rate_provider: pool.rate_provider,
+            shutdown: pool.shutdown,
+            created_at: U64(pool.created_at),
         }
     }
 }
@@ -71,51 +222,146 @@ impl SnailSwap {
         env!("CARGO_PKG_VERSION").to_string()
     }
 
+    /// Returns information about the deployed contract.
+    pub fn metadata(&self) -> ContractMetadata {
+        ContractMetadata {
+            version: self.version(),
+            owner: self.owner_id.clone(),
+            pool_count: self.pools.len(),
+            state: self.state.clone(),
+            guardians: self.guardians.to_vec(),
+            wrap_near_id: self.wrap_near_id.clone(),
+        }
+    }
+
+    /// Returns accounts allowed to shut pools down besides the owner.
+    pub fn get_guardians(&self) -> Vec<AccountId> {
+        self.guardians.to_vec()
+    }
+
+    /// Returns constants used by the stable swap math.
+    pub fn get_math_constants(&self) -> MathConstants {
+        MathConstants {
+            precision: U128(PRECISION),
+            max_coins: MAX_COINS,
+            iterations: MAX_SOLVER_ITERATIONS,
+        }
+    }
+
+    /// Returns list of whitelisted tokens.
+    pub fn get_whitelisted_tokens(&self) -> Vec<AccountId> {
+        self.whitelisted_tokens.to_vec()
+    }
+
+    /// Returns true if the token is paused, see `pause_tokens`.
+    pub fn is_token_paused(&self, token_id: AccountId) -> bool {
+        self.paused_tokens.contains(&token_id)
+    }
+
     /// Returns number of pools.
     pub fn get_number_of_pools(&self) -> u64 {
         self.pools.len()
     }
 
-    /// Returns list of pools of given length from given start index.
+    /// Returns storage used by the contract, number of registered accounts and of pools.
+    pub fn get_storage_report(&self) -> StorageReport {
+        StorageReport {
+            total_bytes: env::storage_usage(),
+            account_count: self.account_count,
+            pool_count: self.pools.len(),
+        }
+    }
+
+    /// Returns amount of the token the contract holds for deposits and pools.
+    pub fn get_managed_balance(&self, token_id: AccountId) -> U128 {
+        U128(self.managed_balances.get(&token_id).unwrap_or(0))
+    }
+
+    /// Returns balance that can be left when unregistering a token.
+    pub fn get_dust_tolerance(&self) -> U128 {
+        U128(self.dust_tolerance)
+    }
+
+    /// Returns list of pools of given length from given start index, in pool id order.
+    /// At most MAX_POOLS_PER_VIEW pools are returned per call.
     pub fn get_pools(&self, from_index: u64, limit: u64) -> Vec<PoolInfo> {
-        (from_index..std::cmp::min(from_index + limit, self.pools.len()))
+        let limit = std::cmp::min(limit, MAX_POOLS_PER_VIEW);
+        (from_index..std::cmp::min(from_index.saturating_add(limit), self.pools.len()))
             .map(|index| self.get_pool(index))
             .collect()
     }
 
     /// Returns information about specified pool.
     pub fn get_pool(&self, pool_id: u64) -> PoolInfo {
-        self.pools.get(pool_id).expect("ERR_NO_POOL").into()
+        PoolInfo::new(pool_id, self.internal_get_pool(pool_id))
     }
 
     /// Return total fee of the given pool.
     pub fn get_pool_fee(&self, pool_id: u64) -> Vec<u128> {
-        self.pools.get(pool_id).expect("ERR_NO_POOL").get_fee()
+        self.internal_get_pool(pool_id).get_fee()
+    }
+
+    /// Returns fees of the given pool with its total and admin fees accrued.
+    pub fn get_pool_fee_info(&self, pool_id: u64) -> PoolFeeInfo {
+        let pool = self.internal_get_pool(pool_id);
+        PoolFeeInfo {
+            fees: pool.fees_info(),
+            total_fees: pool.get_fee().into_iter().map(U128).collect(),
+            admin_fees: pool.get_admin_fee().into_iter().map(U128).collect(),
+        }
     }
 
     pub fn get_pool_admin_fee(&self, pool_id: u64) -> Vec<u128> {
-        self.pools
-            .get(pool_id)
-            .expect("ERR_NO_POOL")
-            .get_admin_fee()
+        self.internal_get_pool(pool_id).get_admin_fee()
+    }
+
+    /// Returns the owner deposits of every pool token, base pool LPs excepted.
+    /// Admin fees are paid into these deposits on every swap, so they cover the admin fees
+    /// not withdrawn yet but also any other deposit the owner holds of the same tokens.
+    /// `get_pool_admin_fee` gives the admin fees a pool ever paid instead.
+    pub fn get_owner_pool_token_deposits(&self) -> Vec<(AccountId, U128)> {
+        let mut result: Vec<(AccountId, U128)> = vec![];
+        for pool in self.pools.iter() {
+            for token_id in pool.tokens() {
+                if pool.base_pool_of(token_id).is_some()
+                    || result.iter().any(|(id, _)| id == token_id)
+                {
+                    continue;
+                }
+                let deposit = self.internal_get_deposit(&self.owner_id, token_id);
+                result.push((token_id.clone(), U128(deposit)));
+            }
+        }
+        result
     }
 
     /// Returns number of shares given account has in given pool.
     pub fn get_pool_shares(&self, pool_id: u64, account_id: AccountId) -> U128 {
-        self.pools
-            .get(pool_id)
-            .expect("ERR_NO_POOL")
-            .share_balances(&account_id)
+        self.internal_get_pool(pool_id)
+            .share_balance_of(&account_id)
             .into()
     }
 
+    /// Returns (pool_id, shares) for every pool where given account has shares.
+    pub fn get_account_lp_positions(&self, account_id: AccountId) -> Vec<(u64, U128)> {
+        (0..self.pools.len())
+            .map(|pool_id| {
+                let pool = self.internal_get_pool(pool_id);
+                (pool_id, pool.share_balance_of(&account_id))
+            })
+            .filter(|(_, shares)| *shares > 0)
+            .map(|(pool_id, shares)| (pool_id, U128(shares)))
+            .collect()
+    }
+
+    /// Returns number of accounts holding shares of the given pool.
+    pub fn get_lp_holder_count(&self, pool_id: u64) -> u64 {
+        self.internal_get_pool(pool_id).lp_holder_count()
+    }
+
     /// Returns total number of shares in the given pool.
     pub fn get_pool_total_shares(&self, pool_id: u64) -> U128 {
-        self.pools
-            .get(pool_id)
-            .expect("ERR_NO_POOL")
-            .share_total_balance()
-            .into()
+        self.internal_get_pool(pool_id).share_total_balance().into()
     }
 
     /// returns all pools we have
@@ -125,33 +371,83 @@ impl SnailSwap {
 
     /// returns pool total supply
     pub fn pool_total_supply(&self, pool_id: u64) -> Balance {
-        let pool = self.pools.get(pool_id).expect("ERR_NO_POOL");
+        let pool = self.internal_get_pool(pool_id);
 
-        match pool {
-            Pool::SimplePool(pool) => pool.shares_total_supply,
-        }
+        pool.share_total_balance()
     }
 
     /// Returns balances of the deposits for given user outside of any pools.
     /// Returns empty list if no tokens deposited.
+    /// For accounts holding many tokens use `get_deposits_paged` instead.
     pub fn get_deposits(&self, account_id: AccountId) -> HashMap<AccountId, U128> {
         let wrapped_account = self.internal_get_account(&account_id);
         if let Some(account) = wrapped_account {
             account
-                .get_tokens()
-                .iter()
-                .map(|token| (token.clone(), U128(account.get_balance(token).unwrap())))
+                .get_balances()
+                .into_iter()
+                .map(|(token_id, amount)| (token_id, U128(amount)))
                 .collect()
         } else {
             HashMap::new()
         }
     }
 
+    /// Returns balance of every token registered in the deposits of given user,
+    /// zero balances included.
+    pub fn get_balances(&self, account_id: AccountId) -> Vec<(AccountId, U128)> {
+        self.internal_get_account(&account_id)
+            .map(|account| {
+                account
+                    .get_balances()
+                    .into_iter()
+                    .map(|(token_id, amount)| (token_id, U128(amount)))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Returns `limit` balances of the deposits for given user starting at `from_index`.
+    /// Returns empty list if no tokens deposited.
+    pub fn get_deposits_paged(
+        &self,
+        account_id: AccountId,
+        from_index: u64,
+        limit: u64,
+    ) -> Vec<(AccountId, U128)> {
+        if let Some(account) = self.internal_get_account(&account_id) {
+            let keys = account.tokens.keys_as_vector();
+            let values = account.tokens.values_as_vector();
+            (from_index..std::cmp::min(from_index.saturating_add(limit), keys.len()))
+                .map(|index| (keys.get(index).unwrap(), U128(values.get(index).unwrap())))
+                .collect()
+        } else {
+            vec![]
+        }
+    }
+
+    /// Returns the swap volume of given account across all pools, in 24 decimals.
+    pub fn get_account_volume(&self, account_id: AccountId) -> SwapVolume {
+        self.account_volumes.get(&account_id).unwrap_or_default()
+    }
+
     /// Returns balance of the deposit for given user outside of any pools.
     pub fn get_deposit(&self, account_id: AccountId, token_id: AccountId) -> U128 {
         self.internal_get_deposit(&account_id, &token_id).into()
     }
 
+    /// Returns how much more of token_in given user must deposit to swap amount_in of it.
+    pub fn get_deposit_shortfall(
+        &self,
+        account_id: AccountId,
+        token_in: AccountId,
+        amount_in: U128,
+    ) -> U128 {
+        amount_in
+            .0
+            .saturating_sub(self.internal_get_deposit(&account_id, &token_in))
+            .into()
+    }
+
     /// Given specific pool, returns amount of token_out recevied swapping amount_in of token_in.
     pub fn get_return(
         &self,
@@ -160,43 +456,315 @@ impl SnailSwap {
         amount_in: U128,
         token_out: AccountId,
     ) -> U128 {
-        let pool = self.pools.get(pool_id).expect("ERR_NO_POOL");
+        let pool = self.internal_get_pool(pool_id);
         pool.get_return(&token_in, amount_in.into(), &token_out)
             .into()
     }
 
+    /// Dry-runs `swap_route` for amount_in and returns the output of each hop.
+    /// Hops are simulated on copies of the pools, so a pool used twice quotes its
+    /// second hop after the first one, deposits and pool state are left untouched.
+    /// Fails like the route would, e.g. on a missing token or ERR_MIN_AMOUNT.
+    pub fn try_swap_route(&self, actions: Vec<SwapAction>, amount_in: U128) -> Vec<U128> {
+        assert!(!actions.is_empty(), "ERR_EMPTY_ROUTE");
+        assert!(actions.len() <= MAX_ROUTE_LEN, "ERR_ROUTE_TOO_LONG");
+        let mut pools: HashMap<u64, Pool> = HashMap::new();
+        let mut amount = amount_in.0;
+        let mut amounts_out = vec![];
+        for (i, action) in actions.iter().enumerate() {
+            if i > 0 {
+                assert_eq!(
+                    action.token_in,
+                    actions[i - 1].token_out,
+                    "ERR_ROUTE_NOT_CONNECTED"
+                );
+            }
+            let pool = pools
+                .entry(action.pool_id)
+                .or_insert_with(|| self.internal_get_pool(action.pool_id));
+            let (amount_out, _, _) = pool.swap(
+                &action.token_in,
+                amount,
+                &action.token_out,
+                action.min_amount_out.0,
+            );
+            amounts_out.push(U128(amount_out));
+            amount = amount_out;
+        }
+        amounts_out
+    }
+
+    /// Quotes a route for amount_in, each hop fed with what the previous one returns after fees.
+    /// Returns the final output and the fee of each hop in its token_out.
+    /// Every hop is quoted on the current state of its pool, so unlike `try_swap_route`
+    /// a pool used twice doesn't see its first hop, and min_amount_out is not checked.
+    pub fn quote_route(&self, actions: Vec<SwapAction>, amount_in: U128) -> RouteQuote {
+        assert!(!actions.is_empty(), "ERR_EMPTY_ROUTE");
+        assert!(actions.len() <= MAX_ROUTE_LEN, "ERR_ROUTE_TOO_LONG");
+        let mut amount = amount_in.0;
+        let mut per_hop_fees = vec![];
+        for (i, action) in actions.iter().enumerate() {
+            if i > 0 {
+                assert_eq!(
+                    action.token_in,
+                    actions[i - 1].token_out,
+                    "ERR_ROUTE_NOT_CONNECTED"
+                );
+            }
+            let pool = match self.internal_get_pool(action.pool_id) {
+                Pool::SimplePool(pool) => pool,
+                Pool::MetaPool(pool) => pool.pool,
+            };
+            let (amount_out, fee) =
+                pool.get_return_and_fee(&action.token_in, amount, &action.token_out);
+            per_hop_fees.push(U128(fee));
+            amount = amount_out;
+        }
+        RouteQuote {
+            final_out: U128(amount),
+            per_hop_fees,
+        }
+    }
+
+    /// Returns the fee charged on the quote of swapping amount_in of token_in for token_out,
+    /// in bps of the output before fee: total_fee / (amount_out + total_fee).
+    /// Price impact of the swap is not part of it.
+    pub fn get_effective_fee_bps(
+        &self,
+        pool_id: u64,
+        token_in: AccountId,
+        amount_in: U128,
+        token_out: AccountId,
+    ) -> u32 {
+        let (amount_out, total_fee) = match self.internal_get_pool(pool_id) {
+            Pool::SimplePool(pool) => pool.get_return_and_fee(&token_in, amount_in.0, &token_out),
+            Pool::MetaPool(pool) => {
+                pool.pool
+                    .get_return_and_fee(&token_in, amount_in.0, &token_out)
+            }
+        };
+        let gross = amount_out + total_fee;
+        if gross == 0 {
+            return 0;
+        }
+        (total_fee * BPS_DENOMINATOR / gross) as u32
+    }
+
+    /// Returns value of all the balances of the pool in reference_token,
+    /// each balance is valued at what swapping it into reference_token returns.
+    pub fn get_pool_tvl(&self, pool_id: u64, reference_token: AccountId) -> U128 {
+        let pool = self.internal_get_pool(pool_id);
+        assert!(
+            pool.tokens().contains(&reference_token),
+            "ERR_MISSING_TOKEN"
+        );
+        let amounts = self.get_pool(pool_id).amounts;
+        pool.tokens()
+            .iter()
+            .zip(amounts)
+            .map(|(token_id, amount)| {
+                if token_id == &reference_token || amount.0 == 0 {
+                    amount.0
+                } else {
+                    pool.get_return(token_id, amount.0, &reference_token)
+                }
+            })
+            .sum::<Balance>()
+            .into()
+    }
+
+    /// Returns how much of each coin of the pool one LP token redeems for,
+    /// that is the proportional share of the balances, before withdraw fee.
+    pub fn get_lp_price_in_coins(&self, pool_id: u64) -> Vec<U128> {
+        let pool = self.get_pool(pool_id);
+        let one_lp = U256::from(10u128.pow(LP_DECIMALS as u32));
+        pool.amounts
+            .iter()
+            .map(|amount| {
+                if pool.shares_total_supply.0 == 0 {
+                    U128(0)
+                } else {
+                    U128(
+                        (U256::from(amount.0) * one_lp / U256::from(pool.shares_total_supply.0))
+                            .as_u128(),
+                    )
+                }
+            })
+            .collect()
+    }
+
+    /// Given a metapool, quotes an underlying swap between its coin and a base pool token.
+    /// Base fee is in the base LP when swapping into the coin and in token_out otherwise.
+    pub fn get_return_underlying(
+        &self,
+        metapool_id: u64,
+        token_in: AccountId,
+        amount_in: U128,
+        token_out: AccountId,
+    ) -> UnderlyingReturn {
+        let (amount_out, meta_fee, base_fee) =
+            self.internal_get_return_underlying(metapool_id, &token_in, amount_in.0, &token_out);
+        UnderlyingReturn {
+            amount_out: amount_out.into(),
+            meta_fee: meta_fee.into(),
+            base_fee: base_fee.into(),
+        }
+    }
+
+    /// Returns the theoretical max amount of token_out one can receive from given pool.
+    pub fn get_max_output(&self, pool_id: u64, token_out: AccountId) -> U128 {
+        let pool = self.internal_get_pool(pool_id);
+        pool.get_max_output(&token_out).into()
+    }
+
+    /// Returns the marginal price of `token_in` in `token_out` at the current balances,
+    /// before fees: amount of `token_out` per unit of `token_in`, scaled by PRECISION (1e24),
+    /// each amount in its own decimals.
+    pub fn get_spot_price(&self, pool_id: u64, token_in: AccountId, token_out: AccountId) -> U128 {
+        assert_ne!(token_in, token_out, "ERR_SAME_TOKEN");
+        let pool = self.internal_get_pool(pool_id);
+        pool.get_spot_price(&token_in, &token_out).into()
+    }
+
     pub fn get_virtual_price(&self, pool_id: u64) -> U128 {
-        let pool = self.pools.get(pool_id).expect("ERR_NO_POOL");
+        let pool = self.internal_get_pool(pool_id);
         pool.get_virtual_price().into()
     }
 
+    /// Returns multipliers bringing each token amount of the pool to 24 decimals,
+    /// the stored rates if set, otherwise from the token decimals.
+    /// The multipliers are over `get_pool_rate_denominator`.
+    pub fn get_pool_rates(&self, pool_id: u64) -> Vec<U128> {
+        let pool = self.internal_get_pool(pool_id);
+        pool.get_rates().into_iter().map(U128).collect()
+    }
+
+    /// Returns the denominator of the pool rates, 1 unless a rate is fractional
+    /// such as the base LP of a metapool.
+    pub fn get_pool_rate_denominator(&self, pool_id: u64) -> U128 {
+        let pool = self.internal_get_pool(pool_id);
+        pool.get_rate_denominator().into()
+    }
+
     pub fn get_amp_factor(&self, pool_id: u64) -> U128 {
-        let pool = self.pools.get(pool_id).expect("ERR_NO_POOL");
+        let pool = self.internal_get_pool(pool_id);
         pool.get_amp_factor().into()
     }
 
+    /// Returns the current amp factor of the pool, interpolated at the block timestamp
+    /// while a ramp is in progress.
+    pub fn get_pool_amp_factor(&self, pool_id: u64) -> U128 {
+        self.get_amp_factor(pool_id)
+    }
+
+    /// Returns the amp factor ramp of the pool with the current amp factor,
+    /// the coin/LP leg for a metapool.
+    pub fn get_ramp_schedule(&self, pool_id: u64) -> RampSchedule {
+        let pool = self.internal_get_pool(pool_id);
+        let current_amp = pool.get_amp_factor();
+        let pool = match pool {
+            Pool::SimplePool(pool) => pool,
+            Pool::MetaPool(pool) => pool.pool,
+        };
+        RampSchedule {
+            initial_amp: U128(pool.initial_amp_factor.into()),
+            target_amp: U128(pool.target_amp_factor.into()),
+            start_ramp_ts: U128(pool.start_ramp_ts.into()),
+            stop_ramp_ts: U128(pool.stop_ramp_ts.into()),
+            current_amp: U128(current_amp),
+        }
+    }
+
+    /// Returns the balance cap of each token of the pool with the headroom left under it,
+    /// the coin/LP leg for a metapool.
+    pub fn get_balance_caps(&self, pool_id: u64) -> Vec<BalanceCap> {
+        let pool = match self.internal_get_pool(pool_id) {
+            Pool::SimplePool(pool) => pool,
+            Pool::MetaPool(pool) => pool.pool,
+        };
+        let caps = pool
+            .balance_caps
+            .unwrap_or_else(|| vec![0; pool.amounts.len()]);
+        caps.into_iter()
+            .zip(pool.amounts.iter())
+            .map(|(cap, amount)| BalanceCap {
+                cap: U128(cap),
+                headroom: if cap == 0 {
+                    None
+                } else {
+                    Some(U128(cap.saturating_sub(*amount)))
+                },
+            })
+            .collect()
+    }
+
     pub fn fees_info(&self, pool_id: u64) -> Fees {
-        let pool = self.pools.get(pool_id).expect("ERR_NO_POOL");
+        let pool = self.internal_get_pool(pool_id);
         pool.fees_info()
     }
 
+    /// Returns the account receiving the admin fee of a swap into token_out in the given pool.
+    /// Admin fees always go to the owner, the base LP leg of a metapool as base pool shares,
+    /// less the share left in the pool for LPs, see `get_admin_fee_split`.
+    pub fn get_fee_destination_for_swap(&self, pool_id: u64, token_out: AccountId) -> AccountId {
+        let pool = self.internal_get_pool(pool_id);
+        assert!(pool.tokens().contains(&token_out), "ERR_MISSING_TOKEN");
+        self.owner_id.clone()
+    }
+
+    /// Returns how the admin fee of the pool is split, in bps of the admin fee:
+    /// (share left in the pool for LPs, share going to the owner).
+    pub fn get_admin_fee_split(&self, pool_id: u64) -> (u32, u32) {
+        let fees = self.fees_info(pool_id);
+        let lp_bps = fees.lp_fee_share_numerator as u128 * BPS_DENOMINATOR
+            / fees.lp_fee_share_denominator as u128;
+        (lp_bps as u32, (BPS_DENOMINATOR - lp_bps) as u32)
+    }
+
     pub fn try_remove_liquidity_one_coin(
         &self,
         pool_id: u64,
         token_out: &AccountId,
         remove_lp_amount: U128,
     ) -> U128 {
-        let pool = self.pools.get(pool_id).expect("ERR_NO_POOL");
+        let pool = self.internal_get_pool(pool_id);
         pool.try_remove_liquidity_one_coin(token_out, remove_lp_amount.0)
             .into()
     }
 
+    /// Returns how much `token_out` removing `lp_amount` of LP for it with
+    /// `remove_liquidity_one_coin` delivers right now, fee included.
+    /// Fails where the removal would, e.g. on a shut down pool or a too small burn.
+    pub fn calc_remove_liquidity_one_coin(
+        &self,
+        pool_id: u64,
+        token_out: AccountId,
+        lp_amount: U128,
+    ) -> U128 {
+        let pool = self.internal_get_pool(pool_id);
+        pool.calc_remove_liquidity_one_coin(&token_out, lp_amount.0)
+            .into()
+    }
+
+    /// Returns how much `in_token` one LP share (1e24) redeems for through
+    /// `remove_liquidity_one_coin` math at the current balances, fee included.
+    /// If less than one share exists, the redemption of the whole supply is scaled up.
+    pub fn get_share_price(&self, pool_id: u64, in_token: AccountId) -> U128 {
+        let pool = self.internal_get_pool(pool_id);
+        let total_supply = pool.share_total_balance();
+        assert!(total_supply > 0, "ERR_EMPTY_POOL");
+        let one_lp = 10u128.pow(LP_DECIMALS as u32);
+        let lp_amount = std::cmp::min(one_lp, total_supply);
+        let amount_out = pool.try_remove_liquidity_one_coin(&in_token, lp_amount);
+        U128((U256::from(amount_out) * U256::from(one_lp) / U256::from(lp_amount)).as_u128())
+    }
+
     pub fn try_remove_liquidity_imbalance(
         &self,
         pool_id: u64,
         remove_coin_amount: Vec<U128>,
     ) -> u128 {
-        let pool = self.pools.get(pool_id).expect("ERR_NO_POOL");
+        let pool = self.internal_get_pool(pool_id);
 
         let remove_coin_amount: Vec<u128> = remove_coin_amount
             .into_iter()
@@ -207,18 +775,72 @@ impl SnailSwap {
     }
 
     pub fn try_remove_liquidity(&self, pool_id: u64, shares: U128) -> Vec<U128> {
-        let pool = self.pools.get(pool_id).expect("ERR_NO_POOL");
+        let pool = self.internal_get_pool(pool_id);
         let amounts = pool.try_remove_liquidity(shares.0);
 
         amounts.into_iter().map(|amount| amount.into()).collect()
     }
 
+    /// Returns what a balanced `remove_liquidity` of all the account's shares would give,
+    /// per token of the pool. Zeros if the account has no shares.
+    pub fn get_lp_redeemable(&self, pool_id: u64, account_id: AccountId) -> Vec<U128> {
+        let pool = self.internal_get_pool(pool_id);
+        let shares = pool.share_balance_of(&account_id);
+        if shares == 0 {
+            return vec![U128(0); pool.tokens().len()];
+        }
+        pool.try_remove_liquidity(shares)
+            .into_iter()
+            .map(U128)
+            .collect()
+    }
+
     pub fn try_add_liquidity(&self, pool_id: u64, deposit_amounts: Vec<U128>) -> U128 {
-        let pool = self.pools.get(pool_id).expect("ERR_NO_POOL");
+        let pool = self.internal_get_pool(pool_id);
 
         let deposit_amounts: Vec<u128> =
             deposit_amounts.into_iter().map(|amount| amount.0).collect();
 
         pool.try_add_liquidity(&deposit_amounts).into()
     }
+
+    /// Quotes a deposit of `amount` of `token_in` alone, comparing the shares minted
+    /// with what the deposit is worth at the virtual price of the pool.
+    pub fn calc_single_deposit_impact(
+        &self,
+        pool_id: u64,
+        token_in: AccountId,
+        amount: U128,
+    ) -> SingleDepositImpact {
+        let pool = self.internal_get_pool(pool_id);
+        assert!(amount.0 > 0, "ERR_ZERO_AMOUNT");
+        assert!(pool.share_total_balance() > 0, "ERR_EMPTY_POOL");
+        let token_index = pool
+            .tokens()
+            .iter()
+            .position(|id| id == &token_in)
+            .expect("ERR_MISSING_TOKEN");
+
+        let mut deposit_amounts = vec![0; pool.tokens().len()];
+        deposit_amounts[token_index] = amount.0;
+        let lp_minted = pool.try_add_liquidity(&deposit_amounts);
+
+        // shares worth of the deposit: its value in 24 decimals over the virtual price.
+        let fair_lp = U256::from(amount.0)
+            * U256::from(pool.get_rates()[token_index])
+            * U256::from(PRECISION)
+            / U256::from(pool.get_rate_denominator())
+            / U256::from(pool.get_virtual_price());
+        let fair_lp = fair_lp.as_u128();
+        let bonus_or_penalty_bps = if fair_lp == 0 {
+            0
+        } else {
+            ((lp_minted as i128 - fair_lp as i128) * 10_000 / fair_lp as i128) as i32
+        };
+
+        SingleDepositImpact {
+            lp_minted: lp_minted.into(),
+            bonus_or_penalty_bps,
+        }
+    }
 }
@@ -12,6 +12,7 @@ use near_sdk_sim::{
 near_sdk_sim::lazy_static_include::lazy_static_include_bytes! {
     TEST_TOKEN_WASM_BYTES => "../res/test_token.wasm",
     EXCHANGE_WASM_BYTES => "../res/snails_exchange.wasm",
+    FARMING_WASM_BYTES => "../res/snails_farming.wasm",
 }
 use snails_exchange::{Fees, PoolInfo, SnailSwapContract as Exchange};
 use test_token::ContractContract as TestToken;
@@ -191,6 +192,10 @@ pub fn swap() -> AccountId {
     get_accountid_from_string("swap")
 }
 
+pub fn farming() -> AccountId {
+    get_accountid_from_string("farming")
+}
+
 fn setup_fee() -> Fees {
     //initial A = 100, target = 500，time可以设计成2周。就是2周A线性过度到500
     //admin_trade_fee = 0.5 , admin_withdraw_fee = 0.4, trade_fee = 3/1000, withdraw_fee = 4/1000
@@ -203,6 +208,10 @@ fn setup_fee() -> Fees {
     let trade_fee_denominator: u64 = 1000;
     let withdraw_fee_numerator: u64 = 4;
     let withdraw_fee_denominator: u64 = 1000;
+    let referral_fee_numerator: u64 = 0;
+    let referral_fee_denominator: u64 = 1;
+    let lp_fee_share_numerator: u64 = 0;
+    let lp_fee_share_denominator: u64 = 1;
 
     Fees {
         admin_trade_fee_numerator,
@@ -213,6 +222,10 @@ fn setup_fee() -> Fees {
         trade_fee_denominator,
         withdraw_fee_numerator,
         withdraw_fee_denominator,
+        referral_fee_numerator,
+        referral_fee_denominator,
+        lp_fee_share_numerator,
+        lp_fee_share_denominator,
     }
 }
 
@@ -319,19 +332,34 @@ pub fn setup_two_coin_pool_with_liquidity() -> (
     .assert_success();
     call!(
         root,
-        pool.add_liquidity(0, vec![U128(to_yocto("10")), U128(to_yocto("20"))], None),
+        pool.add_liquidity(
+            0,
+            vec![U128(to_yocto("10")), U128(to_yocto("20"))],
+            None,
+            None
+        ),
         deposit = to_yocto("0.0007")
     )
     .assert_success();
     call!(
         root,
-        pool.add_liquidity(1, vec![U128(to_yocto("20")), U128(to_yocto("10"))], None),
+        pool.add_liquidity(
+            1,
+            vec![U128(to_yocto("20")), U128(to_yocto("10"))],
+            None,
+            None
+        ),
         deposit = to_yocto("0.0007")
     )
     .assert_success();
     call!(
         root,
-        pool.add_liquidity(2, vec![U128(to_yocto("10")), U128(to_yocto("10"))], None),
+        pool.add_liquidity(
+            2,
+            vec![U128(to_yocto("10")), U128(to_yocto("10"))],
+            None,
+            None
+        ),
         deposit = to_yocto("0.0007")
     )
     .assert_success();
@@ -418,7 +446,8 @@ pub fn setup_three_coin_pool_with_liquidity(
         pool.add_liquidity(
             0,
             amounts.into_iter().map(|x| U128(x)).collect(),
-            Some(U128(1))
+            Some(U128(1)),
+            None
         ),
         deposit = to_yocto("0.0086")
     )
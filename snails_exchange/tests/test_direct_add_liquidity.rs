@@ -0,0 +1,86 @@
+use near_sdk::json_types::U128;
+use near_sdk_sim::{call, to_yocto};
+
+use crate::common::utils::*;
+pub mod common;
+
+#[test]
+fn direct_add_liquidity_scenario_01() {
+    const ONE_DAI: u128 = 1000000000000000000;
+    const ONE_USDT: u128 = 1000000;
+    const ONE_USDC: u128 = 1000000;
+    const ONE_LP: u128 = 1000000000000000000000000;
+    let (root, _owner, pool, tokens) = setup_three_coin_pool_with_liquidity(
+        vec![
+            String::from(dai().as_str()),
+            String::from(usdt().as_str()),
+            String::from(usdc().as_str()),
+        ],
+        vec![100000 * ONE_DAI, 100000 * ONE_USDT, 100000 * ONE_USDC],
+        vec![18u64, 6u64, 6u64],
+    );
+    let token_in = &tokens[0];
+    let new_user = root.create_user(get_accountid_from_string("new_user"), to_yocto("100"));
+    call!(
+        new_user,
+        token_in.mint(new_user.account_id(), U128(10 * ONE_DAI))
+    )
+    .assert_success();
+    let action = format!(
+        "{{\"pool_id\": 0, \"min_mint_amount\": \"{}\"}}",
+        ONE_LP / 2
+    );
+
+    println!("Case 0101: sender not registered to the LP token");
+    let out_come = call!(
+        new_user,
+        token_in.ft_transfer_call(swap(), U128(ONE_DAI), None, action.clone()),
+        deposit = 1
+    );
+    out_come.assert_success();
+    assert_eq!(get_error_count(&out_come), 1);
+    assert!(get_error_status(&out_come).contains("LP not registered"));
+    assert_eq!(balance_of(&token_in, &new_user.account_id()), 10 * ONE_DAI);
+
+    println!("Case 0102: single token added through the imbalanced deposit path");
+    call!(
+        new_user,
+        pool.mft_register(":0".to_string(), new_user.account_id()),
+        deposit = to_yocto("1")
+    )
+    .assert_success();
+    let dai_before = get_pool(&pool, 0).amounts[0].0;
+    let out_come = call!(
+        new_user,
+        token_in.ft_transfer_call(swap(), U128(ONE_DAI), None, action.clone()),
+        deposit = 1
+    );
+    out_come.assert_success();
+    assert_eq!(get_error_count(&out_come), 0);
+    assert_eq!(balance_of(&token_in, &new_user.account_id()), 9 * ONE_DAI);
+    // part of the imbalance fee may leave the pool as admin fee.
+    let dai_after = get_pool(&pool, 0).amounts[0].0;
+    assert!(dai_after > dai_before && dai_after <= dai_before + ONE_DAI);
+    let lp_shares = mft_balance_of(&pool, ":0", &new_user.account_id());
+    assert!(lp_shares > ONE_LP / 2);
+    assert!(lp_shares < ONE_LP);
+
+    println!("Case 0103: less than min_mint_amount");
+    let action = format!(
+        "{{\"pool_id\": 0, \"min_mint_amount\": \"{}\"}}",
+        2 * ONE_LP
+    );
+    let out_come = call!(
+        new_user,
+        token_in.ft_transfer_call(swap(), U128(ONE_DAI), None, action),
+        deposit = 1
+    );
+    out_come.assert_success();
+    assert_eq!(get_error_count(&out_come), 1);
+    assert!(get_error_status(&out_come).contains("ERR_MIN_MINT_AMOUNT"));
+    assert_eq!(balance_of(&token_in, &new_user.account_id()), 9 * ONE_DAI);
+    assert_eq!(
+        mft_balance_of(&pool, ":0", &new_user.account_id()),
+        lp_shares
+    );
+}
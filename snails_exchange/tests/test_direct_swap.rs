@@ -304,7 +304,7 @@ fn instant_swap_scenario_02() {
     out_come.assert_success();
     println!("{}", get_error_status(&out_come));
     assert_eq!(get_error_count(&out_come), 1);
-    assert!(get_error_status(&out_come).contains("ERR_MISSING_TOKEN"));
+    assert!(get_error_status(&out_come).contains("Token not whitelisted"));
     assert_eq!(
         get_deposits(&pool, new_user.account_id())
             .get(&String::from(token_in.account_id().as_str()))
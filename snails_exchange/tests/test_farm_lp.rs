@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+
+use near_sdk::json_types::U128;
+use near_sdk::serde_json::json;
+use near_sdk_sim::{call, to_yocto, DEFAULT_GAS};
+
+use crate::common::utils::*;
+pub mod common;
+
+#[test]
+fn farm_lp_scenario_01() {
+    const ONE_DAI: u128 = 1000000000000000000;
+    const ONE_USDT: u128 = 1000000;
+    const ONE_USDC: u128 = 1000000;
+    const ONE_LP: u128 = 1000000000000000000000000;
+    let (root, owner, pool, _tokens) = setup_three_coin_pool_with_liquidity(
+        vec![
+            String::from(dai().as_str()),
+            String::from(usdt().as_str()),
+            String::from(usdc().as_str()),
+        ],
+        vec![100000 * ONE_DAI, 100000 * ONE_USDT, 100000 * ONE_USDC],
+        vec![18u64, 6u64, 6u64],
+    );
+    let farm = root.deploy(&FARMING_WASM_BYTES, farming(), to_yocto("100"));
+    root.call(
+        farming(),
+        "new",
+        &json!({ "owner_id": owner.account_id() })
+            .to_string()
+            .into_bytes(),
+        DEFAULT_GAS,
+        0,
+    )
+    .assert_success();
+    // seed of the LP of pool 0 is {exchange}@{pool_id}
+    let seed_id = format!("{}@0", swap());
+    owner
+        .call(
+            farming(),
+            "create_simple_farm",
+            &json!({
+                "terms": {
+                    "seed_id": seed_id,
+                    "reward_token": dai(),
+                    "start_at": 0,
+                    "reward_per_session": U128(ONE_DAI),
+                    "session_interval": 60,
+                },
+                "min_deposit": U128(1),
+            })
+            .to_string()
+            .into_bytes(),
+            DEFAULT_GAS,
+            to_yocto("1"),
+        )
+        .assert_success();
+    root.call(
+        farming(),
+        "storage_deposit",
+        &json!({}).to_string().into_bytes(),
+        DEFAULT_GAS,
+        to_yocto("1"),
+    )
+    .assert_success();
+    call!(
+        root,
+        pool.mft_register(":0".to_string(), farm.account_id()),
+        deposit = to_yocto("1")
+    )
+    .assert_success();
+
+    println!("Case 0101: stake LP into the farm with a single mft_transfer_call");
+    let lp_before = mft_balance_of(&pool, ":0", &root.account_id());
+    let out_come = call!(
+        root,
+        pool.mft_transfer_call(
+            ":0".to_string(),
+            farm.account_id(),
+            U128(10 * ONE_LP),
+            None,
+            "".to_string()
+        ),
+        deposit = 1
+    );
+    out_come.assert_success();
+    assert_eq!(get_error_count(&out_come), 0);
+    assert_eq!(
+        mft_balance_of(&pool, ":0", &root.account_id()),
+        lp_before - 10 * ONE_LP
+    );
+    assert_eq!(mft_balance_of(&pool, ":0", &farm.account_id()), 10 * ONE_LP);
+    let seeds: HashMap<String, U128> = root
+        .view(
+            farming(),
+            "list_user_seeds",
+            &json!({ "account_id": root.account_id() })
+                .to_string()
+                .into_bytes(),
+        )
+        .unwrap_json();
+    assert_eq!(seeds[&seed_id], U128(10 * ONE_LP));
+
+    println!("Case 0102: farm rejects the msg, LP is refunded");
+    let out_come = call!(
+        root,
+        pool.mft_transfer_call(
+            ":0".to_string(),
+            farm.account_id(),
+            U128(ONE_LP),
+            None,
+            "not a seed msg".to_string()
+        ),
+        deposit = 1
+    );
+    out_come.assert_success();
+    assert_eq!(get_error_count(&out_come), 1);
+    assert_eq!(
+        mft_balance_of(&pool, ":0", &root.account_id()),
+        lp_before - 10 * ONE_LP
+    );
+    assert_eq!(mft_balance_of(&pool, ":0", &farm.account_id()), 10 * ONE_LP);
+}
@@ -0,0 +1,197 @@
+use near_sdk::json_types::U128;
+use near_sdk_sim::{call, to_yocto, view};
+
+use snails_exchange::{Fees, UnderlyingReturn};
+
+use crate::common::utils::*;
+pub mod common;
+
+fn meta_fee() -> Fees {
+    Fees {
+        admin_trade_fee_numerator: 50,
+        admin_trade_fee_denominator: 100,
+        admin_withdraw_fee_numerator: 40,
+        admin_withdraw_fee_denominator: 100,
+        trade_fee_numerator: 3,
+        trade_fee_denominator: 1000,
+        withdraw_fee_numerator: 4,
+        withdraw_fee_denominator: 1000,
+        referral_fee_numerator: 0,
+        referral_fee_denominator: 1,
+        lp_fee_share_numerator: 0,
+        lp_fee_share_denominator: 1,
+    }
+}
+
+#[test]
+fn meta_pool_swap_scenario_01() {
+    const ONE_DAI: u128 = 1000000000000000000;
+    const ONE_USDT: u128 = 1000000;
+    const ONE_USDC: u128 = 1000000;
+    const ONE_NUSD: u128 = 1000000000000000000;
+    const ONE_LP: u128 = 1000000000000000000000000;
+    let (root, owner, pool, tokens) = setup_three_coin_pool_with_liquidity(
+        vec![
+            String::from(dai().as_str()),
+            String::from(usdt().as_str()),
+            String::from(usdc().as_str()),
+        ],
+        vec![100000 * ONE_DAI, 100000 * ONE_USDT, 100000 * ONE_USDC],
+        vec![18u64, 6u64, 6u64],
+    );
+    let nusd = test_token(&root, get_accountid_from_string("nusd"), vec![swap()]);
+
+    call!(
+        owner,
+        pool.add_meta_pool(nusd.account_id(), 18, 0, 100, 100, 0, 0, meta_fee()),
+        deposit = to_yocto("1")
+    )
+    .assert_success();
+    assert_eq!(get_num_of_pools(&pool), 2);
+    let meta_info = get_pool(&pool, 1);
+    assert_eq!(meta_info.pool_kind, "META_POOL".to_string());
+    assert_eq!(meta_info.base_pool_id, Some(0));
+
+    call!(
+        root,
+        nusd.ft_transfer_call(
+            pool.account_id(),
+            U128(100010 * ONE_NUSD),
+            None,
+            "".to_string()
+        ),
+        deposit = 1
+    )
+    .assert_success();
+    let base_lp_before = mft_balance_of(&pool, ":0", &root.account_id());
+    call!(
+        root,
+        pool.add_liquidity(
+            1,
+            vec![U128(100000 * ONE_NUSD), U128(100000 * ONE_LP)],
+            Some(U128(1)),
+            None
+        ),
+        deposit = to_yocto("0.01")
+    )
+    .assert_success();
+    assert_eq!(
+        mft_balance_of(&pool, ":0", &root.account_id()),
+        base_lp_before - 100000 * ONE_LP
+    );
+    assert_eq!(mft_balance_of(&pool, ":0", &swap()), 100000 * ONE_LP);
+
+    println!("Case 0101: direct swap coin to base LP");
+    let base_lp_before = mft_balance_of(&pool, ":0", &root.account_id());
+    call!(
+        root,
+        pool.swap(
+            1,
+            nusd.account_id(),
+            U128(ONE_NUSD),
+            swap(),
+            U128(1),
+            None,
+            None,
+            None,
+            None
+        ),
+        deposit = 1
+    )
+    .assert_success();
+    let base_lp_received = mft_balance_of(&pool, ":0", &root.account_id()) - base_lp_before;
+    assert!(base_lp_received > 98 * ONE_LP / 100);
+    assert!(base_lp_received < ONE_LP);
+    // pool LP balance matches the LP held by the exchange in the base pool
+    assert_eq!(
+        get_pool(&pool, 1).amounts[1].0,
+        mft_balance_of(&pool, ":0", &swap())
+    );
+
+    println!("Case 0102: underlying swap coin to base coin");
+    let dai_before = get_deposits(&pool, root.account_id())
+        .get(&String::from(dai().as_str()))
+        .map(|x| x.0)
+        .unwrap_or_default();
+    call!(
+        root,
+        pool.swap_underlying(1, nusd.account_id(), U128(ONE_NUSD), dai(), U128(1)),
+        deposit = 1
+    )
+    .assert_success();
+    let dai_received = get_deposits(&pool, root.account_id())
+        .get(&String::from(dai().as_str()))
+        .unwrap()
+        .0
+        - dai_before;
+    assert!(dai_received > 98 * ONE_DAI / 100);
+    assert!(dai_received < ONE_DAI);
+
+    println!("Case 0103: underlying swap base coin to coin");
+    call!(
+        root,
+        tokens[1].ft_transfer_call(pool.account_id(), U128(ONE_USDT), None, "".to_string()),
+        deposit = 1
+    )
+    .assert_success();
+    let nusd_before = get_deposits(&pool, root.account_id())
+        .get(&String::from(nusd.account_id().as_str()))
+        .unwrap()
+        .0;
+    call!(
+        root,
+        pool.swap_underlying(1, usdt(), U128(ONE_USDT), nusd.account_id(), U128(1)),
+        deposit = 1
+    )
+    .assert_success();
+    let nusd_received = get_deposits(&pool, root.account_id())
+        .get(&String::from(nusd.account_id().as_str()))
+        .unwrap()
+        .0
+        - nusd_before;
+    assert!(nusd_received > 98 * ONE_NUSD / 100);
+    assert!(nusd_received < ONE_NUSD);
+    assert_eq!(
+        get_pool(&pool, 1).amounts[1].0,
+        mft_balance_of(&pool, ":0", &swap())
+    );
+
+    println!("Case 0104: underlying swap between base coins is rejected");
+    let out_come = call!(
+        root,
+        pool.swap_underlying(1, dai(), U128(ONE_DAI), usdt(), U128(1)),
+        deposit = 1
+    );
+    assert_eq!(get_error_count(&out_come), 1);
+    assert!(get_error_status(&out_come).contains("ERR_NOT_UNDERLYING_SWAP"));
+
+    println!("Case 0105: quoted underlying swap fees match the executed ones");
+    let quote = view!(pool.get_return_underlying(1, nusd.account_id(), U128(ONE_NUSD), dai()))
+        .unwrap_json::<UnderlyingReturn>();
+    assert!(quote.meta_fee.0 > 0);
+    assert!(quote.base_fee.0 > 0);
+    let meta_fees_before = view!(pool.get_pool_fee(1)).unwrap_json::<Vec<u128>>();
+    let base_fees_before = view!(pool.get_pool_fee(0)).unwrap_json::<Vec<u128>>();
+    let dai_before = get_deposits(&pool, root.account_id())
+        .get(&String::from(dai().as_str()))
+        .unwrap()
+        .0;
+    call!(
+        root,
+        pool.swap_underlying(1, nusd.account_id(), U128(ONE_NUSD), dai(), U128(1)),
+        deposit = 1
+    )
+    .assert_success();
+    let meta_fees = view!(pool.get_pool_fee(1)).unwrap_json::<Vec<u128>>();
+    let base_fees = view!(pool.get_pool_fee(0)).unwrap_json::<Vec<u128>>();
+    assert_eq!(meta_fees[1] - meta_fees_before[1], quote.meta_fee.0);
+    assert_eq!(base_fees[0] - base_fees_before[0], quote.base_fee.0);
+    assert_eq!(
+        get_deposits(&pool, root.account_id())
+            .get(&String::from(dai().as_str()))
+            .unwrap()
+            .0
+            - dai_before,
+        quote.amount_out.0
+    );
+}
@@ -0,0 +1,71 @@
+use near_sdk::json_types::U128;
+use near_sdk_sim::{call, to_yocto};
+
+use snails_exchange::SwapAction;
+
+use crate::common::utils::*;
+pub mod common;
+
+#[test]
+fn swap_route_scenario_01() {
+    const ONE_DAI: u128 = 1000000000000000000;
+    const ONE_USDT: u128 = 1000000;
+    const ONE_USDC: u128 = 1000000;
+    let (root, _owner, pool, tokens) = setup_three_coin_pool_with_liquidity(
+        vec![
+            String::from(dai().as_str()),
+            String::from(usdt().as_str()),
+            String::from(usdc().as_str()),
+        ],
+        vec![100000 * ONE_DAI, 100000 * ONE_USDT, 100000 * ONE_USDC],
+        vec![18u64, 6u64, 6u64],
+    );
+    let user = root.create_user(get_accountid_from_string("user"), to_yocto("100"));
+    mint_and_deposit_token(&user, &tokens[0], &pool, 10 * ONE_DAI);
+    call!(
+        user,
+        pool.register_tokens(vec![usdt(), usdc()]),
+        deposit = 1
+    )
+    .assert_success();
+    let route = |min_amount_out: u128| {
+        vec![
+            SwapAction {
+                pool_id: 0,
+                token_in: dai(),
+                token_out: usdt(),
+                min_amount_out: U128(1),
+            },
+            SwapAction {
+                pool_id: 0,
+                token_in: usdt(),
+                token_out: usdc(),
+                min_amount_out: U128(min_amount_out),
+            },
+        ]
+    };
+
+    println!("Case 0101: failing last hop reverts the whole route");
+    let amounts_before = get_pool(&pool, 0).amounts;
+    let deposits_before = get_deposits(&pool, user.account_id());
+    let out_come = call!(
+        user,
+        pool.swap_route(route(2 * ONE_USDC), U128(ONE_DAI), None)
+    );
+    assert_eq!(get_error_count(&out_come), 1);
+    assert!(get_error_status(&out_come).contains("ERR_MIN_AMOUNT"));
+    assert_eq!(get_pool(&pool, 0).amounts, amounts_before);
+    assert_eq!(get_deposits(&pool, user.account_id()), deposits_before);
+
+    println!("Case 0102: route swaps the whole output of each hop");
+    call!(user, pool.swap_route(route(1), U128(ONE_DAI), None)).assert_success();
+    let deposits = get_deposits(&pool, user.account_id());
+    assert_eq!(
+        deposits.get(&String::from(dai().as_str())).unwrap().0,
+        9 * ONE_DAI
+    );
+    assert_eq!(deposits.get(&String::from(usdt().as_str())).unwrap().0, 0);
+    let usdc_received = deposits.get(&String::from(usdc().as_str())).unwrap().0;
+    assert!(usdc_received > 99 * ONE_USDC / 100);
+    assert!(usdc_received < ONE_USDC);
+}
@@ -0,0 +1,84 @@
+use near_sdk::json_types::U128;
+use near_sdk::serde_json::json;
+use near_sdk_sim::{call, init_simulator, to_yocto, DEFAULT_GAS};
+
+use crate::common::utils::*;
+pub mod common;
+
+#[test]
+fn withdraw_scenario_01() {
+    const ONE_DAI: u128 = 1000000000000000000;
+    let root = init_simulator(None);
+    let (_owner, pool) = setup_exchange(&root);
+    let token = test_token(&root, dai(), vec![swap()]);
+    let user = root.create_user(get_accountid_from_string("user"), to_yocto("100"));
+    mint_and_deposit_token(&user, &token, &pool, 10 * ONE_DAI);
+
+    let withdraw_args = |amount: u128| {
+        json!({ "token_id": dai(), "amount": U128(amount) })
+            .to_string()
+            .into_bytes()
+    };
+
+    println!("Case 0101: two interleaved withdrawals of the same token both fail");
+    // the user leaves the token, so transfers to the user fail.
+    call!(user, token.storage_unregister(None), deposit = 1).assert_success();
+    // both transfers are sent before either callback runs.
+    let out_come = user
+        .create_transaction(swap())
+        .function_call(
+            "withdraw".to_string(),
+            withdraw_args(3 * ONE_DAI),
+            DEFAULT_GAS / 3,
+            1,
+        )
+        .function_call(
+            "withdraw".to_string(),
+            withdraw_args(4 * ONE_DAI),
+            DEFAULT_GAS / 3,
+            1,
+        )
+        .submit();
+    out_come.assert_success();
+    assert_eq!(get_error_count(&out_come), 2);
+    assert_eq!(
+        get_deposits(&pool, user.account_id())
+            .get(&dai().to_string())
+            .unwrap()
+            .0,
+        10 * ONE_DAI
+    );
+
+    println!("Case 0102: two interleaved withdrawals of the same token both succeed");
+    call!(
+        user,
+        token.storage_deposit(None, None),
+        deposit = to_yocto("1")
+    )
+    .assert_success();
+    let out_come = user
+        .create_transaction(swap())
+        .function_call(
+            "withdraw".to_string(),
+            withdraw_args(3 * ONE_DAI),
+            DEFAULT_GAS / 3,
+            1,
+        )
+        .function_call(
+            "withdraw".to_string(),
+            withdraw_args(4 * ONE_DAI),
+            DEFAULT_GAS / 3,
+            1,
+        )
+        .submit();
+    out_come.assert_success();
+    assert_eq!(get_error_count(&out_come), 0);
+    assert_eq!(balance_of(&token, &user.account_id()), 7 * ONE_DAI);
+    assert_eq!(
+        get_deposits(&pool, user.account_id())
+            .get(&dai().to_string())
+            .unwrap()
+            .0,
+        3 * ONE_DAI
+    );
+}
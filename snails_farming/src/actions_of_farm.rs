@@ -3,7 +3,8 @@ use crate::utils::{gen_farm_id, parse_farm_id, MIN_SEED_DEPOSIT};
 use crate::*;
 use near_sdk::json_types::U128;
 use near_sdk::{env, near_bindgen, Promise};
-use simple_farm::{HRSimpleFarmTerms, SimpleFarm};
+use simple_farm::{HRMultiRewardFarmTerms, HRSimpleFarmTerms, SimpleFarm};
+use std::collections::HashSet;
 
 #[near_bindgen]
 impl Contract {
@@ -13,6 +14,16 @@ impl Contract {
         &mut self,
         terms: HRSimpleFarmTerms,
         min_deposit: Option<U128>,
+    ) -> FarmId {
+        self.create_multi_reward_farm((&terms).into(), min_deposit)
+    }
+
+    /// create farm distributing several reward tokens and pay for its storage fee
+    #[payable]
+    pub fn create_multi_reward_farm(
+        &mut self,
+        terms: HRMultiRewardFarmTerms,
+        min_deposit: Option<U128>,
     ) -> FarmId {
         self.assert_owner();
         self.assert_contract_running();
@@ -40,12 +51,21 @@ impl Contract {
         farm_id
     }
 
-    pub fn change_reward_per_session(&mut self, farm_id: FarmId, reward_per_session: U128) {
+    /// Changes reward per session of given reward token, the first reward token by default.
+    pub fn change_reward_per_session(
+        &mut self,
+        farm_id: FarmId,
+        reward_per_session: U128,
+        reward_token: Option<AccountId>,
+    ) {
         self.assert_owner();
         self.assert_contract_running();
-        let mut farm = self.data().farms.get(&farm_id).unwrap();
-        farm.change_reward_per_session(reward_per_session.into());
-        self.data_mut().farms.insert(&farm_id, &farm);
+        let mut farm = self.get_farm_wrapped(&farm_id).unwrap();
+        let reward_token = reward_token.unwrap_or_else(|| farm.get_reward_tokens()[0].clone());
+        farm.change_reward_per_session(&reward_token, reward_per_session.into());
+        self.data_mut()
+            .farms
+            .insert(&farm_id, &VersionedFarm::new(farm));
     }
 }
 
@@ -53,7 +73,25 @@ impl Contract {
     /// Adds given farm to the vec and returns it's id.
     /// If there is not enough attached balance to cover storage, fails.
     /// If too much attached - refunds it back.
-    fn internal_add_farm(&mut self, terms: &HRSimpleFarmTerms, min_deposit: Balance) -> FarmId {
+    fn internal_add_farm(
+        &mut self,
+        terms: &HRMultiRewardFarmTerms,
+        min_deposit: Balance,
+    ) -> FarmId {
+        assert!(
+            !terms.reward_tokens.is_empty()
+                && terms.reward_tokens.len() == terms.reward_per_session.len(),
+            "{}",
+            INVALID_FARM_REWARD_TERMS
+        );
+        let token_set: HashSet<_> = terms.reward_tokens.iter().collect();
+        assert_eq!(
+            token_set.len(),
+            terms.reward_tokens.len(),
+            "{}",
+            INVALID_FARM_REWARD_TERMS
+        );
+
         // let mut farm_seed = self.get_seed_default(&terms.seed_id, min_deposit);
         let mut farm_seed: VersionedFarmSeed;
         if let Some(fs) = self.get_seed_wrapped(&terms.seed_id) {
@@ -85,24 +123,58 @@ impl Contract {
         farm_seed.get_ref_mut().next_index =
             farm_seed.get_ref_mut().next_index.checked_add(1).unwrap();
         self.data_mut().seeds.insert(&terms.seed_id, &farm_seed);
-        self.data_mut().farms.insert(&farm_id.clone(), &farm);
+        self.data_mut()
+            .farms
+            .insert(&farm_id.clone(), &VersionedFarm::new(farm));
         farm_id
     }
 
+    /// Returns the farm upgraded to the current version, None if it doesn't exist.
+    #[inline]
+    pub(crate) fn get_farm_wrapped(&self, farm_id: &FarmId) -> Option<Farm> {
+        self.data().farms.get(farm_id).map(|farm| {
+            if farm.need_upgrade() {
+                farm.upgrade().get()
+            } else {
+                farm.get()
+            }
+        })
+    }
+
+    /// Returns the outdated farm upgraded to the current version, None if it doesn't exist.
+    #[inline]
+    pub(crate) fn get_outdated_farm_wrapped(&self, farm_id: &FarmId) -> Option<Farm> {
+        self.data().outdated_farms.get(farm_id).map(|farm| {
+            if farm.need_upgrade() {
+                farm.upgrade().get()
+            } else {
+                farm.get()
+            }
+        })
+    }
+
     pub(crate) fn internal_remove_farm_by_farm_id(&mut self, farm_id: &FarmId) -> bool {
         let (seed_id, _) = parse_farm_id(farm_id);
         let mut removable = false;
         if let Some(mut farm_seed) = self.get_seed_wrapped(&seed_id) {
-            let seed_amount = farm_seed.get_ref().amount;
-            if let Some(farm) = self.data().farms.get(farm_id) {
-                if farm.can_be_removed(&seed_amount) {
+            let seed_power = farm_seed.get_ref().power;
+            if let Some(farm) = self.get_farm_wrapped(farm_id) {
+                if farm.can_be_removed(&seed_power) {
                     removable = true;
                 }
             }
             if removable {
-                let mut farm = self.data_mut().farms.remove(farm_id).expect(FARM_NOT_EXIST);
-                farm.move_to_clear(&seed_amount);
-                self.data_mut().outdated_farms.insert(farm_id, &farm);
+                let mut farm = self
+                    .data_mut()
+                    .farms
+                    .remove(farm_id)
+                    .expect(FARM_NOT_EXIST)
+                    .upgrade()
+                    .get();
+                farm.move_to_clear(&seed_power);
+                self.data_mut()
+                    .outdated_farms
+                    .insert(farm_id, &VersionedFarm::new(farm));
                 farm_seed.get_ref_mut().farms.remove(farm_id);
                 self.data_mut().seeds.insert(&seed_id, &farm_seed);
                 return true;
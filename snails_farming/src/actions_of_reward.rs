@@ -1,10 +1,13 @@
 use near_sdk::json_types::U128;
 use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId, Balance, PromiseResult};
+use std::collections::HashMap;
 use std::convert::TryInto;
 
 use crate::errors::*;
+use crate::events::emit_reward_claim;
 use crate::utils::{
-    ext_fungible_token, ext_self, parse_farm_id, GAS_FOR_FT_TRANSFER, GAS_FOR_RESOLVE_TRANSFER,
+    ext_fungible_token, ext_self, gen_user_rps_key, parse_farm_id, GAS_FOR_FT_TRANSFER,
+    GAS_FOR_RESOLVE_TRANSFER,
 };
 use crate::*;
 use uint::construct_uint;
@@ -25,7 +28,15 @@ impl Contract {
         let (seed_id, _) = parse_farm_id(&farm_id);
         let farm_seed = self.get_seed(&seed_id);
         if !farm_seed.get_ref().farms.contains(&farm_id) {
-            farmer.get_ref_mut().remove_rps(&farm_id);
+            let reward_count = self
+                .get_outdated_farm_wrapped(&farm_id)
+                .map(|farm| farm.get_reward_tokens().len())
+                .unwrap_or(1);
+            for index in 0..reward_count {
+                farmer
+                    .get_ref_mut()
+                    .remove_rps(&gen_user_rps_key(&farm_id, index));
+            }
             self.data_mut().farmers.insert(&sender_id, &farmer);
             true
         } else {
@@ -43,10 +54,48 @@ impl Contract {
     pub fn claim_reward_by_seed(&mut self, seed_id: SeedId) {
         self.assert_contract_running();
         let sender_id = env::predecessor_account_id();
-        self.internal_claim_user_reward_by_seed_id(&sender_id, &seed_id);
+        self.internal_claim_user_reward_by_seed_id(&sender_id, &seed_id, true);
+        self.assert_storage_usage(&sender_id);
+    }
+
+    /// Claims reward from each of the given farms,
+    /// farms whose seed the sender has not staked are skipped.
+    pub fn claim_reward_by_farms(&mut self, farm_ids: Vec<FarmId>) {
+        self.assert_contract_running();
+        let sender_id = env::predecessor_account_id();
+        let seeds = self.get_farmer(&sender_id).get_ref().seeds.clone();
+        for farm_id in farm_ids.iter() {
+            let (seed_id, _) = parse_farm_id(farm_id);
+            if seeds.contains_key(&seed_id) {
+                self.internal_claim_user_reward_by_farm_id(&sender_id, farm_id);
+            }
+        }
         self.assert_storage_usage(&sender_id);
     }
 
+    /// Moves the vested part of the given farmer's vesting rewards to the claimed rewards,
+    /// from where they can be withdrawn. Returns amount released per reward token.
+    pub fn withdraw_vested(&mut self, account_id: AccountId) -> HashMap<AccountId, U128> {
+        self.assert_contract_running();
+        let mut farmer = self.get_farmer(&account_id);
+        let released = farmer.get_ref_mut().release_vested();
+        self.data_mut().farmers.insert(&account_id, &farmer);
+        self.assert_storage_usage(&account_id);
+        released
+            .into_iter()
+            .map(|(token_id, amount)| {
+                env::log_str(
+                    format!(
+                        "{} released {} {} of vesting reward",
+                        account_id, amount, token_id
+                    )
+                    .as_str(),
+                );
+                (token_id, U128(amount))
+            })
+            .collect()
+    }
+
     /// Withdraws given reward token of given user.
     #[payable]
     pub fn withdraw_reward(&mut self, token_id: AccountId, amount: Option<U128>) {
@@ -123,55 +172,98 @@ impl Contract {
 }
 
 fn claim_user_reward_from_farm(
+    farmer_id: &AccountId,
     farm: &mut Farm,
     farmer: &mut Farmer,
     total_seeds: &Balance,
     silent: bool,
+    keep_remainder: bool,
 ) {
-    let user_seeds = farmer.seeds.get(&farm.get_seed_id()).unwrap_or(&0_u128);
-    let user_rps = farmer.get_rps(&farm.get_farm_id());
-    let (new_user_rps, reward_amount) =
-        farm.claim_user_reward(&user_rps, user_seeds, total_seeds, silent);
-    if !silent {
-        env::log_str(
-            format!(
-                "user_rps@{} increased to {}",
-                farm.get_farm_id(),
-                U256::from_little_endian(&new_user_rps),
-            )
-            .as_str(),
+    let user_seeds = farmer.seed_power(&farm.get_seed_id());
+    for (index, reward_token) in farm.get_reward_tokens().iter().enumerate() {
+        let rps_key = gen_user_rps_key(&farm.get_farm_id(), index);
+        let user_rps = farmer.get_rps(&rps_key);
+        let (new_user_rps, reward_amount) = farm.claim_user_reward(
+            index,
+            &user_rps,
+            &user_seeds,
+            total_seeds,
+            silent,
+            keep_remainder,
         );
-    }
-    farmer.set_rps(&farm.get_farm_id(), new_user_rps);
-    if reward_amount > 0 {
-        farmer.add_reward(&farm.get_reward_token(), reward_amount);
         if !silent {
             env::log_str(
                 format!(
-                    "claimed {} {} as reward from {}",
-                    reward_amount,
-                    farm.get_reward_token(),
+                    "user_rps@{} of {} increased to {}",
                     farm.get_farm_id(),
+                    reward_token,
+                    U256::from_little_endian(&new_user_rps),
                 )
                 .as_str(),
             );
         }
+        farmer.set_rps(&rps_key, new_user_rps);
+        if reward_amount > 0 {
+            emit_reward_claim(
+                farmer_id,
+                &farm.get_seed_id(),
+                &farm.get_farm_id(),
+                reward_token,
+                reward_amount,
+            );
+            let vest_duration = farm.get_vest_duration();
+            if vest_duration > 0 {
+                farmer.add_vesting(reward_token, reward_amount, vest_duration);
+            } else {
+                farmer.add_reward(reward_token, reward_amount);
+            }
+            if !silent {
+                env::log_str(
+                    format!(
+                        "claimed {} {} as reward from {}",
+                        reward_amount,
+                        reward_token,
+                        farm.get_farm_id(),
+                    )
+                    .as_str(),
+                );
+            }
+        }
     }
 }
 
 impl Contract {
+    /// Claims reward of all the farms of the seed,
+    /// keep_remainder must be false when the farmer seed is about to grow.
     pub(crate) fn internal_claim_user_reward_by_seed_id(
         &mut self,
         sender_id: &AccountId,
         seed_id: &SeedId,
+        keep_remainder: bool,
     ) {
         let mut farmer = self.get_farmer(sender_id);
         if let Some(mut farm_seed) = self.get_seed_wrapped(seed_id) {
-            let amount = farm_seed.get_ref().amount;
+            let power = farm_seed.get_ref().power;
             for farm_id in &mut farm_seed.get_ref_mut().farms.iter() {
-                let mut farm = self.data().farms.get(farm_id).unwrap();
-                claim_user_reward_from_farm(&mut farm, farmer.get_ref_mut(), &amount, true);
-                self.data_mut().farms.insert(farm_id, &farm);
+                let mut farm = self.get_farm_wrapped(farm_id).unwrap();
+                claim_user_reward_from_farm(
+                    sender_id,
+                    &mut farm,
+                    farmer.get_ref_mut(),
+                    &power,
+                    true,
+                    keep_remainder,
+                );
+                self.data_mut()
+                    .farms
+                    .insert(farm_id, &VersionedFarm::new(farm));
+            }
+            // rewards are settled with the boost up to now, an expired lock ends here.
+            let prev_power = farmer.get_ref().seed_power(seed_id);
+            if farmer.get_ref_mut().remove_expired_lock(seed_id) {
+                farm_seed
+                    .get_ref_mut()
+                    .update_power(prev_power, farmer.get_ref().seed_power(seed_id));
             }
             self.data_mut().seeds.insert(seed_id, &farm_seed);
             self.data_mut().farmers.insert(sender_id, &farmer);
@@ -188,10 +280,19 @@ impl Contract {
         let (seed_id, _) = parse_farm_id(farm_id);
 
         if let Some(farm_seed) = self.get_seed_wrapped(&seed_id) {
-            let amount = farm_seed.get_ref().amount;
-            if let Some(mut farm) = self.data().farms.get(farm_id) {
-                claim_user_reward_from_farm(&mut farm, farmer.get_ref_mut(), &amount, false);
-                self.data_mut().farms.insert(farm_id, &farm);
+            let power = farm_seed.get_ref().power;
+            if let Some(mut farm) = self.get_farm_wrapped(farm_id) {
+                claim_user_reward_from_farm(
+                    sender_id,
+                    &mut farm,
+                    farmer.get_ref_mut(),
+                    &power,
+                    false,
+                    true,
+                );
+                self.data_mut()
+                    .farms
+                    .insert(farm_id, &VersionedFarm::new(farm));
                 self.data_mut().farmers.insert(sender_id, &farmer);
             }
         }
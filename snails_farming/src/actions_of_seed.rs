@@ -3,10 +3,11 @@ use near_sdk::{AccountId, Balance, PromiseResult};
 use std::convert::TryInto;
 
 use crate::errors::*;
+use crate::events::{emit_seed_deposit, emit_seed_withdraw};
 use crate::farm_seed::SeedType;
 use crate::utils::{
-    assert_one_yocto, ext_fungible_token, ext_multi_fungible_token, ext_self, parse_seed_id,
-    wrap_mft_token_id, GAS_FOR_FT_TRANSFER, GAS_FOR_RESOLVE_TRANSFER,
+    assert_one_yocto, ext_fungible_token, ext_multi_fungible_token, ext_self, gen_user_rps_key,
+    parse_seed_id, wrap_mft_token_id, GAS_FOR_FT_TRANSFER, GAS_FOR_RESOLVE_TRANSFER,
 };
 use crate::*;
 
@@ -22,47 +23,36 @@ impl Contract {
 
         // update inner state
         let seed_type = self.internal_seed_withdraw(&seed_id, &sender_id, amount);
+        emit_seed_withdraw(&sender_id, &seed_id, amount, false);
+        self.internal_send_seed(seed_id, sender_id, amount, seed_type);
+    }
 
-        match seed_type {
-            SeedType::FT => {
-                ext_fungible_token::ft_transfer(
-                    sender_id.clone().try_into().unwrap(),
-                    amount.into(),
-                    None,
-                    AccountId::try_from(seed_id.clone()).unwrap(),
-                    1, // one yocto near
-                    GAS_FOR_FT_TRANSFER,
-                )
-                .then(ext_self::callback_post_withdraw_ft_seed(
-                    seed_id,
-                    sender_id,
-                    amount.into(),
-                    env::current_account_id(),
-                    0,
-                    GAS_FOR_RESOLVE_TRANSFER,
-                ));
-            }
-            SeedType::MFT => {
-                let (receiver_id, token_id) = parse_seed_id(&seed_id);
-                ext_multi_fungible_token::mft_transfer(
-                    wrap_mft_token_id(&token_id),
-                    sender_id.clone().try_into().unwrap(),
-                    amount.into(),
-                    None,
-                    AccountId::try_from(receiver_id.clone()).unwrap(),
-                    1, // one yocto near
-                    GAS_FOR_FT_TRANSFER,
-                )
-                .then(ext_self::callback_post_withdraw_mft_seed(
-                    seed_id,
-                    sender_id,
-                    amount.into(),
-                    env::current_account_id(),
-                    0,
-                    GAS_FOR_RESOLVE_TRANSFER,
-                ));
-            }
-        }
+    /// Withdraws all the seed of the sender without claiming rewards,
+    /// for when a reward token can not be transferred.
+    /// Unclaimed rewards are forfeited, they go to beneficiary once the farm is cleared.
+    /// A locked seed can not be withdrawn before its lock expired.
+    #[payable]
+    pub fn emergency_withdraw_seed(&mut self, seed_id: SeedId) {
+        assert_one_yocto();
+        let sender_id = env::predecessor_account_id();
+
+        let (amount, seed_type) = self.internal_seed_emergency_withdraw(&seed_id, &sender_id);
+        emit_seed_withdraw(&sender_id, &seed_id, amount, true);
+        self.internal_send_seed(seed_id, sender_id, amount, seed_type);
+    }
+
+    /// Settles the farmer rewards of the seed and drops the boost of its expired lock,
+    /// so a farmer not touching the seed does not keep the boost.
+    pub fn expire_seed_lock(&mut self, account_id: AccountId, seed_id: SeedId) {
+        self.assert_contract_running();
+        let farmer = self.get_farmer(&account_id);
+        assert!(
+            farmer.get_ref().locks.contains_key(&seed_id),
+            "{}",
+            SEED_NOT_LOCKED
+        );
+        farmer.get_ref().assert_seed_unlocked(&seed_id);
+        self.internal_claim_user_reward_by_seed_id(&account_id, &seed_id, true);
     }
 
     #[private]
@@ -91,13 +81,19 @@ impl Contract {
                     .as_str(),
                 );
                 // revert withdraw, equal to deposit, claim reward to update user reward_per_seed
-                self.internal_claim_user_reward_by_seed_id(&sender_id, &seed_id);
+                self.internal_claim_user_reward_by_seed_id(&sender_id, &seed_id, false);
                 let mut farm_seed = self.get_seed(&seed_id);
                 let mut farmer = self.get_farmer(&sender_id);
 
                 farm_seed.get_ref_mut().seed_type = SeedType::FT;
+                let prev_power = farmer.get_ref().seed_power(&seed_id);
+                if farmer.get_ref_mut().add_seed(&seed_id, amount) {
+                    farm_seed.get_ref_mut().add_farmer();
+                }
                 farm_seed.get_ref_mut().add_amount(amount);
-                farmer.get_ref_mut().add_seed(&seed_id, amount);
+                farm_seed
+                    .get_ref_mut()
+                    .update_power(prev_power, farmer.get_ref().seed_power(&seed_id));
                 self.data_mut().seeds.insert(&seed_id, &farm_seed);
                 self.data_mut().farmers.insert(&sender_id, &farmer);
             }
@@ -139,13 +135,19 @@ impl Contract {
                     .as_str(),
                 );
                 // revert withdraw, equal to deposit, claim reward to update user reward_per_seed
-                self.internal_claim_user_reward_by_seed_id(&sender_id, &seed_id);
+                self.internal_claim_user_reward_by_seed_id(&sender_id, &seed_id, false);
                 let mut farm_seed = self.get_seed(&seed_id);
                 let mut farmer = self.get_farmer(&sender_id);
 
                 farm_seed.get_ref_mut().seed_type = SeedType::MFT;
+                let prev_power = farmer.get_ref().seed_power(&seed_id);
+                if farmer.get_ref_mut().add_seed(&seed_id, amount) {
+                    farm_seed.get_ref_mut().add_farmer();
+                }
                 farm_seed.get_ref_mut().add_amount(amount);
-                farmer.get_ref_mut().add_seed(&seed_id, amount);
+                farm_seed
+                    .get_ref_mut()
+                    .update_power(prev_power, farmer.get_ref().seed_power(&seed_id));
                 self.data_mut().seeds.insert(&seed_id, &farm_seed);
                 self.data_mut().farmers.insert(&sender_id, &farmer);
             }
@@ -197,20 +199,157 @@ impl Contract {
         sender_id: &AccountId,
         amount: Balance,
         seed_type: SeedType,
+        lock_days: u32,
     ) {
         // first claim all reward of the user for this seed farms
         // to update user reward_per_seed in each farm
-        self.internal_claim_user_reward_by_seed_id(sender_id, seed_id);
+        self.internal_claim_user_reward_by_seed_id(sender_id, seed_id, false);
+
+        let mut farmer = self.get_farmer(sender_id);
+        let prev_power = farmer.get_ref().seed_power(seed_id);
+        if !farmer.get_ref().seeds.contains_key(seed_id) {
+            // lock left from a seed fully withdrawn before.
+            farmer.get_ref_mut().locks.remove(seed_id);
+        }
+        assert!(
+            lock_days > 0 || !farmer.get_ref().is_seed_locked(seed_id),
+            "{}",
+            LOCKED_SEED_TOP_UP
+        );
+        let new_farmer = farmer.get_ref_mut().add_seed(&seed_id, amount);
+        if lock_days > 0 {
+            farmer.get_ref_mut().lock_seed(seed_id, lock_days);
+            let lock = &farmer.get_ref().locks[seed_id];
+            env::log_str(
+                format!(
+                    "{} locked seed {} until {} with boost {} bps",
+                    sender_id, seed_id, lock.locked_until, lock.boost_bps,
+                )
+                .as_str(),
+            );
+        }
 
         // **** update seed (new version)
         let mut farm_seed = self.get_seed(seed_id);
         farm_seed.get_ref_mut().seed_type = seed_type;
+        if new_farmer {
+            farm_seed.get_ref_mut().add_farmer();
+        }
         farm_seed.get_ref_mut().add_amount(amount);
+        farm_seed
+            .get_ref_mut()
+            .update_power(prev_power, farmer.get_ref().seed_power(seed_id));
         self.data_mut().seeds.insert(&seed_id, &farm_seed);
+        self.data_mut().farmers.insert(sender_id, &farmer);
+        emit_seed_deposit(sender_id, seed_id, amount);
+    }
 
+    /// Transfers withdrawn seed to the farmer, the callback reverts the withdraw on failure.
+    fn internal_send_seed(
+        &mut self,
+        seed_id: SeedId,
+        sender_id: AccountId,
+        amount: Balance,
+        seed_type: SeedType,
+    ) {
+        match seed_type {
+            SeedType::FT => {
+                ext_fungible_token::ft_transfer(
+                    sender_id.clone().try_into().unwrap(),
+                    amount.into(),
+                    None,
+                    AccountId::try_from(seed_id.clone()).unwrap(),
+                    1, // one yocto near
+                    GAS_FOR_FT_TRANSFER,
+                )
+                .then(ext_self::callback_post_withdraw_ft_seed(
+                    seed_id,
+                    sender_id,
+                    amount.into(),
+                    env::current_account_id(),
+                    0,
+                    GAS_FOR_RESOLVE_TRANSFER,
+                ));
+            }
+            SeedType::MFT => {
+                let (receiver_id, token_id) = parse_seed_id(&seed_id);
+                ext_multi_fungible_token::mft_transfer(
+                    wrap_mft_token_id(&token_id),
+                    sender_id.clone().try_into().unwrap(),
+                    amount.into(),
+                    None,
+                    AccountId::try_from(receiver_id.clone()).unwrap(),
+                    1, // one yocto near
+                    GAS_FOR_FT_TRANSFER,
+                )
+                .then(ext_self::callback_post_withdraw_mft_seed(
+                    seed_id,
+                    sender_id,
+                    amount.into(),
+                    env::current_account_id(),
+                    0,
+                    GAS_FOR_RESOLVE_TRANSFER,
+                ));
+            }
+        }
+    }
+
+    /// Takes all the seed of the farmer out without claiming rewards,
+    /// farms are distributed first so other farmers rps are not affected.
+    fn internal_seed_emergency_withdraw(
+        &mut self,
+        seed_id: &SeedId,
+        sender_id: &AccountId,
+    ) -> (Balance, SeedType) {
+        let mut farm_seed = self.get_seed(seed_id);
         let mut farmer = self.get_farmer(sender_id);
-        farmer.get_ref_mut().add_seed(&seed_id, amount);
+        farmer.get_ref().assert_seed_unlocked(seed_id);
+        let amount = *farmer
+            .get_ref()
+            .seeds
+            .get(seed_id)
+            .expect(&format!("{}", SEED_NOT_EXIST));
+        let power = farmer.get_ref().seed_power(seed_id);
+        let total_seeds = farm_seed.get_ref().power;
+
+        for farm_id in farm_seed.get_ref().farms.iter() {
+            let mut farm = match self.get_farm_wrapped(farm_id) {
+                Some(farm) => farm,
+                None => continue,
+            };
+            farm.distribute(&total_seeds, true);
+            for (index, reward_token) in farm.get_reward_tokens().iter().enumerate() {
+                let rps_key = gen_user_rps_key(farm_id, index);
+                let forfeited = farm.view_farmer_unclaimed_reward(
+                    index,
+                    &farmer.get_ref().get_rps(&rps_key),
+                    &power,
+                    &total_seeds,
+                );
+                if forfeited > 0 {
+                    env::log_str(
+                        format!(
+                            "{} forfeited {} {} of reward from {}",
+                            sender_id, forfeited, reward_token, farm_id,
+                        )
+                        .as_str(),
+                    );
+                }
+                farmer.get_ref_mut().remove_rps(&rps_key);
+            }
+            self.data_mut()
+                .farms
+                .insert(farm_id, &VersionedFarm::new(farm));
+        }
+
+        farmer.get_ref_mut().sub_seed(seed_id, amount);
+        farmer.get_ref_mut().locks.remove(seed_id);
+        farm_seed.get_ref_mut().sub_amount(amount);
+        farm_seed.get_ref_mut().update_power(power, 0);
+        farm_seed.get_ref_mut().remove_farmer();
         self.data_mut().farmers.insert(sender_id, &farmer);
+        self.data_mut().seeds.insert(seed_id, &farm_seed);
+        (amount, farm_seed.get_ref().seed_type.clone())
     }
 
     fn internal_seed_withdraw(
@@ -219,21 +358,37 @@ impl Contract {
         sender_id: &AccountId,
         amount: Balance,
     ) -> SeedType {
+        self.get_farmer(sender_id)
+            .get_ref()
+            .assert_seed_unlocked(seed_id);
         // first claim all reward of the user for this seed farms
         // to update user reward_per_seed in each farm
-        self.internal_claim_user_reward_by_seed_id(sender_id, seed_id);
+        self.internal_claim_user_reward_by_seed_id(sender_id, seed_id, true);
 
         let mut farm_seed = self.get_seed(seed_id);
         let mut farmer = self.get_farmer(sender_id);
 
         // Then update user seed and total seed of this LPT
+        let prev_power = farmer.get_ref().seed_power(seed_id);
         let farmer_seed_remain = farmer.get_ref_mut().sub_seed(seed_id, amount);
         let _seed_remain = farm_seed.get_ref_mut().sub_amount(amount);
+        farm_seed
+            .get_ref_mut()
+            .update_power(prev_power, farmer.get_ref().seed_power(seed_id));
 
         if farmer_seed_remain == 0 {
+            farm_seed.get_ref_mut().remove_farmer();
             // remove farmer rps of relative farm
             for farm_id in farm_seed.get_ref().farms.iter() {
-                farmer.get_ref_mut().remove_rps(farm_id);
+                let reward_count = self
+                    .get_farm_wrapped(farm_id)
+                    .map(|farm| farm.get_reward_tokens().len())
+                    .unwrap_or(1);
+                for index in 0..reward_count {
+                    farmer
+                        .get_ref_mut()
+                        .remove_rps(&gen_user_rps_key(farm_id, index));
+                }
             }
         }
         self.data_mut().farmers.insert(sender_id, &farmer);
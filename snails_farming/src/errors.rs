@@ -1,6 +1,7 @@
 // Storage errors //
 pub const STORAGE_UNREGISTER_REWARDS_NOT_EMPTY: &str = "Still has rewards when unregister";
 pub const STORAGE_UNREGISTER_SEED_NOT_EMPTY: &str = "Still has staked seed when unregister";
+pub const STORAGE_UNREGISTER_VESTINGS_NOT_EMPTY: &str = "Still has vesting rewards when unregister";
 pub const ERR14_ACC_ALREADY_REGISTERED: &str = "Account already registered";
 
 // Reward errors //
@@ -15,12 +16,18 @@ pub const NOT_ENOUGH_SEED: &str = "Not enough amount of seed";
 pub const INVALID_SEED_ID: &str = "Invalid seed id";
 pub const BELOW_MIN_SEED_DEPOSITED: &str = "Below min_deposit of this seed";
 pub const ILLEGAL_TOKEN_ID: &str = "Illegal token_id in mft_transfer_call";
+pub const INVALID_LOCK_DAYS: &str = "Lock days over max lock period";
+pub const SEED_LOCKED: &str = "Seed still locked";
+pub const LOCKED_SEED_TOP_UP: &str = "Locked seed can only be topped up with a lock";
+pub const SEED_NOT_LOCKED: &str = "Seed not locked";
 
 // farm errors //
 pub const FARM_NOT_EXIST: &str = "Farm not exist";
 pub const INVALID_FARM_ID: &str = "Invalid farm id";
 pub const INVALID_FARM_STATUS: &str = "Invalid farm status";
 pub const INVALID_FARM_REWARD: &str = "Invalid reward token for this farm";
+pub const RESUME_FARM_NOT_ALLOWED: &str = "Only owner can resume an ended farm";
+pub const INVALID_FARM_REWARD_TERMS: &str = "Invalid reward tokens or reward per session";
 
 pub const INTERNAL_ERROR: &str = "Internal ERROR!";
 
@@ -0,0 +1,75 @@
+//! NEP-297 events, logged as `EVENT_JSON:{...}` next to the plain logs.
+
+use near_sdk::json_types::U128;
+use near_sdk::serde_json::{json, Value};
+use near_sdk::{env, AccountId, Balance};
+
+use crate::{FarmId, SeedId};
+
+pub const EVENT_STANDARD: &str = "snails_farming";
+pub const EVENT_VERSION: &str = "1.0.0";
+
+fn emit_event(event: &str, data: Value) {
+    env::log_str(
+        format!(
+            "EVENT_JSON:{}",
+            json!({
+                "standard": EVENT_STANDARD,
+                "version": EVENT_VERSION,
+                "event": event,
+                "data": [data],
+            })
+        )
+        .as_str(),
+    );
+}
+
+/// Seed staked by the farmer.
+pub(crate) fn emit_seed_deposit(farmer_id: &AccountId, seed_id: &SeedId, amount: Balance) {
+    emit_event(
+        "seed_deposit",
+        json!({
+            "farmer_id": farmer_id,
+            "seed_id": seed_id,
+            "amount": U128(amount),
+        }),
+    );
+}
+
+/// Seed taken out by the farmer and sent back, `emergency` if rewards were forfeited.
+pub(crate) fn emit_seed_withdraw(
+    farmer_id: &AccountId,
+    seed_id: &SeedId,
+    amount: Balance,
+    emergency: bool,
+) {
+    emit_event(
+        "seed_withdraw",
+        json!({
+            "farmer_id": farmer_id,
+            "seed_id": seed_id,
+            "amount": U128(amount),
+            "emergency": emergency,
+        }),
+    );
+}
+
+/// Reward of a farm credited to the farmer, to its rewards or vesting.
+pub(crate) fn emit_reward_claim(
+    farmer_id: &AccountId,
+    seed_id: &SeedId,
+    farm_id: &FarmId,
+    reward_token: &AccountId,
+    amount: Balance,
+) {
+    emit_event(
+        "reward_claim",
+        json!({
+            "farmer_id": farmer_id,
+            "seed_id": seed_id,
+            "farm_id": farm_id,
+            "reward_token": reward_token,
+            "amount": U128(amount),
+        }),
+    );
+}
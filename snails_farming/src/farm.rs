@@ -3,7 +3,8 @@
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
 use near_sdk::{AccountId, Balance};
 
-use crate::simple_farm::{SimpleFarm, SimpleFarmRewardDistribution, RPS};
+use crate::simple_farm::{SimpleFarm, SimpleFarmRewardDistribution, SimpleFarmV101, RPS};
+use crate::utils::TimestampSec;
 use crate::SeedId;
 
 pub(crate) type FarmId = String;
@@ -26,9 +27,14 @@ impl Farm {
 
     /// return None if the farm can not accept reward anymore
     /// else return amount of undistributed reward
-    pub fn add_reward(&mut self, amount: &Balance) -> Option<Balance> {
+    pub fn add_reward(
+        &mut self,
+        reward_token: &AccountId,
+        amount: &Balance,
+        total_seeds: &Balance,
+    ) -> Option<Balance> {
         match self {
-            Farm::SimpleFarm(farm) => farm.add_reward(amount),
+            Farm::SimpleFarm(farm) => farm.add_reward(reward_token, amount, total_seeds),
         }
     }
 
@@ -39,10 +45,24 @@ impl Farm {
         }
     }
 
-    /// Returns token contract id this farm used for reward.
-    pub fn get_reward_token(&self) -> AccountId {
+    /// Returns token contract ids this farm used for reward.
+    pub fn get_reward_tokens(&self) -> Vec<AccountId> {
+        match self {
+            Farm::SimpleFarm(farm) => farm.terms.reward_tokens.clone(),
+        }
+    }
+
+    /// Returns duration claimed rewards vest over, 0 if they are not vested.
+    pub fn get_vest_duration(&self) -> TimestampSec {
+        match self {
+            Farm::SimpleFarm(farm) => farm.terms.vest_duration,
+        }
+    }
+
+    /// Returns when the farm runs out of reward, None if it is not running.
+    pub fn projected_end_at(&self) -> Option<TimestampSec> {
         match self {
-            Farm::SimpleFarm(farm) => farm.terms.reward_token.clone(),
+            Farm::SimpleFarm(farm) => farm.projected_end_at(),
         }
     }
 
@@ -54,45 +74,67 @@ impl Farm {
     #[allow(dead_code)]
     pub(crate) fn try_distribute(
         &self,
+        index: usize,
         total_seeds: &Balance,
     ) -> Option<SimpleFarmRewardDistribution> {
         match self {
-            Farm::SimpleFarm(farm) => farm.try_distribute(total_seeds),
+            Farm::SimpleFarm(farm) => farm.try_distribute(index, total_seeds),
         }
     }
 
-    pub(crate) fn change_reward_per_session(&mut self, reward_per_session: Balance) {
+    pub(crate) fn change_reward_per_session(
+        &mut self,
+        reward_token: &AccountId,
+        reward_per_session: Balance,
+    ) {
         match self {
-            Farm::SimpleFarm(farm) => farm.change_reward_per_session(reward_per_session),
+            Farm::SimpleFarm(farm) => {
+                farm.change_reward_per_session(reward_token, reward_per_session)
+            }
         }
     }
-    /// Returns how many reward tokens can given farmer claim.
+    /// Returns how many of the `index`th reward token can given farmer claim.
     pub fn view_farmer_unclaimed_reward(
         &self,
+        index: usize,
         user_rps: &RPS,
         user_seeds: &Balance,
         total_seeds: &Balance,
     ) -> Balance {
         match self {
             Farm::SimpleFarm(farm) => {
-                farm.view_farmer_unclaimed_reward(user_rps, user_seeds, total_seeds)
+                farm.view_farmer_unclaimed_reward(index, user_rps, user_seeds, total_seeds)
             }
         }
     }
 
     /// return the new user reward per seed
-    /// and amount of reward as (user_rps, reward_amount)
+    /// and amount of the `index`th reward token as (user_rps, reward_amount)
     pub fn claim_user_reward(
         &mut self,
+        index: usize,
         user_rps: &RPS,
         user_seeds: &Balance,
         total_seeds: &Balance,
         silent: bool,
+        keep_remainder: bool,
     ) -> (RPS, Balance) {
         match self {
-            Farm::SimpleFarm(farm) => {
-                farm.claim_user_reward(user_rps, user_seeds, total_seeds, silent)
-            }
+            Farm::SimpleFarm(farm) => farm.claim_user_reward(
+                index,
+                user_rps,
+                user_seeds,
+                total_seeds,
+                silent,
+                keep_remainder,
+            ),
+        }
+    }
+
+    /// Distributes reward generated since the last distribution.
+    pub fn distribute(&mut self, total_seeds: &Balance, silent: bool) {
+        match self {
+            Farm::SimpleFarm(farm) => farm.distribute(total_seeds, silent),
         }
     }
 
@@ -108,3 +150,46 @@ impl Farm {
         }
     }
 }
+
+/// Versioned Farm, used for lazy upgrade.
+/// Which means this structure would upgrade automatically when used.
+/// To achieve that, each time the new version comes in,
+/// each function of this enum should be carefully re-code!
+/// V101 reads farms stored as `Farm::SimpleFarm` before farms had several reward tokens.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub enum VersionedFarm {
+    V101(SimpleFarmV101),
+    V102(Farm),
+}
+
+impl VersionedFarm {
+    pub fn new(farm: Farm) -> Self {
+        VersionedFarm::V102(farm)
+    }
+
+    /// Upgrades from other versions to the currently used version.
+    pub fn upgrade(self) -> Self {
+        match self {
+            VersionedFarm::V102(farm) => VersionedFarm::V102(farm),
+            VersionedFarm::V101(farm) => VersionedFarm::V102(Farm::SimpleFarm(farm.into())),
+        }
+    }
+
+    #[inline]
+    #[allow(unreachable_patterns)]
+    pub fn need_upgrade(&self) -> bool {
+        match self {
+            VersionedFarm::V102(_) => false,
+            _ => true,
+        }
+    }
+
+    #[inline]
+    #[allow(unreachable_patterns)]
+    pub fn get(self) -> Farm {
+        match self {
+            VersionedFarm::V102(farm) => farm,
+            _ => unimplemented!(),
+        }
+    }
+}
@@ -5,7 +5,7 @@ use crate::errors::*;
 use crate::farm::FarmId;
 use crate::utils::parse_seed_id;
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::json_types::U128;
+use near_sdk::json_types::{U128, U64};
 use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::Balance;
 use std::collections::HashSet;
@@ -34,7 +34,12 @@ pub struct FarmSeed {
     pub next_index: u32,
     /// total (staked) balance of this seed (Farming Token)
     pub amount: Balance,
+    /// total share of the farmers in the farms, staked amounts boosted by locks
+    pub power: Balance,
     pub min_deposit: Balance,
+    /// number of farmers staking this seed,
+    /// None for seeds staked before farmers were counted
+    pub farmer_count: Option<u64>,
 }
 
 impl FarmSeed {
@@ -52,7 +57,22 @@ impl FarmSeed {
             farms: HashSet::new(),
             next_index: 0,
             amount: 0,
+            power: 0,
             min_deposit,
+            farmer_count: Some(0),
+        }
+    }
+
+    /// Farmers of a seed with an unknown count are not counted.
+    pub fn add_farmer(&mut self) {
+        if let Some(count) = self.farmer_count {
+            self.farmer_count = Some(count.checked_add(1).unwrap());
+        }
+    }
+
+    pub fn remove_farmer(&mut self) {
+        if let Some(count) = self.farmer_count {
+            self.farmer_count = Some(count.checked_sub(1).expect(INTERNAL_ERROR));
         }
     }
 
@@ -66,6 +86,40 @@ impl FarmSeed {
         self.amount = self.amount.checked_sub(amount).unwrap();
         self.amount
     }
+
+    /// Replaces `prev_power` of a farmer in the total power with `power`.
+    pub fn update_power(&mut self, prev_power: Balance, power: Balance) {
+        assert!(self.power >= prev_power, "{}", INTERNAL_ERROR);
+        self.power = (self.power - prev_power).checked_add(power).unwrap();
+    }
+}
+
+/// FarmSeed before staked amounts could be boosted by locks.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct FarmSeedV101 {
+    pub seed_id: SeedId,
+    pub seed_type: SeedType,
+    pub farms: HashSet<FarmId>,
+    pub next_index: u32,
+    pub amount: Balance,
+    pub min_deposit: Balance,
+}
+
+impl From<FarmSeedV101> for FarmSeed {
+    /// No lock existed, the power of every farmer is the staked amount.
+    /// Farmers were not counted, the count is unknown.
+    fn from(farm_seed: FarmSeedV101) -> Self {
+        Self {
+            seed_id: farm_seed.seed_id,
+            seed_type: farm_seed.seed_type,
+            farms: farm_seed.farms,
+            next_index: farm_seed.next_index,
+            amount: farm_seed.amount,
+            power: farm_seed.amount,
+            min_deposit: farm_seed.min_deposit,
+            farmer_count: None,
+        }
+    }
 }
 
 /// Versioned FarmSeed, used for lazy upgrade.
@@ -74,18 +128,20 @@ impl FarmSeed {
 /// each function of this enum should be carefully re-code!
 #[derive(BorshSerialize, BorshDeserialize)]
 pub enum VersionedFarmSeed {
-    V101(FarmSeed),
+    V101(FarmSeedV101),
+    V102(FarmSeed),
 }
 
 impl VersionedFarmSeed {
     pub fn new(seed_id: &SeedId, min_deposit: Balance) -> Self {
-        VersionedFarmSeed::V101(FarmSeed::new(seed_id, min_deposit))
+        VersionedFarmSeed::V102(FarmSeed::new(seed_id, min_deposit))
     }
 
     /// Upgrades from other versions to the currently used version.
     pub fn upgrade(self) -> Self {
         match self {
-            VersionedFarmSeed::V101(farm_seed) => VersionedFarmSeed::V101(farm_seed),
+            VersionedFarmSeed::V102(farm_seed) => VersionedFarmSeed::V102(farm_seed),
+            VersionedFarmSeed::V101(farm_seed) => VersionedFarmSeed::V102(farm_seed.into()),
         }
     }
 
@@ -93,7 +149,7 @@ impl VersionedFarmSeed {
     #[allow(unreachable_patterns)]
     pub fn need_upgrade(&self) -> bool {
         match self {
-            VersionedFarmSeed::V101(_) => false,
+            VersionedFarmSeed::V102(_) => false,
             _ => true,
         }
     }
@@ -102,7 +158,7 @@ impl VersionedFarmSeed {
     #[allow(unreachable_patterns)]
     pub fn get_ref(&self) -> &FarmSeed {
         match self {
-            VersionedFarmSeed::V101(farm_seed) => farm_seed,
+            VersionedFarmSeed::V102(farm_seed) => farm_seed,
             _ => unimplemented!(),
         }
     }
@@ -111,7 +167,7 @@ impl VersionedFarmSeed {
     #[allow(unreachable_patterns)]
     pub fn get_ref_mut(&mut self) -> &mut FarmSeed {
         match self {
-            VersionedFarmSeed::V101(farm_seed) => farm_seed,
+            VersionedFarmSeed::V102(farm_seed) => farm_seed,
             _ => unimplemented!(),
         }
     }
@@ -125,7 +181,9 @@ pub struct SeedInfo {
     pub farms: Vec<FarmId>,
     pub next_index: u32,
     pub amount: U128,
+    pub power: U128,
     pub min_deposit: U128,
+    pub farmer_count: Option<U64>,
 }
 
 impl From<&FarmSeed> for SeedInfo {
@@ -139,7 +197,9 @@ impl From<&FarmSeed> for SeedInfo {
             seed_type,
             next_index: fs.next_index,
             amount: fs.amount.into(),
+            power: fs.power.into(),
             min_deposit: fs.min_deposit.into(),
+            farmer_count: fs.farmer_count.map(U64),
             farms: fs.farms.iter().map(|key| key.clone()).collect(),
         }
     }
@@ -1,11 +1,15 @@
 //! Farmer records a farmer's
 //! * all claimed reward tokens,
+//! * rewards claimed from vesting farms, still to be released,
 //! * all seeds he staked,
+//! * locks boosting his share of the seeds,
 //! * user_rps per farm,
 //! and the deposited near amount prepaid as storage fee
 
 use crate::errors::*;
-use crate::utils::MAX_ACCOUNT_LENGTH;
+use crate::utils::{
+    boost_amount, lock_boost_bps, to_sec, TimestampSec, MAX_ACCOUNT_LENGTH, SECONDS_PER_DAY, U256,
+};
 use crate::StorageKeys;
 use crate::{FarmId, SeedId, RPS};
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
@@ -15,7 +19,45 @@ use std::collections::HashMap;
 /// each entry cost MAX_ACCOUNT_LENGTH bytes,
 /// amount: Balance cost 16 bytes
 /// each empty hashmap cost 4 bytes
-pub const MIN_FARMER_LENGTH: u128 = MAX_ACCOUNT_LENGTH + 16 + 4 * 3;
+pub const MIN_FARMER_LENGTH: u128 = MAX_ACCOUNT_LENGTH + 16 + 4 * 5;
+/// amount, released: Balance cost 16 bytes each,
+/// start_at, duration: TimestampSec cost 4 bytes each
+pub const VESTING_LENGTH: u128 = 16 * 2 + 4 * 2;
+/// locked_until: TimestampSec, boost_bps: u32 cost 4 bytes each
+pub const SEED_LOCK_LENGTH: u128 = 4 * 2;
+
+/// Reward claimed from a vesting farm, released linearly from `start_at` over `duration`.
+#[derive(BorshSerialize, BorshDeserialize, Clone)]
+pub struct Vesting {
+    pub amount: Balance,
+    /// Part of the amount already moved to the claimed rewards.
+    pub released: Balance,
+    pub start_at: TimestampSec,
+    pub duration: TimestampSec,
+}
+
+impl Vesting {
+    /// Returns the part of the amount vested at `now`, released or not.
+    pub fn vested(&self, now: TimestampSec) -> Balance {
+        let elapsed = now.saturating_sub(self.start_at);
+        if elapsed >= self.duration {
+            self.amount
+        } else {
+            (U256::from(self.amount) * U256::from(elapsed) / U256::from(self.duration)).as_u128()
+        }
+    }
+}
+
+/// Lock of the staked seed of a farmer.
+/// The boost applies to the whole staked amount of the seed, which can only be topped up
+/// with a lock while locked, and ends once the lock expired and the farmer rewards are settled.
+#[derive(BorshSerialize, BorshDeserialize, Clone)]
+pub struct SeedLock {
+    /// The seed can not be withdrawn before.
+    pub locked_until: TimestampSec,
+    /// Share of the farmer in the farms of the seed is the staked amount increased by it.
+    pub boost_bps: u32,
+}
 
 /// Account deposits information and storage cost.
 #[derive(BorshSerialize, BorshDeserialize)]
@@ -26,8 +68,12 @@ pub struct Farmer {
     pub amount: Balance,
     /// Amounts of various reward tokens the farmer claimed.
     pub rewards: HashMap<AccountId, Balance>,
+    /// Vesting schedules of rewards claimed from vesting farms, per reward token.
+    pub vestings: HashMap<AccountId, Vec<Vesting>>,
     /// Amounts of various seed tokens the farmer staked.
     pub seeds: HashMap<SeedId, Balance>,
+    /// Locks of the staked seeds.
+    pub locks: HashMap<SeedId, SeedLock>,
     /// record user_last_rps of farms
     pub user_rps: LookupMap<FarmId, RPS>,
     pub rps_count: u32,
@@ -60,12 +106,65 @@ impl Farmer {
         }
     }
 
-    pub fn add_seed(&mut self, seed_id: &SeedId, amount: Balance) {
+    /// Locks amount of given reward token, to be released linearly over duration from now.
+    pub(crate) fn add_vesting(
+        &mut self,
+        token: &AccountId,
+        amount: Balance,
+        duration: TimestampSec,
+    ) {
+        let start_at = to_sec(env::block_timestamp());
+        let vestings = self.vestings.entry(token.clone()).or_insert_with(Vec::new);
+        // claims made at the same time share a schedule.
+        if let Some(last) = vestings.last_mut() {
+            if last.start_at == start_at && last.duration == duration {
+                last.amount = last.amount.checked_add(amount).unwrap();
+                return;
+            }
+        }
+        vestings.push(Vesting {
+            amount,
+            released: 0,
+            start_at,
+            duration,
+        });
+    }
+
+    /// Moves the vested part of every schedule to the claimed rewards,
+    /// fully released schedules are removed.
+    /// Returns amount released per reward token.
+    pub(crate) fn release_vested(&mut self) -> HashMap<AccountId, Balance> {
+        let now = to_sec(env::block_timestamp());
+        let mut released = HashMap::new();
+        for (token, vestings) in self.vestings.iter_mut() {
+            let mut amount: Balance = 0;
+            for vesting in vestings.iter_mut() {
+                let vested = vesting.vested(now);
+                amount = amount.checked_add(vested - vesting.released).unwrap();
+                vesting.released = vested;
+            }
+            vestings.retain(|vesting| vesting.released < vesting.amount);
+            if amount > 0 {
+                released.insert(token.clone(), amount);
+            }
+        }
+        self.vestings.retain(|_, vestings| !vestings.is_empty());
+        for (token, amount) in released.iter() {
+            self.add_reward(token, *amount);
+        }
+        released
+    }
+
+    /// return true if the farmer starts staking the seed.
+    pub fn add_seed(&mut self, seed_id: &SeedId, amount: Balance) -> bool {
         if amount > 0 {
-            self.seeds.insert(
+            let prev_balance = self.seeds.insert(
                 seed_id.clone(),
                 amount + self.seeds.get(seed_id).unwrap_or(&0_u128),
             );
+            prev_balance.is_none()
+        } else {
+            false
         }
     }
 
@@ -85,6 +184,62 @@ impl Farmer {
         cur_balance
     }
 
+    /// Returns share of the farmer in the farms of the seed,
+    /// that is the staked amount boosted by the lock.
+    pub fn seed_power(&self, seed_id: &SeedId) -> Balance {
+        let amount = *self.seeds.get(seed_id).unwrap_or(&0_u128);
+        match self.locks.get(seed_id) {
+            Some(lock) => boost_amount(amount, lock.boost_bps),
+            None => amount,
+        }
+    }
+
+    /// Locks the seed for `lock_days` from now.
+    /// An existing lock is only extended, it never gets shorter or loses boost.
+    pub(crate) fn lock_seed(&mut self, seed_id: &SeedId, lock_days: u32) {
+        let boost_bps = lock_boost_bps(lock_days);
+        let locked_until = to_sec(env::block_timestamp())
+            .checked_add(lock_days * SECONDS_PER_DAY)
+            .unwrap();
+        let lock = self.locks.entry(seed_id.clone()).or_insert(SeedLock {
+            locked_until,
+            boost_bps,
+        });
+        lock.locked_until = std::cmp::max(lock.locked_until, locked_until);
+        lock.boost_bps = std::cmp::max(lock.boost_bps, boost_bps);
+    }
+
+    /// Returns true if the seed is still locked.
+    pub(crate) fn is_seed_locked(&self, seed_id: &SeedId) -> bool {
+        self.locks.get(seed_id).map_or(false, |lock| {
+            to_sec(env::block_timestamp()) < lock.locked_until
+        })
+    }
+
+    /// Panics if the seed is still locked.
+    pub(crate) fn assert_seed_unlocked(&self, seed_id: &SeedId) {
+        if let Some(lock) = self.locks.get(seed_id) {
+            assert!(
+                to_sec(env::block_timestamp()) >= lock.locked_until,
+                "{} until {}",
+                SEED_LOCKED,
+                lock.locked_until
+            );
+        }
+    }
+
+    /// Removes the lock of the seed if it expired, dropping its boost.
+    /// Rewards of the seed farms must be settled before.
+    /// return true if a lock was removed.
+    pub(crate) fn remove_expired_lock(&mut self, seed_id: &SeedId) -> bool {
+        if self.locks.contains_key(seed_id) && !self.is_seed_locked(seed_id) {
+            self.locks.remove(seed_id);
+            true
+        } else {
+            false
+        }
+    }
+
     pub fn get_rps(&self, farm_id: &FarmId) -> RPS {
         self.user_rps.get(farm_id).unwrap_or(RPS::default()).clone()
     }
@@ -107,27 +262,88 @@ impl Farmer {
     pub fn storage_usage(&self) -> Balance {
         (MIN_FARMER_LENGTH
             + self.rewards.len() as u128 * (4 + MAX_ACCOUNT_LENGTH + 16)
+            + self
+                .vestings
+                .values()
+                .map(|vestings| {
+                    4 + MAX_ACCOUNT_LENGTH + 4 + vestings.len() as u128 * VESTING_LENGTH
+                })
+                .sum::<u128>()
             + self.seeds.len() as u128 * (4 + MAX_ACCOUNT_LENGTH + 16)
+            + self.locks.len() as u128 * (4 + MAX_ACCOUNT_LENGTH + SEED_LOCK_LENGTH)
             + self.rps_count as u128 * (4 + 1 + 2 * MAX_ACCOUNT_LENGTH + 32))
             * env::storage_byte_cost()
     }
 }
 
+/// Farmer before rewards claimed from vesting farms were recorded.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct FarmerV101 {
+    pub amount: Balance,
+    pub rewards: HashMap<AccountId, Balance>,
+    pub seeds: HashMap<SeedId, Balance>,
+    pub user_rps: LookupMap<FarmId, RPS>,
+    pub rps_count: u32,
+}
+
+impl From<FarmerV101> for Farmer {
+    fn from(farmer: FarmerV101) -> Self {
+        Self {
+            amount: farmer.amount,
+            rewards: farmer.rewards,
+            vestings: HashMap::new(),
+            seeds: farmer.seeds,
+            locks: HashMap::new(),
+            user_rps: farmer.user_rps,
+            rps_count: farmer.rps_count,
+        }
+    }
+}
+
+/// Farmer before staked seeds could be locked.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct FarmerV102 {
+    pub amount: Balance,
+    pub rewards: HashMap<AccountId, Balance>,
+    pub vestings: HashMap<AccountId, Vec<Vesting>>,
+    pub seeds: HashMap<SeedId, Balance>,
+    pub user_rps: LookupMap<FarmId, RPS>,
+    pub rps_count: u32,
+}
+
+impl From<FarmerV102> for Farmer {
+    fn from(farmer: FarmerV102) -> Self {
+        Self {
+            amount: farmer.amount,
+            rewards: farmer.rewards,
+            vestings: farmer.vestings,
+            seeds: farmer.seeds,
+            locks: HashMap::new(),
+            user_rps: farmer.user_rps,
+            rps_count: farmer.rps_count,
+        }
+    }
+}
+
 /// Versioned Farmer, used for lazy upgrade.
 /// Which means this structure would upgrade automatically when used.
 /// To achieve that, each time the new version comes in,
 /// each function of this enum should be carefully re-code!
 #[derive(BorshSerialize, BorshDeserialize)]
 pub enum VersionedFarmer {
-    V101(Farmer),
+    V101(FarmerV101),
+    V102(FarmerV102),
+    V103(Farmer),
 }
 
 impl VersionedFarmer {
     pub fn new(farmer_id: AccountId, amount: Balance) -> Self {
-        VersionedFarmer::V101(Farmer {
+        VersionedFarmer::V103(Farmer {
             amount: amount,
             rewards: HashMap::new(),
+            vestings: HashMap::new(),
             seeds: HashMap::new(),
+            locks: HashMap::new(),
             user_rps: LookupMap::new(StorageKeys::UserRps {
                 account_id: farmer_id.clone(),
             }),
@@ -138,7 +354,9 @@ impl VersionedFarmer {
     /// Upgrades from other versions to the currently used version.
     pub fn upgrade(self) -> Self {
         match self {
-            VersionedFarmer::V101(farmer) => VersionedFarmer::V101(farmer),
+            VersionedFarmer::V103(farmer) => VersionedFarmer::V103(farmer),
+            VersionedFarmer::V102(farmer) => VersionedFarmer::V103(farmer.into()),
+            VersionedFarmer::V101(farmer) => VersionedFarmer::V103(farmer.into()),
         }
     }
 
@@ -146,7 +364,7 @@ impl VersionedFarmer {
     #[allow(unreachable_patterns)]
     pub fn need_upgrade(&self) -> bool {
         match self {
-            VersionedFarmer::V101(_) => false,
+            VersionedFarmer::V103(_) => false,
             _ => true,
         }
     }
@@ -155,7 +373,7 @@ impl VersionedFarmer {
     #[allow(unreachable_patterns)]
     pub fn get_ref(&self) -> &Farmer {
         match self {
-            VersionedFarmer::V101(farmer) => farmer,
+            VersionedFarmer::V103(farmer) => farmer,
             _ => unimplemented!(),
         }
     }
@@ -164,7 +382,7 @@ impl VersionedFarmer {
     #[allow(unreachable_patterns)]
     pub fn get(self) -> Farmer {
         match self {
-            VersionedFarmer::V101(farmer) => farmer,
+            VersionedFarmer::V103(farmer) => farmer,
             _ => unimplemented!(),
         }
     }
@@ -173,7 +391,7 @@ impl VersionedFarmer {
     #[allow(unreachable_patterns)]
     pub fn get_ref_mut(&mut self) -> &mut Farmer {
         match self {
-            VersionedFarmer::V101(farmer) => farmer,
+            VersionedFarmer::V103(farmer) => farmer,
             _ => unimplemented!(),
         }
     }
@@ -3,7 +3,7 @@
 *
 * lib.rs is the main entry point.
 */
-use crate::farm::{Farm, FarmId};
+use crate::farm::{Farm, FarmId, VersionedFarm};
 use crate::farm_seed::{SeedId, VersionedFarmSeed};
 use crate::farmer::{Farmer, VersionedFarmer};
 use crate::simple_farm::RPS;
@@ -14,10 +14,11 @@ use near_sdk::BorshStorageKey;
 use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId, Balance, PanicOnDefault};
 
 // for simulator test
-pub use crate::simple_farm::HRSimpleFarmTerms;
-pub use crate::view::FarmInfo;
+pub use crate::simple_farm::{HRMultiRewardFarmTerms, HRSimpleFarmTerms};
+pub use crate::view::{FarmInfo, FarmRewardInfo, SeedLockInfo, VestingInfo};
 
 mod errors;
+mod events;
 mod farm;
 mod farm_seed;
 mod farmer;
@@ -73,8 +74,8 @@ pub struct ContractData {
     // farmers: LookupMap<AccountId, Farmer>,
     farmers: LookupMap<AccountId, VersionedFarmer>,
 
-    farms: UnorderedMap<FarmId, Farm>,
-    outdated_farms: UnorderedMap<FarmId, Farm>,
+    farms: UnorderedMap<FarmId, VersionedFarm>,
+    outdated_farms: UnorderedMap<FarmId, VersionedFarm>,
 
     // for statistic
     farmer_count: u64,
@@ -169,8 +170,8 @@ mod tests {
 
     use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
     use near_contract_standards::storage_management::{StorageBalance, StorageManagement};
-    use near_sdk::json_types::U128;
-    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::json_types::{U128, U64};
+    use near_sdk::test_utils::{accounts, get_logs, VMContextBuilder};
     use near_sdk::{testing_env, Balance};
     use simple_farm::HRSimpleFarmTerms;
 
@@ -192,10 +193,10 @@ mod tests {
         session_amount: Balance,
         session_interval: u32,
     ) -> FarmId {
-        // storage needed: 341
+        // storage needed: 624
         testing_env!(context
             .predecessor_account_id(accounts(0))
-            .attached_deposit(env::storage_byte_cost() * 559)
+            .attached_deposit(env::storage_byte_cost() * 650)
             .build());
         contract.create_simple_farm(
             HRSimpleFarmTerms {
@@ -204,6 +205,7 @@ mod tests {
                 start_at: 0,
                 reward_per_session: U128(session_amount),
                 session_interval: session_interval,
+                vest_duration: 0,
             },
             Some(U128(10)),
         )
@@ -217,6 +219,7 @@ mod tests {
     ) {
         testing_env!(context
             .predecessor_account_id(accounts(2))
+            .is_view(false)
             .block_timestamp(to_nano(time_stamp))
             .attached_deposit(1)
             .build());
@@ -231,7 +234,7 @@ mod tests {
         testing_env!(context
             .predecessor_account_id(farmer.clone())
             .is_view(false)
-            .attached_deposit(env::storage_byte_cost() * 1852)
+            .attached_deposit(env::storage_byte_cost() * 1860)
             .build());
         contract.storage_deposit(Some(farmer), Some(true))
     }
@@ -265,6 +268,27 @@ mod tests {
         contract.ft_on_transfer(farmer, U128(amount), String::from(""));
     }
 
+    fn deposit_locked_seed(
+        context: &mut VMContextBuilder,
+        contract: &mut Contract,
+        farmer: AccountId,
+        time_stamp: u32,
+        amount: Balance,
+        lock_days: u32,
+    ) {
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .is_view(false)
+            .block_timestamp(to_nano(time_stamp))
+            .attached_deposit(1)
+            .build());
+        contract.ft_on_transfer(
+            farmer,
+            U128(amount),
+            format!("{{\"lock_days\": {}}}", lock_days),
+        );
+    }
+
     fn withdraw_seed(
         context: &mut VMContextBuilder,
         contract: &mut Contract,
@@ -380,7 +404,7 @@ mod tests {
         // Farmer accounts(0) come in round 1
         register_farmer(&mut context, &mut contract, accounts(0));
         deposit_seed(&mut context, &mut contract, accounts(0), 160, 10);
-        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone());
+        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone())[&accounts(2)];
         assert_eq!(unclaimed, U128(0));
         let farm_info = contract.get_farm(farm_id.clone()).expect("Error");
         assert_eq!(farm_info.beneficiary_reward, U128(5000));
@@ -393,7 +417,7 @@ mod tests {
             .block_timestamp(to_nano(210))
             .is_view(true)
             .build());
-        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone());
+        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone())[&accounts(2)];
         assert_eq!(unclaimed, U128(5000));
         let farm_info = contract.get_farm(farm_id.clone()).expect("Error");
         assert_eq!(farm_info.cur_round, 2);
@@ -403,9 +427,9 @@ mod tests {
         register_farmer(&mut context, &mut contract, accounts(3));
         // deposit seed
         deposit_seed(&mut context, &mut contract, accounts(3), 260, 10);
-        let unclaimed = contract.get_unclaimed_reward(accounts(3), farm_id.clone());
+        let unclaimed = contract.get_unclaimed_reward(accounts(3), farm_id.clone())[&accounts(2)];
         assert_eq!(unclaimed, U128(0));
-        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone());
+        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone())[&accounts(2)];
         assert_eq!(unclaimed, U128(10000));
         let farm_info = contract.get_farm(farm_id.clone()).expect("Error");
         assert_eq!(farm_info.cur_round, 3);
@@ -417,9 +441,9 @@ mod tests {
             .block_timestamp(to_nano(320))
             .is_view(true)
             .build());
-        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone());
+        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone())[&accounts(2)];
         assert_eq!(unclaimed, U128(12500));
-        let unclaimed = contract.get_unclaimed_reward(accounts(3), farm_id.clone());
+        let unclaimed = contract.get_unclaimed_reward(accounts(3), farm_id.clone())[&accounts(2)];
         assert_eq!(unclaimed, U128(2500));
         let farm_info = contract.get_farm(farm_id.clone()).expect("Error");
         assert_eq!(farm_info.cur_round, 4);
@@ -434,9 +458,9 @@ mod tests {
             .block_timestamp(to_nano(380))
             .is_view(true)
             .build());
-        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone());
+        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone())[&accounts(2)];
         assert_eq!(unclaimed, U128(0));
-        let unclaimed = contract.get_unclaimed_reward(accounts(3), farm_id.clone());
+        let unclaimed = contract.get_unclaimed_reward(accounts(3), farm_id.clone())[&accounts(2)];
         assert_eq!(unclaimed, U128(0));
         let rewarded = contract.get_reward(accounts(0), accounts(2));
         assert_eq!(rewarded, U128(15000));
@@ -449,9 +473,9 @@ mod tests {
         // move to round 7, account3 come in again
         println!("----> move to round 7, account3 come in again");
         deposit_seed(&mut context, &mut contract, accounts(3), 460, 10);
-        let unclaimed = contract.get_unclaimed_reward(accounts(3), farm_id.clone());
+        let unclaimed = contract.get_unclaimed_reward(accounts(3), farm_id.clone())[&accounts(2)];
         assert_eq!(unclaimed, U128(0));
-        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone());
+        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone())[&accounts(2)];
         assert_eq!(unclaimed, U128(0));
         let farm_info = contract.get_farm(farm_id.clone()).expect("Error");
         assert_eq!(farm_info.beneficiary_reward, U128(15000));
@@ -461,9 +485,9 @@ mod tests {
         // move to round 8, account0 come in again
         println!("----> move to round 8, account0 come in again");
         deposit_seed(&mut context, &mut contract, accounts(0), 520, 10);
-        let unclaimed = contract.get_unclaimed_reward(accounts(3), farm_id.clone());
+        let unclaimed = contract.get_unclaimed_reward(accounts(3), farm_id.clone())[&accounts(2)];
         assert_eq!(unclaimed, U128(5000));
-        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone());
+        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone())[&accounts(2)];
         assert_eq!(unclaimed, U128(0));
         let farm_info = contract.get_farm(farm_id.clone()).expect("Error");
         assert_eq!(farm_info.cur_round, 8);
@@ -475,9 +499,9 @@ mod tests {
             .block_timestamp(to_nano(580))
             .is_view(true)
             .build());
-        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone());
+        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone())[&accounts(2)];
         assert_eq!(unclaimed, U128(2500));
-        let unclaimed = contract.get_unclaimed_reward(accounts(3), farm_id.clone());
+        let unclaimed = contract.get_unclaimed_reward(accounts(3), farm_id.clone())[&accounts(2)];
         assert_eq!(unclaimed, U128(7500));
         let farm_info = contract.get_farm(farm_id.clone()).expect("Error");
         assert_eq!(farm_info.cur_round, 9);
@@ -490,9 +514,9 @@ mod tests {
             .block_timestamp(to_nano(610))
             .is_view(true)
             .build());
-        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone());
+        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone())[&accounts(2)];
         assert_eq!(unclaimed, U128(5000));
-        let unclaimed = contract.get_unclaimed_reward(accounts(3), farm_id.clone());
+        let unclaimed = contract.get_unclaimed_reward(accounts(3), farm_id.clone())[&accounts(2)];
         assert_eq!(unclaimed, U128(10000));
         let farm_info = contract.get_farm(farm_id.clone()).expect("Error");
         assert_eq!(farm_info.cur_round, 10);
@@ -506,9 +530,9 @@ mod tests {
             .block_timestamp(to_nano(710))
             .is_view(true)
             .build());
-        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone());
+        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone())[&accounts(2)];
         assert_eq!(unclaimed, U128(5000));
-        let unclaimed = contract.get_unclaimed_reward(accounts(3), farm_id.clone());
+        let unclaimed = contract.get_unclaimed_reward(accounts(3), farm_id.clone())[&accounts(2)];
         assert_eq!(unclaimed, U128(10000));
         claim_reward(&mut context, &mut contract, accounts(0), 720);
         claim_reward(&mut context, &mut contract, accounts(3), 730);
@@ -517,9 +541,9 @@ mod tests {
             .block_timestamp(to_nano(740))
             .is_view(true)
             .build());
-        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone());
+        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone())[&accounts(2)];
         assert_eq!(unclaimed, U128(0));
-        let unclaimed = contract.get_unclaimed_reward(accounts(3), farm_id.clone());
+        let unclaimed = contract.get_unclaimed_reward(accounts(3), farm_id.clone())[&accounts(2)];
         assert_eq!(unclaimed, U128(0));
         let rewarded = contract.get_reward(accounts(0), accounts(2));
         assert_eq!(rewarded, U128(20000));
@@ -533,6 +557,10 @@ mod tests {
         println!("----> clean farm");
         remove_farm(&mut context, &mut contract, 750);
         assert!(contract.get_farm(farm_id.clone()).is_none());
+        let farms = contract.list_farms_by_seed(accounts(1).into());
+        assert_eq!(farms.len(), 1);
+        assert_eq!(farms[0].farm_id, farm_id);
+        assert_eq!(farms[0].farm_status, String::from("Cleared"));
 
         // remove user rps
         println!("----> remove user rps");
@@ -601,7 +629,7 @@ mod tests {
         // Farmer1 accounts(0) come in round 0
         register_farmer(&mut context, &mut contract, accounts(0));
         deposit_seed(&mut context, &mut contract, accounts(0), 110, to_yocto("1"));
-        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone());
+        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone())[&accounts(2)];
         assert_eq!(unclaimed, U128(0));
         let farm_info = contract.get_farm(farm_id.clone()).expect("Error");
         assert_eq!(farm_info.cur_round, 0);
@@ -615,7 +643,7 @@ mod tests {
             .block_timestamp(to_nano(160))
             .is_view(true)
             .build());
-        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone());
+        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone())[&accounts(2)];
         assert_eq!(unclaimed.0, to_yocto("1"));
         let farm_info = contract.get_farm(farm_id.clone()).expect("Error");
         assert_eq!(farm_info.cur_round, 1);
@@ -627,9 +655,9 @@ mod tests {
         register_farmer(&mut context, &mut contract, accounts(3));
         // deposit seed
         deposit_seed(&mut context, &mut contract, accounts(3), 180, to_yocto("1"));
-        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone());
+        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone())[&accounts(2)];
         assert_eq!(unclaimed.0, to_yocto("1"));
-        let unclaimed = contract.get_unclaimed_reward(accounts(3), farm_id.clone());
+        let unclaimed = contract.get_unclaimed_reward(accounts(3), farm_id.clone())[&accounts(2)];
         assert_eq!(unclaimed.0, to_yocto("0"));
 
         // move to round 2,
@@ -638,9 +666,9 @@ mod tests {
             .block_timestamp(to_nano(210))
             .is_view(true)
             .build());
-        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone());
+        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone())[&accounts(2)];
         assert_eq!(unclaimed.0, to_yocto("1.5"));
-        let unclaimed = contract.get_unclaimed_reward(accounts(3), farm_id.clone());
+        let unclaimed = contract.get_unclaimed_reward(accounts(3), farm_id.clone())[&accounts(2)];
         assert_eq!(unclaimed.0, to_yocto("0.5"));
         let farm_info = contract.get_farm(farm_id.clone()).expect("Error");
         assert_eq!(farm_info.cur_round, 2);
@@ -650,9 +678,9 @@ mod tests {
 
         // farmer1 claim reward by farm_id at round 3
         claim_reward(&mut context, &mut contract, accounts(0), 260);
-        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone());
+        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone())[&accounts(2)];
         assert_eq!(unclaimed.0, to_yocto("0"));
-        let unclaimed = contract.get_unclaimed_reward(accounts(3), farm_id.clone());
+        let unclaimed = contract.get_unclaimed_reward(accounts(3), farm_id.clone())[&accounts(2)];
         assert_eq!(unclaimed.0, to_yocto("1"));
         let farm_info = contract.get_farm(farm_id.clone()).expect("Error");
         assert_eq!(farm_info.cur_round, 3);
@@ -662,9 +690,9 @@ mod tests {
 
         // farmer2 claim reward by seed_id at round 4
         claim_reward_by_seed(&mut context, &mut contract, accounts(3), 310);
-        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone());
+        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone())[&accounts(2)];
         assert_eq!(unclaimed.0, to_yocto("0.5"));
-        let unclaimed = contract.get_unclaimed_reward(accounts(3), farm_id.clone());
+        let unclaimed = contract.get_unclaimed_reward(accounts(3), farm_id.clone())[&accounts(2)];
         assert_eq!(unclaimed.0, to_yocto("0"));
         let farm_info = contract.get_farm(farm_id.clone()).expect("Error");
         assert_eq!(farm_info.cur_round, 4);
@@ -680,9 +708,9 @@ mod tests {
             360,
             to_yocto("0.4"),
         );
-        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone());
+        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone())[&accounts(2)];
         assert_eq!(unclaimed.0, to_yocto("0"));
-        let unclaimed = contract.get_unclaimed_reward(accounts(3), farm_id.clone());
+        let unclaimed = contract.get_unclaimed_reward(accounts(3), farm_id.clone())[&accounts(2)];
         assert_eq!(unclaimed.0, to_yocto("0.5"));
         let farm_info = contract.get_farm(farm_id.clone()).expect("Error");
         assert_eq!(farm_info.cur_round, 5);
@@ -692,9 +720,9 @@ mod tests {
 
         // farmer2 unstake all his lpt at round 6
         withdraw_seed(&mut context, &mut contract, accounts(3), 410, to_yocto("1"));
-        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone());
+        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone())[&accounts(2)];
         assert_eq!(unclaimed.0, to_yocto("0.375"));
-        let unclaimed = contract.get_unclaimed_reward(accounts(3), farm_id.clone());
+        let unclaimed = contract.get_unclaimed_reward(accounts(3), farm_id.clone())[&accounts(2)];
         assert_eq!(unclaimed.0, to_yocto("0"));
         let farm_info = contract.get_farm(farm_id.clone()).expect("Error");
         assert_eq!(farm_info.cur_round, 6);
@@ -708,9 +736,9 @@ mod tests {
             .block_timestamp(to_nano(460))
             .is_view(true)
             .build());
-        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone());
+        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone())[&accounts(2)];
         assert_eq!(unclaimed.0, to_yocto("1.374999999999999999999999"));
-        let unclaimed = contract.get_unclaimed_reward(accounts(3), farm_id.clone());
+        let unclaimed = contract.get_unclaimed_reward(accounts(3), farm_id.clone())[&accounts(2)];
         assert_eq!(unclaimed.0, to_yocto("0"));
         let farm_info = contract.get_farm(farm_id.clone()).expect("Error");
         assert_eq!(farm_info.cur_round, 7);
@@ -724,9 +752,9 @@ mod tests {
             470,
             to_yocto("0.6"),
         );
-        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone());
+        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone())[&accounts(2)];
         assert_eq!(unclaimed.0, to_yocto("0"));
-        let unclaimed = contract.get_unclaimed_reward(accounts(3), farm_id.clone());
+        let unclaimed = contract.get_unclaimed_reward(accounts(3), farm_id.clone())[&accounts(2)];
         assert_eq!(unclaimed.0, to_yocto("0"));
         let farm_info = contract.get_farm(farm_id.clone()).expect("Error");
         assert_eq!(farm_info.cur_round, 7);
@@ -738,6 +766,842 @@ mod tests {
         assert_eq!(farm_info.unclaimed_reward.0, 1);
     }
 
+    #[test]
+    fn test_claim_reward_by_farms() {
+        let (mut context, mut contract) = setup_contract();
+        // seed is bob, reward is charlie
+        let farm_id = create_farm(
+            &mut context,
+            &mut contract,
+            accounts(1),
+            accounts(2),
+            to_yocto("1"),
+            50,
+        );
+        // seed is eugene, farmer never stakes it
+        let other_farm_id = create_farm(
+            &mut context,
+            &mut contract,
+            accounts(4),
+            accounts(2),
+            to_yocto("1"),
+            50,
+        );
+        deposit_reward(&mut context, &mut contract, to_yocto("10"), 100);
+
+        register_farmer(&mut context, &mut contract, accounts(0));
+        deposit_seed(&mut context, &mut contract, accounts(0), 110, to_yocto("1"));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .is_view(false)
+            .block_timestamp(to_nano(160))
+            .attached_deposit(1)
+            .build());
+        contract.claim_reward_by_farms(vec![
+            other_farm_id.clone(),
+            farm_id.clone(),
+            String::from("unknown#0"),
+        ]);
+        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone())[&accounts(2)];
+        assert_eq!(unclaimed.0, 0);
+        let rewarded = contract.get_reward(accounts(0), accounts(2));
+        assert_eq!(rewarded.0, to_yocto("1"));
+        let farm_info = contract.get_farm(farm_id).expect("Error");
+        assert_eq!(farm_info.claimed_reward.0, to_yocto("1"));
+        let farm_info = contract.get_farm(other_farm_id).expect("Error");
+        assert_eq!(farm_info.claimed_reward.0, 0);
+    }
+
+    #[test]
+    fn test_events() {
+        let (mut context, mut contract) = setup_contract();
+        let farm_id = create_farm(
+            &mut context,
+            &mut contract,
+            accounts(1),
+            accounts(2),
+            to_yocto("1"),
+            50,
+        );
+        deposit_reward(&mut context, &mut contract, to_yocto("10"), 100);
+        register_farmer(&mut context, &mut contract, accounts(0));
+
+        let event_logs = || -> Vec<near_sdk::serde_json::Value> {
+            get_logs()
+                .iter()
+                .filter_map(|log| log.strip_prefix("EVENT_JSON:"))
+                .map(|event| near_sdk::serde_json::from_str(event).unwrap())
+                .collect()
+        };
+
+        deposit_seed(&mut context, &mut contract, accounts(0), 110, to_yocto("1"));
+        let events = event_logs();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0]["standard"], "snails_farming");
+        assert_eq!(events[0]["event"], "seed_deposit");
+        assert_eq!(events[0]["data"][0]["farmer_id"], accounts(0).to_string());
+        assert_eq!(events[0]["data"][0]["amount"], to_yocto("1").to_string());
+
+        claim_reward(&mut context, &mut contract, accounts(0), 160);
+        let events = event_logs();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0]["event"], "reward_claim");
+        assert_eq!(events[0]["data"][0]["farm_id"], farm_id);
+        assert_eq!(
+            events[0]["data"][0]["reward_token"],
+            accounts(2).to_string()
+        );
+        assert_eq!(events[0]["data"][0]["amount"], to_yocto("1").to_string());
+
+        withdraw_seed(&mut context, &mut contract, accounts(0), 170, to_yocto("1"));
+        let events = event_logs();
+        assert_eq!(events.last().unwrap()["event"], "seed_withdraw");
+        assert_eq!(events.last().unwrap()["data"][0]["emergency"], false);
+    }
+
+    #[test]
+    fn test_list_unclaimed_rewards() {
+        let (mut context, mut contract) = setup_contract();
+        // seed is bob, reward is charlie
+        let farm_id = create_farm(
+            &mut context,
+            &mut contract,
+            accounts(1),
+            accounts(2),
+            to_yocto("1"),
+            50,
+        );
+        // seed is eugene, farmer never stakes it
+        create_farm(
+            &mut context,
+            &mut contract,
+            accounts(4),
+            accounts(2),
+            to_yocto("1"),
+            50,
+        );
+        deposit_reward(&mut context, &mut contract, to_yocto("10"), 100);
+
+        register_farmer(&mut context, &mut contract, accounts(0));
+        assert!(contract.list_unclaimed_rewards(accounts(0)).is_empty());
+        deposit_seed(&mut context, &mut contract, accounts(0), 110, to_yocto("1"));
+
+        testing_env!(context.block_timestamp(to_nano(160)).is_view(true).build());
+        let unclaimed = contract.list_unclaimed_rewards(accounts(0));
+        assert_eq!(unclaimed.len(), 1);
+        assert_eq!(unclaimed[0].0, farm_id);
+        assert_eq!(unclaimed[0].1[&accounts(2)].0, to_yocto("1"));
+        assert_eq!(
+            unclaimed[0].1,
+            contract.get_unclaimed_reward(accounts(0), farm_id)
+        );
+        assert!(contract.list_unclaimed_rewards(accounts(3)).is_empty());
+    }
+
+    #[test]
+    fn test_locked_seed_boost() {
+        let (mut context, mut contract) = setup_contract();
+        // seed is bob, reward is charlie
+        let farm_id = create_farm(
+            &mut context,
+            &mut contract,
+            accounts(1),
+            accounts(2),
+            3000,
+            50,
+        );
+        deposit_reward(&mut context, &mut contract, 30000, 100);
+
+        register_farmer(&mut context, &mut contract, accounts(0));
+        register_farmer(&mut context, &mut contract, accounts(3));
+        deposit_seed(&mut context, &mut contract, accounts(0), 110, 10);
+        deposit_locked_seed(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            110,
+            10,
+            MAX_LOCK_DAYS,
+        );
+
+        let locked_until = 110 + MAX_LOCK_DAYS * SECONDS_PER_DAY;
+        assert_eq!(
+            contract.get_seed_lock(accounts(3), accounts(1).into()),
+            Some(SeedLockInfo {
+                locked_until,
+                boost_bps: MAX_LOCK_BOOST_BPS,
+                power: U128(20),
+            })
+        );
+        assert_eq!(
+            contract.get_seed_lock(accounts(0), accounts(1).into()),
+            None
+        );
+        let seed_info = contract.get_seed_info(accounts(1).into()).unwrap();
+        assert_eq!(seed_info.amount, U128(20));
+        assert_eq!(seed_info.power, U128(30));
+
+        // the locked farmer gets a double share of the round
+        testing_env!(context.block_timestamp(to_nano(160)).is_view(true).build());
+        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone());
+        assert_eq!(unclaimed[&accounts(2)], U128(1000));
+        let unclaimed = contract.get_unclaimed_reward(accounts(3), farm_id.clone());
+        assert_eq!(unclaimed[&accounts(2)], U128(2000));
+
+        withdraw_seed(&mut context, &mut contract, accounts(3), locked_until, 10);
+        assert_eq!(
+            contract.get_seed_lock(accounts(3), accounts(1).into()),
+            None
+        );
+        let seed_info = contract.get_seed_info(accounts(1).into()).unwrap();
+        assert_eq!(seed_info.amount, U128(10));
+        assert_eq!(seed_info.power, U128(10));
+
+        // staking again without lock does not inherit the old boost
+        deposit_seed(&mut context, &mut contract, accounts(3), locked_until, 10);
+        assert_eq!(
+            contract.get_seed_lock(accounts(3), accounts(1).into()),
+            None
+        );
+        assert_eq!(
+            contract.get_seed_info(accounts(1).into()).unwrap().power,
+            U128(20)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Seed still locked")]
+    fn test_withdraw_locked_seed() {
+        let (mut context, mut contract) = setup_contract();
+        create_farm(
+            &mut context,
+            &mut contract,
+            accounts(1),
+            accounts(2),
+            3000,
+            50,
+        );
+        register_farmer(&mut context, &mut contract, accounts(0));
+        deposit_locked_seed(&mut context, &mut contract, accounts(0), 110, 10, 30);
+        withdraw_seed(
+            &mut context,
+            &mut contract,
+            accounts(0),
+            110 + 30 * SECONDS_PER_DAY - 1,
+            10,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Locked seed can only be topped up with a lock")]
+    fn test_top_up_locked_seed() {
+        let (mut context, mut contract) = setup_contract();
+        create_farm(
+            &mut context,
+            &mut contract,
+            accounts(1),
+            accounts(2),
+            3000,
+            50,
+        );
+        register_farmer(&mut context, &mut contract, accounts(0));
+        deposit_locked_seed(&mut context, &mut contract, accounts(0), 110, 10, 30);
+        deposit_seed(&mut context, &mut contract, accounts(0), 120, 10);
+    }
+
+    #[test]
+    fn test_locked_seed_boost_expires() {
+        let (mut context, mut contract) = setup_contract();
+        create_farm(
+            &mut context,
+            &mut contract,
+            accounts(1),
+            accounts(2),
+            3000,
+            50,
+        );
+        deposit_reward(&mut context, &mut contract, 30000, 100);
+
+        register_farmer(&mut context, &mut contract, accounts(0));
+        register_farmer(&mut context, &mut contract, accounts(3));
+        deposit_seed(&mut context, &mut contract, accounts(0), 110, 10);
+        deposit_locked_seed(
+            &mut context,
+            &mut contract,
+            accounts(3),
+            110,
+            10,
+            MAX_LOCK_DAYS,
+        );
+        let locked_until = 110 + MAX_LOCK_DAYS * SECONDS_PER_DAY;
+
+        // anyone can end the boost once the lock expired, settling the rewards first
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .is_view(false)
+            .block_timestamp(to_nano(locked_until))
+            .attached_deposit(0)
+            .build());
+        contract.expire_seed_lock(accounts(3), accounts(1).into());
+        assert_eq!(contract.get_reward(accounts(3), accounts(2)), U128(20000));
+        assert_eq!(
+            contract.get_seed_lock(accounts(3), accounts(1).into()),
+            None
+        );
+        let seed_info = contract.get_seed_info(accounts(1).into()).unwrap();
+        assert_eq!(seed_info.amount, U128(20));
+        assert_eq!(seed_info.power, U128(20));
+
+        // the seed can be topped up without lock again
+        deposit_seed(&mut context, &mut contract, accounts(3), locked_until, 10);
+        assert_eq!(
+            contract.get_seed_info(accounts(1).into()).unwrap().power,
+            U128(30)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Seed still locked")]
+    fn test_emergency_withdraw_locked_seed() {
+        let (mut context, mut contract) = setup_contract();
+        create_farm(
+            &mut context,
+            &mut contract,
+            accounts(1),
+            accounts(2),
+            3000,
+            50,
+        );
+        register_farmer(&mut context, &mut contract, accounts(0));
+        deposit_locked_seed(&mut context, &mut contract, accounts(0), 110, 10, 30);
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .is_view(false)
+            .block_timestamp(to_nano(120))
+            .attached_deposit(1)
+            .build());
+        contract.emergency_withdraw_seed(accounts(1).into());
+    }
+
+    #[test]
+    fn test_seed_farmer_count() {
+        let (mut context, mut contract) = setup_contract();
+        create_farm(
+            &mut context,
+            &mut contract,
+            accounts(1),
+            accounts(2),
+            5000,
+            50,
+        );
+        let seed_info = contract.get_seed_info(accounts(1).into()).unwrap();
+        assert_eq!(seed_info.farmer_count, Some(U64(0)));
+
+        register_farmer(&mut context, &mut contract, accounts(0));
+        register_farmer(&mut context, &mut contract, accounts(3));
+        deposit_seed(&mut context, &mut contract, accounts(0), 110, 10);
+        deposit_seed(&mut context, &mut contract, accounts(0), 120, 10);
+        deposit_seed(&mut context, &mut contract, accounts(3), 130, 10);
+        let seed_info = contract.get_seed_info(accounts(1).into()).unwrap();
+        assert_eq!(seed_info.amount.0, 30);
+        assert_eq!(seed_info.farmer_count, Some(U64(2)));
+
+        withdraw_seed(&mut context, &mut contract, accounts(0), 140, 10);
+        assert_eq!(
+            contract
+                .get_seed_info(accounts(1).into())
+                .unwrap()
+                .farmer_count,
+            Some(U64(2))
+        );
+        withdraw_seed(&mut context, &mut contract, accounts(0), 150, 10);
+        let seed_info = contract.get_seed_info(accounts(1).into()).unwrap();
+        assert_eq!(seed_info.amount.0, 10);
+        assert_eq!(seed_info.farmer_count, Some(U64(1)));
+    }
+
+    #[test]
+    fn test_migrate_farmers() {
+        let (mut context, mut contract) = setup_contract();
+        create_farm(
+            &mut context,
+            &mut contract,
+            accounts(1),
+            accounts(2),
+            5000,
+            50,
+        );
+        register_farmer(&mut context, &mut contract, accounts(0));
+        register_farmer(&mut context, &mut contract, accounts(3));
+        deposit_seed(&mut context, &mut contract, accounts(3), 110, 10);
+
+        // accounts(3) is left in the layout before vestings and locks, as `VersionedFarmer::V101`.
+        let farmer = contract.get_farmer(&accounts(3)).get();
+        let old_farmer = farmer::FarmerV101 {
+            amount: farmer.amount,
+            rewards: farmer.rewards,
+            seeds: farmer.seeds,
+            user_rps: farmer.user_rps,
+            rps_count: farmer.rps_count,
+        };
+        let mut bytes = vec![0u8];
+        bytes.extend(old_farmer.try_to_vec().unwrap());
+        contract.data_mut().farmers.insert(
+            &accounts(3),
+            &VersionedFarmer::try_from_slice(&bytes).unwrap(),
+        );
+        assert!(contract
+            .data()
+            .farmers
+            .get(&accounts(3))
+            .unwrap()
+            .need_upgrade());
+
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        assert_eq!(contract.migrate_farmers(vec![accounts(0), accounts(3)]), 1);
+        assert!(!contract
+            .data()
+            .farmers
+            .get(&accounts(3))
+            .unwrap()
+            .need_upgrade());
+        assert_eq!(
+            contract
+                .list_user_seeds(accounts(3))
+                .get(&accounts(1).to_string()),
+            Some(&U128(10))
+        );
+        assert!(!contract.migrate_farmer(accounts(3)));
+    }
+
+    #[test]
+    fn test_upgrade_farm_v101() {
+        let (_context, mut contract) = setup_contract();
+        let farm_id = String::from("bob#0");
+        let old_farm = simple_farm::SimpleFarmV101 {
+            farm_id: farm_id.clone(),
+            terms: simple_farm::SimpleFarmTermsV101 {
+                seed_id: accounts(1).into(),
+                reward_token: accounts(2),
+                start_at: 10,
+                reward_per_session: to_yocto("1"),
+                session_interval: 50,
+            },
+            status: simple_farm::SimpleFarmStatus::Running,
+            last_distribution: simple_farm::SimpleFarmRewardDistribution::default(),
+            amount_of_reward: to_yocto("10"),
+            amount_of_claimed: to_yocto("2"),
+            amount_of_beneficiary: to_yocto("1"),
+        };
+        // a farm stored before farms had several reward tokens, as `Farm::SimpleFarm`.
+        let mut bytes = vec![0u8];
+        bytes.extend(old_farm.try_to_vec().unwrap());
+        let farm = VersionedFarm::try_from_slice(&bytes).unwrap();
+        assert!(farm.need_upgrade());
+        contract.data_mut().farms.insert(&farm_id, &farm);
+
+        let farm_info = contract.get_farm(farm_id).unwrap();
+        assert_eq!(farm_info.reward_token, accounts(2));
+        assert_eq!(farm_info.reward_per_session, U128(to_yocto("1")));
+        assert_eq!(farm_info.start_at, 10);
+        assert_eq!(farm_info.vest_duration, 0);
+        assert_eq!(farm_info.total_reward, U128(to_yocto("10")));
+        assert_eq!(farm_info.claimed_reward, U128(to_yocto("2")));
+        assert_eq!(farm_info.beneficiary_reward, U128(to_yocto("1")));
+    }
+
+    #[test]
+    fn test_upgrade_farm_v101_with_rps() {
+        let (mut context, mut contract) = setup_contract();
+        let farm_id = create_farm(
+            &mut context,
+            &mut contract,
+            accounts(1),
+            accounts(2),
+            5000,
+            50,
+        );
+        deposit_reward(&mut context, &mut contract, 50000, 100);
+        register_farmer(&mut context, &mut contract, accounts(0));
+        deposit_seed(&mut context, &mut contract, accounts(0), 160, 10);
+        // a second farmer coming in distributes the 10k accounts(0) has not claimed yet.
+        register_farmer(&mut context, &mut contract, accounts(3));
+        deposit_seed(&mut context, &mut contract, accounts(3), 260, 10);
+
+        // the farm as it was stored before farms had several reward tokens, with a non-zero rps.
+        let farm = match contract.data().farms.get(&farm_id).unwrap().get() {
+            Farm::SimpleFarm(farm) => farm,
+        };
+        let mut reward = farm.rewards[0].clone();
+        // 10k over 10 seeds at the 1e24 scale farms were stored with.
+        (U256::from(1000) * U256::from(10u128.pow(24)))
+            .to_little_endian(&mut reward.last_distribution.rps);
+        let old_farm = simple_farm::SimpleFarmV101 {
+            farm_id: farm_id.clone(),
+            terms: simple_farm::SimpleFarmTermsV101 {
+                seed_id: farm.terms.seed_id.clone(),
+                reward_token: accounts(2),
+                start_at: farm.terms.start_at,
+                reward_per_session: 5000,
+                session_interval: 50,
+            },
+            status: farm.status.clone(),
+            last_distribution: reward.last_distribution,
+            amount_of_reward: reward.amount_of_reward,
+            amount_of_claimed: reward.amount_of_claimed,
+            amount_of_beneficiary: reward.amount_of_beneficiary,
+        };
+        let mut bytes = vec![0u8];
+        bytes.extend(old_farm.try_to_vec().unwrap());
+        contract
+            .data_mut()
+            .farms
+            .insert(&farm_id, &VersionedFarm::try_from_slice(&bytes).unwrap());
+
+        // rewards earned before the upgrade keep their value, two more rounds are shared.
+        claim_reward(&mut context, &mut contract, accounts(0), 360);
+        assert_eq!(contract.get_reward(accounts(0), accounts(2)), U128(15000));
+        let farm_info = contract.get_farm(farm_id).unwrap();
+        // claimed includes the round given to the beneficiary before any seed was staked.
+        assert_eq!(farm_info.claimed_reward, U128(20000));
+        assert_eq!(farm_info.beneficiary_reward, U128(5000));
+    }
+
+    #[test]
+    fn test_upgrade_farm_seed_v101() {
+        let (mut context, mut contract) = setup_contract();
+        create_farm(
+            &mut context,
+            &mut contract,
+            accounts(1),
+            accounts(2),
+            5000,
+            50,
+        );
+        let seed_id: SeedId = accounts(1).into();
+        let old_seed = farm_seed::FarmSeedV101 {
+            seed_id: seed_id.clone(),
+            seed_type: farm_seed::SeedType::FT,
+            farms: contract.get_seed(&seed_id).get_ref().farms.clone(),
+            next_index: 1,
+            amount: 10,
+            min_deposit: 1,
+        };
+        // a seed stored before staked amounts could be boosted by locks.
+        let mut bytes = vec![0u8];
+        bytes.extend(old_seed.try_to_vec().unwrap());
+        let farm_seed = VersionedFarmSeed::try_from_slice(&bytes).unwrap();
+        assert!(farm_seed.need_upgrade());
+        contract.data_mut().seeds.insert(&seed_id, &farm_seed);
+
+        let seed_info = contract.get_seed_info(seed_id).unwrap();
+        assert_eq!(seed_info.amount.0, 10);
+        assert_eq!(seed_info.power.0, 10);
+        assert_eq!(seed_info.farms, vec![String::from("bob#0")]);
+        assert_eq!(seed_info.farmer_count, None);
+
+        // staking and withdrawing the whole seed keep the count unknown.
+        register_farmer(&mut context, &mut contract, accounts(0));
+        deposit_seed(&mut context, &mut contract, accounts(0), 110, 10);
+        withdraw_seed(&mut context, &mut contract, accounts(0), 120, 10);
+        let seed_info = contract.get_seed_info(accounts(1).into()).unwrap();
+        assert_eq!(seed_info.amount.0, 10);
+        assert_eq!(seed_info.farmer_count, None);
+    }
+
+    #[test]
+    fn test_multi_reward_farm() {
+        let (mut context, mut contract) = setup_contract();
+        // seed is bob, rewards are charlie and eugene
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(env::storage_byte_cost() * 750)
+            .build());
+        let farm_id = contract.create_multi_reward_farm(
+            HRMultiRewardFarmTerms {
+                seed_id: accounts(1).into(),
+                reward_tokens: vec![accounts(2), accounts(4)],
+                start_at: 0,
+                reward_per_session: vec![U128(5000), U128(1000)],
+                session_interval: 50,
+                vest_duration: 0,
+            },
+            Some(U128(10)),
+        );
+        assert_eq!(farm_id, String::from("bob#0"));
+
+        // deposit both rewards, each can last 10 rounds from 0 to 9
+        deposit_reward(&mut context, &mut contract, 50000, 100);
+        testing_env!(context
+            .predecessor_account_id(accounts(4))
+            .block_timestamp(to_nano(100))
+            .attached_deposit(1)
+            .build());
+        contract.ft_on_transfer(accounts(0), U128(10000), farm_id.clone());
+        let farm_info = contract.get_farm(farm_id.clone()).expect("Error");
+        assert_eq!(farm_info.farm_status, String::from("Running"));
+        assert_eq!(farm_info.rewards.len(), 2);
+        assert_eq!(farm_info.rewards[1].reward_token, accounts(4));
+        assert_eq!(farm_info.rewards[1].total_reward, U128(10000));
+
+        register_farmer(&mut context, &mut contract, accounts(0));
+        deposit_seed(&mut context, &mut contract, accounts(0), 110, 10);
+
+        // move to round 2
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .block_timestamp(to_nano(210))
+            .is_view(true)
+            .build());
+        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone());
+        assert_eq!(unclaimed[&accounts(2)], U128(10000));
+        assert_eq!(unclaimed[&accounts(4)], U128(2000));
+
+        claim_reward(&mut context, &mut contract, accounts(0), 210);
+        assert_eq!(contract.get_reward(accounts(0), accounts(2)), U128(10000));
+        assert_eq!(contract.get_reward(accounts(0), accounts(4)), U128(2000));
+        assert_eq!(
+            contract.list_claimed_rewards(accounts(0)),
+            vec![(accounts(2), U128(10000)), (accounts(4), U128(2000))]
+        );
+        assert!(contract.list_claimed_rewards(accounts(5)).is_empty());
+        let farm_info = contract.get_farm(farm_id.clone()).expect("Error");
+        assert_eq!(farm_info.claimed_reward, U128(10000));
+        assert_eq!(farm_info.rewards[1].claimed_reward, U128(2000));
+        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id);
+        assert_eq!(unclaimed[&accounts(2)], U128(0));
+        assert_eq!(unclaimed[&accounts(4)], U128(0));
+    }
+
+    #[test]
+    fn test_top_up_ended_farm() {
+        let (mut context, mut contract) = setup_contract();
+        // seed is bob, reward is charlie
+        let farm_id = create_farm(
+            &mut context,
+            &mut contract,
+            accounts(1),
+            accounts(2),
+            5000,
+            50,
+        );
+        // reward lasts 2 rounds from 100 to 200
+        deposit_reward(&mut context, &mut contract, 10000, 100);
+        register_farmer(&mut context, &mut contract, accounts(0));
+        deposit_seed(&mut context, &mut contract, accounts(0), 110, 10);
+
+        testing_env!(context.block_timestamp(to_nano(300)).is_view(true).build());
+        let farm_info = contract.get_farm(farm_id.clone()).unwrap();
+        assert_eq!(farm_info.farm_status, String::from("Ended"));
+        assert_eq!(farm_info.cur_round, 2);
+
+        // topping up resumes the farm from now on
+        deposit_reward(&mut context, &mut contract, 10000, 300);
+        let farm_info = contract.get_farm(farm_id.clone()).unwrap();
+        assert_eq!(farm_info.farm_status, String::from("Running"));
+        assert_eq!(farm_info.start_at, 300);
+        assert_eq!(farm_info.cur_round, 0);
+        assert_eq!(farm_info.total_reward, U128(20000));
+        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone());
+        assert_eq!(unclaimed[&accounts(2)], U128(10000));
+
+        // rounds resume
+        testing_env!(context.block_timestamp(to_nano(360)).is_view(true).build());
+        let farm_info = contract.get_farm(farm_id.clone()).unwrap();
+        assert_eq!(farm_info.farm_status, String::from("Running"));
+        assert_eq!(farm_info.cur_round, 1);
+        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id.clone());
+        assert_eq!(unclaimed[&accounts(2)], U128(15000));
+
+        claim_reward(&mut context, &mut contract, accounts(0), 410);
+        assert_eq!(contract.get_reward(accounts(0), accounts(2)), U128(20000));
+        testing_env!(context.block_timestamp(to_nano(410)).is_view(true).build());
+        let farm_info = contract.get_farm(farm_id).unwrap();
+        assert_eq!(farm_info.farm_status, String::from("Ended"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only owner can resume an ended farm")]
+    fn test_top_up_ended_farm_not_owner() {
+        let (mut context, mut contract) = setup_contract();
+        let farm_id = create_farm(
+            &mut context,
+            &mut contract,
+            accounts(1),
+            accounts(2),
+            5000,
+            50,
+        );
+        deposit_reward(&mut context, &mut contract, 10000, 100);
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .block_timestamp(to_nano(300))
+            .attached_deposit(1)
+            .build());
+        contract.ft_on_transfer(accounts(3), U128(10000), farm_id);
+    }
+
+    #[test]
+    fn test_get_farms_ending_before() {
+        let (mut context, mut contract) = setup_contract();
+        let first_farm = create_farm(
+            &mut context,
+            &mut contract,
+            accounts(1),
+            accounts(2),
+            5000,
+            50,
+        );
+        let second_farm = create_farm(
+            &mut context,
+            &mut contract,
+            accounts(1),
+            accounts(2),
+            5000,
+            50,
+        );
+        assert!(contract.get_farms_ending_before(10000, 10).is_empty());
+
+        // first farm lasts 10 rounds, ending at 600
+        deposit_reward(&mut context, &mut contract, 50000, 100);
+        // second farm lasts 100 rounds, ending at 5100
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .block_timestamp(to_nano(100))
+            .attached_deposit(1)
+            .build());
+        contract.ft_on_transfer(accounts(0), U128(500000), second_farm.clone());
+
+        let farms = contract.get_farms_ending_before(601, 10);
+        assert_eq!(farms.len(), 1);
+        assert_eq!(farms[0].farm_id, first_farm);
+        assert!(contract.get_farms_ending_before(600, 10).is_empty());
+        assert_eq!(contract.get_farms_ending_before(10000, 10).len(), 2);
+        assert_eq!(contract.get_farms_ending_before(10000, 1).len(), 1);
+    }
+
+    #[test]
+    fn test_vesting_reward() {
+        let (mut context, mut contract) = setup_contract();
+        // seed is bob, reward is charlie, claimed reward vests over 100 seconds
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(env::storage_byte_cost() * 650)
+            .build());
+        let farm_id = contract.create_simple_farm(
+            HRSimpleFarmTerms {
+                seed_id: accounts(1).into(),
+                reward_token: accounts(2).into(),
+                start_at: 0,
+                reward_per_session: U128(to_yocto("1")),
+                session_interval: 50,
+                vest_duration: 100,
+            },
+            Some(U128(10)),
+        );
+        assert_eq!(
+            contract.get_farm(farm_id.clone()).unwrap().vest_duration,
+            100
+        );
+        deposit_reward(&mut context, &mut contract, to_yocto("10"), 100);
+        register_farmer(&mut context, &mut contract, accounts(0));
+        deposit_seed(&mut context, &mut contract, accounts(0), 110, to_yocto("1"));
+
+        // claimed reward is locked
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .block_timestamp(to_nano(200))
+            .build());
+        let first = contract.get_unclaimed_reward(accounts(0), farm_id.clone())[&accounts(2)].0;
+        claim_reward(&mut context, &mut contract, accounts(0), 200);
+        assert!(first > 0);
+        assert_eq!(contract.get_reward(accounts(0), accounts(2)).0, 0);
+        let vesting = contract.get_vesting(accounts(0))[&accounts(2)].clone();
+        assert_eq!(vesting.locked.0, first);
+        assert_eq!(vesting.vested.0, 0);
+
+        // half way, only the vested half is released
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .block_timestamp(to_nano(250))
+            .build());
+        assert_eq!(
+            contract.get_vesting(accounts(0))[&accounts(2)].vested.0,
+            first / 2
+        );
+        let released = contract.withdraw_vested(accounts(0));
+        assert_eq!(released[&accounts(2)].0, first / 2);
+        assert_eq!(contract.get_reward(accounts(0), accounts(2)).0, first / 2);
+
+        // a second claim starts an overlapping schedule
+        let second = contract.get_unclaimed_reward(accounts(0), farm_id.clone())[&accounts(2)].0;
+        claim_reward(&mut context, &mut contract, accounts(0), 250);
+        assert!(second > 0);
+        let vesting = contract.get_vesting(accounts(0))[&accounts(2)].clone();
+        assert_eq!(vesting.locked.0, first - first / 2 + second);
+        assert_eq!(vesting.vested.0, 0);
+
+        // first schedule is over, second one is half way
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .block_timestamp(to_nano(300))
+            .build());
+        let released = contract.withdraw_vested(accounts(0));
+        assert_eq!(released[&accounts(2)].0, first - first / 2 + second / 2);
+        assert_eq!(
+            contract.get_reward(accounts(0), accounts(2)).0,
+            first + second / 2
+        );
+        let vesting = contract.get_vesting(accounts(0))[&accounts(2)].clone();
+        assert_eq!(vesting.locked.0, second - second / 2);
+        assert_eq!(vesting.vested.0, 0);
+    }
+
+    #[test]
+    fn test_emergency_withdraw_seed() {
+        let (mut context, mut contract) = setup_contract();
+        // seed is bob, reward is charlie
+        let farm_id = create_farm(
+            &mut context,
+            &mut contract,
+            accounts(1),
+            accounts(2),
+            to_yocto("1"),
+            50,
+        );
+        deposit_reward(&mut context, &mut contract, to_yocto("10"), 100);
+
+        register_farmer(&mut context, &mut contract, accounts(0));
+        deposit_seed(&mut context, &mut contract, accounts(0), 110, to_yocto("1"));
+        register_farmer(&mut context, &mut contract, accounts(3));
+        deposit_seed(&mut context, &mut contract, accounts(3), 110, to_yocto("1"));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .is_view(false)
+            .block_timestamp(to_nano(160))
+            .attached_deposit(1)
+            .build());
+        contract.emergency_withdraw_seed(accounts(1).into());
+        assert!(contract.list_user_seeds(accounts(0)).is_empty());
+        let seed_info = contract.get_seed_info(accounts(1).into()).expect("Error");
+        assert_eq!(seed_info.amount.0, to_yocto("1"));
+        assert_eq!(seed_info.farmer_count, Some(U64(1)));
+
+        // farmer0 forfeits half of round 1, the rest goes to farmer3
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .is_view(true)
+            .block_timestamp(to_nano(210))
+            .build());
+        let unclaimed = contract.get_unclaimed_reward(accounts(3), farm_id.clone())[&accounts(2)];
+        assert_eq!(unclaimed.0, to_yocto("1.5"));
+        let unclaimed = contract.get_unclaimed_reward(accounts(0), farm_id)[&accounts(2)];
+        assert_eq!(unclaimed.0, 0);
+        assert_eq!(contract.get_reward(accounts(0), accounts(2)).0, 0);
+    }
+
     #[test]
     #[should_panic(expected = "Insufficient storage deposit")]
     fn test_storage_withdraw() {
@@ -747,7 +1611,7 @@ mod tests {
         // println!("locked: {}, deposited: {}", sb.total.0, sb.available.0);
         let sb = storage_withdraw(&mut context, &mut contract, accounts(0));
         // println!("locked: {}, deposited: {}", sb.total.0, sb.available.0);
-        assert_eq!(sb.total.0, 920000000000000000000);
+        assert_eq!(sb.total.0, 1000000000000000000000);
         assert_eq!(sb.available.0, 0);
 
         let farm_id = create_farm(
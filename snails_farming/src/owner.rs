@@ -18,6 +18,22 @@ impl Contract {
         farm_seed.get_ref_mut().min_deposit = min_deposit.into();
     }
 
+    /// Rewrites the farmer in the current version, returns false if it already was.
+    /// Farmers upgrade lazily when used, this moves idle ones after the version enum is bumped.
+    pub fn migrate_farmer(&mut self, account_id: AccountId) -> bool {
+        self.assert_owner();
+        self.internal_migrate_farmer(&account_id)
+    }
+
+    /// Batch of `migrate_farmer`, returns how many farmers were rewritten.
+    pub fn migrate_farmers(&mut self, account_ids: Vec<AccountId>) -> u32 {
+        self.assert_owner();
+        account_ids
+            .iter()
+            .filter(|account_id| self.internal_migrate_farmer(account_id))
+            .count() as u32
+    }
+
     /// Migration function between versions.
     /// For next version upgrades, change this function.
     #[init(ignore_state)]
@@ -32,6 +48,21 @@ impl Contract {
         contract
     }
 
+    fn internal_migrate_farmer(&mut self, account_id: &AccountId) -> bool {
+        let farmer = self
+            .data()
+            .farmers
+            .get(account_id)
+            .expect(format!("Account [{}] not registered", account_id).as_str());
+        if !farmer.need_upgrade() {
+            return false;
+        }
+        self.data_mut()
+            .farmers
+            .insert(account_id, &farmer.upgrade());
+        true
+    }
+
     pub(crate) fn assert_owner(&self) {
         assert_eq!(
             env::predecessor_account_id(),
@@ -25,6 +25,7 @@ pub type RPS = [u8; 32];
 
 // to ensure precision, all reward_per_seed would be multiplied by this DENOM
 // this value should be carefully choosen, now is 10**24.
+// Farms and farmers stored before keep their RPS, so it must not change.
 pub const DENOM: u128 = 1_000_000_000_000_000_000_000_000;
 
 ///   The terms defines how the farm works.
@@ -33,13 +34,17 @@ pub const DENOM: u128 = 1_000_000_000_000_000_000_000_000;
 ///   In this way, the farm will take the amount from undistributed reward to  
 /// unclaimed reward each session. And all farmers would got reward token pro  
 /// rata of their seeds.
+///   A farm can distribute several reward tokens, each with its own amount per session.
+///   With a non-zero `vest_duration`, claimed rewards are released linearly
+/// over that duration instead of being withdrawable at once.
 #[derive(BorshSerialize, BorshDeserialize, Clone)]
 pub struct SimpleFarmTerms {
     pub seed_id: SeedId,
-    pub reward_token: AccountId,
+    pub reward_tokens: Vec<AccountId>,
     pub start_at: TimestampSec,
-    pub reward_per_session: Balance,
+    pub reward_per_session: Vec<Balance>,
     pub session_interval: TimestampSec,
+    pub vest_duration: TimestampSec,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -50,16 +55,46 @@ pub struct HRSimpleFarmTerms {
     pub start_at: u32,
     pub reward_per_session: U128,
     pub session_interval: u32,
+    #[serde(default)]
+    pub vest_duration: u32,
 }
 
-impl From<&HRSimpleFarmTerms> for SimpleFarmTerms {
+/// Terms of a farm distributing several reward tokens,
+/// `reward_per_session` is given per reward token.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct HRMultiRewardFarmTerms {
+    pub seed_id: SeedId,
+    pub reward_tokens: Vec<AccountId>,
+    pub start_at: u32,
+    pub reward_per_session: Vec<U128>,
+    pub session_interval: u32,
+    #[serde(default)]
+    pub vest_duration: u32,
+}
+
+impl From<&HRSimpleFarmTerms> for HRMultiRewardFarmTerms {
     fn from(terms: &HRSimpleFarmTerms) -> Self {
+        HRMultiRewardFarmTerms {
+            seed_id: terms.seed_id.clone(),
+            reward_tokens: vec![terms.reward_token.clone()],
+            start_at: terms.start_at,
+            reward_per_session: vec![terms.reward_per_session],
+            session_interval: terms.session_interval,
+            vest_duration: terms.vest_duration,
+        }
+    }
+}
+
+impl From<&HRMultiRewardFarmTerms> for SimpleFarmTerms {
+    fn from(terms: &HRMultiRewardFarmTerms) -> Self {
         SimpleFarmTerms {
             seed_id: terms.seed_id.clone(),
-            reward_token: terms.reward_token.clone().into(),
+            reward_tokens: terms.reward_tokens.clone(),
             start_at: terms.start_at,
-            reward_per_session: terms.reward_per_session.into(),
+            reward_per_session: terms.reward_per_session.iter().map(|x| x.0).collect(),
             session_interval: terms.session_interval,
+            vest_duration: terms.vest_duration,
         }
     }
 }
@@ -98,6 +133,20 @@ pub struct SimpleFarmRewardDistribution {
     pub rr: u32,
 }
 
+/// Distribution state of one reward token of the farm.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Default)]
+pub struct SimpleFarmReward {
+    pub last_distribution: SimpleFarmRewardDistribution,
+
+    /// total reward send into this farm by far,
+    /// every time reward deposited in, add to this field
+    pub amount_of_reward: Balance,
+    /// reward token has been claimed by farmer by far
+    pub amount_of_claimed: Balance,
+    /// when there is no seed token staked, reward goes to beneficiary
+    pub amount_of_beneficiary: Balance,
+}
+
 ///   Implementation of simple farm, Similar to the design of "berry farm".
 ///   Farmer stake their seed to farming on multiple farm accept that seed.
 #[derive(BorshSerialize, BorshDeserialize)]
@@ -107,38 +156,93 @@ pub struct SimpleFarm {
 
     pub status: SimpleFarmStatus,
 
-    pub last_distribution: SimpleFarmRewardDistribution,
+    /// distribution state of each reward token, in the order of `terms.reward_tokens`.
+    pub rewards: Vec<SimpleFarmReward>,
+}
 
-    /// total reward send into this farm by far,
-    /// every time reward deposited in, add to this field
+/// Terms of a farm before farms distributed several reward tokens.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct SimpleFarmTermsV101 {
+    pub seed_id: SeedId,
+    pub reward_token: AccountId,
+    pub start_at: TimestampSec,
+    pub reward_per_session: Balance,
+    pub session_interval: TimestampSec,
+}
+
+/// SimpleFarm before farms distributed several reward tokens.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct SimpleFarmV101 {
+    pub farm_id: FarmId,
+    pub terms: SimpleFarmTermsV101,
+    pub status: SimpleFarmStatus,
+    pub last_distribution: SimpleFarmRewardDistribution,
     pub amount_of_reward: Balance,
-    /// reward token has been claimed by farmer by far
     pub amount_of_claimed: Balance,
-    /// when there is no seed token staked, reward goes to beneficiary
     pub amount_of_beneficiary: Balance,
 }
 
+impl From<SimpleFarmV101> for SimpleFarm {
+    fn from(farm: SimpleFarmV101) -> Self {
+        Self {
+            farm_id: farm.farm_id,
+            terms: SimpleFarmTerms {
+                seed_id: farm.terms.seed_id,
+                reward_tokens: vec![farm.terms.reward_token],
+                start_at: farm.terms.start_at,
+                reward_per_session: vec![farm.terms.reward_per_session],
+                session_interval: farm.terms.session_interval,
+                vest_duration: 0,
+            },
+            status: farm.status,
+            rewards: vec![SimpleFarmReward {
+                last_distribution: farm.last_distribution,
+                amount_of_reward: farm.amount_of_reward,
+                amount_of_claimed: farm.amount_of_claimed,
+                amount_of_beneficiary: farm.amount_of_beneficiary,
+            }],
+        }
+    }
+}
+
 impl SimpleFarm {
     pub(crate) fn new(id: FarmId, terms: SimpleFarmTerms) -> Self {
         Self {
             farm_id: id.clone(),
-            amount_of_reward: 0,
-            amount_of_claimed: 0,
-            amount_of_beneficiary: 0,
-
             status: SimpleFarmStatus::Created,
-            last_distribution: SimpleFarmRewardDistribution::default(),
+            rewards: vec![SimpleFarmReward::default(); terms.reward_tokens.len()],
             terms,
         }
     }
 
-    pub(crate) fn change_reward_per_session(&mut self, reward_per_session: Balance) {
-        self.terms.reward_per_session = reward_per_session;
+    /// Returns index of given reward token in this farm.
+    pub(crate) fn reward_index(&self, reward_token: &AccountId) -> usize {
+        self.terms
+            .reward_tokens
+            .iter()
+            .position(|token| token == reward_token)
+            .expect(INVALID_FARM_REWARD)
+    }
+
+    pub(crate) fn change_reward_per_session(
+        &mut self,
+        reward_token: &AccountId,
+        reward_per_session: Balance,
+    ) {
+        let index = self.reward_index(reward_token);
+        self.terms.reward_per_session[index] = reward_per_session;
     }
 
     /// return None if the farm can not accept reward anymore
     /// else return amount of undistributed reward
-    pub(crate) fn add_reward(&mut self, amount: &Balance) -> Option<Balance> {
+    /// Note, an Ended farm resumes with a fresh `start_at`.
+    pub(crate) fn add_reward(
+        &mut self,
+        reward_token: &AccountId,
+        amount: &Balance,
+        total_seeds: &Balance,
+    ) -> Option<Balance> {
+        let index = self.reward_index(reward_token);
         match self.status {
             SimpleFarmStatus::Created => {
                 // When a farm gots first deposit of reward, it turns to Running state,
@@ -149,40 +253,51 @@ impl SimpleFarm {
                     // would trigger the farming
                     self.terms.start_at = to_sec(env::block_timestamp());
                 }
-                self.amount_of_reward = self.amount_of_reward.checked_add(*amount).unwrap();
-                self.last_distribution.undistributed = self
-                    .last_distribution
-                    .undistributed
-                    .checked_add(*amount)
-                    .unwrap();
-                Some(self.last_distribution.undistributed)
+                Some(self.internal_add_reward(index, amount))
             }
-            SimpleFarmStatus::Running => {
-                if let Some(dis) = self.try_distribute(&DENOM) {
-                    if dis.undistributed == 0 {
-                        // farm has ended actually
-                        return None;
+            SimpleFarmStatus::Running | SimpleFarmStatus::Ended => {
+                // settle the rounds farmed so far, a farm ran out of reward turns to Ended
+                self.distribute(total_seeds, true);
+                let now = to_sec(env::block_timestamp());
+                if let SimpleFarmStatus::Ended = self.status {
+                    // rounds restart from now, rps keeps growing from where it stopped
+                    self.terms.start_at = now;
+                    for reward in self.rewards.iter_mut() {
+                        reward.last_distribution.rr = 0;
                     }
+                    self.status = SimpleFarmStatus::Running;
+                } else if self.rewards[index].last_distribution.undistributed == 0
+                    && now >= self.terms.start_at
+                {
+                    // reward token ran out or is deposited the first time,
+                    // it is farmed from current round
+                    self.rewards[index].last_distribution.rr =
+                        (now - self.terms.start_at) / self.terms.session_interval;
                 }
-                // For a running farm, can add reward to extend duration
-                self.amount_of_reward = self.amount_of_reward.checked_add(*amount).unwrap();
-                self.last_distribution.undistributed = self
-                    .last_distribution
-                    .undistributed
-                    .checked_add(*amount)
-                    .unwrap();
-                Some(self.last_distribution.undistributed)
+                Some(self.internal_add_reward(index, amount))
             }
             _ => None,
         }
     }
 
-    /// Try to distribute reward according to current timestamp
+    fn internal_add_reward(&mut self, index: usize, amount: &Balance) -> Balance {
+        let reward = &mut self.rewards[index];
+        reward.amount_of_reward = reward.amount_of_reward.checked_add(*amount).unwrap();
+        reward.last_distribution.undistributed = reward
+            .last_distribution
+            .undistributed
+            .checked_add(*amount)
+            .unwrap();
+        reward.last_distribution.undistributed
+    }
+
+    /// Try to distribute reward of the `index`th reward token according to current timestamp
     /// return None if farm is not in Running state or haven't start farming yet;
     /// return new dis :SimpleFarmRewardDistribution
     /// Note, if total_seed is 0, the rps in new dis would be reset to 0 too.
     pub(crate) fn try_distribute(
         &self,
+        index: usize,
         total_seeds: &Balance,
     ) -> Option<SimpleFarmRewardDistribution> {
         if let SimpleFarmStatus::Running = self.status {
@@ -190,24 +305,24 @@ impl SimpleFarm {
                 // a farm haven't start yet
                 return None;
             }
-            let mut dis = self.last_distribution.clone();
+            let reward_per_session = self.terms.reward_per_session[index];
+            let last_distribution = &self.rewards[index].last_distribution;
+            let mut dis = last_distribution.clone();
             // calculate rr according to cur_timestamp
             dis.rr = (to_sec(env::block_timestamp())
                 .checked_sub(self.terms.start_at)
                 .unwrap())
                 / self.terms.session_interval;
-            let mut reward_added = (dis.rr.checked_sub(self.last_distribution.rr).unwrap()) as u128
-                * self.terms.reward_per_session;
-            if self.last_distribution.undistributed < reward_added {
+            let mut reward_added =
+                (dis.rr.checked_sub(last_distribution.rr).unwrap()) as u128 * reward_per_session;
+            if last_distribution.undistributed < reward_added {
                 // all undistribution would be distributed this time
-                reward_added = self.last_distribution.undistributed;
+                reward_added = last_distribution.undistributed;
                 // recalculate rr according to undistributed
-                let increased_rr = (reward_added
-                    .checked_div(self.terms.reward_per_session)
-                    .unwrap()) as u32;
-                dis.rr = self.last_distribution.rr.checked_add(increased_rr).unwrap();
+                let increased_rr = (reward_added.checked_div(reward_per_session).unwrap()) as u32;
+                dis.rr = last_distribution.rr.checked_add(increased_rr).unwrap();
                 let reward_caculated = (increased_rr as u128)
-                    .checked_mul(self.terms.reward_per_session)
+                    .checked_mul(reward_per_session)
                     .unwrap();
                 if reward_caculated < reward_added {
                     // add the tail round
@@ -221,7 +336,7 @@ impl SimpleFarm {
             if total_seeds == &0 {
                 U256::from(0).to_little_endian(&mut dis.rps);
             } else {
-                (U256::from_little_endian(&self.last_distribution.rps)
+                (U256::from_little_endian(&last_distribution.rps)
                     + U256::from(reward_added) * U256::from(DENOM) / U256::from(*total_seeds))
                 .to_little_endian(&mut dis.rps);
             }
@@ -231,10 +346,39 @@ impl SimpleFarm {
         }
     }
 
-    /// Return how many reward token that the user hasn't claimed yet.
+    /// Returns timestamp in sec the farm runs out of reward at the current reward per session,
+    /// the latest one over its reward tokens.
+    /// return None if farm is not in Running state.
+    pub(crate) fn projected_end_at(&self) -> Option<TimestampSec> {
+        if let SimpleFarmStatus::Running = self.status {
+            let end_round = self
+                .rewards
+                .iter()
+                .zip(self.terms.reward_per_session.iter())
+                .map(|(reward, reward_per_session)| {
+                    let dis = &reward.last_distribution;
+                    let rounds = match dis.undistributed.checked_div(*reward_per_session) {
+                        Some(rounds) if dis.undistributed % reward_per_session > 0 => rounds + 1,
+                        Some(rounds) => rounds,
+                        None => u32::MAX as u128,
+                    };
+                    dis.rr as u128 + rounds
+                })
+                .max()
+                .unwrap_or(0);
+            let end_at = self.terms.start_at as u128
+                + end_round.saturating_mul(self.terms.session_interval as u128);
+            Some(std::cmp::min(end_at, TimestampSec::MAX as u128) as TimestampSec)
+        } else {
+            None
+        }
+    }
+
+    /// Return how many of the `index`th reward token that the user hasn't claimed yet.
     /// return (cur_rps - last_user_rps) * user_seeds / DENOM
     pub(crate) fn view_farmer_unclaimed_reward(
         &self,
+        index: usize,
         user_rps: &RPS,
         user_seeds: &Balance,
         total_seeds: &Balance,
@@ -245,14 +389,14 @@ impl SimpleFarm {
         if user_seeds == &0 {
             return 0;
         }
-        if let Some(dis) = self.try_distribute(total_seeds) {
+        if let Some(dis) = self.try_distribute(index, total_seeds) {
             (U256::from(*user_seeds)
                 * (U256::from_little_endian(&dis.rps) - U256::from_little_endian(user_rps))
                 / U256::from(DENOM))
             .as_u128()
         } else {
             (U256::from(*user_seeds)
-                * (U256::from_little_endian(&self.last_distribution.rps)
+                * (U256::from_little_endian(&self.rewards[index].last_distribution.rps)
                     - U256::from_little_endian(user_rps))
                 / U256::from(DENOM))
             .as_u128()
@@ -261,29 +405,46 @@ impl SimpleFarm {
 
     /// Distribute reward generated from previous distribution to now,
     /// only works for farm in Running state and has reward deposited in,
-    /// Note 1, if undistribute equals 0, the farm goes to Ended state;
+    /// Note 1, if undistribute of every reward token equals 0, the farm goes to Ended state;
     /// Note 2, if total_seed is 0, reward is claimed directly by beneficiary
     pub(crate) fn distribute(&mut self, total_seeds: &Balance, silent: bool) {
-        if let Some(dis) = self.try_distribute(total_seeds) {
-            if self.last_distribution.rr != dis.rr {
-                self.last_distribution = dis.clone();
+        if let SimpleFarmStatus::Running = self.status {
+            for index in 0..self.rewards.len() {
+                self.distribute_reward(index, total_seeds, silent);
+            }
+            if self
+                .rewards
+                .iter()
+                .all(|reward| reward.last_distribution.undistributed == 0)
+            {
+                self.status = SimpleFarmStatus::Ended;
+            }
+        }
+    }
+
+    fn distribute_reward(&mut self, index: usize, total_seeds: &Balance, silent: bool) {
+        if let Some(dis) = self.try_distribute(index, total_seeds) {
+            let reward = &mut self.rewards[index];
+            if reward.last_distribution.rr != dis.rr {
+                reward.last_distribution = dis.clone();
                 if total_seeds == &0 {
                     // if total_seeds == &0, reward goes to beneficiary,
-                    self.amount_of_claimed = self
+                    reward.amount_of_claimed = reward
                         .amount_of_claimed
-                        .checked_add(self.last_distribution.unclaimed)
+                        .checked_add(reward.last_distribution.unclaimed)
                         .unwrap();
-                    self.amount_of_beneficiary = self
+                    reward.amount_of_beneficiary = reward
                         .amount_of_beneficiary
-                        .checked_add(self.last_distribution.unclaimed)
+                        .checked_add(reward.last_distribution.unclaimed)
                         .unwrap();
-                    self.last_distribution.unclaimed = 0;
+                    reward.last_distribution.unclaimed = 0;
                 }
                 if !silent {
                     env::log_str(
                         format!(
-                            "{} RPS increased to {} and RR update to #{}",
+                            "{} {} RPS increased to {} and RR update to #{}",
                             self.farm_id,
+                            self.terms.reward_tokens[index],
                             U256::from_little_endian(&dis.rps),
                             dis.rr,
                         )
@@ -291,46 +452,58 @@ impl SimpleFarm {
                     );
                 }
             }
-            if self.last_distribution.undistributed == 0 {
-                self.status = SimpleFarmStatus::Ended;
-            }
         }
     }
 
-    /// Claim user's unclaimed reward in this farm,
+    /// Claim user's unclaimed reward of the `index`th reward token in this farm,
     /// return the new user RPS (reward per seed),  
     /// and amount of reward
+    /// Note, if keep_remainder, user RPS only moves by what is paid,
+    /// so the rounding remainder is paid by a later claim.
+    /// It must be false when user seeds are about to grow,
+    /// otherwise the remainder would be paid on the added seeds too.
     pub(crate) fn claim_user_reward(
         &mut self,
+        index: usize,
         user_rps: &RPS,
         user_seeds: &Balance,
         total_seeds: &Balance,
         silent: bool,
+        keep_remainder: bool,
     ) -> (RPS, Balance) {
         self.distribute(total_seeds, silent);
-        let claimed = (U256::from(*user_seeds)
-            * (U256::from_little_endian(&self.last_distribution.rps)
-                - U256::from_little_endian(user_rps))
-            / U256::from(DENOM))
-        .as_u128();
+        let reward = &mut self.rewards[index];
+        let cur_rps = U256::from_little_endian(&reward.last_distribution.rps);
+        let user_rps = U256::from_little_endian(user_rps);
+        let seeds = U256::from(*user_seeds);
+        let claimed = (seeds * (cur_rps - user_rps) / U256::from(DENOM)).as_u128();
+
+        let mut new_user_rps: RPS = [0; 32];
+        if keep_remainder && user_seeds > &0 {
+            // round up the paid rps, the carried remainder never pays more than distributed
+            let paid_rps = (U256::from(claimed) * U256::from(DENOM) + seeds - U256::one()) / seeds;
+            (user_rps + paid_rps).to_little_endian(&mut new_user_rps);
+        } else {
+            cur_rps.to_little_endian(&mut new_user_rps);
+        }
 
         if claimed > 0 {
             assert!(
-                self.last_distribution.unclaimed >= claimed,
+                reward.last_distribution.unclaimed >= claimed,
                 "{} unclaimed:{}, cur_claim:{}",
                 INTERNAL_ERROR,
-                self.last_distribution.unclaimed,
+                reward.last_distribution.unclaimed,
                 claimed
             );
-            self.last_distribution.unclaimed = self
+            reward.last_distribution.unclaimed = reward
                 .last_distribution
                 .unclaimed
                 .checked_sub(claimed)
                 .unwrap();
-            self.amount_of_claimed = self.amount_of_claimed.checked_add(claimed).unwrap();
+            reward.amount_of_claimed = reward.amount_of_claimed.checked_add(claimed).unwrap();
         }
 
-        (self.last_distribution.rps, claimed)
+        (new_user_rps, claimed)
     }
 
     /// Move an Ended farm to Cleared, if any unclaimed reward exists, go to beneficiary
@@ -339,16 +512,18 @@ impl SimpleFarm {
             self.distribute(total_seeds, true);
         }
         if let SimpleFarmStatus::Ended = self.status {
-            if self.last_distribution.unclaimed > 0 {
-                self.amount_of_claimed = self
-                    .amount_of_claimed
-                    .checked_add(self.last_distribution.unclaimed)
-                    .unwrap();
-                self.amount_of_beneficiary = self
-                    .amount_of_beneficiary
-                    .checked_add(self.last_distribution.unclaimed)
-                    .unwrap();
-                self.last_distribution.unclaimed = 0;
+            for reward in self.rewards.iter_mut() {
+                if reward.last_distribution.unclaimed > 0 {
+                    reward.amount_of_claimed = reward
+                        .amount_of_claimed
+                        .checked_add(reward.last_distribution.unclaimed)
+                        .unwrap();
+                    reward.amount_of_beneficiary = reward
+                        .amount_of_beneficiary
+                        .checked_add(reward.last_distribution.unclaimed)
+                        .unwrap();
+                    reward.last_distribution.unclaimed = 0;
+                }
             }
             self.status = SimpleFarmStatus::Cleared;
             true
@@ -360,18 +535,93 @@ impl SimpleFarm {
     pub fn can_be_removed(&self, total_seeds: &Balance) -> bool {
         match self.status {
             SimpleFarmStatus::Ended => true,
-            SimpleFarmStatus::Running => {
-                if let Some(dis) = self.try_distribute(total_seeds) {
-                    if dis.undistributed == 0 {
-                        true
-                    } else {
-                        false
-                    }
+            SimpleFarmStatus::Running => (0..self.rewards.len()).all(|index| {
+                if let Some(dis) = self.try_distribute(index, total_seeds) {
+                    dis.undistributed == 0
                 } else {
                     false
                 }
-            }
+            }),
             _ => false,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+    use proptest::prelude::*;
+
+    const SESSION_INTERVAL: u32 = 50;
+    const SESSIONS: u128 = 40;
+
+    fn set_time(sec: u32) {
+        testing_env!(VMContextBuilder::new()
+            .block_timestamp(to_nano(sec))
+            .build());
+    }
+
+    proptest! {
+        /// Farmers claim or stake more at random rounds,
+        /// then all claim after the farm ended.
+        #[test]
+        fn test_claims_never_exceed_distributed(
+            reward_per_session in 1..u64::MAX as u128,
+            initial_seeds in prop::collection::vec(1..u64::MAX as u128, 1..5),
+            actions in prop::collection::vec(
+                (0..5usize, 0..3u32, prop::option::of(1..u64::MAX as u128)),
+                1..30,
+            ),
+        ) {
+            set_time(100);
+            let mut farm = SimpleFarm::new(
+                String::from("seed#0"),
+                SimpleFarmTerms {
+                    seed_id: String::from("seed"),
+                    reward_tokens: vec![accounts(2)],
+                    start_at: 0,
+                    reward_per_session: vec![reward_per_session],
+                    session_interval: SESSION_INTERVAL,
+                    vest_duration: 0,
+                },
+            );
+            farm.add_reward(&accounts(2), &(reward_per_session * SESSIONS), &0);
+
+            let mut total_seeds: Balance = initial_seeds.iter().sum();
+            let mut farmers: Vec<(Balance, RPS)> =
+                initial_seeds.iter().map(|seeds| (*seeds, [0; 32])).collect();
+            let mut claimed: Balance = 0;
+            let mut stakes = 0;
+            let mut now = 100;
+            for (farmer_index, rounds, added) in actions {
+                now += rounds * SESSION_INTERVAL;
+                set_time(now);
+                let (seeds, rps) = &mut farmers[farmer_index % initial_seeds.len()];
+                let (new_rps, amount) =
+                    farm.claim_user_reward(0, rps, seeds, &total_seeds, true, added.is_none());
+                *rps = new_rps;
+                claimed += amount;
+                if let Some(added) = added {
+                    *seeds += added;
+                    total_seeds += added;
+                    stakes += 1;
+                }
+            }
+
+            set_time(now + SESSIONS as u32 * SESSION_INTERVAL);
+            for (seeds, rps) in farmers.iter_mut() {
+                let (_, amount) = farm.claim_user_reward(0, rps, seeds, &total_seeds, true, true);
+                claimed += amount;
+            }
+
+            let reward = &farm.rewards[0];
+            prop_assert_eq!(reward.last_distribution.undistributed, 0);
+            prop_assert_eq!(reward.amount_of_claimed, claimed);
+            prop_assert!(claimed <= reward.amount_of_reward);
+            // at most a yocto is left per farmer and per stake
+            prop_assert!(reward.amount_of_reward - claimed <= (farmers.len() + stakes) as u128);
+        }
+    }
+}
@@ -124,6 +124,11 @@ impl StorageManagement for Contract {
                 "{}",
                 STORAGE_UNREGISTER_SEED_NOT_EMPTY
             );
+            assert!(
+                farmer.get_ref().vestings.is_empty(),
+                "{}",
+                STORAGE_UNREGISTER_VESTINGS_NOT_EMPTY
+            );
             self.data_mut().farmers.remove(&account_id);
             self.data_mut().farmer_count = self.data_mut().farmer_count.checked_sub(1).unwrap();
             // TODO: should make sure tranfer is OK with a callback
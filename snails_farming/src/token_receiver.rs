@@ -3,15 +3,26 @@ use crate::farm_seed::SeedType;
 use crate::utils::MFT_TAG;
 use crate::*;
 use near_sdk::json_types::U128;
-use near_sdk::PromiseOrValue;
+use near_sdk::serde::Deserialize;
+use near_sdk::{serde_json, PromiseOrValue};
 
 use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
 
+/// Message of a seed deposit locking the seed,
+/// an empty message deposits the seed without lock.
+#[derive(Deserialize, Default)]
+#[serde(crate = "near_sdk::serde")]
+struct SeedDepositMsg {
+    /// Days the staked seed is locked for, boosting the farmer share of the seed.
+    lock_days: u32,
+}
+
 #[near_bindgen]
 impl FungibleTokenReceiver for Contract {
     /// Callback on receiving tokens by this contract.
     /// transfer reward token with specific msg indicate
     /// which farm to be deposited to.
+    /// transfer seed token with empty msg or a `SeedDepositMsg`.
     fn ft_on_transfer(
         &mut self,
         sender_id: AccountId,
@@ -21,7 +32,12 @@ impl FungibleTokenReceiver for Contract {
         self.assert_contract_running();
         let sender: AccountId = sender_id.into();
         let amount: u128 = amount.into();
-        if msg.is_empty() {
+        let seed_msg = if msg.is_empty() {
+            Some(SeedDepositMsg::default())
+        } else {
+            serde_json::from_str::<SeedDepositMsg>(&msg).ok()
+        };
+        if let Some(seed_msg) = seed_msg {
             // ****** seed Token deposit in ********
 
             // if seed not exist, it will panic
@@ -42,6 +58,7 @@ impl FungibleTokenReceiver for Contract {
                 &sender,
                 amount.into(),
                 SeedType::FT,
+                seed_msg.lock_days,
             );
             self.assert_storage_usage(&sender);
 
@@ -60,17 +77,29 @@ impl FungibleTokenReceiver for Contract {
             let farm_id = msg
                 .parse::<FarmId>()
                 .expect(&format!("{}", INVALID_FARM_ID));
-            let mut farm = self.data().farms.get(&farm_id).expect(FARM_NOT_EXIST);
+            let mut farm = self.get_farm_wrapped(&farm_id).expect(FARM_NOT_EXIST);
 
             // update farm
-            assert_eq!(
-                farm.get_reward_token(),
-                env::predecessor_account_id(),
+            assert!(
+                farm.get_reward_tokens()
+                    .contains(&env::predecessor_account_id()),
                 "{}",
                 INVALID_FARM_REWARD
             );
-            if let Some(cur_remain) = farm.add_reward(&amount) {
-                self.data_mut().farms.insert(&farm_id, &farm);
+            let total_seeds = self
+                .get_seed_wrapped(&farm.get_seed_id())
+                .map(|farm_seed| farm_seed.get_ref().power)
+                .unwrap_or(0);
+            if farm.can_be_removed(&total_seeds) {
+                // topping up an ended farm resumes it
+                assert_eq!(sender, self.data().owner_id, "{}", RESUME_FARM_NOT_ALLOWED);
+            }
+            if let Some(cur_remain) =
+                farm.add_reward(&env::predecessor_account_id(), &amount, &total_seeds)
+            {
+                self.data_mut()
+                    .farms
+                    .insert(&farm_id, &VersionedFarm::new(farm));
                 let old_balance = self
                     .data()
                     .reward_info
@@ -154,7 +183,11 @@ impl MFTTokenReceiver for Contract {
             }
         }
 
-        assert!(msg.is_empty(), "ERR_MSG_INCORRECT");
+        let seed_msg = if msg.is_empty() {
+            SeedDepositMsg::default()
+        } else {
+            serde_json::from_str::<SeedDepositMsg>(&msg).expect("ERR_MSG_INCORRECT")
+        };
 
         // if seed not exist, it will panic
         let amount: u128 = amount.into();
@@ -169,7 +202,13 @@ impl MFTTokenReceiver for Contract {
                 .as_str(),
             )
         }
-        self.internal_seed_deposit(&seed_id, &sender_id, amount, SeedType::MFT);
+        self.internal_seed_deposit(
+            &seed_id,
+            &sender_id,
+            amount,
+            SeedType::MFT,
+            seed_msg.lock_days,
+        );
 
         self.assert_storage_usage(&sender_id);
 
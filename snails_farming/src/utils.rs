@@ -1,7 +1,7 @@
 use crate::errors::*;
 use crate::{FarmId, SeedId};
 use near_sdk::json_types::U128;
-use near_sdk::{env, ext_contract, Gas, Timestamp};
+use near_sdk::{env, ext_contract, Balance, Gas, Timestamp};
 use uint::construct_uint;
 
 pub type TimestampSec = u32;
@@ -13,6 +13,12 @@ pub const GAS_FOR_FT_TRANSFER: Gas = Gas(10_000_000_000_000);
 /// hotfix_insuffient_gas_for_mft_resolve_transfer, increase from 5T to 20T
 pub const GAS_FOR_RESOLVE_TRANSFER: Gas = Gas(20_000_000_000_000);
 pub const MFT_TAG: &str = "@";
+pub const SECONDS_PER_DAY: u32 = 86_400;
+/// Longest lock period of a seed deposit.
+pub const MAX_LOCK_DAYS: u32 = 365;
+/// Boost of a seed locked for MAX_LOCK_DAYS, shorter locks get a linear part of it.
+pub const MAX_LOCK_BOOST_BPS: u32 = 10_000;
+pub const BPS_DENOM: u32 = 10_000;
 
 construct_uint! {
     /// 256-bit unsigned integer.
@@ -101,6 +107,27 @@ pub fn gen_farm_id(seed_id: &SeedId, index: usize) -> FarmId {
     format!("{}#{}", seed_id, index)
 }
 
+/// Key of the farmer rps of the `index`th reward token of a farm,
+/// the first reward token uses the farm id.
+pub fn gen_user_rps_key(farm_id: &FarmId, index: usize) -> String {
+    if index == 0 {
+        farm_id.clone()
+    } else {
+        format!("{}#{}", farm_id, index)
+    }
+}
+
+/// Returns boost granted to a seed locked for `lock_days`.
+pub fn lock_boost_bps(lock_days: u32) -> u32 {
+    assert!(lock_days <= MAX_LOCK_DAYS, "{}", INVALID_LOCK_DAYS);
+    (MAX_LOCK_BOOST_BPS as u64 * lock_days as u64 / MAX_LOCK_DAYS as u64) as u32
+}
+
+/// Returns amount increased by `boost_bps`.
+pub fn boost_amount(amount: Balance, boost_bps: u32) -> Balance {
+    (U256::from(amount) * U256::from(BPS_DENOM + boost_bps) / U256::from(BPS_DENOM)).as_u128()
+}
+
 pub(crate) fn to_nano(timestamp: TimestampSec) -> Timestamp {
     Timestamp::from(timestamp) * 10u64.pow(9)
 }
@@ -4,11 +4,11 @@ use std::collections::HashMap;
 
 use near_sdk::json_types::{U128, U64};
 use near_sdk::serde::{Deserialize, Serialize};
-use near_sdk::{near_bindgen, AccountId};
+use near_sdk::{env, near_bindgen, AccountId};
 
 use crate::farm_seed::SeedInfo;
 use crate::simple_farm::DENOM;
-use crate::utils::parse_farm_id;
+use crate::utils::{gen_farm_id, gen_user_rps_key, parse_farm_id, to_sec};
 use crate::*;
 
 use std::convert::TryInto;
@@ -58,6 +58,44 @@ pub struct Metadata {
     pub reward_count: U64,
 }
 
+/// Vesting rewards of a farmer in one reward token.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VestingInfo {
+    /// not vested yet.
+    pub locked: U128,
+    /// vested, can be moved to claimed rewards with `withdraw_vested`.
+    pub vested: U128,
+}
+
+/// Lock of a farmer's staked seed.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SeedLockInfo {
+    /// the seed can not be withdrawn before, in seconds.
+    pub locked_until: u32,
+    pub boost_bps: u32,
+    /// staked amount boosted by the lock, used as share in the farms.
+    pub power: U128,
+}
+
+/// Distribution of one reward token of a farm.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FarmRewardInfo {
+    pub reward_token: AccountId,
+    pub reward_per_session: U128,
+
+    pub total_reward: U128,
+    pub cur_round: u32,
+    pub last_round: u32,
+    pub claimed_reward: U128,
+    pub unclaimed_reward: U128,
+    pub beneficiary_reward: U128,
+}
+
+/// Reward fields are the ones of the first reward token,
+/// `rewards` lists every reward token of the farm.
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 #[serde(crate = "near_sdk::serde")]
 pub struct FarmInfo {
@@ -69,6 +107,7 @@ pub struct FarmInfo {
     pub start_at: u32,
     pub reward_per_session: U128,
     pub session_interval: u32,
+    pub vest_duration: u32,
 
     pub total_reward: U128,
     pub cur_round: u32,
@@ -76,6 +115,8 @@ pub struct FarmInfo {
     pub claimed_reward: U128,
     pub unclaimed_reward: U128,
     pub beneficiary_reward: U128,
+
+    pub rewards: Vec<FarmRewardInfo>,
 }
 
 impl From<&Farm> for FarmInfo {
@@ -83,46 +124,56 @@ impl From<&Farm> for FarmInfo {
         let farm_kind = farm.kind();
         match farm {
             Farm::SimpleFarm(farm) => {
-                if let Some(dis) = farm.try_distribute(&DENOM) {
-                    let mut farm_status: String = (&farm.status).into();
-                    if farm_status == "Running".to_string() && dis.undistributed == 0 {
-                        farm_status = "Ended".to_string();
-                    }
-                    Self {
-                        farm_id: farm.farm_id.clone(),
-                        farm_kind,
-                        farm_status,
-                        seed_id: farm.terms.seed_id.clone(),
-                        reward_token: farm.terms.reward_token.clone(),
-                        start_at: farm.terms.start_at,
-                        reward_per_session: farm.terms.reward_per_session.into(),
-                        session_interval: farm.terms.session_interval,
-
-                        total_reward: farm.amount_of_reward.into(),
-                        cur_round: dis.rr.into(),
-                        last_round: farm.last_distribution.rr.into(),
-                        claimed_reward: farm.amount_of_claimed.into(),
-                        unclaimed_reward: dis.unclaimed.into(),
-                        beneficiary_reward: farm.amount_of_beneficiary.into(),
-                    }
-                } else {
-                    Self {
-                        farm_id: farm.farm_id.clone(),
-                        farm_kind,
-                        farm_status: (&farm.status).into(),
-                        seed_id: farm.terms.seed_id.clone(),
-                        reward_token: farm.terms.reward_token.clone(),
-                        start_at: farm.terms.start_at.into(),
-                        reward_per_session: farm.terms.reward_per_session.into(),
-                        session_interval: farm.terms.session_interval.into(),
-                        total_reward: farm.amount_of_reward.into(),
-                        cur_round: farm.last_distribution.rr.into(),
-                        last_round: farm.last_distribution.rr.into(),
-                        claimed_reward: farm.amount_of_claimed.into(),
-                        // unclaimed_reward: (farm.amount_of_reward - farm.amount_of_claimed).into(),
-                        unclaimed_reward: farm.last_distribution.unclaimed.into(),
-                        beneficiary_reward: farm.amount_of_beneficiary.into(),
-                    }
+                let mut farm_status: String = (&farm.status).into();
+                let mut ended = true;
+                let mut rewards = vec![];
+                for (index, reward) in farm.rewards.iter().enumerate() {
+                    let (cur_round, unclaimed_reward) =
+                        if let Some(dis) = farm.try_distribute(index, &DENOM) {
+                            ended = ended && dis.undistributed == 0;
+                            (dis.rr, dis.unclaimed)
+                        } else {
+                            ended = false;
+                            (
+                                reward.last_distribution.rr,
+                                reward.last_distribution.unclaimed,
+                            )
+                        };
+                    rewards.push(FarmRewardInfo {
+                        reward_token: farm.terms.reward_tokens[index].clone(),
+                        reward_per_session: farm.terms.reward_per_session[index].into(),
+
+                        total_reward: reward.amount_of_reward.into(),
+                        cur_round,
+                        last_round: reward.last_distribution.rr,
+                        claimed_reward: reward.amount_of_claimed.into(),
+                        unclaimed_reward: unclaimed_reward.into(),
+                        beneficiary_reward: reward.amount_of_beneficiary.into(),
+                    });
+                }
+                if farm_status == "Running".to_string() && ended {
+                    farm_status = "Ended".to_string();
+                }
+                let first = rewards[0].clone();
+                Self {
+                    farm_id: farm.farm_id.clone(),
+                    farm_kind,
+                    farm_status,
+                    seed_id: farm.terms.seed_id.clone(),
+                    reward_token: first.reward_token,
+                    start_at: farm.terms.start_at,
+                    reward_per_session: first.reward_per_session,
+                    session_interval: farm.terms.session_interval,
+                    vest_duration: farm.terms.vest_duration,
+
+                    total_reward: first.total_reward,
+                    cur_round: first.cur_round,
+                    last_round: first.last_round,
+                    claimed_reward: first.claimed_reward,
+                    unclaimed_reward: first.unclaimed_reward,
+                    beneficiary_reward: first.beneficiary_reward,
+
+                    rewards,
                 }
             }
         }
@@ -156,7 +207,7 @@ impl Contract {
         let keys = self.data().farms.keys_as_vector();
 
         (from_index..std::cmp::min(from_index + limit, keys.len()))
-            .map(|index| (&self.data().farms.get(&keys.get(index).unwrap()).unwrap()).into())
+            .map(|index| (&self.get_farm_wrapped(&keys.get(index).unwrap()).unwrap()).into())
             .collect()
     }
 
@@ -166,27 +217,45 @@ impl Contract {
         (from_index..std::cmp::min(from_index + limit, keys.len()))
             .map(|index| {
                 (&self
-                    .data()
-                    .outdated_farms
-                    .get(&keys.get(index).unwrap())
+                    .get_outdated_farm_wrapped(&keys.get(index).unwrap())
                     .unwrap())
                     .into()
             })
             .collect()
     }
 
-    pub fn list_farms_by_seed(&self, seed_id: SeedId) -> Vec<FarmInfo> {
-        self.get_seed(&seed_id)
-            .get_ref()
+    /// Returns running farms which run out of reward before given timestamp in sec,
+    /// at most `limit` of them.
+    pub fn get_farms_ending_before(&self, ts: u64, limit: u64) -> Vec<FarmInfo> {
+        self.data()
             .farms
-            .iter()
-            .map(|farm_id| (&self.data().farms.get(&farm_id).unwrap()).into())
+            .keys()
+            .filter_map(|farm_id| self.get_farm_wrapped(&farm_id))
+            .filter(|farm| {
+                farm.projected_end_at()
+                    .map_or(false, |end_at| (end_at as u64) < ts)
+            })
+            .take(limit as usize)
+            .map(|farm| (&farm).into())
+            .collect()
+    }
+
+    /// Returns all farms ever created on the seed, outdated ones included, in creation order.
+    pub fn list_farms_by_seed(&self, seed_id: SeedId) -> Vec<FarmInfo> {
+        let next_index = self.get_seed(&seed_id).get_ref().next_index as usize;
+        (0..next_index)
+            .filter_map(|index| {
+                let farm_id = gen_farm_id(&seed_id, index);
+                self.get_farm_wrapped(&farm_id)
+                    .or_else(|| self.get_outdated_farm_wrapped(&farm_id))
+            })
+            .map(|farm| (&farm).into())
             .collect()
     }
 
     /// Returns information about specified farm.
     pub fn get_farm(&self, farm_id: FarmId) -> Option<FarmInfo> {
-        if let Some(farm) = self.data().farms.get(&farm_id) {
+        if let Some(farm) = self.get_farm_wrapped(&farm_id) {
             Some((&farm).into())
         } else {
             None
@@ -194,7 +263,7 @@ impl Contract {
     }
 
     pub fn get_outdated_farm(&self, farm_id: FarmId) -> Option<FarmInfo> {
-        if let Some(farm) = self.data().outdated_farms.get(&farm_id) {
+        if let Some(farm) = self.get_outdated_farm_wrapped(&farm_id) {
             Some((&farm).into())
         } else {
             None
@@ -228,33 +297,116 @@ impl Contract {
             .collect()
     }
 
+    /// Returns vesting rewards of given user per reward token.
+    pub fn get_vesting(&self, account_id: AccountId) -> HashMap<AccountId, VestingInfo> {
+        let now = to_sec(env::block_timestamp());
+        self.get_farmer_default(&account_id)
+            .get()
+            .vestings
+            .into_iter()
+            .map(|(token_id, vestings)| {
+                let (mut locked, mut vested) = (0, 0);
+                for vesting in vestings.iter() {
+                    let amount = vesting.vested(now);
+                    locked += vesting.amount - amount;
+                    vested += amount - vesting.released;
+                }
+                (
+                    token_id,
+                    VestingInfo {
+                        locked: locked.into(),
+                        vested: vested.into(),
+                    },
+                )
+            })
+            .collect()
+    }
+
     /// Returns balance of amount of given reward token that ready to withdraw.
     pub fn get_reward(&self, account_id: AccountId, token_id: AccountId) -> U128 {
         self.internal_get_reward(&account_id, &token_id).into()
     }
 
-    pub fn get_unclaimed_reward(&self, account_id: AccountId, farm_id: FarmId) -> U128 {
+    /// Returns claimed balance of every reward token of given user, sorted by token.
+    /// Returns empty list if no rewards claimed.
+    pub fn list_claimed_rewards(&self, account_id: AccountId) -> Vec<(AccountId, U128)> {
+        let mut rewards: Vec<(AccountId, U128)> = self
+            .get_farmer_default(&account_id)
+            .get()
+            .rewards
+            .into_iter()
+            .map(|(token_id, balance)| (token_id, U128(balance)))
+            .collect();
+        rewards.sort_by(|a, b| a.0.cmp(&b.0));
+        rewards
+    }
+
+    /// Returns reward of each reward token of the farm the farmer can claim.
+    pub fn get_unclaimed_reward(
+        &self,
+        account_id: AccountId,
+        farm_id: FarmId,
+    ) -> HashMap<AccountId, U128> {
         let (seed_id, _) = parse_farm_id(&farm_id);
 
-        if let (Some(farmer), Some(farm_seed)) = (
+        if let (Some(farmer), Some(farm_seed), Some(farm)) = (
             self.get_farmer_wrapped(&account_id),
             self.get_seed_wrapped(&seed_id),
+            self.get_farm_wrapped(&farm_id),
         ) {
-            if let Some(farm) = self.data().farms.get(&farm_id) {
-                let reward_amount = farm.view_farmer_unclaimed_reward(
-                    &farmer.get_ref().get_rps(&farm.get_farm_id()),
-                    farmer.get_ref().seeds.get(&seed_id).unwrap_or(&0_u128),
-                    &farm_seed.get_ref().amount,
-                );
-                reward_amount.into()
-            } else {
-                0.into()
-            }
+            farm.get_reward_tokens()
+                .into_iter()
+                .enumerate()
+                .map(|(index, reward_token)| {
+                    let reward_amount = farm.view_farmer_unclaimed_reward(
+                        index,
+                        &farmer
+                            .get_ref()
+                            .get_rps(&gen_user_rps_key(&farm.get_farm_id(), index)),
+                        &farmer.get_ref().seed_power(&seed_id),
+                        &farm_seed.get_ref().power,
+                    );
+                    (reward_token, reward_amount.into())
+                })
+                .collect()
         } else {
-            0.into()
+            HashMap::new()
         }
     }
 
+    /// Returns unclaimed reward of each reward token for every running farm
+    /// of the seeds the farmer staked, ordered by farm id.
+    pub fn list_unclaimed_rewards(
+        &self,
+        account_id: AccountId,
+    ) -> Vec<(FarmId, HashMap<AccountId, U128>)> {
+        let farmer = match self.get_farmer_wrapped(&account_id) {
+            Some(farmer) => farmer,
+            None => return vec![],
+        };
+        let mut farm_ids: Vec<FarmId> = farmer
+            .get_ref()
+            .seeds
+            .keys()
+            .filter_map(|seed_id| self.get_seed_wrapped(seed_id))
+            .flat_map(|farm_seed| {
+                let farm_seed = farm_seed.get_ref();
+                (0..farm_seed.next_index)
+                    .map(|index| gen_farm_id(&farm_seed.seed_id, index as usize))
+                    .collect::<Vec<_>>()
+            })
+            .filter(|farm_id| self.data().farms.get(farm_id).is_some())
+            .collect();
+        farm_ids.sort();
+        farm_ids
+            .into_iter()
+            .map(|farm_id| {
+                let reward = self.get_unclaimed_reward(account_id.clone(), farm_id.clone());
+                (farm_id, reward)
+            })
+            .collect()
+    }
+
     /// return all seed and its amount staked in this contract in a hashmap
     pub fn list_seeds(&self, from_index: u64, limit: u64) -> HashMap<SeedId, U128> {
         let keys = self.data().seeds.keys_as_vector();
@@ -271,6 +423,20 @@ impl Contract {
             .collect()
     }
 
+    /// Returns lock of the farmer's seed, None if the seed is not locked.
+    pub fn get_seed_lock(&self, account_id: AccountId, seed_id: SeedId) -> Option<SeedLockInfo> {
+        let farmer = self.get_farmer_wrapped(&account_id)?;
+        let farmer = farmer.get_ref();
+        if !farmer.seeds.contains_key(&seed_id) {
+            return None;
+        }
+        farmer.locks.get(&seed_id).map(|lock| SeedLockInfo {
+            locked_until: lock.locked_until,
+            boost_bps: lock.boost_bps,
+            power: farmer.seed_power(&seed_id).into(),
+        })
+    }
+
     /// return user staked seeds and its amount in a hashmap
     pub fn list_user_seeds(&self, account_id: AccountId) -> HashMap<SeedId, U128> {
         if let Some(farmer) = self.get_farmer_wrapped(&account_id) {